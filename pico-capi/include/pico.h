@@ -0,0 +1,87 @@
+/*
+ * C ABI of the picoNES core, built by the pico-capi crate as a shared
+ * (libpico_capi.so / .dylib / pico_capi.dll) or static library.
+ *
+ * Every function taking a `Pico *` accepts NULL and then does nothing,
+ * returns -1 or returns NULL. A handle must not be used from two threads at
+ * once.
+ */
+#ifndef PICO_H
+#define PICO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define PICO_ABI_VERSION 1
+
+/* Joypad bits for pico_set_buttons. */
+#define PICO_BUTTON_A      0x01
+#define PICO_BUTTON_B      0x02
+#define PICO_BUTTON_SELECT 0x04
+#define PICO_BUTTON_START  0x08
+#define PICO_BUTTON_UP     0x10
+#define PICO_BUTTON_DOWN   0x20
+#define PICO_BUTTON_LEFT   0x40
+#define PICO_BUTTON_RIGHT  0x80
+
+typedef struct Pico Pico;
+
+/* Compare with PICO_ABI_VERSION to catch a mismatched library. */
+uint32_t pico_abi_version(void);
+
+/* Never returns NULL. Release with pico_free. */
+Pico *pico_new(void);
+void pico_free(Pico *pico);
+
+/*
+ * Message for the last failed call, or "" if the last load succeeded.
+ * Valid until the next call on the handle.
+ */
+const char *pico_last_error(const Pico *pico);
+
+/*
+ * Copies `len` bytes of an iNES or NES 2.0 image and powers on with it,
+ * replacing any ROM already loaded. Returns 0 on success and -1 on failure.
+ */
+int pico_load_rom(Pico *pico, const uint8_t *data, size_t len);
+
+void pico_reset(Pico *pico);
+
+/* Runs one video frame. Returns -1 if no ROM is loaded. */
+int pico_run_frame(Pico *pico);
+
+/*
+ * pico_framebuffer_width() x pico_framebuffer_height() pixels of packed
+ * RGB24, rows top to bottom. NULL until a ROM is loaded. Valid until the
+ * next pico_load_rom or pico_free.
+ */
+const uint8_t *pico_framebuffer(const Pico *pico);
+uint32_t pico_framebuffer_width(void);
+uint32_t pico_framebuffer_height(void);
+
+/* Buttons held on `port` (0 or 1) from now on, as PICO_BUTTON_* bits. */
+void pico_set_buttons(Pico *pico, uint32_t port, uint8_t buttons);
+
+/*
+ * Mono float samples produced by the last pico_run_frame, at
+ * pico_sample_rate(). Stores their number in `count` if it isn't NULL.
+ * Valid until the next pico_run_frame, pico_load_rom or pico_free.
+ */
+const float *pico_audio_samples(const Pico *pico, size_t *count);
+uint32_t pico_sample_rate(void);
+
+/*
+ * Frames per second of the loaded ROM's region (NTSC, PAL or Dendy), for
+ * pacing pico_run_frame calls. NTSC's until a ROM is loaded.
+ */
+double pico_frame_rate(const Pico *pico);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
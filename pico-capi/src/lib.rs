@@ -0,0 +1,249 @@
+// A C ABI over the core, for frontends written in C, Python (ctypes), Godot
+// and the like. The contract of every function, including which pointers
+// may be null and how long returned pointers stay valid, is in
+// include/pico.h, which is kept in step with this file by hand.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{CString, c_char, c_int};
+use std::ptr;
+
+use pico_core::cart::Cart;
+use pico_core::headless::{Headless, SAMPLE_RATE};
+use pico_core::joypad::JoypadButton;
+use pico_core::ppu::framebuffer::Framebuffer;
+use pico_core::region::Region;
+
+#[cfg(feature = "web")]
+mod web;
+
+// Bumped whenever a function's signature or meaning changes.
+pub const PICO_ABI_VERSION: u32 = 1;
+
+pub struct Pico {
+    console: Option<Headless>,
+    error: CString,
+}
+
+impl Pico {
+    fn fail(&mut self, message: String) -> c_int {
+        self.error = CString::new(message.replace('\0', " ")).unwrap_or_default();
+        -1
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pico_abi_version() -> u32 {
+    PICO_ABI_VERSION
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pico_new() -> *mut Pico {
+    Box::into_raw(Box::new(Pico {
+        console: None,
+        error: CString::default(),
+    }))
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_free(pico: *mut Pico) {
+    if !pico.is_null() {
+        drop(unsafe { Box::from_raw(pico) });
+    }
+}
+
+// The last error from `pico_load_rom` or `pico_run_frame`, or "" if none.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_last_error(pico: *const Pico) -> *const c_char {
+    match unsafe { pico.as_ref() } {
+        Some(pico) => pico.error.as_ptr(),
+        None => c"".as_ptr(),
+    }
+}
+
+// Copies an iNES or NES 2.0 image and powers the console on with it.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_load_rom(pico: *mut Pico, data: *const u8, len: usize) -> c_int {
+    let Some(pico) = (unsafe { pico.as_mut() }) else {
+        return -1;
+    };
+    if data.is_null() {
+        return pico.fail("No ROM data given".to_string());
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(data, len) };
+    match Cart::with_rom_db(bytes, pico_core::rom_db::RomDb::builtin()) {
+        Ok(cart) => {
+            pico.console = Some(Headless::new(cart));
+            pico.error = CString::default();
+            0
+        }
+        Err(e) => pico.fail(e),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_reset(pico: *mut Pico) {
+    if let Some(console) = unsafe { pico.as_mut() }.and_then(|pico| pico.console.as_mut()) {
+        console.nes.reset();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_run_frame(pico: *mut Pico) -> c_int {
+    let Some(pico) = (unsafe { pico.as_mut() }) else {
+        return -1;
+    };
+    match pico.console.as_mut() {
+        Some(console) => {
+            console.run_frame();
+            0
+        }
+        None => pico.fail("No ROM loaded".to_string()),
+    }
+}
+
+// 256x240 pixels of packed RGB24, rows top to bottom.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_framebuffer(pico: *const Pico) -> *const u8 {
+    match unsafe { pico.as_ref() }.and_then(|pico| pico.console.as_ref()) {
+        Some(console) => console.framebuffer().data.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pico_framebuffer_width() -> u32 {
+    Framebuffer::WIDTH as u32
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pico_framebuffer_height() -> u32 {
+    Framebuffer::HEIGHT as u32
+}
+
+// `buttons` uses the bit order the console reads them in: A, B, Select,
+// Start, Up, Down, Left, Right from bit 0. Held until changed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_set_buttons(pico: *mut Pico, port: u32, buttons: u8) {
+    let Some(console) = unsafe { pico.as_mut() }.and_then(|pico| pico.console.as_mut()) else {
+        return;
+    };
+    if let Some(joypad) = console.nes.joypad_mut(port as usize) {
+        joypad.button_status = JoypadButton::from_bits_truncate(buttons);
+    }
+}
+
+// Mono samples in -1.0..=1.0 produced by the last frame, at
+// `pico_sample_rate()`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_audio_samples(pico: *const Pico, count: *mut usize) -> *const f32 {
+    let samples = match unsafe { pico.as_ref() }.and_then(|pico| pico.console.as_ref()) {
+        Some(console) => console.audio(),
+        None => &[],
+    };
+    if let Some(count) = unsafe { count.as_mut() } {
+        *count = samples.len();
+    }
+    if samples.is_empty() {
+        ptr::null()
+    } else {
+        samples.as_ptr()
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pico_sample_rate() -> u32 {
+    SAMPLE_RATE
+}
+
+// Frames per second of the loaded ROM's region, or NTSC's if none is loaded.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pico_frame_rate(pico: *const Pico) -> f64 {
+    match unsafe { pico.as_ref() }.and_then(|pico| pico.console.as_ref()) {
+        Some(console) => console.nes.region().frame_rate(),
+        None => Region::Ntsc.frame_rate(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    // NROM-128 spinning on a JMP.
+    pub(crate) fn test_rom() -> Vec<u8> {
+        let mut rom = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let mut prg = vec![0; 0x4000];
+        prg[..3].copy_from_slice(&[0x4C, 0x00, 0x80]);
+        prg[0x3FFC] = 0x00;
+        prg[0x3FFD] = 0x80;
+        rom.extend_from_slice(&prg);
+        rom.extend_from_slice(&[0; 0x2000]);
+        rom
+    }
+
+    #[test]
+    fn test_runs_a_rom_through_the_c_abi() {
+        let rom = test_rom();
+        unsafe {
+            let pico = pico_new();
+            assert_eq!(pico_run_frame(pico), -1);
+            assert!(pico_framebuffer(pico).is_null());
+
+            assert_eq!(pico_load_rom(pico, rom.as_ptr(), rom.len()), 0);
+            for _ in 0..3 {
+                assert_eq!(pico_run_frame(pico), 0);
+            }
+            assert!(!pico_framebuffer(pico).is_null());
+
+            let mut count = 0;
+            assert!(!pico_audio_samples(pico, &mut count).is_null());
+            let per_frame = pico_sample_rate() as usize / 60;
+            assert!((per_frame - 20..per_frame + 20).contains(&count));
+
+            pico_set_buttons(pico, 0, 0b1000_0001);
+            let console = (*pico).console.as_mut().unwrap();
+            let joypad = console.nes.joypad_mut(0).unwrap();
+            assert_eq!(
+                joypad.button_status,
+                JoypadButton::RIGHT | JoypadButton::BUTTON_A
+            );
+
+            pico_free(pico);
+        }
+    }
+
+    #[test]
+    fn test_frame_rate_follows_the_region() {
+        let mut rom = test_rom();
+        unsafe {
+            let pico = pico_new();
+            assert_eq!(pico_frame_rate(pico), 60.0988);
+            // NES 2.0 header with PAL timing.
+            rom[7] = 0x08;
+            rom[12] = 1;
+            assert_eq!(pico_load_rom(pico, rom.as_ptr(), rom.len()), 0);
+            assert_eq!(pico_frame_rate(pico), 50.0070);
+            pico_free(pico);
+        }
+    }
+
+    #[test]
+    fn test_bad_roms_leave_an_error() {
+        let mut rom = test_rom();
+        rom.truncate(100);
+        unsafe {
+            let pico = pico_new();
+            assert_eq!(pico_load_rom(pico, rom.as_ptr(), rom.len()), -1);
+            let error = CStr::from_ptr(pico_last_error(pico));
+            assert!(error.to_str().unwrap().contains("truncated"));
+            assert_eq!(pico_load_rom(pico, ptr::null(), 0), -1);
+            pico_free(pico);
+
+            assert_eq!(pico_load_rom(ptr::null_mut(), rom.as_ptr(), rom.len()), -1);
+            assert_eq!(CStr::from_ptr(pico_last_error(ptr::null())), c"");
+        }
+    }
+}
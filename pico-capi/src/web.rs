@@ -0,0 +1,150 @@
+// Bindings for the browser frontend in web/, exported with wasm-bindgen so
+// JavaScript gets ordinary classes and typed arrays instead of raw pointers.
+use wasm_bindgen::prelude::*;
+
+use pico_core::cart::Cart;
+use pico_core::headless::{Headless, SAMPLE_RATE};
+use pico_core::joypad::JoypadButton;
+use pico_core::ppu::framebuffer::Framebuffer;
+use pico_core::region::Region;
+use pico_core::rom_db::RomDb;
+
+#[wasm_bindgen]
+pub struct WebNes {
+    console: Option<Headless>,
+}
+
+#[wasm_bindgen]
+impl WebNes {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WebNes {
+        WebNes { console: None }
+    }
+
+    // Powers on with an iNES or NES 2.0 image, replacing any ROM already
+    // loaded. Throws with the reason if the image can't be used.
+    #[wasm_bindgen(js_name = loadRom)]
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        let cart = Cart::with_rom_db(rom, RomDb::builtin()).map_err(|e| JsError::new(&e))?;
+        self.console = Some(Headless::new(cart));
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        if let Some(console) = self.console.as_mut() {
+            console.nes.reset();
+        }
+    }
+
+    // Does nothing until a ROM is loaded.
+    #[wasm_bindgen(js_name = runFrame)]
+    pub fn run_frame(&mut self) {
+        if let Some(console) = self.console.as_mut() {
+            console.run_frame();
+        }
+    }
+
+    // `width` x `height` pixels of packed RGB24, rows top to bottom; black
+    // until a ROM is loaded.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        match &self.console {
+            Some(console) => console.framebuffer().data.to_vec(),
+            None => vec![0; Framebuffer::WIDTH * Framebuffer::HEIGHT * 3],
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        Framebuffer::WIDTH as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        Framebuffer::HEIGHT as u32
+    }
+
+    // Mono samples in -1.0..=1.0 produced by the last frame, at
+    // `sampleRate`.
+    pub fn audio(&self) -> Vec<f32> {
+        self.console
+            .as_ref()
+            .map_or_else(Vec::new, |console| console.audio().to_vec())
+    }
+
+    #[wasm_bindgen(getter, js_name = sampleRate)]
+    pub fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    // Frames per second of the loaded ROM's region, or NTSC's if none is
+    // loaded.
+    #[wasm_bindgen(getter, js_name = frameRate)]
+    pub fn frame_rate(&self) -> f64 {
+        match &self.console {
+            Some(console) => console.nes.region().frame_rate(),
+            None => Region::Ntsc.frame_rate(),
+        }
+    }
+
+    // Buttons held on `port` from now on, in the same bit order as
+    // pico_set_buttons.
+    #[wasm_bindgen(js_name = setButtons)]
+    pub fn set_buttons(&mut self, port: u32, buttons: u8) {
+        let Some(console) = self.console.as_mut() else {
+            return;
+        };
+        if let Some(joypad) = console.nes.joypad_mut(port as usize) {
+            joypad.button_status = JoypadButton::from_bits_truncate(buttons);
+        }
+    }
+}
+
+impl Default for WebNes {
+    fn default() -> Self {
+        WebNes::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::test::test_rom;
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_runs_a_rom_through_the_bindings() {
+        let mut nes = WebNes::new();
+        nes.run_frame();
+        assert!(nes.audio().is_empty());
+        assert_eq!(
+            nes.framebuffer().len(),
+            (nes.width() * nes.height() * 3) as usize
+        );
+
+        nes.load_rom(&test_rom()).unwrap();
+        for _ in 0..3 {
+            nes.run_frame();
+        }
+        let per_frame = (nes.sample_rate() as f64 / nes.frame_rate()) as usize;
+        assert!((per_frame - 20..per_frame + 20).contains(&nes.audio().len()));
+
+        nes.set_buttons(0, 0b1000_0001);
+        let joypad = nes.console.as_mut().unwrap().nes.joypad_mut(0).unwrap();
+        assert_eq!(
+            joypad.button_status,
+            JoypadButton::RIGHT | JoypadButton::BUTTON_A
+        );
+    }
+
+    // Only on wasm32, where JsError can be made.
+    #[wasm_bindgen_test]
+    fn test_bad_roms_throw() {
+        let mut rom = test_rom();
+        rom.truncate(100);
+        let mut nes = WebNes::new();
+        assert!(nes.load_rom(&rom).is_err());
+        nes.run_frame();
+        assert!(nes.audio().is_empty());
+    }
+}
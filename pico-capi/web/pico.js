@@ -0,0 +1,129 @@
+// Runs the core in the browser through the wasm-bindgen exports of the
+// `web` feature (src/web.rs):
+//
+//     cargo build -p pico-capi --release --target wasm32-unknown-unknown --features web
+//     wasm-bindgen --target web --out-dir pico-capi/web/pkg \
+//         target/wasm32-unknown-unknown/release/pico_capi.wasm
+//
+// then serve pico-capi/web over HTTP and open index.html.
+import init, { WebNes } from "./pkg/pico_capi.js";
+
+// How far ahead of the audio clock sound is scheduled, in seconds.
+const AUDIO_LATENCY = 0.05;
+
+// PICO_BUTTON_* bits.
+const KEYS = {
+  KeyX: 0x01,
+  KeyZ: 0x02,
+  Space: 0x04,
+  Enter: 0x08,
+  ArrowUp: 0x10,
+  ArrowDown: 0x20,
+  ArrowLeft: 0x40,
+  ArrowRight: 0x80,
+};
+
+const screen = document.getElementById("screen");
+const context = screen.getContext("2d");
+const status = document.getElementById("status");
+const romInput = document.getElementById("rom");
+
+let nes = null;
+let audio = null;
+let audioTime = 0;
+let buttons = 0;
+let running = false;
+
+function drawFrame(image) {
+  const rgb = nes.framebuffer();
+  const rgba = image.data;
+  for (let i = 0, j = 0; i < rgb.length; i += 3, j += 4) {
+    rgba[j] = rgb[i];
+    rgba[j + 1] = rgb[i + 1];
+    rgba[j + 2] = rgb[i + 2];
+    rgba[j + 3] = 255;
+  }
+  context.putImageData(image, 0, 0);
+}
+
+// Each frame's samples are queued back to back after the ones before,
+// starting over a little ahead of the clock whenever the queue runs dry.
+function queueAudio() {
+  const samples = nes.audio();
+  if (samples.length === 0) {
+    return;
+  }
+  const buffer = audio.createBuffer(1, samples.length, nes.sampleRate);
+  buffer.copyToChannel(samples, 0);
+  const source = audio.createBufferSource();
+  source.buffer = buffer;
+  source.connect(audio.destination);
+  audioTime = Math.max(audioTime, audio.currentTime + AUDIO_LATENCY);
+  source.start(audioTime);
+  audioTime += buffer.duration;
+}
+
+function run() {
+  const image = context.createImageData(screen.width, screen.height);
+  let due = performance.now();
+  const frame = (now) => {
+    // Read each time, since a newly loaded ROM can be for another region.
+    const period = 1000 / nes.frameRate;
+    // Catch up at most a couple of frames after the tab was in the background.
+    due = Math.max(due, now - 2 * period);
+    while (due <= now) {
+      nes.setButtons(0, buttons);
+      nes.runFrame();
+      queueAudio();
+      due += period;
+    }
+    drawFrame(image);
+    requestAnimationFrame(frame);
+  };
+  requestAnimationFrame(frame);
+}
+
+document.addEventListener("keydown", (event) => {
+  if (event.code in KEYS) {
+    buttons |= KEYS[event.code];
+    event.preventDefault();
+  }
+});
+document.addEventListener("keyup", (event) => {
+  if (event.code in KEYS) {
+    buttons &= ~KEYS[event.code];
+    event.preventDefault();
+  }
+});
+
+romInput.addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (!file) {
+    return;
+  }
+  try {
+    nes.loadRom(new Uint8Array(await file.arrayBuffer()));
+  } catch (e) {
+    status.textContent = `Failed to load ${file.name}: ${e.message}`;
+    return;
+  }
+  status.textContent = file.name;
+  // Browsers only start sound after the page is interacted with.
+  audio ??= new AudioContext({ sampleRate: nes.sampleRate });
+  await audio.resume();
+  if (!running) {
+    running = true;
+    run();
+  }
+});
+
+// The ROM picker stays disabled until the module is ready to take a ROM.
+init().then(
+  () => {
+    nes = new WebNes();
+    romInput.disabled = false;
+  },
+  (e) => {
+    status.textContent = `Failed to load pico_capi.wasm: ${e.message}`;
+  },
+);
@@ -0,0 +1,74 @@
+// Frames per second of the core on a small NROM program that keeps the CPU
+// busy with rendering on, with tracing off and with a hook that only counts
+// instructions. The two should be close: capturing a trace record formats
+// nothing. A third run decodes code ahead with the fast CPU.
+//
+//     cargo bench --bench frame
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use pico_core::apu::APU;
+use pico_core::cart::Cart;
+use pico_core::nes::Nes;
+
+const FRAMES: u32 = 600;
+
+// LDA #$1E; STA $2001; loop: INX; STX $10; INC $0200; JMP loop
+const PROGRAM: [u8; 14] = [
+    0xA9, 0x1E, 0x8D, 0x01, 0x20, 0xE8, 0x86, 0x10, 0xEE, 0x00, 0x02, 0x4C, 0x05, 0x80,
+];
+
+fn new_nes() -> Nes {
+    let mut rom = vec![
+        0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = PROGRAM.to_vec();
+    prg.resize(0x8000, 0);
+    prg[0x7FFC] = 0x00;
+    prg[0x7FFD] = 0x80;
+    rom.extend_from_slice(&prg);
+    rom.extend(std::iter::repeat_n(0x55, 0x2000));
+
+    let cart = Cart::new(&rom).expect("bench ROM is valid");
+    let apu = APU::new(48_000);
+    let mut nes = Nes::new(cart, apu);
+    nes.reset();
+    nes
+}
+
+fn run(nes: &mut Nes) -> Duration {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        nes.step_frame();
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<18}{:.0} frames/s ({:.1} us/frame)",
+        name,
+        FRAMES as f64 / elapsed.as_secs_f64(),
+        elapsed.as_secs_f64() * 1e6 / FRAMES as f64
+    );
+}
+
+fn main() {
+    let mut nes = new_nes();
+    report("no trace hook", run(&mut nes));
+
+    let mut nes = new_nes();
+    nes.set_fast_cpu(true);
+    report("fast cpu", run(&mut nes));
+
+    let instructions = Arc::new(AtomicU64::new(0));
+    let counter = instructions.clone();
+    let mut nes = new_nes();
+    nes.set_trace_hook(Some(Box::new(move |_| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    })));
+    report("counting hook", run(&mut nes));
+    println!("instructions      {}", instructions.load(Ordering::Relaxed));
+}
@@ -0,0 +1,98 @@
+// Cost of finding each instruction's opcode, on the opcodes a few seconds of
+// frame stepping run, with the table `find_by_code` uses against a search of
+// the opcode list the way it used to look them up.
+//
+//     cargo bench --bench opcode_lookup
+
+use std::hint::black_box;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use pico_core::apu::APU;
+use pico_core::cart::Cart;
+use pico_core::nes::Nes;
+use pico_core::opcodes::CPU_OPCODES;
+
+const FRAMES: u32 = 300;
+
+// LDA #$1E; STA $2001; loop: LDY #$08; inner: LDA $10,X; STA $0200,Y; DEY;
+// BNE inner; INX; CPX #$40; BNE loop; JMP loop
+const PROGRAM: [u8; 23] = [
+    0xA9, 0x1E, 0x8D, 0x01, 0x20, 0xA0, 0x08, 0xB5, 0x10, 0x99, 0x00, 0x02, 0x88, 0xD0, 0xF8, 0xE8,
+    0xE0, 0x40, 0xD0, 0xF1, 0x4C, 0x05, 0x80,
+];
+
+fn new_nes() -> Nes {
+    let mut rom = vec![
+        0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = PROGRAM.to_vec();
+    prg.resize(0x8000, 0);
+    prg[0x7FFC] = 0x00;
+    prg[0x7FFD] = 0x80;
+    rom.extend_from_slice(&prg);
+    rom.extend(std::iter::repeat_n(0x55, 0x2000));
+
+    let cart = Cart::new(&rom).expect("bench ROM is valid");
+    let apu = APU::new(48_000);
+    let mut nes = Nes::new(cart, apu);
+    nes.reset();
+    nes
+}
+
+// The opcode byte of every instruction run over FRAMES frames.
+fn executed_opcodes() -> Vec<u8> {
+    let codes = Arc::new(Mutex::new(Vec::new()));
+    let recorder = codes.clone();
+    let mut nes = new_nes();
+    nes.set_trace_hook(Some(Box::new(move |record| {
+        recorder.lock().unwrap().push(record.opcode.code);
+    })));
+    for _ in 0..FRAMES {
+        nes.step_frame();
+    }
+    drop(nes);
+    Arc::try_unwrap(codes).unwrap().into_inner().unwrap()
+}
+
+fn time(codes: &[u8], find: impl Fn(u8) -> u8) -> Duration {
+    let start = Instant::now();
+    for &code in codes {
+        black_box(find(black_box(code)));
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, codes: &[u8], elapsed: Duration) {
+    println!(
+        "{:<18}{:.2} ns/lookup ({:.1} us/frame)",
+        name,
+        elapsed.as_secs_f64() * 1e9 / codes.len() as f64,
+        elapsed.as_secs_f64() * 1e6 / FRAMES as f64
+    );
+}
+
+fn main() {
+    let codes = executed_opcodes();
+    println!("instructions      {}", codes.len());
+
+    let linear = time(&codes, |code| {
+        let opcodes = CPU_OPCODES.get_opcodes();
+        opcodes
+            .iter()
+            .find(|opcode| opcode.code == code)
+            .unwrap()
+            .cycles
+    });
+    report("linear search", &codes, linear);
+
+    let table = time(&codes, |code| {
+        CPU_OPCODES.find_by_code(code).unwrap().cycles
+    });
+    report("table", &codes, table);
+
+    println!(
+        "speedup           {:.1}x",
+        linear.as_secs_f64() / table.as_secs_f64()
+    );
+}
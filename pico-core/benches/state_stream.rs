@@ -0,0 +1,58 @@
+// Size and speed of the rewind stream on synthetic savestates shaped like the
+// real thing: a mostly static blob with a few hundred bytes touched per frame.
+//
+//     cargo bench --bench state_stream
+
+use std::time::Instant;
+
+use pico_core::state_stream::{DEFAULT_KEYFRAME_INTERVAL, DEFAULT_MEMORY_BUDGET, StateStream};
+
+const STATE_SIZE: usize = 16 * 1024;
+const CHANGED_BYTES: usize = 300;
+const FRAMES: usize = 60 * 60;
+
+fn main() {
+    let mut state = vec![0u8; STATE_SIZE];
+    let mut seed = 0x1234_5678u32;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as usize
+    };
+    for byte in state.iter_mut().step_by(7) {
+        *byte = next() as u8;
+    }
+
+    let mut stream = StateStream::new(DEFAULT_MEMORY_BUDGET, DEFAULT_KEYFRAME_INTERVAL);
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        for _ in 0..CHANGED_BYTES {
+            let index = next() % 2048;
+            state[index] = next() as u8;
+        }
+        stream.push(&state);
+    }
+    let encode = start.elapsed();
+    let kept = stream.len();
+    let memory = stream.memory_usage();
+
+    let start = Instant::now();
+    while stream.pop().is_some() {}
+    let decode = start.elapsed();
+
+    println!("state size        {} bytes", STATE_SIZE);
+    println!(
+        "kept              {} of {} snapshots in {} bytes (budget {})",
+        kept, FRAMES, memory, DEFAULT_MEMORY_BUDGET
+    );
+    println!("average snapshot  {} bytes", memory / kept.max(1));
+    println!(
+        "encode            {:.1} us/snapshot",
+        encode.as_secs_f64() * 1e6 / FRAMES as f64
+    );
+    println!(
+        "decode            {:.1} us/snapshot",
+        decode.as_secs_f64() * 1e6 / kept.max(1) as f64
+    );
+}
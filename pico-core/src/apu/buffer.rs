@@ -23,4 +23,18 @@ impl RingBuffer {
             self.filled = true;
         }
     }
+
+    // Up to `count` of the most recent samples, oldest first.
+    pub fn latest(&self, count: usize) -> Vec<i16> {
+        let available = if self.filled {
+            self.data.len()
+        } else {
+            self.index
+        };
+        let count = count.min(available);
+        let len = self.data.len();
+        (0..count)
+            .map(|i| self.data[(self.index + len - count + i) % len])
+            .collect()
+    }
 }
@@ -1,10 +1,15 @@
 use crate::apu::buffer::RingBuffer;
 use crate::apu::channel::{Channel, PlaybackRate, Timbre, Volume};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 pub const DMC_RATE_TABLE: [u16; 16] = [
     428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
 ];
 
+pub const DMC_RATE_TABLE_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
 // TODO: This thing sounds kinda off compared to real hardware, needs more investigation.
 pub struct DmcChannel {
     pub debug_disable: bool,
@@ -186,3 +191,47 @@ impl Channel for DmcChannel {
         None
     }
 }
+
+impl Savestate for DmcChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.last_edge);
+        state.write_bool(self.looping);
+        state.write_u16(self.period_initial);
+        state.write_u16(self.period_current);
+        state.write_u8(self.output_level);
+        state.write_u16(self.starting_address);
+        state.write_u16(self.sample_length);
+        state.write_u16(self.current_address);
+        state.write_bool(self.sample_buffer.is_some());
+        state.write_u8(self.sample_buffer.unwrap_or(0));
+        state.write_u8(self.shift_register);
+        state.write_u8(self.bits_remaining);
+        state.write_u16(self.bytes_remaining);
+        state.write_bool(self.silence_flag);
+        state.write_bool(self.interrupt_enabled);
+        state.write_bool(self.interrupt_flag);
+        state.write_bool(self.sample_fetch_pending);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.last_edge = state.read_bool()?;
+        self.looping = state.read_bool()?;
+        self.period_initial = state.read_u16()?;
+        self.period_current = state.read_u16()?;
+        self.output_level = state.read_u8()?;
+        self.starting_address = state.read_u16()?;
+        self.sample_length = state.read_u16()?;
+        self.current_address = state.read_u16()?;
+        let buffered = state.read_bool()?;
+        let sample = state.read_u8()?;
+        self.sample_buffer = buffered.then_some(sample);
+        self.shift_register = state.read_u8()?;
+        self.bits_remaining = state.read_u8()?;
+        self.bytes_remaining = state.read_u16()?;
+        self.silence_flag = state.read_bool()?;
+        self.interrupt_enabled = state.read_bool()?;
+        self.interrupt_flag = state.read_bool()?;
+        self.sample_fetch_pending = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
 #[derive(Clone, Copy)]
 pub struct Envelope {
     pub looping: bool,
@@ -54,3 +56,24 @@ impl Envelope {
         self.volume_register.saturating_add(1)
     }
 }
+
+impl Savestate for Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.looping);
+        state.write_bool(self.enabled);
+        state.write_bool(self.start_flag);
+        state.write_u8(self.divider);
+        state.write_u8(self.decay_level_counter);
+        state.write_u8(self.volume_register);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.looping = state.read_bool()?;
+        self.enabled = state.read_bool()?;
+        self.start_flag = state.read_bool()?;
+        self.divider = state.read_u8()?;
+        self.decay_level_counter = state.read_u8()?;
+        self.volume_register = state.read_u8()?;
+        Ok(())
+    }
+}
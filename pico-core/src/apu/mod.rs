@@ -1,27 +1,35 @@
 // thanks zeta for original APU implementation
 
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
-
 mod buffer;
 mod channel;
 mod dmc;
 mod envelope;
 mod noise;
 mod pulse;
+pub mod register_log;
+pub mod sink;
 mod triangle;
 
-use channel::Channel;
+use channel::{Channel, PlaybackRate};
 use dmc::DmcChannel;
 use noise::NoiseChannel;
 use pulse::PulseChannel;
+use sink::AudioSink;
 use triangle::TriangleChannel;
 
-use crate::apu::dmc::DMC_RATE_TABLE;
-use crate::apu::noise::NOISE_PERIOD_TABLE;
+use crate::apu::dmc::{DMC_RATE_TABLE, DMC_RATE_TABLE_PAL};
+use crate::apu::noise::{NOISE_PERIOD_TABLE, NOISE_PERIOD_TABLE_PAL};
+use crate::region::Region;
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 const CPU_CLOCK_NTSC: u64 = 1_789_773;
 
+// CPU cycles at which the frame sequencer acts: the three quarter-frame
+// steps shared by both modes, then the last step of the 4-step and 5-step
+// sequences. https://www.nesdev.org/wiki/APU_Frame_Counter
+const FRAME_STEPS_NTSC: [u16; 5] = [7457, 14913, 22371, 29829, 37281];
+const FRAME_STEPS_PAL: [u16; 5] = [8313, 16627, 24939, 33253, 41565];
+
 pub const LENGTH_TABLE: [u8; 32] = [
     10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
     192, 24, 72, 26, 16, 28, 32, 30,
@@ -57,6 +65,50 @@ impl LengthCounter {
     }
 }
 
+impl Savestate for LengthCounter {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.length);
+        state.write_bool(self.halt_flag);
+        state.write_bool(self.channel_enabled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.length = state.read_u8()?;
+        self.halt_flag = state.read_bool()?;
+        self.channel_enabled = state.read_bool()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelId {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl ChannelId {
+    pub const ALL: [ChannelId; 5] = [
+        ChannelId::Pulse1,
+        ChannelId::Pulse2,
+        ChannelId::Triangle,
+        ChannelId::Noise,
+        ChannelId::Dmc,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelId::Pulse1 => "pulse1",
+            ChannelId::Pulse2 => "pulse2",
+            ChannelId::Triangle => "triangle",
+            ChannelId::Noise => "noise",
+            ChannelId::Dmc => "dmc",
+        }
+    }
+}
+
 pub struct APU {
     current_cycle: u64,
 
@@ -69,21 +121,24 @@ pub struct APU {
     frame_interrupt: bool,
     disable_interrupt: bool,
 
+    frame_steps: [u16; 5],
+    noise_periods: &'static [u16; 16],
+    dmc_rates: &'static [u16; 16],
+
     pulse1: PulseChannel,
     pulse2: PulseChannel,
     triangle: TriangleChannel,
     noise: NoiseChannel,
     dmc: DmcChannel,
+    // The cartridge's own sound this cycle, for boards with expansion audio.
+    expansion_audio: f32,
 
     sample_rate: u64,
     cpu_clock_rate: u64,
     generated_samples: u64,
     next_sample_at: u64,
 
-    pulse_table: Vec<f32>,
-    tnd_table: Vec<f32>,
-
-    audio_buffer: Arc<Mutex<VecDeque<f32>>>,
+    audio_sink: AudioSink,
     max_buffer_samples: usize,
 
     // DC offset removal filter for click/pop prevention
@@ -92,7 +147,13 @@ pub struct APU {
 }
 
 impl APU {
-    pub fn new(sample_rate: u32, audio_buffer: Arc<Mutex<VecDeque<f32>>>) -> Self {
+    // Keeps the samples until `take_samples`, for running the console
+    // without an audio device.
+    pub fn new(sample_rate: u32) -> Self {
+        APU::with_sink(sample_rate, AudioSink::Collect(Vec::new()))
+    }
+
+    pub fn with_sink(sample_rate: u32, audio_sink: AudioSink) -> Self {
         let sample_rate = sample_rate.max(1) as u64;
         let max_samples = sample_rate as usize * 4;
 
@@ -105,24 +166,126 @@ impl APU {
             half_frame_counter: 0,
             frame_interrupt: false,
             disable_interrupt: false,
+            frame_steps: FRAME_STEPS_NTSC,
+            noise_periods: &NOISE_PERIOD_TABLE,
+            dmc_rates: &DMC_RATE_TABLE,
             pulse1: PulseChannel::new(true),
             pulse2: PulseChannel::new(false),
             triangle: TriangleChannel::new(),
             noise: NoiseChannel::new(),
             dmc: DmcChannel::new(),
+            expansion_audio: 0.0,
             sample_rate,
             cpu_clock_rate: CPU_CLOCK_NTSC,
             generated_samples: 0,
             next_sample_at: 0,
-            pulse_table: generate_pulse_table(),
-            tnd_table: generate_tnd_table(),
-            audio_buffer,
+            audio_sink,
             max_buffer_samples: max_samples,
             dc_filter_x1: 0.0,
             dc_filter_y1: 0.0,
         }
     }
 
+    pub fn cycle(&self) -> u64 {
+        self.current_cycle
+    }
+
+    pub fn cpu_clock_rate(&self) -> u64 {
+        self.cpu_clock_rate
+    }
+
+    pub fn samples_generated(&self) -> u64 {
+        self.generated_samples
+    }
+
+    // Sound from the cartridge, on the scale of the mixed APU output, to add in
+    // from this cycle on.
+    pub fn set_expansion_audio(&mut self, level: f32) {
+        self.expansion_audio = level;
+    }
+
+    // CPU cycle on which the sample with the given index was produced.
+    pub fn sample_cycle(&self, index: u64) -> u64 {
+        index * self.cpu_clock_rate / self.sample_rate
+    }
+
+    fn channel(&self, id: ChannelId) -> &dyn Channel {
+        match id {
+            ChannelId::Pulse1 => &self.pulse1,
+            ChannelId::Pulse2 => &self.pulse2,
+            ChannelId::Triangle => &self.triangle,
+            ChannelId::Noise => &self.noise,
+            ChannelId::Dmc => &self.dmc,
+        }
+    }
+
+    fn channel_mut(&mut self, id: ChannelId) -> &mut dyn Channel {
+        match id {
+            ChannelId::Pulse1 => &mut self.pulse1,
+            ChannelId::Pulse2 => &mut self.pulse2,
+            ChannelId::Triangle => &mut self.triangle,
+            ChannelId::Noise => &mut self.noise,
+            ChannelId::Dmc => &mut self.dmc,
+        }
+    }
+
+    pub fn channel_muted(&self, id: ChannelId) -> bool {
+        self.channel(id).muted()
+    }
+
+    // The channel's recent output, one value per audio sample, for scopes.
+    pub fn channel_samples(&self, id: ChannelId, count: usize) -> Vec<i16> {
+        self.channel(id).sample_buffer().latest(count)
+    }
+
+    pub fn channel_range(&self, id: ChannelId) -> (i16, i16) {
+        let channel = self.channel(id);
+        (channel.min_sample(), channel.max_sample())
+    }
+
+    // How loud the channel is playing, from 0 to 1.
+    pub fn channel_volume(&self, id: ChannelId) -> f32 {
+        self.channel(id).amplitude()
+    }
+
+    // The pitch the channel is playing in Hz. Noise and DMC have none, and a
+    // silent channel plays nothing.
+    pub fn channel_frequency(&self, id: ChannelId) -> Option<f32> {
+        let channel = self.channel(id);
+        match channel.rate() {
+            // Channels work their rate out from the NTSC clock.
+            PlaybackRate::SampleRate { frequency } if channel.playing() => {
+                Some(frequency * self.cpu_clock_rate as f32 / CPU_CLOCK_NTSC as f32)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn set_channel_muted(&mut self, id: ChannelId, muted: bool) {
+        let channel = self.channel_mut(id);
+        if muted {
+            channel.mute();
+        } else {
+            channel.unmute();
+        }
+    }
+
+    // Dendy famiclones clock their APU like an NTSC console.
+    pub fn set_region(&mut self, region: Region) {
+        self.cpu_clock_rate = region.cpu_clock_rate();
+        if region == Region::Pal {
+            self.frame_steps = FRAME_STEPS_PAL;
+            self.noise_periods = &NOISE_PERIOD_TABLE_PAL;
+            self.dmc_rates = &DMC_RATE_TABLE_PAL;
+        } else {
+            self.frame_steps = FRAME_STEPS_NTSC;
+            self.noise_periods = &NOISE_PERIOD_TABLE;
+            self.dmc_rates = &DMC_RATE_TABLE;
+        }
+        self.next_sample_at =
+            ((self.generated_samples + 1) * self.cpu_clock_rate) / self.sample_rate;
+    }
+
     pub fn set_sample_rate(&mut self, sample_rate: u32) {
         self.sample_rate = sample_rate.max(1) as u64;
         self.max_buffer_samples = (self.sample_rate as usize).saturating_mul(4);
@@ -229,7 +392,7 @@ impl APU {
             0x400E => {
                 let period_index = value & 0b0000_1111;
                 self.noise.mode = (value & 0b1000_0000) >> 7;
-                self.noise.period_initial = NOISE_PERIOD_TABLE[period_index as usize];
+                self.noise.period_initial = self.noise_periods[period_index as usize];
                 self.noise.period_current = self.noise.period_initial;
             }
             0x400F => {
@@ -244,7 +407,7 @@ impl APU {
                     self.dmc.interrupt_flag = false;
                 }
                 let period_index = value & 0b0000_1111;
-                self.dmc.period_initial = DMC_RATE_TABLE[period_index as usize];
+                self.dmc.period_initial = self.dmc_rates[period_index as usize];
                 self.dmc.period_current = self.dmc.period_initial;
             }
             0x4011 => {
@@ -292,6 +455,14 @@ impl APU {
     }
 
     pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_interrupt = false;
+        self.dmc.interrupt_flag = false;
+        status
+    }
+
+    // $4015 as a read would see it, leaving the interrupt flags set.
+    pub fn peek_status(&self) -> u8 {
         let mut status = 0u8;
         if self.pulse1.length_counter.length > 0 {
             status |= 0x01;
@@ -314,8 +485,6 @@ impl APU {
         if self.dmc.interrupt_flag {
             status |= 0x80;
         }
-        self.frame_interrupt = false;
-        self.dmc.interrupt_flag = false;
         status
     }
 
@@ -336,6 +505,14 @@ impl APU {
         self.dmc.provide_sample(value);
     }
 
+    pub fn frame_irq(&self) -> bool {
+        self.frame_interrupt
+    }
+
+    pub fn dmc_irq(&self) -> bool {
+        self.dmc.interrupt_flag
+    }
+
     pub fn poll_irq(&mut self) -> Option<u8> {
         if self.frame_interrupt || self.dmc.interrupt_flag {
             Some(0)
@@ -379,12 +556,30 @@ impl APU {
         dma_request
     }
 
+    // Returns the previous sink, e.g. to finish a WAV file.
+    pub fn set_audio_sink(&mut self, audio_sink: AudioSink) -> AudioSink {
+        std::mem::replace(&mut self.audio_sink, audio_sink)
+    }
+
+    // Replaces `out` with the samples an `AudioSink::Collect` sink has
+    // gathered since the last call, reusing both allocations.
+    pub fn take_samples(&mut self, out: &mut Vec<f32>) {
+        out.clear();
+        if let AudioSink::Collect(samples) = &mut self.audio_sink {
+            std::mem::swap(samples, out);
+        }
+    }
+
     fn push_sample(&mut self, sample: f32) {
-        if let Ok(mut buffer) = self.audio_buffer.lock() {
-            if buffer.len() >= self.max_buffer_samples {
-                let _ = buffer.pop_front();
+        match &mut self.audio_sink {
+            AudioSink::Collect(samples) => {
+                if samples.len() >= self.max_buffer_samples {
+                    samples.drain(..samples.len() / 2);
+                }
+                samples.push(sample);
             }
-            buffer.push_back(sample);
+            AudioSink::Wav(wav) => wav.push(sample),
+            AudioSink::Null => {}
         }
     }
 
@@ -398,7 +593,7 @@ impl APU {
             combined_pulse += self.pulse2.output();
         }
 
-        let pulse_output = self.pulse_table[combined_pulse.min(30) as usize];
+        let pulse_output = PULSE_TABLE[combined_pulse.min(30) as usize];
 
         let triangle_output = if self.triangle.debug_disable {
             0
@@ -420,9 +615,9 @@ impl APU {
             + (noise_output as usize).min(15) * 2
             + (dmc_output as usize).min(127);
 
-        let tnd_output = self.tnd_table[tnd_index];
+        let tnd_output = TND_TABLE[tnd_index];
 
-        let mixed = (pulse_output - 0.5) + (tnd_output - 0.5);
+        let mixed = (pulse_output - 0.5) + (tnd_output - 0.5) + self.expansion_audio;
 
         // Apply DC offset removal filter to eliminate pops and clicks
         // High-pass filter: y = 0.9999 * (y + x - x_prev)
@@ -446,51 +641,35 @@ impl APU {
             }
         }
 
-        if self.frame_sequencer_mode == 0 {
-            match self.frame_sequencer {
-                7457 => self.clock_quarter_frame(),
-                14913 => {
-                    self.clock_quarter_frame();
-                    self.clock_half_frame();
-                }
-                22371 => self.clock_quarter_frame(),
-                29828 => {
-                    if !self.disable_interrupt {
-                        self.frame_interrupt = true;
-                    }
-                }
-                29829 => {
-                    if !self.disable_interrupt {
-                        self.frame_interrupt = true;
-                    }
-                    self.clock_quarter_frame();
-                    self.clock_half_frame();
-                }
-                29830 => {
-                    if !self.disable_interrupt {
-                        self.frame_interrupt = true;
-                    }
-                    self.frame_sequencer = 0;
+        let [quarter, half, three_quarters, four_step_end, five_step_end] = self.frame_steps;
+        let step = self.frame_sequencer;
+        if step == quarter || step == three_quarters {
+            self.clock_quarter_frame();
+        } else if step == half {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        } else if self.frame_sequencer_mode == 0 {
+            if step + 1 == four_step_end {
+                if !self.disable_interrupt {
+                    self.frame_interrupt = true;
                 }
-                _ => {}
-            }
-        } else {
-            match self.frame_sequencer {
-                7457 => self.clock_quarter_frame(),
-                14913 => {
-                    self.clock_quarter_frame();
-                    self.clock_half_frame();
-                }
-                22371 => self.clock_quarter_frame(),
-                37281 => {
-                    self.clock_quarter_frame();
-                    self.clock_half_frame();
+            } else if step == four_step_end {
+                if !self.disable_interrupt {
+                    self.frame_interrupt = true;
                 }
-                37282 => {
-                    self.frame_sequencer = 0;
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            } else if step == four_step_end + 1 {
+                if !self.disable_interrupt {
+                    self.frame_interrupt = true;
                 }
-                _ => {}
+                self.frame_sequencer = 0;
             }
+        } else if step == five_step_end {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        } else if step == five_step_end + 1 {
+            self.frame_sequencer = 0;
         }
 
         self.frame_sequencer += 1;
@@ -516,18 +695,150 @@ impl APU {
     }
 }
 
-fn generate_pulse_table() -> Vec<f32> {
-    let mut pulse_table = vec![0f32; 31];
-    for n in 1..31 {
+impl Savestate for APU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u64(self.current_cycle);
+        state.write_u8(self.frame_sequencer_mode);
+        state.write_u16(self.frame_sequencer);
+        state.write_u8(self.frame_reset_delay);
+        state.write_u32(self.quarter_frame_counter);
+        state.write_u32(self.half_frame_counter);
+        state.write_bool(self.frame_interrupt);
+        state.write_bool(self.disable_interrupt);
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        self.triangle.save_state(state);
+        self.noise.save_state(state);
+        self.dmc.save_state(state);
+        state.write_u64(self.generated_samples);
+        state.write_f32(self.dc_filter_x1);
+        state.write_f32(self.dc_filter_y1);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.current_cycle = state.read_u64()?;
+        self.frame_sequencer_mode = state.read_u8()?;
+        self.frame_sequencer = state.read_u16()?;
+        self.frame_reset_delay = state.read_u8()?;
+        self.quarter_frame_counter = state.read_u32()?;
+        self.half_frame_counter = state.read_u32()?;
+        self.frame_interrupt = state.read_bool()?;
+        self.disable_interrupt = state.read_bool()?;
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.triangle.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.dmc.load_state(state)?;
+        self.generated_samples = state.read_u64()?;
+        // The sample rate belongs to the host, not the state, so the next
+        // sample is scheduled against the one in use now.
+        self.next_sample_at =
+            ((self.generated_samples + 1) * self.cpu_clock_rate) / self.sample_rate;
+        self.dc_filter_x1 = state.read_f32()?;
+        self.dc_filter_y1 = state.read_f32()?;
+        Ok(())
+    }
+}
+
+// Lookup-table approximations of the 2A03 mixer from the nesdev wiki. The
+// tnd table is indexed by 3 * triangle + 2 * noise + dmc.
+static PULSE_TABLE: [f32; 31] = generate_pulse_table();
+static TND_TABLE: [f32; 203] = generate_tnd_table();
+
+const fn generate_pulse_table() -> [f32; 31] {
+    let mut pulse_table = [0f32; 31];
+    let mut n = 1;
+    while n < 31 {
         pulse_table[n] = 95.52 / (8128.0 / (n as f32) + 100.0);
+        n += 1;
     }
     pulse_table
 }
 
-fn generate_tnd_table() -> Vec<f32> {
-    let mut tnd_table = vec![0f32; 203];
-    for n in 1..203 {
+const fn generate_tnd_table() -> [f32; 203] {
+    let mut tnd_table = [0f32; 203];
+    let mut n = 1;
+    while n < 203 {
         tnd_table[n] = 163.67 / (24329.0 / n as f32 + 100.0);
+        n += 1;
     }
     tnd_table
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn exact_pulse(pulse1: u8, pulse2: u8) -> f64 {
+        let sum = (pulse1 + pulse2) as f64;
+        if sum == 0.0 {
+            return 0.0;
+        }
+        95.88 / (8128.0 / sum + 100.0)
+    }
+
+    fn exact_tnd(triangle: u8, noise: u8, dmc: u8) -> f64 {
+        let sum = triangle as f64 / 8227.0 + noise as f64 / 12241.0 + dmc as f64 / 22638.0;
+        if sum == 0.0 {
+            return 0.0;
+        }
+        159.79 / (1.0 / sum + 100.0)
+    }
+
+    #[test]
+    fn test_pulse_table_matches_exact_mixer() {
+        for pulse1 in 0..16u8 {
+            for pulse2 in 0..16u8 {
+                let table = PULSE_TABLE[(pulse1 + pulse2) as usize] as f64;
+                let exact = exact_pulse(pulse1, pulse2);
+                assert!(
+                    (table - exact).abs() < 0.0015,
+                    "pulse {pulse1}+{pulse2}: table {table}, exact {exact}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_tnd_table_matches_exact_mixer() {
+        for triangle in 0..16u8 {
+            for noise in 0..16u8 {
+                for dmc in 0..128u8 {
+                    let index = triangle as usize * 3 + noise as usize * 2 + dmc as usize;
+                    let table = TND_TABLE[index] as f64;
+                    let exact = exact_tnd(triangle, noise, dmc);
+                    assert!(
+                        (table - exact).abs() < 0.015,
+                        "tnd {triangle}/{noise}/{dmc}: table {table}, exact {exact}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_channel_frequency_and_volume() {
+        let mut apu = APU::new(48_000);
+        assert_eq!(apu.channel_frequency(ChannelId::Pulse1), None);
+
+        // A4 at constant volume 12.
+        apu.write_status(0b0000_0001);
+        apu.write_register(0x4000, 0b0011_1100);
+        apu.write_register(0x4002, 253);
+        apu.write_register(0x4003, 0b0000_1000);
+        let frequency = apu.channel_frequency(ChannelId::Pulse1).unwrap();
+        assert!((frequency - 440.0).abs() < 1.0, "{frequency}");
+        assert_eq!(apu.channel_volume(ChannelId::Pulse1), 12.0 / 16.0);
+        assert_eq!(apu.channel_frequency(ChannelId::Noise), None);
+        assert_eq!(apu.channel_volume(ChannelId::Pulse2), 0.0);
+    }
+
+    #[test]
+    fn test_mixer_tables_are_monotonic_and_bounded() {
+        assert_eq!(PULSE_TABLE[0], 0.0);
+        assert_eq!(TND_TABLE[0], 0.0);
+        assert!(PULSE_TABLE.windows(2).all(|w| w[0] < w[1]));
+        assert!(TND_TABLE.windows(2).all(|w| w[0] < w[1]));
+        assert!(PULSE_TABLE[30] + TND_TABLE[202] < 1.0);
+    }
+}
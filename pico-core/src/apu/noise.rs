@@ -2,11 +2,16 @@ use crate::apu::LengthCounter;
 use crate::apu::buffer::RingBuffer;
 use crate::apu::channel::{Channel, PlaybackRate, Timbre, Volume};
 use crate::apu::envelope::Envelope;
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 pub const NOISE_PERIOD_TABLE: [u16; 16] = [
     4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
 ];
 
+pub const NOISE_PERIOD_TABLE_PAL: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
 pub struct NoiseChannel {
     pub debug_disable: bool,
     pub output_buffer: RingBuffer,
@@ -129,3 +134,26 @@ impl Channel for NoiseChannel {
         })
     }
 }
+
+impl Savestate for NoiseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.last_edge);
+        self.envelope.save_state(state);
+        self.length_counter.save_state(state);
+        state.write_u8(self.mode);
+        state.write_u16(self.period_initial);
+        state.write_u16(self.period_current);
+        state.write_u16(self.shift_register);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.last_edge = state.read_bool()?;
+        self.envelope.load_state(state)?;
+        self.length_counter.load_state(state)?;
+        self.mode = state.read_u8()?;
+        self.period_initial = state.read_u16()?;
+        self.period_current = state.read_u16()?;
+        self.shift_register = state.read_u16()?;
+        Ok(())
+    }
+}
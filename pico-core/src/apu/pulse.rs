@@ -6,6 +6,7 @@ use crate::apu::channel::PlaybackRate;
 use crate::apu::channel::Timbre;
 use crate::apu::channel::Volume;
 use crate::apu::envelope::Envelope;
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 pub struct PulseChannel {
     pub debug_disable: bool,
@@ -187,3 +188,38 @@ impl Channel for PulseChannel {
         };
     }
 }
+
+impl Savestate for PulseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.last_edge);
+        self.envelope.save_state(state);
+        self.length_counter.save_state(state);
+        state.write_bool(self.sweep_enabled);
+        state.write_u8(self.sweep_period);
+        state.write_u8(self.sweep_divider);
+        state.write_bool(self.sweep_negate);
+        state.write_u8(self.sweep_shift);
+        state.write_bool(self.sweep_reload);
+        state.write_u8(self.duty);
+        state.write_u8(self.sequence_counter);
+        state.write_u16(self.period_initial);
+        state.write_u16(self.period_current);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.last_edge = state.read_bool()?;
+        self.envelope.load_state(state)?;
+        self.length_counter.load_state(state)?;
+        self.sweep_enabled = state.read_bool()?;
+        self.sweep_period = state.read_u8()?;
+        self.sweep_divider = state.read_u8()?;
+        self.sweep_negate = state.read_bool()?;
+        self.sweep_shift = state.read_u8()?;
+        self.sweep_reload = state.read_bool()?;
+        self.duty = state.read_u8()?;
+        self.sequence_counter = state.read_u8()?;
+        self.period_initial = state.read_u16()?;
+        self.period_current = state.read_u16()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const VGM_VERSION: u32 = 0x161;
+const VGM_HEADER_SIZE: usize = 0xC0;
+const VGM_SAMPLE_RATE: u64 = 44100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterWrite {
+    pub cycle: u64,
+    pub addr: u16,
+    pub value: u8,
+}
+
+// Sound register writes as the game made them, for pulling music out of games.
+// Covers $4000-$4017 and any registers the mapper reports as expansion audio.
+#[derive(Default)]
+pub struct RegisterLog {
+    writes: Vec<RegisterWrite>,
+}
+
+impl RegisterLog {
+    pub fn new() -> Self {
+        RegisterLog { writes: Vec::new() }
+    }
+
+    pub fn record(&mut self, cycle: u64, addr: u16, value: u8) {
+        self.writes.push(RegisterWrite { cycle, addr, value });
+    }
+
+    pub fn writes(&self) -> &[RegisterWrite] {
+        &self.writes
+    }
+
+    pub fn clear(&mut self) {
+        self.writes.clear();
+    }
+
+    // One "cycle addr value" line per write, cycles relative to the first one.
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> Result<(), String> {
+        let start = self.writes.first().map_or(0, |w| w.cycle);
+        for write in &self.writes {
+            writeln!(
+                writer,
+                "{} ${:04X} ${:02X}",
+                write.cycle - start,
+                write.addr,
+                write.value
+            )
+            .map_err(|e| format!("Failed to write log: {}", e))?;
+        }
+        Ok(())
+    }
+
+    // VGM 1.61 with the NES APU chip. Writes outside $4000-$401F (mapper
+    // expansion audio) have no VGM command and are left out.
+    pub fn write_vgm<W: Write>(&self, writer: &mut W, cpu_clock_rate: u64) -> Result<(), String> {
+        let mut data = Vec::new();
+        let start = self.writes.first().map_or(0, |w| w.cycle);
+        let mut samples_written = 0u64;
+
+        for write in self
+            .writes
+            .iter()
+            .filter(|w| (0x4000..=0x401F).contains(&w.addr))
+        {
+            let sample = (write.cycle - start) * VGM_SAMPLE_RATE / cpu_clock_rate;
+            push_vgm_wait(&mut data, sample - samples_written);
+            samples_written = sample;
+            data.extend_from_slice(&[0xB4, (write.addr - 0x4000) as u8, write.value]);
+        }
+        data.push(0x66);
+
+        let mut header = vec![0u8; VGM_HEADER_SIZE];
+        header[0x00..0x04].copy_from_slice(b"Vgm ");
+        let eof_offset = (VGM_HEADER_SIZE + data.len() - 0x04) as u32;
+        header[0x04..0x08].copy_from_slice(&eof_offset.to_le_bytes());
+        header[0x08..0x0C].copy_from_slice(&VGM_VERSION.to_le_bytes());
+        header[0x18..0x1C].copy_from_slice(&(samples_written as u32).to_le_bytes());
+        let data_offset = (VGM_HEADER_SIZE - 0x34) as u32;
+        header[0x34..0x38].copy_from_slice(&data_offset.to_le_bytes());
+        header[0x84..0x88].copy_from_slice(&(cpu_clock_rate as u32).to_le_bytes());
+
+        writer
+            .write_all(&header)
+            .and_then(|_| writer.write_all(&data))
+            .map_err(|e| format!("Failed to write VGM: {}", e))
+    }
+
+    pub fn save_text<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        self.write_text(&mut writer)?;
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write file: {}", e))
+    }
+
+    pub fn save_vgm<P: AsRef<Path>>(&self, path: P, cpu_clock_rate: u64) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        self.write_vgm(&mut writer, cpu_clock_rate)?;
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write file: {}", e))
+    }
+}
+
+fn push_vgm_wait(data: &mut Vec<u8>, mut samples: u64) {
+    while samples > 0 {
+        match samples {
+            735 => {
+                data.push(0x62);
+                samples = 0;
+            }
+            882 => {
+                data.push(0x63);
+                samples = 0;
+            }
+            1..=16 => {
+                data.push(0x70 + (samples - 1) as u8);
+                samples = 0;
+            }
+            _ => {
+                let chunk = samples.min(0xFFFF);
+                data.push(0x61);
+                data.extend_from_slice(&(chunk as u16).to_le_bytes());
+                samples -= chunk;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vgm_encodes_writes_and_waits() {
+        let mut log = RegisterLog::new();
+        log.record(1000, 0x4015, 0x0F);
+        log.record(1000 + 29830, 0x4000, 0xBF);
+        log.record(1000 + 29830, 0x5000, 0x12);
+
+        let mut vgm = Vec::new();
+        log.write_vgm(&mut vgm, 1_789_773).unwrap();
+
+        assert_eq!(&vgm[0..4], b"Vgm ");
+        assert_eq!(
+            u32::from_le_bytes(vgm[4..8].try_into().unwrap()) as usize,
+            vgm.len() - 4
+        );
+        // 29830 CPU cycles is 735 samples at 44.1 kHz
+        assert_eq!(
+            &vgm[VGM_HEADER_SIZE..],
+            &[0xB4, 0x15, 0x0F, 0x62, 0xB4, 0x00, 0xBF, 0x66]
+        );
+
+        let mut text = Vec::new();
+        log.write_text(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "0 $4015 $0F\n29830 $4000 $BF\n29830 $5000 $12\n"
+        );
+    }
+}
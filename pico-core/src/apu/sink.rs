@@ -0,0 +1,340 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+// Where the APU puts the samples it generates. Frontends playing sound take
+// them from `Collect` after each frame and hand them to their own audio
+// device, so the mixer never waits on a lock.
+pub enum AudioSink {
+    // Kept by the APU until taken with `APU::take_samples`.
+    // Past four seconds the older half is dropped.
+    Collect(Vec<f32>),
+    // Streamed to a WAV file as they are generated.
+    Wav(WavWriter),
+    // Thrown away.
+    Null,
+}
+
+impl AudioSink {
+    pub fn wav(path: &Path, sample_rate: u32) -> Result<AudioSink, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let writer = WavWriter::new(BufWriter::new(file), sample_rate)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(AudioSink::Wav(writer))
+    }
+
+    // Completes a WAV file; other sinks have nothing to finish.
+    pub fn finish(self) -> Result<(), String> {
+        match self {
+            AudioSink::Wav(writer) => writer.finish(),
+            _ => Ok(()),
+        }
+    }
+}
+
+// How long a gap takes to fade from the last sample to silence, and to fade
+// back in once samples arrive again.
+const CONCEAL_FADE_MS: u32 = 5;
+
+// Fills in for samples an audio device asks for before the emulator has made
+// them, as happens when the host doesn't schedule it in time. A sudden drop
+// to silence clicks; holding the last sample and fading it out doesn't.
+pub struct Concealer {
+    fade_step: f32,
+    gain: f32,
+    last: f32,
+    underrun: bool,
+    underruns: Arc<AtomicUsize>,
+}
+
+impl Concealer {
+    // `underruns` counts the times the buffer runs dry, for another thread to
+    // read. A gap counts once however long it lasts.
+    pub fn new(sample_rate: u32, underruns: Arc<AtomicUsize>) -> Self {
+        let fade_samples = (sample_rate * CONCEAL_FADE_MS / 1000).max(1);
+        Concealer {
+            fade_step: 1.0 / fade_samples as f32,
+            gain: 1.0,
+            last: 0.0,
+            // The wait for the first samples isn't counted.
+            underrun: true,
+            underruns,
+        }
+    }
+
+    // For a device that was paused on purpose: the wait for samples after it
+    // resumes isn't counted, and playback fades back in.
+    pub fn restart(&mut self) {
+        self.underrun = true;
+        self.gain = 0.0;
+    }
+
+    pub fn fill(&mut self, buffer: &mut VecDeque<f32>, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            match buffer.pop_front() {
+                Some(next) => {
+                    self.underrun = false;
+                    self.gain = (self.gain + self.fade_step).min(1.0);
+                    self.last = next;
+                }
+                None => {
+                    if !self.underrun {
+                        self.underrun = true;
+                        self.underruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                    self.gain = (self.gain - self.fade_step).max(0.0);
+                }
+            }
+            *sample = self.last * self.gain;
+        }
+    }
+}
+
+// What is kept of the sound made while fast-forwarding, which comes out
+// several times faster than a device plays it. Left alone it would fill the
+// shared buffer to its cap and then lose whatever is oldest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FastForwardAudio {
+    Mute,
+    // One frame's sound out of each batch, at the right pitch.
+    #[default]
+    Skip,
+    // Whole batches at the right pitch, dropping those made while the last
+    // one is still playing.
+    Chunks,
+}
+
+impl FastForwardAudio {
+    pub const ALL: [FastForwardAudio; 3] = [
+        FastForwardAudio::Mute,
+        FastForwardAudio::Skip,
+        FastForwardAudio::Chunks,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FastForwardAudio::Mute => "mute",
+            FastForwardAudio::Skip => "skip",
+            FastForwardAudio::Chunks => "chunks",
+        }
+    }
+
+    // Cuts down the last `batch` samples in `buffer`, made by `frames`
+    // fast-forwarded frames. Anything queued before them is left to play.
+    pub fn trim(&self, buffer: &mut VecDeque<f32>, batch: usize, frames: usize) {
+        let batch = batch.min(buffer.len());
+        let start = buffer.len() - batch;
+        let frame = batch / frames.max(1);
+        match self {
+            FastForwardAudio::Mute => buffer.truncate(start),
+            FastForwardAudio::Skip => {
+                buffer.drain(start..buffer.len() - frame);
+            }
+            FastForwardAudio::Chunks => {
+                if start > frame {
+                    buffer.truncate(start);
+                }
+            }
+        }
+    }
+}
+
+// Plays sound made in slow motion for as long as its frames are shown, by
+// repeating each sample. The pitch drops with the speed, as on a tape.
+pub fn stretch(samples: &mut Vec<f32>, repeat: usize) {
+    if repeat > 1 {
+        *samples = samples
+            .iter()
+            .flat_map(|&sample| std::iter::repeat_n(sample, repeat))
+            .collect();
+    }
+}
+
+const HEADER_LEN: u32 = 44;
+
+// Mono 16-bit PCM. The header's lengths are only filled in by `finish`.
+pub struct WavWriter<W: Write + Seek = BufWriter<File>> {
+    writer: W,
+    samples: u32,
+    error: Option<io::Error>,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_LEN - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&1u16.to_le_bytes()); // mono
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        writer.write_all(&header)?;
+
+        Ok(WavWriter {
+            writer,
+            samples: 0,
+            error: None,
+        })
+    }
+
+    // Write errors are kept until `finish` rather than stopping emulation.
+    pub fn push(&mut self, sample: f32) {
+        if self.error.is_some() {
+            return;
+        }
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        match self.writer.write_all(&value.to_le_bytes()) {
+            Ok(()) => self.samples += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    fn write_lengths(&mut self) -> io::Result<()> {
+        let data_len = self.samples * 2;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_LEN - 8 + data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_LEN as u64 - 4))?;
+        self.writer.write_all(&data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        self.into_inner().map(|_| ())
+    }
+
+    pub fn into_inner(mut self) -> Result<W, String> {
+        if let Some(e) = self.error.take() {
+            return Err(format!("Failed to write audio: {}", e));
+        }
+        self.write_lengths()
+            .map_err(|e| format!("Failed to write audio: {}", e))?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_concealer_holds_and_fades_through_gaps() {
+        let underruns = Arc::new(AtomicUsize::new(0));
+        let mut concealer = Concealer::new(1000, underruns.clone());
+        let mut buffer = VecDeque::from([0.5, 1.0]);
+        let mut out = [0.0; 8];
+        concealer.fill(&mut buffer, &mut out);
+        assert_close(&out, &[0.5, 1.0, 0.8, 0.6, 0.4, 0.2, 0.0, 0.0]);
+        assert_eq!(underruns.load(Ordering::Relaxed), 1);
+
+        // Samples come back faded in, and a gap over two calls counts once.
+        concealer.fill(&mut buffer, &mut out[..2]);
+        buffer.extend([1.0; 3]);
+        concealer.fill(&mut buffer, &mut out[..3]);
+        assert_close(&out[..3], &[0.2, 0.4, 0.6]);
+        assert_eq!(underruns.load(Ordering::Relaxed), 1);
+
+        concealer.fill(&mut buffer, &mut out[..1]);
+        assert_eq!(underruns.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_concealer_restart_fades_in_without_counting() {
+        let underruns = Arc::new(AtomicUsize::new(0));
+        let mut concealer = Concealer::new(1000, underruns.clone());
+        let mut buffer = VecDeque::from([1.0]);
+        let mut out = [0.0; 2];
+        concealer.fill(&mut buffer, &mut out[..1]);
+
+        concealer.restart();
+        concealer.fill(&mut buffer, &mut out[..1]);
+        buffer.extend([1.0; 2]);
+        concealer.fill(&mut buffer, &mut out);
+        assert_close(&out, &[0.2, 0.4]);
+        assert_eq!(underruns.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_fast_forward_audio_trims_each_batch() {
+        // Three samples already queued, then four frames of two samples each.
+        let queued = || VecDeque::from([9.0, 9.0, 9.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0]);
+        let trimmed = |policy: FastForwardAudio, mut buffer: VecDeque<f32>| {
+            policy.trim(&mut buffer, 8, 4);
+            Vec::from(buffer)
+        };
+        assert_eq!(trimmed(FastForwardAudio::Mute, queued()), [9.0; 3]);
+        assert_eq!(
+            trimmed(FastForwardAudio::Skip, queued()),
+            [9.0, 9.0, 9.0, 4.0, 4.0]
+        );
+        assert_eq!(trimmed(FastForwardAudio::Chunks, queued()), [9.0; 3]);
+
+        // A batch is only played once the last one has about run out.
+        let mut drained = queued();
+        drained.pop_front();
+        assert_eq!(
+            trimmed(FastForwardAudio::Chunks, drained),
+            [9.0, 9.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn test_stretch_repeats_each_sample() {
+        let mut samples = vec![1.0, 2.0];
+        stretch(&mut samples, 3);
+        assert_eq!(samples, [1.0, 1.0, 1.0, 2.0, 2.0, 2.0]);
+        stretch(&mut samples, 1);
+        assert_eq!(samples.len(), 6);
+    }
+
+    #[test]
+    fn test_wav_header_counts_the_samples_written() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000).unwrap();
+        for sample in [0.0, 1.0, -1.0, 2.0] {
+            wav.push(sample);
+        }
+        assert_eq!(wav.samples(), 4);
+
+        let bytes = wav.into_inner().unwrap().into_inner();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(
+            u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            48_000
+        );
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 8);
+        let samples: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        assert_eq!(samples, [0, i16::MAX, -i16::MAX, i16::MAX]);
+    }
+}
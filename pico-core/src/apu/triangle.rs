@@ -1,6 +1,7 @@
 use crate::apu::buffer::RingBuffer;
 use crate::apu::channel::{Channel, PlaybackRate, Timbre, Volume};
 use crate::apu::{CPU_CLOCK_NTSC, LengthCounter};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 pub struct TriangleChannel {
     pub debug_disable: bool,
@@ -136,3 +137,30 @@ impl Channel for TriangleChannel {
         if self.playing() { 0.55 } else { 0.0 }
     }
 }
+
+impl Savestate for TriangleChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.last_edge);
+        self.length_counter.save_state(state);
+        state.write_bool(self.control_flag);
+        state.write_bool(self.linear_reload_flag);
+        state.write_u8(self.linear_counter_initial);
+        state.write_u8(self.linear_counter_current);
+        state.write_u8(self.sequence_counter);
+        state.write_u16(self.period_initial);
+        state.write_u16(self.period_current);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.last_edge = state.read_bool()?;
+        self.length_counter.load_state(state)?;
+        self.control_flag = state.read_bool()?;
+        self.linear_reload_flag = state.read_bool()?;
+        self.linear_counter_initial = state.read_u8()?;
+        self.linear_counter_current = state.read_u8()?;
+        self.sequence_counter = state.read_u8()?;
+        self.period_initial = state.read_u16()?;
+        self.period_current = state.read_u16()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::nes::Nes;
+
+// Five seconds of play: little is lost to a crash, and games that use their
+// save RAM as scratch space don't have it written out every frame.
+pub const DEFAULT_FLUSH_FRAMES: u32 = 300;
+
+// Keeps a cartridge's battery-backed PRG RAM in a save file, followed by its
+// CHR RAM on boards whose battery keeps that too. The file is read at power
+// on and written back a while after the game changes the RAM, not just on
+// exit.
+pub struct BatterySave {
+    path: PathBuf,
+    flush_frames: u32,
+    // Frames since the oldest change that isn't on disk yet.
+    dirty_frames: Option<u32>,
+}
+
+impl BatterySave {
+    pub fn new(path: PathBuf, flush_frames: u32) -> Self {
+        BatterySave {
+            path,
+            flush_frames: flush_frames.max(1),
+            dirty_frames: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Fills the RAM from the save file. Returns false if there is none yet. A
+    // file shorter than the RAM, like one saved before CHR RAM was kept,
+    // fills as much as it covers.
+    pub fn load(&self, nes: &mut Nes) -> Result<bool, String> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => {
+                return Err(format!("Failed to read {}: {}", self.path.display(), e));
+            }
+        };
+        let (battery, chr_battery) = (nes.bus.cart.battery, nes.bus.cart.chr_battery);
+        let mapper = nes.mapper_mut();
+        let mut rest = data.as_slice();
+        let mut loaded = false;
+        if battery && let Some(ram) = mapper.prg_ram_mut() {
+            rest = fill(ram, rest);
+            loaded = true;
+        }
+        if chr_battery && let Some(ram) = mapper.chr_ram_mut() {
+            fill(ram, rest);
+            loaded = true;
+        }
+        nes.bus.take_prg_ram_dirty();
+        Ok(loaded)
+    }
+
+    // Called after every frame run. Returns whether the file was written.
+    pub fn end_frame(&mut self, nes: &mut Nes) -> Result<bool, String> {
+        if nes.bus.take_prg_ram_dirty() && self.dirty_frames.is_none() {
+            self.dirty_frames = Some(0);
+        }
+        match &mut self.dirty_frames {
+            Some(frames) if *frames + 1 >= self.flush_frames => self.flush(nes),
+            Some(frames) => {
+                *frames += 1;
+                Ok(false)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // Writes out any change not on disk yet, for exits and for moments the
+    // player expects their progress to be safe, like saving a state.
+    pub fn flush(&mut self, nes: &mut Nes) -> Result<bool, String> {
+        let dirty = nes.bus.take_prg_ram_dirty() || self.dirty_frames.is_some();
+        let data = saved_ram(nes);
+        if !dirty || data.is_empty() {
+            return Ok(false);
+        }
+        // On failure, try again after another interval.
+        self.dirty_frames = Some(0);
+        write_atomic(&self.path, &data)?;
+        self.dirty_frames = None;
+        Ok(true)
+    }
+}
+
+// Copies the start of `data` into `ram` and returns what is left.
+fn fill<'a>(ram: &mut [u8], data: &'a [u8]) -> &'a [u8] {
+    let len = data.len().min(ram.len());
+    ram[..len].copy_from_slice(&data[..len]);
+    &data[len..]
+}
+
+fn saved_ram(nes: &Nes) -> Vec<u8> {
+    let cart = &nes.bus.cart;
+    let mut data = Vec::new();
+    if let Some(ram) = cart.mapper.prg_ram().filter(|_| cart.battery) {
+        data.extend_from_slice(ram);
+    }
+    if cart.chr_battery {
+        data.extend_from_slice(cart.mapper.chr_data());
+    }
+    data
+}
+
+// Writes a temporary file next to `path`, syncs it and renames it over
+// `path`, so a crash leaves either the old contents or the new ones.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let mut temp_name = path
+        .file_name()
+        .ok_or_else(|| format!("Failed to write {}: not a file", path.display()))?
+        .to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .map_err(|e| format!("Failed to write {}: {}", temp_path.display(), e))?;
+    fs::rename(&temp_path, path)
+        .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))?;
+
+    // The rename is only durable once the directory is synced, which not
+    // every platform supports, so this is best effort.
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apu::APU;
+    use crate::cart::test::test_rom;
+    use crate::testutil::{Asm, CODE_ORIGIN, RomBuilder};
+
+    // INC $6000 once, then spins on a JMP.
+    fn saving_nes() -> Nes {
+        let mut program = vec![0xEE, 0x00, 0x60, 0x4C, 0x03, 0x80];
+        program.resize(0x8000, 0);
+        program[0x7FFC] = 0x00;
+        program[0x7FFD] = 0x80;
+        let mut cart = test_rom(program);
+        cart.battery = true;
+        let apu = APU::new(48_000);
+        let mut nes = Nes::new(cart, apu);
+        nes.reset();
+        nes
+    }
+
+    fn save_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("pico-battery-{}-{}.sav", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_changes_are_flushed_after_the_interval() {
+        let path = save_path("interval");
+        let mut battery = BatterySave::new(path.clone(), 3);
+        let mut nes = saving_nes();
+
+        let mut written = Vec::new();
+        for _ in 0..5 {
+            nes.step_frame();
+            written.push(battery.end_frame(&mut nes).unwrap());
+        }
+        assert_eq!(written, [false, false, true, false, false]);
+        assert_eq!(fs::read(&path).unwrap()[0], 1);
+        assert!(!battery.flush(&mut nes).unwrap(), "nothing left to write");
+
+        // Power on again with the saved RAM.
+        let mut nes = saving_nes();
+        nes.mapper_mut().prg_ram_mut().unwrap()[0] = 0;
+        assert!(battery.load(&mut nes).unwrap());
+        nes.step_frame();
+        assert!(battery.flush(&mut nes).unwrap());
+        assert_eq!(fs::read(&path).unwrap()[0], 2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_chr_ram_follows_prg_ram_in_the_file() {
+        // Writes $5A to CHR RAM at $0003, then spins.
+        let mut asm = Asm::new(CODE_ORIGIN);
+        asm.label("reset")
+            .poke(0x2006, 0x00)
+            .poke(0x2006, 0x03)
+            .poke(0x2007, 0x5A)
+            .label("forever")
+            .jmp("forever");
+        let cart = || {
+            let mut cart = RomBuilder::new(1)
+                .banks(2, 0)
+                .code(&asm, "reset", "reset", "reset")
+                .cart();
+            cart.battery = true;
+            cart.chr_battery = true;
+            cart
+        };
+        let path = save_path("chr");
+        let mut battery = BatterySave::new(path.clone(), 1);
+        let mut nes = Nes::new(cart(), APU::new(48_000));
+        nes.reset();
+        nes.step_frame();
+        assert!(battery.end_frame(&mut nes).unwrap());
+        let prg_len = nes.bus.cart.mapper.prg_ram().unwrap().len();
+        let saved = fs::read(&path).unwrap();
+        assert_eq!(saved.len(), prg_len + 0x2000);
+        assert_eq!(saved[prg_len + 3], 0x5A);
+
+        let mut nes = Nes::new(cart(), APU::new(48_000));
+        assert!(battery.load(&mut nes).unwrap());
+        assert_eq!(nes.bus.cart.mapper.chr_data()[3], 0x5A);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_atomic_write_replaces_the_file() {
+        let path = save_path("atomic");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!path.with_extension("sav.tmp").exists());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,238 @@
+// BizHawk movies: a zip archive whose "Input Log.txt" holds one line of input
+// per frame, laid out by its "LogKey" line. They are converted to the same
+// records FM2 movies use, keeping the two NES pads and resets.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+use zip::ZipArchive;
+
+use crate::input_history::COMMAND_RESET;
+use crate::joypad::JoypadButton;
+use crate::movie::{FM2Movie, GamepadInput, InputRecord, MovieHeader};
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+// What NesHawk writes for two standard controllers, for logs without a key.
+const NES_LOG_KEY: &str = "#Reset|Power|#P1 Up|P1 Down|P1 Left|P1 Right|P1 Start|P1 Select|P1 B|P1 A|#P2 Up|P2 Down|P2 Left|P2 Right|P2 Start|P2 Select|P2 B|P2 A|";
+
+pub fn is_bk2(bytes: &[u8]) -> bool {
+    bytes.starts_with(ZIP_MAGIC)
+}
+
+pub fn load_bk2<P: AsRef<Path>>(path: P) -> Result<FM2Movie, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    parse_bk2(BufReader::new(file))
+}
+
+pub fn parse_bk2<R: Read + Seek>(reader: R) -> Result<FM2Movie, String> {
+    let mut archive =
+        ZipArchive::new(reader).map_err(|e| format!("Failed to open BK2 archive: {}", e))?;
+    let header = match read_entry(&mut archive, "Header.txt") {
+        Ok(text) => parse_header(&text)?,
+        Err(_) => MovieHeader::for_recording(""),
+    };
+    let input_log = parse_input_log(&read_entry(&mut archive, "Input Log.txt")?)?;
+    Ok(FM2Movie { header, input_log })
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<String, String> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| format!("Failed to find {} in BK2: {}", name, e))?;
+    let mut text = String::new();
+    entry
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Failed to read {} from BK2: {}", name, e))?;
+    Ok(text)
+}
+
+fn parse_header(text: &str) -> Result<MovieHeader, String> {
+    let pairs: HashMap<&str, &str> = text
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .collect();
+
+    if let Some(platform) = pairs.get("Platform")
+        && *platform != "NES"
+    {
+        return Err(format!("BK2 movie is for {}, not the NES", platform));
+    }
+    if pairs.get("StartsFromSavestate") == Some(&"True")
+        || pairs.get("StartsFromSaveRam") == Some(&"True")
+    {
+        return Err(
+            "BK2 movies that start from a savestate or save RAM are not supported".to_string(),
+        );
+    }
+
+    let mut header = MovieHeader::for_recording(pairs.get("GameName").copied().unwrap_or(""));
+    header.emu_version = pairs
+        .get("MovieVersion")
+        .copied()
+        .unwrap_or("BizHawk")
+        .to_string();
+    header.rerecord_count = pairs.get("rerecordCount").and_then(|v| v.parse().ok());
+    header.comment = pairs
+        .get("Author")
+        .map(|author| format!("author {}", author));
+    Ok(header)
+}
+
+#[derive(Clone, Copy)]
+enum Control {
+    Command(u8),
+    Button(usize, JoypadButton),
+    // Power is only ever held to boot the console, which it already is;
+    // anything else isn't a standard NES controller.
+    Ignored,
+}
+
+fn control(name: &str) -> Control {
+    if name == "Reset" {
+        return Control::Command(COMMAND_RESET);
+    }
+    let Some((port, button)) = name.split_once(' ') else {
+        return Control::Ignored;
+    };
+    let port = match port {
+        "P1" => 0,
+        "P2" => 1,
+        _ => return Control::Ignored,
+    };
+    let button = match button {
+        "Up" => JoypadButton::UP,
+        "Down" => JoypadButton::DOWN,
+        "Left" => JoypadButton::LEFT,
+        "Right" => JoypadButton::RIGHT,
+        "Start" => JoypadButton::START,
+        "Select" => JoypadButton::SELECT,
+        "B" => JoypadButton::BUTTON_B,
+        "A" => JoypadButton::BUTTON_A,
+        _ => return Control::Ignored,
+    };
+    Control::Button(port, button)
+}
+
+// Parses the text of "Input Log.txt". Each frame line has a column per
+// control, in LogKey order with groups split by '|', and any character but
+// '.' or ' ' in a column means it is held.
+pub fn parse_input_log(text: &str) -> Result<Vec<InputRecord>, String> {
+    let mut controls: Option<Vec<Control>> = None;
+    let mut input_log = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if let Some(key) = line.strip_prefix("LogKey:") {
+            controls = Some(parse_log_key(key));
+            continue;
+        }
+        if !line.starts_with('|') {
+            continue;
+        }
+        let controls = controls.get_or_insert_with(|| parse_log_key(NES_LOG_KEY));
+
+        let columns: Vec<char> = line.chars().filter(|&c| c != '|').collect();
+        if columns.len() != controls.len() {
+            return Err(format!(
+                "Frame {} of BK2 input log has {} columns, expected {}",
+                input_log.len(),
+                columns.len(),
+                controls.len()
+            ));
+        }
+
+        let mut commands = 0;
+        let mut pads = [JoypadButton::empty(); 2];
+        for (&column, control) in columns.iter().zip(controls.iter()) {
+            if column == '.' || column == ' ' {
+                continue;
+            }
+            match *control {
+                Control::Command(command) => commands |= command,
+                Control::Button(port, button) => pads[port] |= button,
+                Control::Ignored => {}
+            }
+        }
+        input_log.push(InputRecord {
+            commands,
+            port0_input: Some(GamepadInput::from_buttons(pads[0])),
+            port1_input: Some(GamepadInput::from_buttons(pads[1])),
+            port2_input: None,
+        });
+    }
+
+    Ok(input_log)
+}
+
+fn parse_log_key(key: &str) -> Vec<Control> {
+    key.split(['#', '|'])
+        .filter(|name| !name.is_empty())
+        .map(control)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    const INPUT_LOG: &str = "[Input]\n\
+        LogKey:#Reset|Power|#P1 Up|P1 Down|P1 Left|P1 Right|P1 Start|P1 Select|P1 B|P1 A|#P2 Up|P2 Down|P2 Left|P2 Right|P2 Start|P2 Select|P2 B|P2 A|\n\
+        |.P|........|........|\n\
+        |..|...R...A|U.......|\n\
+        |r.|....S...|........|\n\
+        [/Input]\n";
+
+    fn bk2(header: &str) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        zip.start_file("Header.txt", options).unwrap();
+        zip.write_all(header.as_bytes()).unwrap();
+        zip.start_file("Input Log.txt", options).unwrap();
+        zip.write_all(INPUT_LOG.as_bytes()).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_input_log_follows_the_log_key() {
+        let log = parse_input_log(INPUT_LOG).unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[0].commands, 0);
+        let pad = |record: &InputRecord, port: usize| {
+            [&record.port0_input, &record.port1_input][port]
+                .as_ref()
+                .unwrap()
+                .to_buttons()
+        };
+        assert_eq!(
+            pad(&log[1], 0),
+            JoypadButton::RIGHT | JoypadButton::BUTTON_A
+        );
+        assert_eq!(pad(&log[1], 1), JoypadButton::UP);
+        assert_eq!(log[2].commands, COMMAND_RESET);
+        assert_eq!(pad(&log[2], 0), JoypadButton::START);
+
+        assert!(parse_input_log("|..|....|........|\n").is_err());
+    }
+
+    #[test]
+    fn test_archives_convert_to_movies() {
+        let bytes =
+            bk2("MovieVersion BizHawk v2.0.0\nPlatform NES\nGameName Test\nrerecordCount 42\n");
+        assert!(is_bk2(&bytes));
+        let movie = parse_bk2(Cursor::new(bytes)).unwrap();
+        assert_eq!(movie.header.rom_filename, "Test");
+        assert_eq!(movie.header.rerecord_count, Some(42));
+        assert_eq!(movie.frame_count(), 3);
+
+        let bytes = bk2("Platform SNES\n");
+        assert!(parse_bk2(Cursor::new(bytes)).is_err());
+        let bytes = bk2("Platform NES\nStartsFromSavestate True\n");
+        assert!(parse_bk2(Cursor::new(bytes)).is_err());
+    }
+}
@@ -0,0 +1,675 @@
+use crate::{
+    apu::{APU, register_log::RegisterLog},
+    cart::Cart,
+    cheats::Cheats,
+    compat::IrqSource,
+    cpu::CPU,
+    debugger::{Access, Debugger},
+    decode_cache::{DecodeCache, Decoded},
+    joypad::Joypad,
+    mapper::Mapper,
+    memory::Memory,
+    nes::AccuracyProfile,
+    ppu::{PPU, framebuffer::Framebuffer, registers::PpuRegisters, render},
+    savestate::{Savestate, StateReader, StateWriter},
+    vs_system::{Console, VsSystem},
+};
+
+// Address ranges per https://www.nesdev.org/wiki/CPU_memory_map
+const CPU_RAM_MIRROR_MASK: u16 = 0x07FF;
+const CPU_RAM_MIRRORS_END: u16 = 0x1FFF;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const DISABLED_APU_IO_END: u16 = 0x401F;
+const CARTRIDGE_SPACE_START: u16 = 0x4020;
+
+// An OAM DMA under way. The CPU is halted while the bus reads a byte from
+// `page` and writes it to $2004 every two cycles, after one or two cycles of
+// halting and lining up with a read cycle.
+#[derive(Clone, Copy)]
+struct OamDma {
+    page: u8,
+    cycles_left: u16,
+    value: u8,
+}
+
+pub struct Bus {
+    // The console's 2 KiB of work RAM, mirrored up to $1FFF.
+    pub ram: [u8; 0x800],
+    pub cart: Cart,
+    pub ppu: PPU,
+    pub apu: APU,
+    pub register_log: Option<RegisterLog>,
+    pub debugger: Option<Debugger>,
+    pub accuracy: AccuracyProfile,
+    pub cheats: Cheats,
+    // The cabinet's coin slots and DIP switches, for Vs. System games.
+    pub vs_system: Option<VsSystem>,
+    joypads: [Joypad; 2],
+    oam_dma: Option<OamDma>,
+    // Cycles the CPU has yet to wait for DMC sample fetches.
+    dmc_stall: u8,
+    // The controller port read by the instruction the CPU is running, and
+    // the port it reads on this cycle, the instruction's last.
+    controller_read: Option<usize>,
+    reading_port: Option<usize>,
+    // Last value on the CPU data bus, which reads of unmapped addresses see.
+    open_bus: u8,
+    // Set by writes that may have changed battery-backed RAM.
+    prg_ram_dirty: bool,
+    // Code decoded ahead for the CPU, when running it that way.
+    decode_cache: Option<DecodeCache>,
+}
+
+impl Bus {
+    pub fn new(cart: Cart, apu: APU) -> Bus {
+        let vs_system = match cart.console {
+            Console::VsSystem(ppu) => Some(VsSystem::new(ppu)),
+            _ => None,
+        };
+        Bus {
+            ram: [0; 0x800],
+            cart,
+            ppu: PPU::new(),
+            apu,
+            register_log: None,
+            debugger: None,
+            accuracy: AccuracyProfile::default(),
+            cheats: Cheats::default(),
+            vs_system,
+            joypads: [Joypad::new(), Joypad::new()],
+            oam_dma: None,
+            dmc_stall: 0,
+            controller_read: None,
+            reading_port: None,
+            open_bus: 0,
+            prg_ram_dirty: false,
+            decode_cache: None,
+        }
+    }
+
+    fn mirror_ram_addr(addr: u16) -> usize {
+        (addr & CPU_RAM_MIRROR_MASK) as usize
+    }
+
+    fn normalize_ppu_register_addr(&self, addr: u16) -> u16 {
+        let reg = addr & 0b00100000_00000111;
+        match &self.vs_system {
+            Some(vs) if vs.ppu.swaps_ctrl_and_mask() && reg < 0x2002 => reg ^ 1,
+            _ => reg,
+        }
+    }
+
+    fn read_ppu_status(&mut self) -> u8 {
+        let status = self.ppu.read_status();
+        self.vs_ppu_status(status)
+    }
+
+    // A Vs. System PPU puts its ID in the bits of $2002 that are otherwise
+    // open bus.
+    fn vs_ppu_status(&self, status: u8) -> u8 {
+        match self.vs_system.as_ref().and_then(|vs| vs.ppu.status_id()) {
+            Some(id) => (status & 0xE0) | id,
+            None => status,
+        }
+    }
+
+    // Controller reads only drive the low bits, except on a Vs. System,
+    // whose cabinet inputs fill the rest.
+    fn controller_port_bits(&self, port: usize) -> u8 {
+        match &self.vs_system {
+            Some(vs) => vs.port_bits(port),
+            None => self.open_bus & 0xE0,
+        }
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut dyn Mapper {
+        self.cart.mapper.as_mut()
+    }
+
+    pub fn ppu_registers(&mut self) -> PpuRegisters<'_> {
+        PpuRegisters::new(&mut self.ppu, self.cart.mapper.as_mut())
+    }
+
+    pub fn joypad_mut(&mut self, idx: usize) -> Option<&mut Joypad> {
+        self.joypads.get_mut(idx)
+    }
+
+    pub fn joypad(&self, idx: usize) -> Option<&Joypad> {
+        self.joypads.get(idx)
+    }
+
+    pub fn joypads_mut(&mut self) -> (&mut Joypad, &mut Joypad) {
+        let (left, right) = self.joypads.split_at_mut(1);
+        (&mut left[0], &mut right[0])
+    }
+
+    pub fn ppu_clock(&mut self) -> bool {
+        let mapper = self.cart.mapper.as_mut();
+        self.ppu.clock(mapper)
+    }
+
+    pub fn apu_clock(&mut self) {
+        self.cart.mapper.clock_cpu();
+        self.apu
+            .set_expansion_audio(self.cart.mapper.audio_output());
+        if let Some(addr) = self.apu.clock() {
+            if self.accuracy.timed_dma() {
+                // The halted CPU repeats the read it was making, so a
+                // controller being read shifts out an extra bit. Here the
+                // instruction already has its bit, so the next read skips one.
+                if let Some(port) = self.reading_port {
+                    self.joypads[port].read();
+                }
+                // Fewer cycles when OAM DMA has already halted the CPU.
+                self.dmc_stall += if self.oam_dma.is_some() { 2 } else { 4 };
+            }
+            let value = self.read(addr);
+            self.apu.provide_dmc_sample(value);
+        }
+    }
+
+    fn log_audio_write(&mut self, addr: u16, data: u8) {
+        if let Some(log) = &mut self.register_log {
+            log.record(self.apu.cycle(), addr, data);
+        }
+    }
+
+    pub fn poll_irq(&mut self) -> bool {
+        let sources = [
+            (IrqSource::FrameCounter, self.apu.frame_irq()),
+            (IrqSource::Dmc, self.apu.dmc_irq()),
+            (IrqSource::Mapper, self.cart.mapper.poll_irq().is_some()),
+        ];
+        for (source, _) in sources.iter().filter(|(_, irq)| *irq) {
+            self.cart.compat.irq(*source);
+        }
+        sources.iter().any(|(_, irq)| *irq)
+    }
+
+    // What a CPU read of `addr` would return, with none of its side effects,
+    // for traces and debugger views that mustn't disturb the console.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=CPU_RAM_MIRRORS_END => self.ram[Self::mirror_ram_addr(addr)],
+            0x2000..=PPU_REGISTERS_MIRRORS_END => match self.normalize_ppu_register_addr(addr) {
+                0x2002 => self.vs_ppu_status(self.ppu.peek_register(0x2002)),
+                reg => self.ppu.peek_register(reg),
+            },
+            0x4000..=0x4014 => self.open_bus,
+            0x4015 => self.apu.peek_status() | (self.open_bus & 0x20),
+            0x4016 => {
+                self.joypads[0].peek()
+                    | self.joypads[1].microphone_bit()
+                    | self.controller_port_bits(0)
+            }
+            0x4017 => self.joypads[1].peek() | self.controller_port_bits(1),
+            0x4018..=DISABLED_APU_IO_END => self.open_bus,
+            CARTRIDGE_SPACE_START..=0xFFFF if !self.cart.mapper.maps_read(addr) => self.open_bus,
+            CARTRIDGE_SPACE_START..=0xFFFF => self
+                .cheats
+                .patch_read(addr, self.cart.mapper.peek_prg(addr)),
+        }
+    }
+
+    // Whether battery-backed RAM may have changed since the last call.
+    pub fn take_prg_ram_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.prg_ram_dirty)
+    }
+
+    pub fn mark_prg_ram_dirty(&mut self) {
+        self.prg_ram_dirty |= self.cart.battery;
+    }
+
+    fn mark_chr_ram_dirty(&mut self) {
+        self.prg_ram_dirty |= self.cart.chr_battery;
+    }
+
+    // Freeze codes hold their RAM at a value from one frame to the next.
+    pub fn apply_cheat_freezes(&mut self) {
+        for (addr, value) in self.cheats.freezes() {
+            if let Some(cache) = &mut self.decode_cache {
+                cache.written(addr);
+            }
+            match addr {
+                0x0000..=CPU_RAM_MIRRORS_END => {
+                    self.ram[Self::mirror_ram_addr(addr)] = value;
+                }
+                _ => self.cart.mapper.write_prg(addr, value),
+            }
+        }
+        self.check_prg_remapped();
+    }
+
+    pub fn render_frame(&mut self, framebuffer: &mut Framebuffer) {
+        let mapper = self.cart.mapper.as_mut();
+        render::render(&self.ppu, mapper, framebuffer);
+        self.ppu.reset_scroll_segments_for_new_frame();
+    }
+
+    pub fn cpu_clock(&mut self, cpu: &mut CPU) -> bool {
+        self.reading_port = None;
+        if self.dmc_stall > 0 {
+            self.dmc_stall -= 1;
+            return false;
+        }
+        if let Some(dma) = self.oam_dma {
+            self.step_oam_dma(dma);
+            return false;
+        }
+        let complete = cpu.clock(self);
+        if complete {
+            self.reading_port = self.controller_read.take();
+        }
+        complete
+    }
+
+    fn step_oam_dma(&mut self, mut dma: OamDma) {
+        if dma.cycles_left <= 512 {
+            let step = 512 - dma.cycles_left;
+            if step.is_multiple_of(2) {
+                dma.value = self.read(u16::from_be_bytes([dma.page, (step / 2) as u8]));
+            } else {
+                self.write(0x2004, dma.value);
+            }
+        }
+        dma.cycles_left -= 1;
+        self.oam_dma = (dma.cycles_left > 0).then_some(dma);
+        self.controller_read = None;
+    }
+
+    // Runs the CPU from code decoded a block at a time instead of fetching
+    // each instruction. Reads of the code itself stop reaching the bus, which
+    // the open bus value and read breakpoints can notice.
+    pub fn set_fast_cpu(&mut self, enabled: bool) {
+        if enabled != self.decode_cache.is_some() {
+            self.decode_cache = enabled.then(DecodeCache::new);
+        }
+    }
+
+    pub fn fast_cpu(&self) -> bool {
+        self.decode_cache.is_some()
+    }
+
+    fn clear_decode_cache(&mut self) {
+        if let Some(cache) = &mut self.decode_cache {
+            cache.clear();
+        }
+    }
+
+    // Asked after every write that reaches the board, so the flag is taken
+    // whether or not anything is decoded.
+    fn check_prg_remapped(&mut self) {
+        if self.cart.mapper.take_prg_remapped()
+            && let Some(cache) = &mut self.decode_cache
+        {
+            cache.prg_remapped();
+        }
+    }
+
+    pub fn cpu_reset(&mut self, cpu: &mut CPU) {
+        self.clear_decode_cache();
+        cpu.reset(self);
+    }
+}
+
+impl Savestate for Bus {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        self.ppu.save_state(state);
+        self.apu.save_state(state);
+        self.cart.mapper.save_state(state);
+        for joypad in &self.joypads {
+            joypad.save_state(state);
+        }
+        state.write_u8(self.open_bus);
+        state.write_bool(self.oam_dma.is_some());
+        if let Some(dma) = &self.oam_dma {
+            state.write_u8(dma.page);
+            state.write_u16(dma.cycles_left);
+            state.write_u8(dma.value);
+        }
+        state.write_u8(self.dmc_stall);
+        state.write_u8(self.controller_read.map_or(0xFF, |port| port as u8));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.ppu.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.cart.mapper.load_state(state)?;
+        for joypad in &mut self.joypads {
+            joypad.load_state(state)?;
+        }
+        self.open_bus = state.read_u8()?;
+        self.oam_dma = match state.read_bool()? {
+            true => Some(OamDma {
+                page: state.read_u8()?,
+                cycles_left: state.read_u16()?,
+                value: state.read_u8()?,
+            }),
+            false => None,
+        };
+        self.dmc_stall = state.read_u8()?;
+        self.controller_read = match state.read_u8()? {
+            0xFF => None,
+            port => Some(port as usize & 1),
+        };
+        self.clear_decode_cache();
+        Ok(())
+    }
+}
+
+impl Memory for Bus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = match addr {
+            0x0000..=CPU_RAM_MIRRORS_END => self.ram[Self::mirror_ram_addr(addr)],
+            0x2000..=PPU_REGISTERS_MIRRORS_END => match self.normalize_ppu_register_addr(addr) {
+                0x2002 => self.read_ppu_status(),
+                0x2004 => self.ppu.read_oam_data(),
+                0x2007 => {
+                    let mapper = self.cart.mapper.as_mut();
+                    self.ppu.read_data(mapper)
+                }
+                _ => self.ppu.open_bus(),
+            },
+            0x4000..=0x4014 => self.open_bus,
+            // Bit 5 isn't driven.
+            0x4015 => self.apu.read_status() | (self.open_bus & 0x20),
+            0x4016 => {
+                self.controller_read = Some(0);
+                self.joypads[0].read()
+                    | self.joypads[1].microphone_bit()
+                    | self.controller_port_bits(0)
+            }
+            0x4017 => {
+                self.controller_read = Some(1);
+                self.joypads[1].read() | self.controller_port_bits(1)
+            }
+            0x4018..=DISABLED_APU_IO_END => self.open_bus,
+            CARTRIDGE_SPACE_START..=0xFFFF if !self.cart.mapper.maps_read(addr) => self.open_bus,
+            CARTRIDGE_SPACE_START..=0xFFFF => self
+                .cheats
+                .patch_read(addr, self.cart.mapper.read_prg(addr)),
+        };
+        self.open_bus = value;
+        if let Some(debugger) = &mut self.debugger {
+            debugger.access(addr, value, Access::Read);
+        }
+        value
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
+        if let Some(cache) = &mut self.decode_cache {
+            cache.written(addr);
+        }
+        if let Some(debugger) = &mut self.debugger {
+            debugger.access(addr, data, Access::Write);
+        }
+        match addr {
+            0x0000..=CPU_RAM_MIRRORS_END => {
+                self.ram[Self::mirror_ram_addr(addr)] = data;
+            }
+            0x2000..=PPU_REGISTERS_MIRRORS_END => {
+                let reg = self.normalize_ppu_register_addr(addr);
+                self.ppu.drive_io_latch(data, 0xFF);
+
+                // if reg == 0x2000 || reg == 0x2005 || reg == 0x2006 {
+                //     eprintln!(
+                //         "[PPU WRITE] addr={:04X} norm={:04X} data={:02X}",
+                //         addr, reg, data
+                //     );
+                // }
+
+                match reg {
+                    0x2000 => self.ppu.write_to_ctrl(data),
+                    0x2001 => self.ppu.write_to_mask(data),
+                    0x2003 => self.ppu.write_to_oam_addr(data),
+                    0x2004 => self.ppu.write_to_oam_data(data),
+                    0x2005 => self.ppu.write_to_scroll(data),
+                    0x2006 => self.ppu.write_to_ppu_addr(data),
+                    0x2007 => {
+                        if self.ppu.scroll.addr() < 0x2000 {
+                            self.mark_chr_ram_dirty();
+                        }
+                        let mapper = self.cart.mapper.as_mut();
+                        self.ppu.write_to_data(mapper, data);
+                    }
+                    _ => {}
+                }
+            }
+            0x4000..=0x4013 => {
+                self.log_audio_write(addr, data);
+                self.apu.write_register(addr, data);
+            }
+            0x4014 => {
+                if self.accuracy.timed_dma() {
+                    let alignment = self.apu.cycle() & 1;
+                    self.oam_dma = Some(OamDma {
+                        page: data,
+                        cycles_left: 513 + alignment as u16,
+                        value: 0,
+                    });
+                } else {
+                    let mut buffer: [u8; 256] = [0; 256];
+                    let hi: u16 = (data as u16) << 8;
+                    for i in 0..256u16 {
+                        buffer[i as usize] = self.read(hi + i);
+                    }
+                    self.ppu.write_oam_dma(&buffer);
+                }
+            }
+            0x4015 => {
+                self.log_audio_write(addr, data);
+                self.apu.write_status(data);
+            }
+            0x4016 => {
+                self.joypads[0].write(data);
+                self.joypads[1].write(data);
+                self.cart.mapper.write_controller_port(data);
+                self.check_prg_remapped();
+            }
+            0x4017 => {
+                self.log_audio_write(addr, data);
+                self.apu.write_frame_counter(data);
+            }
+            0x4018..=DISABLED_APU_IO_END => {
+                // disabled APU and IO functionality
+            }
+            CARTRIDGE_SPACE_START..=0xFFFF => {
+                let expansion_audio = self.cart.mapper.is_audio_register(addr);
+                if expansion_audio {
+                    self.log_audio_write(addr, data);
+                }
+                self.cart.compat.cart_write(addr, expansion_audio);
+                if !self.cart.mapper.handles_write(addr) {
+                    self.cart.compat.ignored_write(addr, data);
+                }
+                if (0x6000..=0x7FFF).contains(&addr) {
+                    self.mark_prg_ram_dirty();
+                }
+                self.cart.mapper.write_prg(addr, data);
+                self.check_prg_remapped();
+            }
+        }
+    }
+
+    // Game Genie codes patch what code reads, so with any entered nothing is
+    // decoded ahead, and what was is dropped.
+    fn decoded(&mut self, addr: u16) -> Option<Decoded> {
+        if !self.cheats.is_empty() {
+            self.clear_decode_cache();
+            return None;
+        }
+        let cache = self.decode_cache.as_ref()?;
+        if let Some(decoded) = cache.get(addr) {
+            return Some(decoded);
+        }
+        let mut cache = self.decode_cache.take()?;
+        cache.decode_block(addr, |addr| self.peek(addr));
+        let decoded = cache.get(addr);
+        self.decode_cache = Some(cache);
+        decoded
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apu::sink::AudioSink;
+    use crate::cart::test::test_rom;
+    use crate::joypad::JoypadButton;
+
+    #[test]
+    fn test_unmapped_reads_see_the_last_bus_value() {
+        let apu = APU::with_sink(48_000, AudioSink::Null);
+        let mut bus = Bus::new(test_rom(vec![0xA5]), apu);
+        bus.write(0x0000, 0x47);
+        assert_eq!(bus.read(0x4000), 0x47);
+        assert_eq!(bus.read(0x5000), 0x47);
+
+        assert_eq!(bus.read(0x8000), 0xA5);
+        assert_eq!(bus.read(0x4018), 0xA5);
+        // Controllers drive the low bits and the APU all but bit 5.
+        assert_eq!(bus.read(0x4016), 0xA0);
+        assert_eq!(bus.read(0x4015) & 0x20, 0x20);
+        // Write-only PPU registers read back the PPU's own latch, which also
+        // fills the bits $2002 doesn't drive.
+        bus.write(0x2000, 0x00);
+        bus.write(0x0000, 0xFF);
+        assert_eq!(bus.read(0x2005), 0x00);
+        bus.write(0x2003, 0x1F);
+        for reg in [0x2000, 0x2001, 0x2003, 0x2005, 0x2006, 0x3FFB] {
+            assert_eq!(bus.read(reg), 0x1F, "{:04X}", reg);
+        }
+        assert_eq!(bus.read(0x2002) & 0x1F, 0x1F);
+    }
+
+    #[test]
+    fn test_peek_sees_what_a_read_would_without_its_side_effects() {
+        let apu = APU::with_sink(48_000, AudioSink::Null);
+        let mut bus = Bus::new(test_rom(vec![0xEA]), apu);
+        bus.ram[0x10] = 0x5A;
+        bus.ppu.status.set_vblank_status(true);
+        bus.write(0x2003, 0x08);
+        bus.write(0x2004, 0x77);
+        bus.write(0x2003, 0x08);
+        while !bus.apu.frame_irq() {
+            bus.apu.clock();
+        }
+        bus.joypads[0].button_status = JoypadButton::BUTTON_A;
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+
+        for addr in [
+            0x0810, 0x2002, 0x200C, 0x4015, 0x4016, 0x4016, 0x5000, 0x8000,
+        ] {
+            let peeked = bus.peek(addr);
+            assert_eq!(bus.peek(addr), peeked, "{:04X}", addr);
+            assert_eq!(bus.read(addr), peeked, "{:04X}", addr);
+        }
+        // The reads themselves still have theirs.
+        assert!(!bus.ppu.status.is_in_vblank());
+        assert!(!bus.apu.frame_irq());
+        assert_eq!(bus.joypads[0].shift_index(), 2);
+    }
+
+    #[test]
+    fn test_oam_dma_copies_a_byte_every_other_cycle() {
+        let apu = APU::with_sink(48_000, AudioSink::Null);
+        let mut bus = Bus::new(test_rom(vec![0xEA]), apu);
+        for i in 0..256 {
+            bus.write(0x0300 + i, i as u8 ^ 0x80);
+        }
+        bus.write(0x2003, 0x10);
+        bus.write(0x4014, 0x03);
+        let halt = 1 + (bus.apu.cycle() & 1) as usize;
+        let mut cpu = CPU::new();
+
+        for _ in 0..halt + 8 {
+            bus.cpu_clock(&mut cpu);
+        }
+        // Four bytes so far, written through $2004 from OAMADDR on.
+        assert_eq!(bus.ppu.oam_data[0x10..0x15], [0x80, 0x81, 0x82, 0x83, 0]);
+
+        for _ in halt + 8..halt + 511 {
+            bus.cpu_clock(&mut cpu);
+        }
+        assert!(bus.oam_dma.is_some());
+        assert_eq!(bus.ppu.oam_data[0x0F], 0);
+        bus.cpu_clock(&mut cpu);
+        assert!(bus.oam_dma.is_none());
+        assert_eq!(bus.ppu.oam_data[0x0F], 0x7F);
+    }
+
+    #[test]
+    fn test_controller_strobe_latches_both_pads_on_the_falling_edge() {
+        let apu = APU::with_sink(48_000, AudioSink::Null);
+        let mut bus = Bus::new(test_rom(vec![0xEA]), apu);
+        let read = |bus: &mut Bus, addr: u16| bus.read(addr) & 1;
+
+        // While the strobe is high, reads see A as it is now.
+        bus.write(0x4016, 1);
+        bus.joypads[0].button_status = JoypadButton::BUTTON_A;
+        assert_eq!(read(&mut bus, 0x4016), 1);
+        bus.joypads[0].button_status = JoypadButton::BUTTON_B;
+        assert_eq!(read(&mut bus, 0x4016), 0);
+        assert_eq!(bus.joypads[0].shift_index(), 0);
+
+        // Dropping it latches the buttons, so later changes wait for the next
+        // strobe.
+        bus.joypads[1].button_status = JoypadButton::SELECT;
+        bus.write(0x4016, 0);
+        bus.joypads[0].button_status = JoypadButton::BUTTON_A;
+        bus.joypads[1].button_status = JoypadButton::empty();
+        assert_eq!(read(&mut bus, 0x4016), 0);
+        assert_eq!(read(&mut bus, 0x4016), 1);
+
+        // Each pad shifts on its own port's reads, and writing 0 again
+        // latches nothing.
+        bus.write(0x4016, 0);
+        assert_eq!(bus.joypads[0].shift_index(), 2);
+        assert_eq!(bus.joypads[1].shift_index(), 0);
+        let pad2: Vec<u8> = (0..3).map(|_| read(&mut bus, 0x4017)).collect();
+        assert_eq!(pad2, [0, 0, 1]);
+        assert_eq!(bus.joypads[0].shift_index(), 2);
+
+        // A new strobe starts both over from the buttons held now.
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+        assert_eq!(bus.joypads[1].shift_index(), 0);
+        assert_eq!(read(&mut bus, 0x4016), 1);
+        assert_eq!(read(&mut bus, 0x4017), 0);
+    }
+
+    #[test]
+    fn test_dmc_fetch_stalls_the_cpu_and_repeats_a_controller_read() {
+        // LDA $4016, twice.
+        let apu = APU::with_sink(48_000, AudioSink::Null);
+        let mut bus = Bus::new(test_rom(vec![0xAD, 0x16, 0x40, 0xAD, 0x16, 0x40]), apu);
+        bus.joypads[0].button_status = JoypadButton::SELECT;
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+        bus.write(0x4013, 0x01);
+        bus.write(0x4015, 0x10);
+        let mut cpu = CPU::new();
+
+        // The sample fetch lands on the first read's last cycle.
+        for _ in 0..3 {
+            assert!(!bus.cpu_clock(&mut cpu));
+        }
+        assert!(bus.cpu_clock(&mut cpu));
+        assert_eq!(cpu.registers.a & 1, 0);
+        bus.apu_clock();
+
+        for _ in 0..4 {
+            assert!(!bus.cpu_clock(&mut cpu));
+            assert_eq!(cpu.registers.pc, 0x8003);
+        }
+        for _ in 0..4 {
+            bus.cpu_clock(&mut cpu);
+        }
+        // B was shifted out by the repeated read, so Select comes next.
+        assert_eq!(cpu.registers.a & 1, 1);
+    }
+}
@@ -0,0 +1,599 @@
+use crate::compat::{CompatNote, CompatReport};
+use crate::mapper::{
+    BoardInfo, Mapper, NametableLayout, NametablePage, axrom::AxromMapper, cnrom::CnromMapper,
+    discrete, discrete::DiscreteMapper, fxrom::FxromMapper, mmc1::Mmc1Mapper, mmc2::Mmc2Mapper,
+    mmc3::Mmc3Mapper, namco163::Namco163Mapper, nina001, nina001::Nina001Mapper, nrom::NromMapper,
+    nsf::NsfMapper, uxrom::UxromMapper, vs::VsMapper,
+};
+use crate::ppu::render::Renderer;
+use crate::region::Region;
+use crate::rom_db::{Quirks, RomDb, crc32};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+use crate::vs_system::{Console, VsPpu};
+
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const PRG_ROM_PAGE_SIZE: usize = 16384;
+const CHR_ROM_PAGE_SIZE: usize = 8192;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Mirroring {
+    Vertical,
+    Horizontal,
+    FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+impl Mirroring {
+    pub fn nametable_layout(&self) -> NametableLayout {
+        use NametablePage::*;
+        match self {
+            Mirroring::Vertical => [CiramA, CiramB, CiramA, CiramB],
+            Mirroring::Horizontal => [CiramA, CiramA, CiramB, CiramB],
+            Mirroring::FourScreen => [CiramA, CiramB, CartRam(0), CartRam(1)],
+            Mirroring::SingleScreenLower => [CiramA; 4],
+            Mirroring::SingleScreenUpper => [CiramB; 4],
+        }
+    }
+}
+
+// Mappers that switch mirroring at runtime keep it in their state.
+impl Savestate for Mirroring {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(match self {
+            Mirroring::Vertical => 0,
+            Mirroring::Horizontal => 1,
+            Mirroring::FourScreen => 2,
+            Mirroring::SingleScreenLower => 3,
+            Mirroring::SingleScreenUpper => 4,
+        });
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        *self = match state.read_u8()? {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::FourScreen,
+            3 => Mirroring::SingleScreenLower,
+            4 => Mirroring::SingleScreenUpper,
+            other => return Err(format!("Invalid mirroring {} in savestate", other)),
+        };
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RomFormat {
+    INes,
+    Nes2,
+    // An NSF music rip, which has no cartridge header; see `nsf::NsfPlayer`.
+    Nsf,
+}
+
+#[derive(Debug, Clone)]
+pub struct Nes2Data {
+    pub submapper: u8,
+    pub console_type: u8,
+    pub timing: u8,
+    pub prg_ram_size: usize,
+    pub chr_ram_size: usize,
+    // The part of CHR RAM kept by the battery.
+    pub chr_nvram_size: usize,
+    pub misc_rom_count: u8,
+    pub default_expansion_device: u8,
+}
+
+fn calculate_nes2_prg_size(lsb: u8, msb: u8) -> usize {
+    let msb_nibble = (msb >> 4) & 0x0F;
+    if msb_nibble == 0x0F {
+        // Exponent-multiplier notation
+        let multiplier = ((msb & 0x03) * 2) + 1;
+        let exponent = (msb >> 2) & 0x3F;
+        (2u64.pow(exponent as u32) * multiplier as u64) as usize
+    } else {
+        // Simple notation: (MSB << 8) | LSB in 16 KiB units
+        (((msb_nibble as usize) << 8) | (lsb as usize)) * PRG_ROM_PAGE_SIZE
+    }
+}
+
+fn calculate_nes2_chr_size(lsb: u8, msb: u8) -> usize {
+    let msb_nibble = msb & 0x0F;
+    if msb_nibble == 0x0F {
+        // Exponent-multiplier notation
+        let multiplier = ((lsb & 0x03) * 2) + 1;
+        let exponent = (lsb >> 2) & 0x3F;
+        (2u64.pow(exponent as u32) * multiplier as u64) as usize
+    } else {
+        // Simple notation: (MSB << 8) | LSB in 8 KiB units
+        (((msb_nibble as usize) << 8) | (lsb as usize)) * CHR_ROM_PAGE_SIZE
+    }
+}
+
+fn calculate_ram_size(shift_count: u8) -> usize {
+    if shift_count == 0 {
+        0
+    } else {
+        64 << shift_count
+    }
+}
+
+pub struct Cart {
+    pub mapper: Box<dyn Mapper>,
+    pub screen_mirroring: Mirroring,
+    pub format: RomFormat,
+    pub nes2_data: Option<Nes2Data>,
+    pub console: Console,
+    pub compat: CompatReport,
+    // CRC32 of PRG and CHR, the key into the ROM database.
+    pub crc32: u32,
+    pub quirks: Quirks,
+    // TV system the ROM was made for, from its NES 2.0 header or the ROM
+    // database. Plain iNES ROMs are assumed to be NTSC.
+    pub region: Region,
+    // Renderer the ROM database picks for this game, if any.
+    pub renderer: Option<Renderer>,
+    // PRG RAM is kept by a battery, so it belongs in a save file.
+    pub battery: bool,
+    // So is CHR RAM, which only NES 2.0 headers can say.
+    pub chr_battery: bool,
+}
+
+// Submappers that the mapper implementations tell apart from submapper 0.
+fn submapper_supported(mapper: u8, submapper: u8) -> bool {
+    match mapper {
+        1 => submapper == 5,
+        2 | 3 | 7 | 34 => matches!(submapper, 1 | 2),
+        4 => submapper == 4,
+        _ => false,
+    }
+}
+
+impl Cart {
+    pub fn new(raw: &Vec<u8>) -> Result<Cart, String> {
+        Cart::with_rom_db(raw, RomDb::builtin())
+    }
+
+    pub fn with_rom_db(raw: &[u8], rom_db: &RomDb) -> Result<Cart, String> {
+        if raw.len() < 16 || raw[0..4] != NES_TAG {
+            return Err("File is not in iNES file format".to_string());
+        }
+
+        // Check for NES 2.0 format: header[7] bits 2 and 3 set to 1 and 0 respectively
+        let format = if (raw[7] & 0x0C) == 0x08 {
+            RomFormat::Nes2
+        } else {
+            RomFormat::INes
+        };
+
+        let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
+
+        // For iNES, ensure version is 0
+        if let RomFormat::INes = format {
+            let ines_ver = (raw[7] >> 2) & 0b11;
+            if ines_ver != 0 {
+                return Err("Invalid iNES format version".to_string());
+            }
+        }
+
+        let four_screen = raw[6] & 0b1000 != 0;
+        let vertical_mirroring = raw[6] & 0b1 != 0;
+        let mut screen_mirroring = match (four_screen, vertical_mirroring) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+
+        let (prg_rom_size, chr_rom_size) = match format {
+            RomFormat::Nes2 => (
+                calculate_nes2_prg_size(raw[4], raw[9]),
+                calculate_nes2_chr_size(raw[5], raw[9]),
+            ),
+            _ => (
+                raw[4] as usize * PRG_ROM_PAGE_SIZE,
+                raw[5] as usize * CHR_ROM_PAGE_SIZE,
+            ),
+        };
+
+        let battery = raw[6] & 0b10 != 0;
+        let skip_trainer = raw[6] & 0b100 != 0;
+
+        let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
+        let chr_rom_start = prg_rom_start + prg_rom_size;
+
+        if raw.len() < chr_rom_start + chr_rom_size {
+            return Err(format!(
+                "ROM is truncated: expected {} bytes, found {}",
+                chr_rom_start + chr_rom_size,
+                raw.len()
+            ));
+        }
+
+        let prg_rom = raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec();
+        let chr_rom = raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec();
+
+        let crc32 = crc32(&raw[prg_rom_start..chr_rom_start + chr_rom_size]);
+        let renderer = rom_db.lookup(crc32).and_then(|entry| entry.renderer);
+        let quirks = match rom_db.lookup(crc32) {
+            Some(entry) => {
+                log::info!(
+                    "Applying quirks for {} ({:08X}): {}",
+                    entry.name,
+                    crc32,
+                    entry.quirks.quirk_names().join(", ")
+                );
+                entry.quirks
+            }
+            None => Quirks::empty(),
+        };
+        if quirks.contains(Quirks::FOUR_SCREEN) {
+            screen_mirroring = Mirroring::FourScreen;
+        }
+
+        let nes2_data = if let RomFormat::Nes2 = format {
+            Some(Nes2Data {
+                submapper: raw[8] >> 4,
+                console_type: raw[7] & 0x03,
+                timing: raw[12],
+                prg_ram_size: calculate_ram_size(raw[10] & 0x0F) + calculate_ram_size(raw[10] >> 4),
+                chr_ram_size: calculate_ram_size(raw[11] & 0x0F) + calculate_ram_size(raw[11] >> 4),
+                chr_nvram_size: calculate_ram_size(raw[11] >> 4),
+                misc_rom_count: raw[14] & 0x03,
+                default_expansion_device: raw[15],
+            })
+        } else {
+            None
+        };
+
+        // NES 2.0 console type 3 is for clones and other extended consoles,
+        // which are run as an NES.
+        let console = match (&nes2_data, raw[7] & 0x03) {
+            (Some(_), 1) => Console::VsSystem(VsPpu::from_nes2(raw[13])),
+            // iNES has no field for the PPU, so assume the common one.
+            (None, 1) => Console::VsSystem(VsPpu::Rp2c03),
+            (_, 2) => Console::PlayChoice10,
+            _ => Console::Nes,
+        };
+
+        let region = match &nes2_data {
+            Some(data) => Region::from_nes2_timing(data.timing),
+            None if quirks.contains(Quirks::PAL) => Region::Pal,
+            None => Region::Ntsc,
+        };
+
+        log::info!("Mapper: {mapper}");
+
+        let chr_battery = chr_rom.is_empty()
+            && nes2_data
+                .as_ref()
+                .is_some_and(|data| data.chr_nvram_size > 0);
+
+        let board = match &nes2_data {
+            Some(data) => BoardInfo {
+                submapper: data.submapper,
+                prg_ram_size: Some(data.prg_ram_size),
+                chr_ram_size: Some(data.chr_ram_size),
+            },
+            None => BoardInfo::default(),
+        };
+
+        let mut compat = CompatReport::new(mapper);
+        if board.submapper != 0 && !submapper_supported(mapper, board.submapper) {
+            compat.note(CompatNote::UnsupportedSubmapper {
+                submapper: board.submapper,
+            });
+        }
+        if let Console::VsSystem(VsPpu::Rp2c04(model)) = console {
+            compat.note(CompatNote::VsPalette { model });
+        }
+        // Discrete boards say in the submapper whether they have bus conflicts.
+        let bus_conflicts = quirks.contains(Quirks::BUS_CONFLICTS)
+            || (matches!(mapper, 2 | 3 | 7) && board.submapper == 2);
+
+        let mut mapper: Box<dyn Mapper> = match mapper {
+            0 => Box::new(NromMapper::new(prg_rom, chr_rom, screen_mirroring.clone())),
+            1 => Box::new(Mmc1Mapper::with_board(
+                prg_rom,
+                chr_rom,
+                screen_mirroring.clone(),
+                &board,
+            )),
+            2 => Box::new(UxromMapper::new(prg_rom, chr_rom, screen_mirroring.clone())),
+            3 => Box::new(CnromMapper::new(prg_rom, chr_rom, screen_mirroring.clone())),
+            4 => Box::new(Mmc3Mapper::with_board(
+                prg_rom,
+                chr_rom,
+                screen_mirroring.clone(),
+                &board,
+            )),
+            7 => Box::new(AxromMapper::new(prg_rom, chr_rom)),
+            9 => Box::new(Mmc2Mapper::new(prg_rom, chr_rom, screen_mirroring.clone())),
+            10 => Box::new(FxromMapper::new(prg_rom, chr_rom, screen_mirroring.clone())),
+            19 => Box::new(Namco163Mapper::new(
+                prg_rom,
+                chr_rom,
+                screen_mirroring.clone(),
+            )),
+            31 => Box::new(NsfMapper::new(prg_rom, chr_rom, screen_mirroring.clone())),
+            34 if nina001::detected(&board, chr_rom.len()) => Box::new(Nina001Mapper::new(
+                prg_rom,
+                chr_rom,
+                screen_mirroring.clone(),
+            )),
+            99 => Box::new(VsMapper::new(prg_rom, chr_rom, screen_mirroring.clone())),
+            _ => match discrete::board(mapper) {
+                Some(board) => Box::new(DiscreteMapper::new(
+                    board,
+                    prg_rom,
+                    chr_rom,
+                    screen_mirroring.clone(),
+                )),
+                None => return Err(format!("Mapper {} not supported", mapper)),
+            },
+        };
+
+        if bus_conflicts {
+            mapper.set_bus_conflicts(true);
+        }
+
+        Ok(Cart {
+            mapper,
+            screen_mirroring,
+            format,
+            nes2_data,
+            console,
+            compat,
+            crc32,
+            quirks,
+            region,
+            renderer,
+            battery,
+            chr_battery,
+        })
+    }
+
+    pub fn empty() -> Cart {
+        Cart {
+            mapper: Box::new(NromMapper::new(vec![], vec![], Mirroring::Vertical)),
+            screen_mirroring: Mirroring::Vertical,
+            format: RomFormat::INes,
+            nes2_data: None,
+            console: Console::Nes,
+            compat: CompatReport::new(0),
+            crc32: 0,
+            quirks: Quirks::empty(),
+            region: Region::Ntsc,
+            renderer: None,
+            battery: false,
+            chr_battery: false,
+        }
+    }
+
+    // Whether anything on the cartridge outlives power off.
+    pub fn has_battery_save(&self) -> bool {
+        self.battery || self.chr_battery
+    }
+}
+
+pub mod test {
+
+    use super::*;
+
+    struct TestRom {
+        header: Vec<u8>,
+        trainer: Option<Vec<u8>>,
+        pgp_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+    }
+
+    fn create_rom(rom: TestRom) -> Vec<u8> {
+        let mut result = Vec::with_capacity(
+            rom.header.len()
+                + rom.trainer.as_ref().map_or(0, |t| t.len())
+                + rom.pgp_rom.len()
+                + rom.chr_rom.len(),
+        );
+
+        result.extend(&rom.header);
+        if let Some(t) = rom.trainer {
+            result.extend(t);
+        }
+        result.extend(&rom.pgp_rom);
+        result.extend(&rom.chr_rom);
+
+        result
+    }
+
+    pub fn test_rom(program: Vec<u8>) -> Cart {
+        let mut pgp_rom_contents = program;
+        pgp_rom_contents.resize(2 * PRG_ROM_PAGE_SIZE, 0);
+
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: pgp_rom_contents,
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        Cart::new(&test_rom).unwrap()
+    }
+
+    #[test]
+    fn test() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom: Cart = Cart::new(&test_rom).unwrap();
+
+        // assert_eq!(rom.chr_rom, vec!(2; 1 * CHR_ROM_PAGE_SIZE));
+        // assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
+        // assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_with_trainer() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E,
+                0x45,
+                0x53,
+                0x1A,
+                0x02,
+                0x01,
+                0x31 | 0b100,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+            ],
+            trainer: Some(vec![0; 512]),
+            pgp_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom: Cart = Cart::new(&test_rom).unwrap();
+
+        // assert_eq!(rom.chr_rom, vec!(2; 1 * CHR_ROM_PAGE_SIZE));
+        // assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
+        // assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_rom_db_quirks_are_applied() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+        let crc = crc32(&test_rom[16..]);
+        let rom_db = RomDb::from_toml(&format!(
+            "[[rom]]\ncrc32 = \"{:08X}\"\nname = \"Test\"\nquirks = [\"four_screen\", \"pal\"]",
+            crc
+        ))
+        .unwrap();
+
+        let cart = Cart::with_rom_db(&test_rom, &rom_db).unwrap();
+        assert_eq!(cart.crc32, crc);
+        assert_eq!(cart.quirks, Quirks::FOUR_SCREEN | Quirks::PAL);
+        assert_eq!(cart.screen_mirroring, Mirroring::FourScreen);
+        assert_eq!(cart.region, Region::Pal);
+
+        let cart = Cart::new(&test_rom).unwrap();
+        assert_eq!(cart.quirks, Quirks::empty());
+        assert_eq!(cart.screen_mirroring, Mirroring::Horizontal);
+        assert_eq!(cart.region, Region::Ntsc);
+    }
+
+    #[test]
+    fn test_nes2_is_supported() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x01, 0x8, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![1; 1 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; 1 * CHR_ROM_PAGE_SIZE],
+        });
+        let rom = Cart::new(&test_rom);
+        match rom {
+            Result::Ok(cart) => {
+                assert_eq!(cart.format, RomFormat::Nes2);
+                assert!(cart.nes2_data.is_some());
+            }
+            Result::Err(_) => assert!(false, "should load NES 2.0 rom"),
+        }
+    }
+
+    #[test]
+    fn test_nes2_timing_selects_region() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x01, 0x8, 00, 00, 00, 00, 0x03, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+        assert_eq!(Cart::new(&test_rom).unwrap().region, Region::Dendy);
+    }
+
+    #[test]
+    fn test_console_type_is_parsed() {
+        let rom = |flags7: u8, byte13: u8| {
+            let test_rom = create_rom(TestRom {
+                header: vec![
+                    0x4E, 0x45, 0x53, 0x1A, 0x02, 0x02, 0x00, flags7, 00, 00, 00, 00, 00, byte13,
+                    00, 00,
+                ],
+                trainer: None,
+                pgp_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+                chr_rom: vec![2; 2 * CHR_ROM_PAGE_SIZE],
+            });
+            Cart::new(&test_rom).unwrap()
+        };
+        assert_eq!(rom(0x00, 0x00).console, Console::Nes);
+        assert_eq!(rom(0x01, 0x00).console, Console::VsSystem(VsPpu::Rp2c03));
+        assert_eq!(rom(0x02, 0x00).console, Console::PlayChoice10);
+        assert_eq!(
+            rom(0x09, 0x0A).console,
+            Console::VsSystem(VsPpu::Rc2c05(0x1C))
+        );
+
+        let cart = rom(0x09, 0x04);
+        assert_eq!(cart.console, Console::VsSystem(VsPpu::Rp2c04(3)));
+        assert_eq!(cart.compat.notes(), &[CompatNote::VsPalette { model: 3 }]);
+    }
+
+    #[test]
+    fn test_battery_backed_chr_ram_needs_chr_nvram() {
+        let rom = |chr_banks: u8, byte11: u8| {
+            let test_rom = create_rom(TestRom {
+                header: vec![
+                    0x4E, 0x45, 0x53, 0x1A, 0x02, chr_banks, 0x00, 0x08, 00, 00, 00, byte11, 00,
+                    00, 00, 00,
+                ],
+                trainer: None,
+                pgp_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+                chr_rom: vec![2; chr_banks as usize * CHR_ROM_PAGE_SIZE],
+            });
+            Cart::new(&test_rom).unwrap()
+        };
+        let cart = rom(0, 0x70);
+        assert!(cart.chr_battery && cart.has_battery_save());
+        assert_eq!(cart.mapper.chr_data().len(), 0x2000);
+        assert!(!rom(0, 0x07).chr_battery);
+        assert!(!rom(1, 0x70).chr_battery);
+    }
+
+    #[test]
+    fn test_short_files_are_rejected() {
+        let mut test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+        test_rom.truncate(test_rom.len() - 1);
+        assert!(Cart::new(&test_rom).is_err());
+        assert!(Cart::new(&b"NES\x1A".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+// Game Genie letters, in the order of the nibble each one stands for.
+const GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatEffect {
+    // Game Genie: the CPU reads `value` from ROM at `addr` instead. 8-letter
+    // codes only do so while the ROM holds `compare` there, so they leave
+    // other banks mapped to the same address alone. Raw codes on a ROM
+    // address patch it too, as "AAAA:VV" or "AAAA?CC:VV" with a compare.
+    Patch {
+        addr: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    // Pro Action Replay style "AAAA:VV": `value` is written to RAM at `addr`
+    // at the end of every frame, freezing it.
+    Freeze {
+        addr: u16,
+        value: u8,
+    },
+}
+
+impl CheatEffect {
+    pub fn decode(code: &str) -> Result<CheatEffect, String> {
+        let code = code.trim().to_ascii_uppercase();
+        if let Some((target, value)) = code.split_once(':') {
+            return decode_raw(&code, target, value);
+        }
+
+        let n = code
+            .chars()
+            .map(|letter| GENIE_LETTERS.find(letter).map(|nibble| nibble as u16))
+            .collect::<Option<Vec<u16>>>()
+            .ok_or_else(|| format!("Invalid Game Genie code {}", code))?;
+        if n.len() != 6 && n.len() != 8 {
+            return Err(format!("Game Genie code {} is not 6 or 8 letters", code));
+        }
+
+        let addr = 0x8000
+            | ((n[3] & 7) << 12)
+            | ((n[5] & 7) << 8)
+            | ((n[4] & 8) << 8)
+            | ((n[2] & 7) << 4)
+            | ((n[1] & 8) << 4)
+            | (n[4] & 7)
+            | (n[3] & 8);
+        // The last letter's high bit goes to the value on 6-letter codes and
+        // to the compare value on 8-letter ones.
+        let last = n[n.len() - 1];
+        let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (last & 8);
+        let compare = (n.len() == 8)
+            .then(|| ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8))
+            .map(|compare| compare as u8);
+        Ok(CheatEffect::Patch {
+            addr,
+            value: value as u8,
+            compare,
+        })
+    }
+}
+
+fn decode_raw(code: &str, target: &str, value: &str) -> Result<CheatEffect, String> {
+    let (addr, compare) = match target.split_once('?') {
+        Some((addr, compare)) => (addr, Some(compare)),
+        None => (target, None),
+    };
+    let addr =
+        u16::from_str_radix(addr, 16).map_err(|_| format!("Invalid address in cheat {}", code))?;
+    let value =
+        u8::from_str_radix(value, 16).map_err(|_| format!("Invalid value in cheat {}", code))?;
+    let compare = compare
+        .map(|compare| u8::from_str_radix(compare, 16))
+        .transpose()
+        .map_err(|_| format!("Invalid compare value in cheat {}", code))?;
+    match (addr, compare) {
+        (0x8000..=0xFFFF, _) => Ok(CheatEffect::Patch {
+            addr,
+            value,
+            compare,
+        }),
+        (0x0000..=0x1FFF | 0x6000..=0x7FFF, None) => Ok(CheatEffect::Freeze { addr, value }),
+        (0x0000..=0x1FFF | 0x6000..=0x7FFF, Some(_)) => Err(format!(
+            "Cheat {} compares a RAM address, which only works on ROM",
+            code
+        )),
+        _ => Err(format!("Cheat {} is not a RAM or ROM address", code)),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub code: String,
+    pub name: String,
+    pub enabled: bool,
+    pub effect: CheatEffect,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct RawCheats {
+    #[serde(default)]
+    cheat: Vec<RawCheat>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct RawCheat {
+    code: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+// The cheats for one ROM. Kept out of savestates, so loading a state never
+// turns codes on or off.
+#[derive(Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+    // Enabled Game Genie codes, checked on every PRG read.
+    patches: Vec<(u16, u8, Option<u8>)>,
+}
+
+impl Cheats {
+    pub fn from_toml(text: &str) -> Result<Cheats, String> {
+        let raw: RawCheats =
+            toml::from_str(text).map_err(|e| format!("Failed to parse cheats: {}", e))?;
+        let mut cheats = Cheats::default();
+        for raw in raw.cheat {
+            cheats.add(&raw.code, &raw.name)?;
+            cheats.set_enabled(cheats.cheats.len() - 1, raw.enabled);
+        }
+        Ok(cheats)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let raw = RawCheats {
+            cheat: self
+                .cheats
+                .iter()
+                .map(|cheat| RawCheat {
+                    code: cheat.code.clone(),
+                    name: cheat.name.clone(),
+                    enabled: cheat.enabled,
+                })
+                .collect(),
+        };
+        toml::to_string_pretty(&raw).expect("cheats always serialize")
+    }
+
+    pub fn load(path: &Path) -> Result<Cheats, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read cheats {}: {}", path.display(), e))?;
+        Cheats::from_toml(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_toml())
+            .map_err(|e| format!("Failed to write cheats {}: {}", path.display(), e))
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // New cheats start enabled.
+    pub fn add(&mut self, code: &str, name: &str) -> Result<(), String> {
+        let effect = CheatEffect::decode(code)?;
+        self.cheats.push(Cheat {
+            code: code.trim().to_ascii_uppercase(),
+            name: name.to_string(),
+            enabled: true,
+            effect,
+        });
+        self.update_patches();
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = enabled;
+            self.update_patches();
+        }
+    }
+
+    fn update_patches(&mut self) {
+        self.patches = self
+            .cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.effect {
+                CheatEffect::Patch {
+                    addr,
+                    value,
+                    compare,
+                } => Some((addr, value, compare)),
+                CheatEffect::Freeze { .. } => None,
+            })
+            .collect();
+    }
+
+    // What the CPU sees when it reads `value` from ROM at `addr`.
+    pub fn patch_read(&self, addr: u16, value: u8) -> u8 {
+        for &(patch_addr, patch_value, compare) in &self.patches {
+            if patch_addr == addr && compare.is_none_or(|compare| compare == value) {
+                return patch_value;
+            }
+        }
+        value
+    }
+
+    // RAM writes for the end of a frame.
+    pub fn freezes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.effect {
+                CheatEffect::Freeze { addr, value } => Some((addr, value)),
+                CheatEffect::Patch { .. } => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decodes_game_genie_codes() {
+        // Super Mario Bros. infinite lives.
+        assert_eq!(
+            CheatEffect::decode("SXIOPO"),
+            Ok(CheatEffect::Patch {
+                addr: 0x91D9,
+                value: 0xAD,
+                compare: None
+            })
+        );
+        assert_eq!(
+            CheatEffect::decode("gxvuzgvg"),
+            Ok(CheatEffect::Patch {
+                addr: 0xB4EA,
+                value: 0x24,
+                compare: Some(0xC6)
+            })
+        );
+        assert_eq!(
+            CheatEffect::decode("0075:09"),
+            Ok(CheatEffect::Freeze {
+                addr: 0x0075,
+                value: 0x09
+            })
+        );
+        assert_eq!(
+            CheatEffect::decode("91d9:ad"),
+            Ok(CheatEffect::Patch {
+                addr: 0x91D9,
+                value: 0xAD,
+                compare: None
+            })
+        );
+        assert_eq!(
+            CheatEffect::decode("B4EA?C6:24"),
+            CheatEffect::decode("GXVUZGVG")
+        );
+        assert!(CheatEffect::decode("0075?01:09").is_err());
+        assert!(CheatEffect::decode("B4EA?:24").is_err());
+        assert!(CheatEffect::decode("SXIOP").is_err());
+        assert!(CheatEffect::decode("SXIOPB").is_err());
+        assert!(CheatEffect::decode("2002:00").is_err());
+    }
+
+    #[test]
+    fn test_compare_codes_only_patch_the_matching_bank() {
+        let mut cheats = Cheats::default();
+        cheats.add("GXVUZGVG", "").unwrap();
+        assert_eq!(cheats.patch_read(0xB4EA, 0xC6), 0x24);
+        assert_eq!(cheats.patch_read(0xB4EA, 0x12), 0x12);
+        assert_eq!(cheats.patch_read(0xB4EB, 0xC6), 0xC6);
+
+        cheats.set_enabled(0, false);
+        assert_eq!(cheats.patch_read(0xB4EA, 0xC6), 0xC6);
+    }
+
+    #[test]
+    fn test_cheat_file_round_trips() {
+        let text = r#"
+            [[cheat]]
+            code = "SXIOPO"
+            name = "Infinite lives"
+
+            [[cheat]]
+            code = "0075:09"
+            enabled = false
+        "#;
+        let cheats = Cheats::from_toml(text).unwrap();
+        assert_eq!(cheats.cheats().len(), 2);
+        assert!(cheats.cheats()[0].enabled);
+        assert!(!cheats.cheats()[1].enabled);
+        assert_eq!(cheats.freezes().count(), 0);
+
+        let again = Cheats::from_toml(&cheats.to_toml()).unwrap();
+        assert_eq!(again.cheats(), cheats.cheats());
+    }
+}
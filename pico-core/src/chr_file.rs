@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use crate::mapper::Mapper;
+
+const PATTERN_TABLES_SIZE: usize = 0x2000;
+
+// The 8 KiB of CHR currently banked into $0000-$1FFF.
+pub fn visible_chr(mapper: &dyn Mapper) -> Vec<u8> {
+    (0..PATTERN_TABLES_SIZE as u16)
+        .map(|addr| mapper.peek_chr(addr))
+        .collect()
+}
+
+pub fn export_visible<P: AsRef<Path>>(mapper: &dyn Mapper, path: P) -> Result<(), String> {
+    std::fs::write(path, visible_chr(mapper)).map_err(|e| format!("Failed to write CHR: {}", e))
+}
+
+pub fn export_full<P: AsRef<Path>>(mapper: &dyn Mapper, path: P) -> Result<(), String> {
+    let chr = mapper.chr_data();
+    if chr.is_empty() {
+        return Err("Cartridge has no CHR data".to_string());
+    }
+    std::fs::write(path, chr).map_err(|e| format!("Failed to write CHR: {}", e))
+}
+
+pub fn import<P: AsRef<Path>>(mapper: &mut dyn Mapper, path: P) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read CHR: {}", e))?;
+    import_bytes(mapper, &data)
+}
+
+// A file the size of the whole CHR RAM replaces all of it; an 8 KiB file is
+// written through the current banking, matching what `export_visible` saved.
+pub fn import_bytes(mapper: &mut dyn Mapper, data: &[u8]) -> Result<(), String> {
+    let Some(chr_ram) = mapper.chr_ram_mut() else {
+        return Err("Cartridge uses CHR ROM, only CHR RAM can be replaced".to_string());
+    };
+
+    let ram_size = chr_ram.len();
+    if data.len() == ram_size {
+        chr_ram.copy_from_slice(data);
+    } else if data.len() == PATTERN_TABLES_SIZE {
+        for (addr, &byte) in data.iter().enumerate() {
+            mapper.write_chr(addr as u16, byte);
+        }
+    } else {
+        return Err(format!(
+            "CHR file is {} bytes, expected {} or {}",
+            data.len(),
+            ram_size,
+            PATTERN_TABLES_SIZE
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cart::Mirroring;
+    use crate::mapper::nrom::NromMapper;
+
+    #[test]
+    fn test_import_replaces_chr_ram_only() {
+        let mut mapper = NromMapper::new(vec![0; 0x8000], vec![], Mirroring::Vertical);
+        let tiles: Vec<u8> = (0..PATTERN_TABLES_SIZE).map(|i| i as u8).collect();
+        import_bytes(&mut mapper, &tiles).unwrap();
+        assert_eq!(visible_chr(&mapper), tiles);
+        assert!(import_bytes(&mut mapper, &[0; 16]).is_err());
+
+        let mut mapper = NromMapper::new(vec![0; 0x8000], vec![0; 0x2000], Mirroring::Vertical);
+        assert!(import_bytes(&mut mapper, &tiles).is_err());
+    }
+}
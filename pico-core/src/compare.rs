@@ -0,0 +1,162 @@
+// Runs two consoles in lockstep on the same input and finds the first frame
+// where they stop agreeing, e.g. the fast and accurate profiles, or this build
+// against an older one wrapped up as a `ComparedConsole`.
+
+use std::fmt;
+
+use crate::headless::Headless;
+use crate::movie::FM2Movie;
+
+// What the comparison needs from a console. Kept to plain data so a console
+// from another build can sit behind it.
+pub trait ComparedConsole {
+    // Runs one frame with the movie's input for it, or no input without a
+    // movie. Returns false once the movie has run out.
+    fn run_frame(&mut self, movie: Option<&FM2Movie>) -> bool;
+    // RGB24, 256x240.
+    fn framebuffer(&self) -> &[u8];
+    // The 2 KiB of CPU RAM.
+    fn ram(&self) -> &[u8];
+    fn cpu_cycles(&self) -> u64;
+}
+
+impl ComparedConsole for Headless {
+    fn run_frame(&mut self, movie: Option<&FM2Movie>) -> bool {
+        match movie {
+            Some(movie) => self.run_movie_frame(movie).is_some(),
+            None => {
+                Headless::run_frame(self);
+                true
+            }
+        }
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        &Headless::framebuffer(self).data
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.nes.bus.ram
+    }
+
+    fn cpu_cycles(&self) -> u64 {
+        self.nes.emulated_time().cpu_cycles
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Difference {
+    Ram { addr: u16, a: u8, b: u8 },
+    Pixel { x: usize, y: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    // 0-based frame after which the consoles differed.
+    pub frame: usize,
+    // CPU cycles each console had run when that frame ended.
+    pub cpu_cycles: (u64, u64),
+    pub difference: Difference,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Diverged after frame {} (CPU cycle {} vs {}): ",
+            self.frame, self.cpu_cycles.0, self.cpu_cycles.1
+        )?;
+        match self.difference {
+            Difference::Ram { addr, a, b } => {
+                write!(f, "RAM ${:04X} is {:02X} vs {:02X}", addr, a, b)
+            }
+            Difference::Pixel { x, y } => write!(f, "pixel ({}, {}) differs", x, y),
+        }
+    }
+}
+
+// Runs both consoles for up to `frames` frames, or until the movie ends, and
+// returns the first difference. RAM is checked before the picture since it
+// usually goes wrong first and says more.
+pub fn compare(
+    a: &mut dyn ComparedConsole,
+    b: &mut dyn ComparedConsole,
+    movie: Option<&FM2Movie>,
+    frames: usize,
+) -> Option<Divergence> {
+    for frame in 0..frames {
+        if !a.run_frame(movie) || !b.run_frame(movie) {
+            return None;
+        }
+
+        let difference = first_ram_difference(a.ram(), b.ram())
+            .or_else(|| first_pixel_difference(a.framebuffer(), b.framebuffer()));
+        if let Some(difference) = difference {
+            return Some(Divergence {
+                frame,
+                cpu_cycles: (a.cpu_cycles(), b.cpu_cycles()),
+                difference,
+            });
+        }
+    }
+    None
+}
+
+fn first_ram_difference(a: &[u8], b: &[u8]) -> Option<Difference> {
+    a.iter()
+        .zip(b)
+        .position(|(a, b)| a != b)
+        .map(|addr| Difference::Ram {
+            addr: addr as u16,
+            a: a[addr],
+            b: b[addr],
+        })
+}
+
+fn first_pixel_difference(a: &[u8], b: &[u8]) -> Option<Difference> {
+    a.chunks(3)
+        .zip(b.chunks(3))
+        .position(|(a, b)| a != b)
+        .map(|pixel| Difference::Pixel {
+            x: pixel % 256,
+            y: pixel / 256,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cart::test::test_rom;
+    use crate::nes::AccuracyProfile;
+
+    // Starts an OAM DMA every loop and counts loops in $10.
+    fn dma_loop(accuracy: AccuracyProfile) -> Headless {
+        let mut program = vec![0x8D, 0x14, 0x40, 0xE8, 0x86, 0x10, 0x4C, 0x00, 0x80];
+        program.resize(0x8000, 0);
+        program[0x7FFC] = 0x00;
+        program[0x7FFD] = 0x80;
+        let mut headless = Headless::new(test_rom(program));
+        headless.nes.set_accuracy(accuracy);
+        headless
+    }
+
+    #[test]
+    fn test_identical_consoles_never_diverge() {
+        let mut a = dma_loop(AccuracyProfile::Balanced);
+        let mut b = dma_loop(AccuracyProfile::Balanced);
+        assert_eq!(compare(&mut a, &mut b, None, 5), None);
+        assert_eq!(a.frame(), 5);
+    }
+
+    #[test]
+    fn test_dma_timing_shows_up_in_ram() {
+        let mut a = dma_loop(AccuracyProfile::Fast);
+        let mut b = dma_loop(AccuracyProfile::Balanced);
+        let divergence = compare(&mut a, &mut b, None, 5).unwrap();
+        assert_eq!(divergence.frame, 0);
+        assert!(matches!(
+            divergence.difference,
+            Difference::Ram { addr: 0x10, .. }
+        ));
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashSet;
+use std::fmt;
+
+// Distinct ignored addresses worth listing before the report stops growing.
+const MAX_IGNORED_WRITES: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompatNote {
+    // A write to cartridge space that the mapper implementation does nothing with.
+    IgnoredWrite { addr: u16, value: u8 },
+    // NES 2.0 submapper the mapper implementation doesn't distinguish.
+    UnsupportedSubmapper { submapper: u8 },
+    // Vs. System PPU whose colour lookup table isn't emulated, 1 to 4 for
+    // RP2C04-0001 to -0004.
+    VsPalette { model: u8 },
+}
+
+impl fmt::Display for CompatNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompatNote::IgnoredWrite { addr, value } => {
+                write!(f, "ignored write ${:04X} = ${:02X}", addr, value)
+            }
+            CompatNote::UnsupportedSubmapper { submapper } => {
+                write!(f, "submapper {} treated as submapper 0", submapper)
+            }
+            CompatNote::VsPalette { model } => {
+                write!(f, "RP2C04-000{} palette not emulated", model)
+            }
+        }
+    }
+}
+
+// What can ask the CPU for an interrupt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrqSource {
+    FrameCounter,
+    Dmc,
+    Mapper,
+}
+
+impl IrqSource {
+    pub const ALL: [IrqSource; 3] = [IrqSource::FrameCounter, IrqSource::Dmc, IrqSource::Mapper];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IrqSource::FrameCounter => "frame_counter",
+            IrqSource::Dmc => "dmc",
+            IrqSource::Mapper => "mapper",
+        }
+    }
+
+    fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+// Things the current ROM does that the emulator silently doesn't handle, so
+// they can be pasted into bug reports, and which parts of the console it
+// used, to tell which bugs and missing features matter most.
+pub struct CompatReport {
+    mapper: u8,
+    notes: Vec<CompatNote>,
+    ignored_addrs: HashSet<u16>,
+    // Writes to each 4 KiB page of cartridge space, from $4000.
+    cart_writes: [u64; 12],
+    expansion_audio_writes: u64,
+    irq_sources: u8,
+}
+
+impl CompatReport {
+    pub fn new(mapper: u8) -> Self {
+        CompatReport {
+            mapper,
+            notes: Vec::new(),
+            ignored_addrs: HashSet::new(),
+            cart_writes: [0; 12],
+            expansion_audio_writes: 0,
+            irq_sources: 0,
+        }
+    }
+
+    pub fn mapper(&self) -> u8 {
+        self.mapper
+    }
+
+    pub fn notes(&self) -> &[CompatNote] {
+        &self.notes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    // Each note is only kept the first time it happens. The console raises
+    // new ones as `EmulatorEvent::Compat` for the frontend to show.
+    pub fn note(&mut self, note: CompatNote) {
+        if self.notes.contains(&note) {
+            return;
+        }
+        self.notes.push(note);
+    }
+
+    pub fn ignored_write(&mut self, addr: u16, value: u8) {
+        if self.ignored_addrs.len() >= MAX_IGNORED_WRITES || !self.ignored_addrs.insert(addr) {
+            return;
+        }
+        self.note(CompatNote::IgnoredWrite { addr, value });
+        if self.ignored_addrs.len() == MAX_IGNORED_WRITES {
+            log::warn!(
+                "[compat] mapper={} further ignored writes not reported",
+                self.mapper
+            );
+        }
+    }
+
+    pub fn cart_write(&mut self, addr: u16, expansion_audio: bool) {
+        if let Some(count) = self.cart_writes.get_mut((addr >> 12) as usize - 4) {
+            *count += 1;
+        }
+        self.expansion_audio_writes += expansion_audio as u64;
+    }
+
+    pub fn irq(&mut self, source: IrqSource) {
+        self.irq_sources |= source.bit();
+    }
+
+    pub fn irq_sources(&self) -> impl Iterator<Item = IrqSource> + '_ {
+        IrqSource::ALL
+            .into_iter()
+            .filter(|source| self.irq_sources & source.bit() != 0)
+    }
+
+    // The report and what was used as one JSON object, with `setup` saying
+    // how the console was configured, e.g. which renderer it drew with.
+    pub fn to_json(&self, setup: &[(&str, &str)], frames: u64) -> String {
+        let mut fields = vec![
+            ("mapper".to_string(), self.mapper.to_string()),
+            ("frames".to_string(), frames.to_string()),
+        ];
+        for (name, value) in setup {
+            fields.push((name.to_string(), json_string(value)));
+        }
+        let pages: Vec<String> = self
+            .cart_writes
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(page, count)| {
+                format!("{}: {}", json_string(&format!("${:X}000", page + 4)), count)
+            })
+            .collect();
+        fields.push((
+            "cart_writes".to_string(),
+            format!("{{{}}}", pages.join(", ")),
+        ));
+        fields.push((
+            "expansion_audio_writes".to_string(),
+            self.expansion_audio_writes.to_string(),
+        ));
+        let sources: Vec<String> = self
+            .irq_sources()
+            .map(|source| json_string(source.name()))
+            .collect();
+        fields.push((
+            "irq_sources".to_string(),
+            format!("[{}]", sources.join(", ")),
+        ));
+        let notes: Vec<String> = self
+            .notes
+            .iter()
+            .map(|note| json_string(&note.to_string()))
+            .collect();
+        fields.push(("notes".to_string(), format!("[{}]", notes.join(", "))));
+
+        let body: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!("  {}: {}", json_string(name), value))
+            .collect();
+        format!("{{\n{}\n}}\n", body.join(",\n"))
+    }
+
+    pub fn report(&self) -> String {
+        let mut text = format!("Compatibility notes for mapper {}:\n", self.mapper);
+        if self.notes.is_empty() {
+            text.push_str("  none\n");
+        }
+        for note in &self.notes {
+            text.push_str(&format!("  {}\n", note));
+        }
+        text
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ignored_writes_are_reported_once_per_address() {
+        let mut report = CompatReport::new(5);
+        report.ignored_write(0x5200, 0x80);
+        report.ignored_write(0x5200, 0x00);
+        report.ignored_write(0x5201, 0x10);
+        report.note(CompatNote::UnsupportedSubmapper { submapper: 1 });
+        report.note(CompatNote::UnsupportedSubmapper { submapper: 1 });
+
+        assert_eq!(
+            report.notes(),
+            &[
+                CompatNote::IgnoredWrite {
+                    addr: 0x5200,
+                    value: 0x80
+                },
+                CompatNote::IgnoredWrite {
+                    addr: 0x5201,
+                    value: 0x10
+                },
+                CompatNote::UnsupportedSubmapper { submapper: 1 },
+            ]
+        );
+        assert!(report.report().contains("ignored write $5200 = $80"));
+
+        for addr in 0..100 {
+            report.ignored_write(0x6000 + addr, 0);
+        }
+        assert_eq!(report.notes().len(), MAX_IGNORED_WRITES + 1);
+    }
+
+    #[test]
+    fn test_json_lists_what_was_used() {
+        let mut report = CompatReport::new(4);
+        report.cart_write(0x8001, false);
+        report.cart_write(0xE000, false);
+        report.cart_write(0xE001, false);
+        report.irq(IrqSource::Mapper);
+        report.irq(IrqSource::Mapper);
+        report.note(CompatNote::VsPalette { model: 2 });
+
+        assert_eq!(
+            report.to_json(&[("renderer", "scanline")], 60),
+            concat!(
+                "{\n",
+                "  \"mapper\": 4,\n",
+                "  \"frames\": 60,\n",
+                "  \"renderer\": \"scanline\",\n",
+                "  \"cart_writes\": {\"$8000\": 1, \"$E000\": 2},\n",
+                "  \"expansion_audio_writes\": 0,\n",
+                "  \"irq_sources\": [\"mapper\"],\n",
+                "  \"notes\": [\"RP2C04-0002 palette not emulated\"]\n",
+                "}\n"
+            )
+        );
+        assert_eq!(json_string("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\u000a\"");
+    }
+}
@@ -4,6 +4,7 @@ use bitflags::bitflags;
 
 use crate::memory::Memory;
 use crate::opcodes::{AddressingMode, CPU_OPCODES, Mnemonic};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 pub const STACK_START: u16 = 0x0100;
 pub const PRG_START: u16 = 0x8000;
@@ -40,8 +41,10 @@ impl Debug for Registers {
     }
 }
 
+pub use interrupt::InterruptType;
+
 mod interrupt {
-    #[derive(PartialEq, Eq)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub enum InterruptType {
         NMI,
         IRQ,
@@ -60,30 +63,56 @@ mod interrupt {
         itype: InterruptType::NMI,
         vector_addr: 0xFFFA,
         b_flag_mask: 0b00100000,
-        cpu_cycles: 2,
+        cpu_cycles: 7,
     };
 
     pub(super) const IRQ: Interrupt = Interrupt {
         itype: InterruptType::IRQ,
         vector_addr: 0xFFFE,
         b_flag_mask: 0b00100000,
-        cpu_cycles: 2,
+        cpu_cycles: 7,
     };
 
     pub(super) const BRK: Interrupt = Interrupt {
         itype: InterruptType::BRK,
         vector_addr: 0xFFFE,
         b_flag_mask: 0b00110000,
-        cpu_cycles: 1,
+        cpu_cycles: 7,
     };
+
+    pub(super) fn of(itype: InterruptType) -> Interrupt {
+        match itype {
+            InterruptType::NMI => NMI,
+            InterruptType::IRQ => IRQ,
+            InterruptType::BRK => BRK,
+        }
+    }
 }
 
 pub struct CPU {
     pub registers: Registers,
-    pub vram: [u8; 2048],
     extra_cycles: u8,
     cycles_wait: u8,
+    // Cycles the CPU has been clocked for since power-on, running or halted.
+    // The bus doesn't clock it while DMA holds it off.
+    cycles: u64,
     halted: bool,
+    nmi_line: bool,
+    nmi_pending: bool,
+    // Level triggered, unlike NMI, and masked by the I flag.
+    irq_line: bool,
+    // What polling on the last cycle of an instruction found, to run in
+    // place of the next one.
+    next_interrupt: Option<InterruptType>,
+    // The I flag from before a CLI, SEI or PLP, which is what their poll
+    // sees, so that the change only takes effect an instruction later.
+    poll_interrupt_disable: Option<bool>,
+    // The interrupt sequence running, if any.
+    sequence: Option<InterruptType>,
+    // Set when a sequence starts, for `take_interrupt_started`.
+    started: Option<InterruptType>,
+    // The operand of the instruction running, when it came decoded.
+    operand: Option<u16>,
 }
 
 impl CPU {
@@ -97,56 +126,153 @@ impl CPU {
                 pc: PRG_START,
                 sp: 0xFD,
             },
-            vram: [0; 2048],
             extra_cycles: 0,
             cycles_wait: 0,
+            cycles: 0,
             halted: false,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: false,
+            next_interrupt: None,
+            poll_interrupt_disable: None,
+            sequence: None,
+            started: None,
+            operand: None,
         }
     }
 
     pub fn clock<M: Memory>(&mut self, memory: &mut M) -> bool {
+        self.cycles += 1;
         if self.halted {
             return false;
         }
 
-        if self.cycles_wait == 0 {
-            let opcode = memory.read(self.registers.pc);
+        if self.cycles_wait == 0
+            && let Some(itype) = self.next_interrupt.take()
+        {
+            if itype == InterruptType::NMI {
+                self.nmi_pending = false;
+            }
+            self.interrupt(memory, interrupt::of(itype));
+        } else if self.cycles_wait == 0 {
+            let opcode_info = match memory.decoded(self.registers.pc) {
+                Some(decoded) => {
+                    self.operand = Some(decoded.operand);
+                    decoded.opcode
+                }
+                None => {
+                    let opcode = memory.read(self.registers.pc);
+                    CPU_OPCODES
+                        .find_by_code(opcode)
+                        .unwrap_or_else(|| panic!("Unknown opcode: {opcode:#04X}"))
+                }
+            };
             self.registers.pc = self.registers.pc.wrapping_add(1);
 
-            if let Some(opcode_info) = CPU_OPCODES.find_by_code(opcode) {
-                self.extra_cycles = 0;
-                self.execute_instruction(
-                    memory,
-                    opcode_info.bytes,
-                    &opcode_info.mnemonic,
-                    &opcode_info.mode,
-                );
-                self.cycles_wait = opcode_info.cycles + self.extra_cycles;
-                self.extra_cycles = 0;
-            } else {
-                panic!("Unknown opcode: {opcode:#04X}");
+            let interrupt_disable = self
+                .registers
+                .status
+                .contains(StatusFlags::INTERRUPT_DISABLE);
+            self.extra_cycles = 0;
+            self.execute_instruction(
+                memory,
+                opcode_info.bytes,
+                &opcode_info.mnemonic,
+                &opcode_info.mode,
+            );
+            self.cycles_wait = opcode_info.cycles + self.extra_cycles;
+            self.extra_cycles = 0;
+            self.operand = None;
+            if matches!(
+                opcode_info.mnemonic,
+                Mnemonic::CLI | Mnemonic::SEI | Mnemonic::PLP
+            ) {
+                self.poll_interrupt_disable = Some(interrupt_disable);
             }
         }
 
+        // An NMI that arrives by the end of the fourth cycle of a BRK or IRQ
+        // sequence sends it to the NMI vector instead.
+        if matches!(self.sequence, Some(InterruptType::BRK | InterruptType::IRQ))
+            && self.cycles_wait == 3
+            && self.nmi_pending
+        {
+            self.nmi_pending = false;
+            self.registers.pc = memory.read_u16(interrupt::NMI.vector_addr);
+            self.sequence = Some(InterruptType::NMI);
+            self.started = Some(InterruptType::NMI);
+        }
+
+        // Interrupts are polled going into an instruction's last cycle, so
+        // one that arrives later waits for the end of the next instruction.
+        // Interrupt sequences don't poll, so a handler's first instruction
+        // always runs.
+        if self.cycles_wait == 1 && self.sequence.is_none() {
+            self.poll_interrupts();
+        }
+
         if self.cycles_wait > 0 {
             self.cycles_wait -= 1;
         }
+        if self.cycles_wait == 0 {
+            self.sequence = None;
+        }
 
         self.cycles_wait == 0
     }
 
+    fn poll_interrupts(&mut self) {
+        let interrupt_disable = self.poll_interrupt_disable.take().unwrap_or(
+            self.registers
+                .status
+                .contains(StatusFlags::INTERRUPT_DISABLE),
+        );
+        self.next_interrupt = if self.nmi_pending {
+            Some(InterruptType::NMI)
+        } else if self.irq_line && !interrupt_disable {
+            Some(InterruptType::IRQ)
+        } else {
+            None
+        };
+    }
+
+    // The interrupt sequence started on the last clock, if any.
+    pub fn take_interrupt_started(&mut self) -> Option<InterruptType> {
+        self.started.take()
+    }
+
     pub fn nmi<M: Memory>(&mut self, memory: &mut M) {
         self.interrupt(memory, interrupt::NMI);
     }
 
-    pub fn irq<M: Memory>(&mut self, memory: &mut M) {
-        if !self
-            .registers
-            .status
-            .contains(StatusFlags::INTERRUPT_DISABLE)
-        {
-            self.interrupt(memory, interrupt::IRQ);
+    // NMI is edge triggered: a rising edge latches a request that is serviced
+    // at the next instruction boundary, even if the line has dropped again by
+    // then.
+    pub fn set_nmi_line(&mut self, level: bool) {
+        if level && !self.nmi_line {
+            self.nmi_pending = true;
         }
+        self.nmi_line = level;
+    }
+
+    // Cycles the CPU has been clocked for since power-on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Stopped by a JAM opcode until the next reset.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    // Held by the mappers and APU that want an IRQ, and taken at the next
+    // poll unless the I flag masks it.
+    pub fn set_irq_line(&mut self, level: bool) {
+        self.irq_line = level;
     }
 
     fn execute_instruction<M: Memory>(
@@ -257,6 +383,10 @@ impl CPU {
 
         self.registers.pc = memory.read_u16(0xFFFC);
         self.halted = false;
+        self.nmi_pending = false;
+        // The reset sequence's 7 cycles pass at once here, but are counted
+        // so the count lines up with nestest logs.
+        self.cycles += 7;
     }
 }
 
@@ -428,15 +558,10 @@ impl CPU {
         }
     }
 
+    // Runs whatever the I flag says, unlike IRQ.
     fn brk<M: Memory>(&mut self, memory: &mut M, _mode: &AddressingMode) {
-        self.registers.pc += 1;
-        if !self
-            .registers
-            .status
-            .contains(StatusFlags::INTERRUPT_DISABLE)
-        {
-            self.interrupt(memory, interrupt::BRK);
-        }
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        self.interrupt(memory, interrupt::BRK);
     }
 
     fn bvc<M: Memory>(&mut self, memory: &mut M, mode: &AddressingMode) {
@@ -1118,42 +1243,42 @@ impl CPU {
         match mode {
             AddressingMode::Immediate => (self.registers.pc, false),
 
-            AddressingMode::ZeroPage => (memory.read(self.registers.pc) as u16, false),
+            AddressingMode::ZeroPage => (self.operand_byte(memory) as u16, false),
 
-            AddressingMode::Absolute => (memory.read_u16(self.registers.pc), false),
+            AddressingMode::Absolute => (self.operand_word(memory), false),
 
             AddressingMode::ZeroPageX => {
-                let pos = memory.read(self.registers.pc);
+                let pos = self.operand_byte(memory);
                 let addr = pos.wrapping_add(self.registers.x) as u16;
                 (addr, false)
             }
             AddressingMode::ZeroPageY => {
-                let pos = memory.read(self.registers.pc);
+                let pos = self.operand_byte(memory);
                 let addr = pos.wrapping_add(self.registers.y) as u16;
                 (addr, false)
             }
 
             AddressingMode::Relative => {
-                let offset = memory.read(self.registers.pc) as i8;
+                let offset = self.operand_byte(memory) as i8;
                 let next = self.registers.pc.wrapping_add(1) as i32;
                 ((next + offset as i32) as u16, false)
             }
 
             AddressingMode::AbsoluteX => {
-                let base = memory.read_u16(self.registers.pc);
+                let base = self.operand_word(memory);
                 let addr = base.wrapping_add(self.registers.x as u16);
                 let page_cross = (base & 0xFF00) != (addr & 0xFF00);
                 (addr, page_cross)
             }
             AddressingMode::AbsoluteY => {
-                let base = memory.read_u16(self.registers.pc);
+                let base = self.operand_word(memory);
                 let addr = base.wrapping_add(self.registers.y as u16);
                 let page_cross = (base & 0xFF00) != (addr & 0xFF00);
                 (addr, page_cross)
             }
 
             AddressingMode::Indirect => {
-                let addr = memory.read_u16(self.registers.pc);
+                let addr = self.operand_word(memory);
 
                 let indirect_ref = if addr & 0x00FF == 0x00FF {
                     let lo = memory.read(addr);
@@ -1165,7 +1290,7 @@ impl CPU {
                 (indirect_ref, false)
             }
             AddressingMode::IndirectX => {
-                let base = memory.read(self.registers.pc);
+                let base = self.operand_byte(memory);
 
                 let ptr: u8 = base.wrapping_add(self.registers.x);
                 let lo = memory.read(ptr as u16);
@@ -1173,7 +1298,7 @@ impl CPU {
                 ((hi as u16) << 8 | (lo as u16), false)
             }
             AddressingMode::IndirectY => {
-                let base = memory.read(self.registers.pc);
+                let base = self.operand_byte(memory);
 
                 let lo = memory.read(base as u16);
                 let hi = memory.read(base.wrapping_add(1) as u16);
@@ -1193,6 +1318,22 @@ impl CPU {
 
 /// Helpers
 impl CPU {
+    // The bytes after the opcode, from the decoded instruction if it came
+    // that way and otherwise from memory.
+    fn operand_byte<M: Memory>(&self, memory: &mut M) -> u8 {
+        match self.operand {
+            Some(operand) => operand as u8,
+            None => memory.read(self.registers.pc),
+        }
+    }
+
+    fn operand_word<M: Memory>(&self, memory: &mut M) -> u16 {
+        match self.operand {
+            Some(operand) => operand,
+            None => memory.read_u16(self.registers.pc),
+        }
+    }
+
     fn stack_addr(&self) -> u16 {
         STACK_START + self.registers.sp as u16
     }
@@ -1223,14 +1364,210 @@ impl CPU {
 
     fn interrupt<M: Memory>(&mut self, memory: &mut M, interrupt: interrupt::Interrupt) {
         self.push_stack_u16(memory, self.registers.pc);
-        let mut flag = StatusFlags::from_bits_truncate(self.registers.status.bits());
-        flag.remove(StatusFlags::BREAK_COMMAND);
-        flag.insert(StatusFlags::UNUSED);
-
-        self.push_stack(memory, flag.bits());
+        // Only BRK pushes the B flag set.
+        let flags = self.registers.status.bits() & !StatusFlags::BREAK_COMMAND.bits();
+        self.push_stack(memory, flags | interrupt.b_flag_mask);
         self.registers.status.insert(StatusFlags::INTERRUPT_DISABLE);
 
-        self.cycles_wait = self.cycles_wait.wrapping_add(interrupt.cpu_cycles);
+        self.cycles_wait = interrupt.cpu_cycles;
         self.registers.pc = memory.read_u16(interrupt.vector_addr);
+        self.sequence = Some(interrupt.itype);
+        self.started = Some(interrupt.itype);
+    }
+}
+
+impl Savestate for CPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.registers.a);
+        state.write_u8(self.registers.x);
+        state.write_u8(self.registers.y);
+        state.write_u8(self.registers.status.bits());
+        state.write_u16(self.registers.pc);
+        state.write_u8(self.registers.sp);
+        state.write_u8(self.extra_cycles);
+        state.write_u8(self.cycles_wait);
+        state.write_u64(self.cycles);
+        state.write_bool(self.halted);
+        state.write_bool(self.nmi_line);
+        state.write_bool(self.nmi_pending);
+        state.write_bool(self.irq_line);
+        state.write_u8(interrupt_id(self.next_interrupt));
+        state.write_u8(match self.poll_interrupt_disable {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        });
+        state.write_u8(interrupt_id(self.sequence));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.registers.a = state.read_u8()?;
+        self.registers.x = state.read_u8()?;
+        self.registers.y = state.read_u8()?;
+        self.registers.status = StatusFlags::from_bits_truncate(state.read_u8()?);
+        self.registers.pc = state.read_u16()?;
+        self.registers.sp = state.read_u8()?;
+        self.extra_cycles = state.read_u8()?;
+        self.cycles_wait = state.read_u8()?;
+        self.cycles = state.read_u64()?;
+        self.halted = state.read_bool()?;
+        self.nmi_line = state.read_bool()?;
+        self.nmi_pending = state.read_bool()?;
+        self.irq_line = state.read_bool()?;
+        self.next_interrupt = read_interrupt(state)?;
+        self.poll_interrupt_disable = match state.read_u8()? {
+            0 => None,
+            1 => Some(false),
+            2 => Some(true),
+            other => return Err(format!("Invalid polled I flag {} in savestate", other)),
+        };
+        self.sequence = read_interrupt(state)?;
+        self.started = None;
+        Ok(())
+    }
+}
+
+fn interrupt_id(itype: Option<InterruptType>) -> u8 {
+    match itype {
+        None => 0,
+        Some(InterruptType::NMI) => 1,
+        Some(InterruptType::IRQ) => 2,
+        Some(InterruptType::BRK) => 3,
+    }
+}
+
+fn read_interrupt(state: &mut StateReader) -> Result<Option<InterruptType>, String> {
+    match state.read_u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(InterruptType::NMI)),
+        2 => Ok(Some(InterruptType::IRQ)),
+        3 => Ok(Some(InterruptType::BRK)),
+        other => Err(format!("Invalid interrupt {} in savestate", other)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct FlatMemory([u8; 0x10000]);
+
+    impl Memory for FlatMemory {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.0[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, data: u8) {
+            self.0[addr as usize] = data;
+        }
+    }
+
+    // NOPs from $8000, NMI handler at $9000.
+    fn setup() -> (CPU, FlatMemory) {
+        let mut memory = FlatMemory([0xEA; 0x10000]);
+        memory.write_u16(0xFFFA, 0x9000);
+        memory.write_u16(0xFFFC, 0x8000);
+        let mut cpu = CPU::new();
+        cpu.reset(&mut memory);
+        (cpu, memory)
+    }
+
+    fn run_instruction(cpu: &mut CPU, memory: &mut FlatMemory) {
+        while !cpu.clock(memory) {}
+    }
+
+    #[test]
+    fn test_cycles_count_page_crosses_and_branches() {
+        let (mut cpu, mut memory) = setup();
+        // LDA $12FF,X; BNE +0; then NOPs.
+        memory.0[0x8000..0x8005].copy_from_slice(&[0xBD, 0xFF, 0x12, 0xD0, 0x00]);
+        cpu.registers.x = 1;
+        assert_eq!(cpu.cycles(), 7);
+
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.cycles(), 7 + 5);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.cycles(), 12 + 3);
+    }
+
+    #[test]
+    fn test_nmi_latched_even_if_line_drops_before_boundary() {
+        let (mut cpu, mut memory) = setup();
+        cpu.clock(&mut memory);
+
+        // vblank starts and ends (pre-render clears it) mid-instruction
+        cpu.set_nmi_line(true);
+        cpu.set_nmi_line(false);
+        assert!(cpu.nmi_pending());
+
+        run_instruction(&mut cpu, &mut memory);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0x9000);
+        assert!(!cpu.nmi_pending());
+    }
+
+    #[test]
+    fn test_nmi_fires_once_per_rising_edge() {
+        let (mut cpu, mut memory) = setup();
+
+        // Each NMI is polled during an instruction and taken after it.
+        cpu.set_nmi_line(true);
+        run_instruction(&mut cpu, &mut memory);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0x9000);
+
+        cpu.set_nmi_line(true);
+        run_instruction(&mut cpu, &mut memory);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0x9002);
+
+        cpu.set_nmi_line(false);
+        cpu.set_nmi_line(true);
+        run_instruction(&mut cpu, &mut memory);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0x9000);
+    }
+
+    #[test]
+    fn test_cli_lets_an_irq_in_one_instruction_late() {
+        let (mut cpu, mut memory) = setup();
+        memory.write_u16(0xFFFE, 0xA000);
+        memory.0[0x8000] = 0x58;
+        cpu.set_irq_line(true);
+
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0x8001);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0x8002);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0xA000);
+        assert_eq!(cpu.take_interrupt_started(), Some(InterruptType::IRQ));
+        // Pushed with B clear, and masked from then on by the I flag.
+        assert_eq!(memory.0[0x01FB] & 0x30, 0x20);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0xA001);
+    }
+
+    #[test]
+    fn test_nmi_takes_over_a_brk() {
+        let (mut cpu, mut memory) = setup();
+        memory.write_u16(0xFFFE, 0xA000);
+        memory.0[0x8000] = 0x00;
+
+        // BRK runs with interrupts disabled, and an NMI by its fourth cycle
+        // still sends it to the NMI vector.
+        for _ in 0..4 {
+            cpu.clock(&mut memory);
+        }
+        cpu.set_nmi_line(true);
+        run_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.pc, 0x9000);
+        assert_eq!(cpu.take_interrupt_started(), Some(InterruptType::NMI));
+        assert!(!cpu.nmi_pending());
+        assert_eq!(memory.0[0x01FB] & 0x30, 0x30);
+        assert_eq!(
+            u16::from_le_bytes([memory.0[0x01FC], memory.0[0x01FD]]),
+            0x8002
+        );
     }
 }
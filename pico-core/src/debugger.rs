@@ -0,0 +1,513 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::disasm;
+use crate::nes::Nes;
+use crate::ram_search::{Filter, RamSearch};
+use crate::trace::TraceRecord;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// Stops the CPU after an instruction that reads or writes in `range`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: u16, access: Access) -> bool {
+        let watched = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+        watched && self.range.contains(&addr)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match (self.read, self.write) {
+            (true, true) => "access",
+            (true, false) => "read",
+            _ => "write",
+        };
+        let (start, end) = (*self.range.start(), *self.range.end());
+        if start == end {
+            write!(f, "{} ${:04X}", kind, start)
+        } else {
+            write!(f, "{} ${:04X}-${:04X}", kind, start, end)
+        }
+    }
+}
+
+// Why the debugger stopped the CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(u16),
+    Watchpoint {
+        addr: u16,
+        value: u8,
+        access: Access,
+    },
+    Step,
+    FrameEnd,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(pc) => write!(f, "Breakpoint at ${:04X}", pc),
+            Stop::Watchpoint {
+                addr,
+                value,
+                access: Access::Read,
+            } => write!(f, "Read ${:02X} from ${:04X}", value, addr),
+            Stop::Watchpoint {
+                addr,
+                value,
+                access: Access::Write,
+            } => write!(f, "Wrote ${:02X} to ${:04X}", value, addr),
+            Stop::Step => write!(f, "Stepped"),
+            Stop::FrameEnd => write!(f, "End of frame"),
+        }
+    }
+}
+
+// What the CPU does until the debugger next stops it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RunMode {
+    #[default]
+    Running,
+    Paused,
+    StepInstruction,
+    StepFrame,
+}
+
+// Breakpoints, watchpoints and stepping, checked as the console runs through
+// `Nes::debug_step_frame`. Stops fall between instructions, so a stopped CPU
+// is always about to start the instruction at its PC.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    mode: RunMode,
+    // The first watched access of the instruction under way, reported once
+    // it completes.
+    watch_hit: Option<Stop>,
+    stop: Option<Stop>,
+    // Show a listing from the PC at each stop rather than one trace line.
+    follow: bool,
+    search: Option<RamSearch>,
+}
+
+impl Debugger {
+    pub fn mode(&self) -> RunMode {
+        self.mode
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == RunMode::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = RunMode::Paused;
+    }
+
+    pub fn resume(&mut self) {
+        self.mode = RunMode::Running;
+    }
+
+    pub fn step_instruction(&mut self) {
+        self.mode = RunMode::StepInstruction;
+    }
+
+    // Runs to the end of the frame under way.
+    pub fn step_frame(&mut self) {
+        self.mode = RunMode::StepFrame;
+    }
+
+    pub fn follows(&self) -> bool {
+        self.follow
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        (index < self.watchpoints.len()).then(|| self.watchpoints.remove(index))
+    }
+
+    pub(crate) fn access(&mut self, addr: u16, value: u8, access: Access) {
+        if self.watch_hit.is_none()
+            && self
+                .watchpoints
+                .iter()
+                .any(|watchpoint| watchpoint.matches(addr, access))
+        {
+            self.watch_hit = Some(Stop::Watchpoint {
+                addr,
+                value,
+                access,
+            });
+        }
+    }
+
+    // `pc` is the next instruction's.
+    pub(crate) fn instruction_complete(&mut self, pc: u16) {
+        let stop = if let Some(hit) = self.watch_hit.take() {
+            Some(hit)
+        } else if self.breakpoints.contains(&pc) {
+            Some(Stop::Breakpoint(pc))
+        } else {
+            (self.mode == RunMode::StepInstruction).then_some(Stop::Step)
+        };
+        if stop.is_some() {
+            self.stop = stop;
+            self.mode = RunMode::Paused;
+        }
+    }
+
+    pub(crate) fn frame_complete(&mut self) {
+        if self.mode == RunMode::StepFrame && self.stop.is_none() {
+            self.stop = Some(Stop::FrameEnd);
+            self.mode = RunMode::Paused;
+        }
+    }
+
+    pub(crate) fn take_stop(&mut self) -> Option<Stop> {
+        self.stop.take()
+    }
+}
+
+const HELP: &str = "\
+break ADDR         stop before the instruction at ADDR (b)
+delete ADDR        remove the breakpoint at ADDR (d)
+watch RANGE        stop after a write in RANGE, ADDR or ADDR-END (w)
+rwatch RANGE       stop after a read in RANGE
+awatch RANGE       stop after a read or write in RANGE
+unwatch N          remove watchpoint N
+list               show breakpoints and watchpoints (l)
+continue           run until something stops the CPU (c)
+pause              stop the CPU where it is (p)
+step               run one instruction (s)
+frame              run to the end of the frame (f)
+where              show the next instruction and the registers
+disasm [ADDR [N]]  list N instructions from ADDR, 10 from the PC by default (u)
+follow             switch listing from the PC at each stop on or off
+x ADDR [LEN]       show LEN bytes of memory from ADDR, 16 by default
+search             start a search of work RAM and PRG RAM for a value
+search FILTER      keep the addresses that match: = N, != N, > N or < N, or
+                   =, !=, > or < against the last search, or +N or -N for a
+                   change by N
+help               show this
+";
+
+// Runs a debugger command typed by the user and returns what to show them.
+// Addresses are hex, with or without a leading `$`.
+pub fn execute(nes: &mut Nes, line: &str) -> Result<String, String> {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return Ok(String::new());
+    };
+    let args: Vec<&str> = words.collect();
+    let arg = |index: usize| {
+        args.get(index)
+            .copied()
+            .ok_or_else(|| format!("{} needs more arguments, see help", command))
+    };
+
+    let where_line = |nes: &Nes| format!("{}\n", TraceRecord::capture(&nes.cpu, &nes.bus));
+    if command == "where" {
+        return Ok(where_line(nes));
+    }
+    if command == "disasm" || command == "u" {
+        let pc = nes.cpu.registers.pc;
+        let start = match args.first() {
+            Some(addr) => parse_addr(addr)?,
+            None => pc,
+        };
+        let count = match args.get(1) {
+            Some(count) => count
+                .parse()
+                .map_err(|e| format!("Invalid count {}: {}", count, e))?,
+            None => 10,
+        };
+        let mut listing = disasm::disassemble_count(&nes.bus, start, count);
+        listing.current = Some(pc);
+        return Ok(listing.to_string());
+    }
+    if command == "x" {
+        let start = parse_addr(arg(0)?)?;
+        let len = match args.get(1) {
+            Some(len) => len
+                .parse::<u16>()
+                .map_err(|e| format!("Invalid length {}: {}", len, e))?,
+            None => 16,
+        };
+        return Ok(hex_dump(nes, start, len));
+    }
+    if command == "search" {
+        return search(nes, &args);
+    }
+
+    let Some(debugger) = &mut nes.bus.debugger else {
+        return Err("The debugger isn't enabled".to_string());
+    };
+    match command {
+        "break" | "b" => {
+            let pc = parse_addr(arg(0)?)?;
+            debugger.add_breakpoint(pc);
+            Ok(format!("Breakpoint at ${:04X}\n", pc))
+        }
+        "delete" | "d" => {
+            let pc = parse_addr(arg(0)?)?;
+            if !debugger.remove_breakpoint(pc) {
+                return Err(format!("No breakpoint at ${:04X}", pc));
+            }
+            Ok(String::new())
+        }
+        "watch" | "w" | "rwatch" | "awatch" => {
+            let watchpoint = Watchpoint {
+                range: parse_range(arg(0)?)?,
+                read: command != "watch" && command != "w",
+                write: command != "rwatch",
+            };
+            let text = format!(
+                "Watchpoint {}: {}\n",
+                debugger.watchpoints.len(),
+                watchpoint
+            );
+            debugger.add_watchpoint(watchpoint);
+            Ok(text)
+        }
+        "unwatch" => {
+            let text = arg(0)?;
+            let index = text
+                .parse()
+                .map_err(|e| format!("Invalid watchpoint number {}: {}", text, e))?;
+            match debugger.remove_watchpoint(index) {
+                Some(_) => Ok(String::new()),
+                None => Err(format!("No watchpoint {}", index)),
+            }
+        }
+        "list" | "l" => {
+            let mut text = String::new();
+            for pc in debugger.breakpoints() {
+                text.push_str(&format!("Breakpoint at ${:04X}\n", pc));
+            }
+            for (index, watchpoint) in debugger.watchpoints().iter().enumerate() {
+                text.push_str(&format!("Watchpoint {}: {}\n", index, watchpoint));
+            }
+            Ok(text)
+        }
+        "continue" | "c" => {
+            debugger.resume();
+            Ok(String::new())
+        }
+        "pause" | "p" => {
+            debugger.pause();
+            Ok(where_line(nes))
+        }
+        "step" | "s" => {
+            debugger.step_instruction();
+            Ok(String::new())
+        }
+        "frame" | "f" => {
+            debugger.step_frame();
+            Ok(String::new())
+        }
+        "follow" => {
+            debugger.follow = !debugger.follow;
+            let state = if debugger.follow { "on" } else { "off" };
+            Ok(format!("Following {}\n", state))
+        }
+        "help" | "h" | "?" => Ok(HELP.to_string()),
+        _ => Err(format!("Unknown command {}, see help", command)),
+    }
+}
+
+fn search(nes: &mut Nes, args: &[&str]) -> Result<String, String> {
+    let filter = match args.is_empty() {
+        true => None,
+        false => Some(Filter::parse(&args.join(" "))?),
+    };
+    let Some(debugger) = &mut nes.bus.debugger else {
+        return Err("The debugger isn't enabled".to_string());
+    };
+    let (search, text) = match (debugger.search.take(), filter) {
+        (_, None) => {
+            let search = RamSearch::new(&nes.bus);
+            let text = format!("Searching {} addresses\n", search.matches().len());
+            (search, text)
+        }
+        (Some(mut search), Some(filter)) => {
+            search.filter(&nes.bus, filter);
+            let text = search.to_string();
+            (search, text)
+        }
+        (None, Some(_)) => return Err("No search under way, start one with search".to_string()),
+    };
+    if let Some(debugger) = &mut nes.bus.debugger {
+        debugger.search = Some(search);
+    }
+    Ok(text)
+}
+
+pub fn parse_addr(text: &str) -> Result<u16, String> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|e| format!("Invalid address {}: {}", text, e))
+}
+
+fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (parse_addr(start)?, parse_addr(end)?),
+        None => {
+            let addr = parse_addr(text)?;
+            (addr, addr)
+        }
+    };
+    if start > end {
+        return Err(format!("Invalid range {}: it ends before it starts", text));
+    }
+    Ok(start..=end)
+}
+
+// Reads without side effects, so registers show as 0.
+fn hex_dump(nes: &Nes, start: u16, len: u16) -> String {
+    let mut text = String::new();
+    for offset in 0..len {
+        let addr = start.wrapping_add(offset);
+        if offset % 16 == 0 {
+            if offset > 0 {
+                text.push('\n');
+            }
+            text.push_str(&format!("${:04X}:", addr));
+        }
+        text.push_str(&format!(" {:02X}", nes.bus.peek(addr)));
+    }
+    if len > 0 {
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apu::APU;
+    use crate::testutil::{Asm, CODE_ORIGIN, RomBuilder};
+
+    // Counts up in $10 forever, storing the count to $0300 as well.
+    fn debugged_nes() -> (Nes, Asm) {
+        let mut asm = Asm::new(CODE_ORIGIN);
+        asm.label("reset")
+            .ldx_imm(0)
+            .label("loop")
+            .inx()
+            .txa()
+            .sta(0x0010)
+            .label("store")
+            .sta(0x0300)
+            .jmp("loop");
+        let cart = RomBuilder::new(0)
+            .code(&asm, "reset", "reset", "reset")
+            .cart();
+        let mut nes = Nes::new(cart, APU::new(48_000));
+        nes.reset();
+        nes.bus.debugger = Some(Debugger::default());
+        (nes, asm)
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping_stop_between_instructions() {
+        let (mut nes, asm) = debugged_nes();
+        let store = asm.address_of("store");
+        execute(&mut nes, &format!("b {:04X}", store)).unwrap();
+
+        assert_eq!(nes.debug_step_frame(), Some(Stop::Breakpoint(store)));
+        assert_eq!(nes.cpu.registers.pc, store);
+        assert_eq!(nes.bus.peek(0x0300), 0);
+        assert_eq!(nes.debug_step_frame(), None, "paused");
+        let listing = execute(&mut nes, "u").unwrap();
+        assert!(listing.starts_with(&format!("> {:04X}  8D 00 03  STA $0300\n", store)));
+
+        execute(&mut nes, "s").unwrap();
+        assert_eq!(nes.debug_step_frame(), Some(Stop::Step));
+        assert_eq!(nes.bus.peek(0x0300), 1);
+
+        execute(&mut nes, "d $").unwrap_err();
+        execute(&mut nes, &format!("d ${:04X}", store)).unwrap();
+        execute(&mut nes, "f").unwrap();
+        assert_eq!(nes.debug_step_frame(), Some(Stop::FrameEnd));
+        assert!(nes.bus.peek(0x0300) > 1);
+    }
+
+    #[test]
+    fn test_watchpoints_report_the_access() {
+        let (mut nes, _) = debugged_nes();
+        execute(&mut nes, "rwatch 0300").unwrap();
+        execute(&mut nes, "watch $0200-$03ff").unwrap();
+        assert_eq!(
+            execute(&mut nes, "l").unwrap(),
+            "Watchpoint 0: read $0300\nWatchpoint 1: write $0200-$03FF\n"
+        );
+
+        let stop = nes.debug_step_frame();
+        assert_eq!(
+            stop,
+            Some(Stop::Watchpoint {
+                addr: 0x0300,
+                value: 1,
+                access: Access::Write
+            })
+        );
+        assert_eq!(stop.unwrap().to_string(), "Wrote $01 to $0300");
+
+        execute(&mut nes, "unwatch 1").unwrap();
+        execute(&mut nes, "c").unwrap();
+        assert_eq!(nes.debug_step_frame(), None);
+        assert!(execute(&mut nes, "unwatch 1").is_err());
+        assert!(execute(&mut nes, "watch 0400-0300").is_err());
+        assert_eq!(
+            execute(&mut nes, "x 10 2").unwrap(),
+            format!("$0010: {:02X} 00\n", nes.bus.peek(0x0010))
+        );
+
+        assert!(execute(&mut nes, "search >").is_err());
+        assert_eq!(
+            execute(&mut nes, "search").unwrap(),
+            "Searching 2048 addresses\n"
+        );
+        execute(&mut nes, "s").unwrap();
+        nes.debug_step_frame();
+        let text = execute(&mut nes, "search +1").unwrap();
+        assert!(text.starts_with("1 address matches\n$0010: "), "{text}");
+    }
+}
@@ -0,0 +1,198 @@
+use std::ops::Range;
+
+use crate::opcodes::{CPU_OPCODES, Mnemonic, Opcode};
+
+// Most instructions decoded in one go before a block is cut off.
+const MAX_BLOCK_LEN: usize = 64;
+
+// An instruction decoded ahead of running it, with the bytes after its
+// opcode as a little-endian operand.
+#[derive(Clone, Copy, Debug)]
+pub struct Decoded {
+    pub opcode: &'static Opcode,
+    pub operand: u16,
+}
+
+// Code decoded a straight-line block at a time, from where the CPU lands up
+// to the next jump, branch or return, so that running it again skips fetching
+// and looking up each instruction. Only RAM and cartridge space from $6000
+// are decoded, and code there can change under a block: a write drops the
+// blocks it lands in, and a PRG bank switch, which the board reports, drops
+// every block from $6000 up.
+pub struct DecodeCache {
+    // The decoded instruction starting at each address, if any.
+    instructions: Vec<Option<Decoded>>,
+    // The bytes each block was decoded from, operands included.
+    blocks: Vec<Range<u32>>,
+    // Pages with part of a block in them, so writes elsewhere cost nothing.
+    code_pages: [bool; 256],
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        DecodeCache {
+            instructions: vec![None; 0x10000],
+            blocks: Vec::new(),
+            code_pages: [false; 256],
+        }
+    }
+
+    pub fn get(&self, addr: u16) -> Option<Decoded> {
+        self.instructions[addr as usize]
+    }
+
+    // Decodes the block starting at `start`, reading code with `peek`, which
+    // mustn't have side effects. It ends early where another block starts.
+    pub fn decode_block(&mut self, start: u16, mut peek: impl FnMut(u16) -> u8) {
+        let mut addr = start as u32;
+        for _ in 0..MAX_BLOCK_LEN {
+            if !decodable(addr) || self.instructions[addr as usize].is_some() {
+                break;
+            }
+            let opcode = CPU_OPCODES.find_by_code(peek(addr as u16)).unwrap();
+            let end = addr + opcode.bytes as u32;
+            if !decodable(end - 1) {
+                break;
+            }
+            let mut operand = [0; 2];
+            for (byte, operand_addr) in operand.iter_mut().zip(addr + 1..end) {
+                *byte = peek(operand_addr as u16);
+            }
+            self.instructions[addr as usize] = Some(Decoded {
+                opcode,
+                operand: u16::from_le_bytes(operand),
+            });
+            addr = end;
+            if ends_block(&opcode.mnemonic) {
+                break;
+            }
+        }
+
+        if addr > start as u32 {
+            for page in start as u32 >> 8..=(addr - 1) >> 8 {
+                self.code_pages[page as usize] = true;
+            }
+            self.blocks.push(start as u32..addr);
+        }
+    }
+
+    // Drops whatever a CPU write to `addr` may have changed.
+    pub fn written(&mut self, addr: u16) {
+        match addr {
+            // Each byte of RAM shows up at four addresses.
+            0x0000..=0x1FFF => {
+                for mirror in 0..4 {
+                    self.drop_blocks_at((addr & 0x07FF) | (mirror << 11));
+                }
+            }
+            0x6000..=0xFFFF => self.drop_blocks_at(addr),
+            _ => {}
+        }
+    }
+
+    // Drops everything decoded from cartridge space, which now shows other
+    // banks.
+    pub fn prg_remapped(&mut self) {
+        self.drop_blocks(|block| block.start >= 0x6000);
+    }
+
+    pub fn clear(&mut self) {
+        if !self.blocks.is_empty() {
+            self.drop_blocks(|_| true);
+        }
+    }
+
+    fn drop_blocks_at(&mut self, addr: u16) {
+        if self.code_pages[addr as usize >> 8] {
+            self.drop_blocks(|block| block.contains(&(addr as u32)));
+        }
+    }
+
+    fn drop_blocks(&mut self, dropped: impl Fn(&Range<u32>) -> bool) {
+        let instructions = &mut self.instructions;
+        self.blocks.retain(|block| {
+            if dropped(block) {
+                instructions[block.start as usize..block.end as usize].fill(None);
+                false
+            } else {
+                true
+            }
+        });
+        self.code_pages = [false; 256];
+        for block in &self.blocks {
+            for page in block.start >> 8..=(block.end - 1) >> 8 {
+                self.code_pages[page as usize] = true;
+            }
+        }
+    }
+}
+
+// Registers and expansion space can read differently every time.
+fn decodable(addr: u32) -> bool {
+    addr < 0x2000 || (0x6000..0x10000).contains(&addr)
+}
+
+// Instructions after which the CPU may go somewhere other than the next one.
+fn ends_block(mnemonic: &Mnemonic) -> bool {
+    matches!(
+        mnemonic,
+        Mnemonic::BCC
+            | Mnemonic::BCS
+            | Mnemonic::BEQ
+            | Mnemonic::BMI
+            | Mnemonic::BNE
+            | Mnemonic::BPL
+            | Mnemonic::BVC
+            | Mnemonic::BVS
+            | Mnemonic::BRK
+            | Mnemonic::JMP
+            | Mnemonic::JSR
+            | Mnemonic::RTI
+            | Mnemonic::RTS
+            | Mnemonic::STP
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_blocks_end_at_jumps_and_drop_when_written() {
+        // $8000: LDA $1234; INX; JMP $8000, and at $0300 the same in RAM.
+        let code = [0xAD, 0x34, 0x12, 0xE8, 0x4C, 0x00, 0x80, 0xEA];
+        let peek = |addr: u16| code[(addr & 0xFF) as usize];
+        let mut cache = DecodeCache::new();
+        cache.decode_block(0x8000, peek);
+        cache.decode_block(0x0300, peek);
+
+        let lda = cache.get(0x8000).unwrap();
+        assert_eq!((lda.opcode.code, lda.operand), (0xAD, 0x1234));
+        assert_eq!(cache.get(0x8003).unwrap().opcode.code, 0xE8);
+        assert_eq!(cache.get(0x8004).unwrap().operand, 0x8000);
+        assert!(cache.get(0x8007).is_none());
+
+        // RAM writes drop only the blocks they land in, mirrors included.
+        cache.written(0x0000);
+        cache.written(0x4000);
+        assert!(cache.get(0x0300).is_some());
+        cache.written(0x0B05);
+        assert!(cache.get(0x0300).is_none());
+        assert!(cache.get(0x8000).is_some());
+
+        // Cartridge writes too, until the board reports a bank switch.
+        cache.written(0xE000);
+        assert!(cache.get(0x8000).is_some());
+        cache.written(0x8001);
+        assert!(cache.get(0x8000).is_none());
+        cache.decode_block(0x8000, peek);
+        cache.prg_remapped();
+        assert!(cache.get(0x8000).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::bus::Bus;
+use crate::opcodes::{AddressingMode, CPU_OPCODES, Mnemonic, Opcode};
+
+// An instruction decoded from memory as the CPU sees it now, with the PRG
+// banks the mapper has switched in, without running it.
+#[derive(Clone, Copy, Debug)]
+pub struct Instruction {
+    pub addr: u16,
+    pub opcode: &'static Opcode,
+    pub bytes: [u8; 3],
+}
+
+impl Instruction {
+    pub fn decode(bus: &Bus, addr: u16) -> Instruction {
+        let opcode = CPU_OPCODES.find_by_code(bus.peek(addr)).unwrap();
+        let mut bytes = [0; 3];
+        for (i, byte) in bytes.iter_mut().enumerate().take(opcode.bytes as usize) {
+            *byte = bus.peek(addr.wrapping_add(i as u16));
+        }
+        Instruction {
+            addr,
+            opcode,
+            bytes,
+        }
+    }
+
+    fn len(&self) -> u16 {
+        self.opcode.bytes as u16
+    }
+
+    pub fn next(&self) -> u16 {
+        self.addr.wrapping_add(self.len())
+    }
+
+    fn absolute(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[1], self.bytes[2]])
+    }
+
+    // Where a branch, JMP or JSR goes, leaving out JMP ($nnnn), whose target
+    // is only known when it runs.
+    pub fn target(&self) -> Option<u16> {
+        match (&self.opcode.mode, &self.opcode.mnemonic) {
+            (AddressingMode::Relative, _) => {
+                let offset = self.bytes[1] as i8 as i16;
+                Some(self.next().wrapping_add_signed(offset))
+            }
+            (AddressingMode::Absolute, Mnemonic::JMP | Mnemonic::JSR) => Some(self.absolute()),
+            _ => None,
+        }
+    }
+
+    // The operand in the usual assembler syntax, naming the target if it has
+    // a label.
+    fn operand(&self, labels: &BTreeMap<u16, String>) -> String {
+        if let Some(label) = self.target().and_then(|target| labels.get(&target)) {
+            return label.clone();
+        }
+        let byte = self.bytes[1];
+        let absolute = self.absolute();
+        match self.opcode.mode {
+            AddressingMode::None => String::new(),
+            AddressingMode::Accumulator => "A".to_string(),
+            AddressingMode::Immediate => format!("#${:02X}", byte),
+            AddressingMode::ZeroPage => format!("${:02X}", byte),
+            AddressingMode::ZeroPageX => format!("${:02X},X", byte),
+            AddressingMode::ZeroPageY => format!("${:02X},Y", byte),
+            AddressingMode::Relative => format!("${:04X}", self.target().unwrap()),
+            AddressingMode::Absolute => format!("${:04X}", absolute),
+            AddressingMode::AbsoluteX => format!("${:04X},X", absolute),
+            AddressingMode::AbsoluteY => format!("${:04X},Y", absolute),
+            AddressingMode::Indirect => format!("(${:04X})", absolute),
+            AddressingMode::IndirectX => format!("(${:02X},X)", byte),
+            AddressingMode::IndirectY => format!("(${:02X}),Y", byte),
+        }
+    }
+}
+
+// Instructions decoded one after another, with labels on the vectors'
+// addresses and on branch and jump targets among them. Data between the code
+// decodes as whatever instructions its bytes make.
+pub struct Listing {
+    pub instructions: Vec<Instruction>,
+    pub labels: BTreeMap<u16, String>,
+    // Marked in the listing, such as the PC the CPU is stopped at.
+    pub current: Option<u16>,
+}
+
+impl Listing {
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            if let Some(label) = self.label(instruction.addr) {
+                writeln!(f, "{}:", label)?;
+            }
+            let hex = instruction.bytes[..instruction.len() as usize]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let marker = if self.current == Some(instruction.addr) {
+                '>'
+            } else {
+                ' '
+            };
+            let line = format!(
+                "{} {:04X}  {:8}  {} {}",
+                marker,
+                instruction.addr,
+                hex,
+                instruction.opcode.mnemonic,
+                instruction.operand(&self.labels)
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+// Decodes from the start of `range` to its end. An instruction that starts
+// in the range is decoded whole.
+pub fn disassemble(bus: &Bus, range: RangeInclusive<u16>) -> Listing {
+    let (start, end) = (*range.start(), *range.end());
+    let mut instructions = Vec::new();
+    let mut addr = start;
+    loop {
+        let instruction = Instruction::decode(bus, addr);
+        instructions.push(instruction);
+        let next = instruction.next();
+        // Stop at the end of the range or of the address space.
+        if next > end || next <= addr {
+            break;
+        }
+        addr = next;
+    }
+
+    let mut labels = BTreeMap::new();
+    let starts: HashSet<u16> = instructions.iter().map(|i| i.addr).collect();
+    let in_listing = |addr: u16| starts.contains(&addr);
+    for (name, vector) in [("reset", 0xFFFC), ("nmi", 0xFFFA), ("irq", 0xFFFE)] {
+        let addr = u16::from_le_bytes([bus.peek(vector), bus.peek(vector + 1)]);
+        if in_listing(addr) {
+            labels.entry(addr).or_insert_with(|| name.to_string());
+        }
+    }
+    for instruction in &instructions {
+        let Some(target) = instruction.target() else {
+            continue;
+        };
+        if in_listing(target) {
+            let prefix = match instruction.opcode.mnemonic {
+                Mnemonic::JSR => "sub",
+                _ => "L",
+            };
+            labels
+                .entry(target)
+                .or_insert_with(|| format!("{}_{:04X}", prefix, target));
+        }
+    }
+
+    Listing {
+        instructions,
+        labels,
+        current: None,
+    }
+}
+
+// `count` instructions from `start`.
+pub fn disassemble_count(bus: &Bus, start: u16, count: usize) -> Listing {
+    let mut end = start;
+    let mut addr = start;
+    for _ in 0..count {
+        end = addr;
+        addr = Instruction::decode(bus, addr).next();
+        if addr < end {
+            break;
+        }
+    }
+    disassemble(bus, start..=end)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apu::APU;
+    use crate::nes::Nes;
+    use crate::testutil::{Asm, CODE_ORIGIN, RomBuilder};
+
+    #[test]
+    fn test_listing_labels_vectors_and_targets() {
+        let mut asm = Asm::new(CODE_ORIGIN);
+        asm.label("reset")
+            .sei()
+            .ldx_imm(0x10)
+            .label("loop")
+            .dex()
+            .bne("loop")
+            .bytes(&[0x20])
+            .bytes(&(CODE_ORIGIN + 0x20).to_le_bytes())
+            .sta_x(0x0300)
+            .jmp("reset");
+        let cart = RomBuilder::new(0)
+            .code(&asm, "reset", "reset", "reset")
+            .cart();
+        let nes = Nes::new(cart, APU::new(48_000));
+
+        let listing = disassemble_count(&nes.bus, CODE_ORIGIN, 7);
+        assert_eq!(listing.instructions.len(), 7);
+        assert_eq!(
+            listing.to_string(),
+            "\
+reset:
+  E200  78        SEI
+  E201  A2 10     LDX #$10
+L_E203:
+  E203  CA        DEX
+  E204  D0 FD     BNE L_E203
+  E206  20 20 E2  JSR $E220
+  E209  9D 00 03  STA $0300,X
+  E20C  4C 00 E2  JMP reset
+"
+        );
+
+        let mut listing = disassemble(&nes.bus, 0xE204..=0xE206);
+        listing.current = Some(0xE206);
+        assert_eq!(listing.instructions.len(), 2);
+        assert!(
+            listing
+                .to_string()
+                .ends_with("> E206  20 20 E2  JSR $E220\n")
+        );
+    }
+}
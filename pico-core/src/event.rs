@@ -0,0 +1,53 @@
+use std::fmt;
+
+use crate::compat::CompatNote;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventLevel {
+    Notice,
+    Warning,
+    Error,
+}
+
+impl EventLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventLevel::Notice => "notice",
+            EventLevel::Warning => "warning",
+            EventLevel::Error => "error",
+        }
+    }
+}
+
+// Something worth telling the player about, queued by the console until the
+// frontend takes it with `Nes::take_events`. Headless runs get them per frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EmulatorEvent {
+    // The game did something the emulator doesn't handle, raised the first
+    // time each compatibility note is recorded.
+    Compat { mapper: u8, note: CompatNote },
+    // The CPU ran a JAM opcode at `pc` and stays stopped until a reset.
+    CpuJam { pc: u16 },
+    // Anything a frontend wants shown alongside, such as a file being saved.
+    Notice(String),
+}
+
+impl EmulatorEvent {
+    pub fn level(&self) -> EventLevel {
+        match self {
+            EmulatorEvent::Compat { .. } => EventLevel::Warning,
+            EmulatorEvent::CpuJam { .. } => EventLevel::Error,
+            EmulatorEvent::Notice(_) => EventLevel::Notice,
+        }
+    }
+}
+
+impl fmt::Display for EmulatorEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorEvent::Compat { mapper, note } => write!(f, "mapper {}: {}", mapper, note),
+            EmulatorEvent::CpuJam { pc } => write!(f, "CPU jam at ${:04X}", pc),
+            EmulatorEvent::Notice(text) => f.write_str(text),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::apu::APU;
+use crate::apu::sink::AudioSink;
+use crate::cart::Cart;
+use crate::event::EmulatorEvent;
+use crate::input_history::COMMAND_RESET;
+use crate::movie::FM2Movie;
+use crate::nes::{EmulatedTime, Nes};
+use crate::ppu::framebuffer::Framebuffer;
+
+pub const SAMPLE_RATE: u32 = 48000;
+
+// Runs the console without a window or audio device, for tools and tests.
+pub struct Headless {
+    pub nes: Nes,
+    framebuffer: Framebuffer,
+    audio: Vec<f32>,
+    audio_time: EmulatedTime,
+    events: Vec<EmulatorEvent>,
+    frame: usize,
+}
+
+impl Headless {
+    pub fn new(cart: Cart) -> Self {
+        Headless::with_audio_sink(cart, AudioSink::Collect(Vec::new()))
+    }
+
+    // With a sink other than `Collect`, `audio` stays empty: samples can go
+    // to a WAV file, or nowhere when only the picture matters.
+    pub fn with_audio_sink(cart: Cart, audio_sink: AudioSink) -> Self {
+        let apu = APU::with_sink(SAMPLE_RATE, audio_sink);
+        let mut nes = Nes::new(cart, apu);
+        nes.reset();
+
+        Headless {
+            nes,
+            framebuffer: Framebuffer::new(),
+            audio: Vec::new(),
+            audio_time: EmulatedTime::default(),
+            events: Vec::new(),
+            frame: 0,
+        }
+    }
+
+    // Number of frames run so far.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    // When the last frame run finished.
+    pub fn frame_time(&self) -> EmulatedTime {
+        self.nes.frame_time()
+    }
+
+    // Samples produced while running the last frame.
+    pub fn audio(&self) -> &[f32] {
+        &self.audio
+    }
+
+    // When the first sample of `audio` was produced.
+    pub fn audio_time(&self) -> EmulatedTime {
+        self.audio_time
+    }
+
+    // Warnings and errors raised while running the last frame.
+    pub fn events(&self) -> &[EmulatorEvent] {
+        &self.events
+    }
+
+    pub fn run_frame(&mut self) -> &Framebuffer {
+        self.nes.step_frame();
+        self.events = self.nes.take_events();
+
+        self.nes.bus.apu.take_samples(&mut self.audio);
+        let apu = &self.nes.bus.apu;
+        let first = apu
+            .samples_generated()
+            .saturating_sub(self.audio.len() as u64);
+        self.audio_time =
+            EmulatedTime::from_cpu_cycles(apu.sample_cycle(first), apu.cpu_clock_rate());
+
+        self.framebuffer.data.fill(0);
+        self.nes.bus.render_frame(&mut self.framebuffer);
+        self.frame += 1;
+        &self.framebuffer
+    }
+
+    // Completes the audio sink, such as a WAV file's header, and discards
+    // audio from then on.
+    pub fn finish_audio(&mut self) -> Result<(), String> {
+        self.nes.bus.apu.set_audio_sink(AudioSink::Null).finish()
+    }
+
+    // Applies the movie's input for the next frame and runs it. Returns `None`
+    // once the movie has run out.
+    pub fn run_movie_frame(&mut self, movie: &FM2Movie) -> Option<&Framebuffer> {
+        let record = movie.get_frame_input(self.frame)?;
+        if record.commands & COMMAND_RESET != 0 {
+            self.nes.reset();
+        }
+
+        let (joypad1, joypad2) = self.nes.joypads_mut();
+        movie.apply_frame_input(self.frame, joypad1, joypad2).ok()?;
+        Some(self.run_frame())
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+
+use crate::joypad::JoypadButton;
+use crate::movie::{FM2Movie, GamepadInput, InputRecord, MovieHeader};
+
+// FM2 command bit for a soft reset on that frame.
+pub const COMMAND_RESET: u8 = 0x01;
+
+#[derive(Clone, Copy)]
+struct HistoryFrame {
+    commands: u8,
+    port0: JoypadButton,
+    port1: JoypadButton,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keyframe {
+    pub frame: usize,
+    pub state: Vec<u8>,
+}
+
+// Rolling record of the inputs fed to the console during live play, so the
+// last few minutes can be saved as an FM2 movie after the fact.
+//
+// A movie has to start from a known machine state: either power-on, or a
+// savestate blob handed in through `add_keyframe`. Once the window has scrolled
+// past power-on, old frames are only dropped up to the newest keyframe that
+// still leaves `capacity` frames of history, so a dump always has an anchor.
+// `keyframe_due` says when to hand in the next one; a tenth of the window
+// apart, so a dump keeps at least nine tenths of it.
+pub struct InputHistory {
+    capacity: usize,
+    start_frame: usize,
+    from_power_on: bool,
+    frames: VecDeque<HistoryFrame>,
+    keyframes: VecDeque<Keyframe>,
+}
+
+impl InputHistory {
+    pub fn new(capacity: usize) -> Self {
+        InputHistory {
+            capacity: capacity.max(1),
+            start_frame: 0,
+            from_power_on: true,
+            frames: VecDeque::with_capacity(capacity),
+            keyframes: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Whether it's time for another keyframe, taken before the next `record`.
+    pub fn keyframe_due(&self) -> bool {
+        let last = match self.keyframes.back() {
+            Some(keyframe) => keyframe.frame,
+            None if self.from_power_on => self.start_frame,
+            None => return true,
+        };
+        self.next_frame() - last >= (self.capacity / 10).max(1)
+    }
+
+    // Frame number the next call to `record` will be stored under.
+    pub fn next_frame(&self) -> usize {
+        self.start_frame + self.frames.len()
+    }
+
+    // Forget everything and anchor at power-on again.
+    pub fn clear(&mut self) {
+        self.start_frame = 0;
+        self.from_power_on = true;
+        self.frames.clear();
+        self.keyframes.clear();
+    }
+
+    // Forget everything and anchor at `state`, after the console jumped to it
+    // by loading a savestate or rewinding.
+    pub fn restart_from(&mut self, state: Vec<u8>) {
+        self.clear();
+        self.from_power_on = false;
+        self.keyframes.push_back(Keyframe { frame: 0, state });
+    }
+
+    pub fn record(&mut self, commands: u8, port0: JoypadButton, port1: JoypadButton) {
+        self.frames.push_back(HistoryFrame {
+            commands,
+            port0,
+            port1,
+        });
+        self.trim();
+    }
+
+    // `state` is the machine state at the start of the next recorded frame.
+    pub fn add_keyframe(&mut self, state: Vec<u8>) {
+        let frame = self.next_frame();
+        if self.keyframes.back().is_some_and(|k| k.frame == frame) {
+            self.keyframes.pop_back();
+        }
+        self.keyframes.push_back(Keyframe { frame, state });
+    }
+
+    fn trim(&mut self) {
+        if self.frames.len() <= self.capacity {
+            return;
+        }
+
+        let end = self.next_frame();
+        let anchor = self
+            .keyframes
+            .iter()
+            .rposition(|k| k.frame > self.start_frame && end - k.frame >= self.capacity);
+
+        match anchor {
+            Some(index) => {
+                self.keyframes.drain(..index);
+                self.drop_front(self.keyframes[0].frame - self.start_frame);
+            }
+            // Without keyframes the history can't be anchored anyway, so keep
+            // memory bounded and wait for one.
+            None if self.keyframes.is_empty() => {
+                self.drop_front(self.frames.len() - self.capacity);
+            }
+            None => {}
+        }
+    }
+
+    fn drop_front(&mut self, count: usize) {
+        self.frames.drain(..count);
+        self.start_frame += count;
+        self.from_power_on = false;
+        while self
+            .keyframes
+            .front()
+            .is_some_and(|k| k.frame < self.start_frame)
+        {
+            self.keyframes.pop_front();
+        }
+    }
+
+    // Builds a movie covering as much of the retained history as possible.
+    pub fn to_movie(&self, mut header: MovieHeader) -> Result<FM2Movie, String> {
+        let start = if self.from_power_on {
+            header.savestate = None;
+            self.start_frame
+        } else {
+            let keyframe = self
+                .keyframes
+                .front()
+                .ok_or("Input history has no power-on or savestate anchor")?;
+            header.savestate = Some(keyframe.state.clone());
+            keyframe.frame
+        };
+
+        let input_log: Vec<InputRecord> = self
+            .frames
+            .iter()
+            .skip(start - self.start_frame)
+            .map(|frame| InputRecord {
+                commands: frame.commands,
+                port0_input: Some(GamepadInput::from_buttons(frame.port0)),
+                port1_input: Some(GamepadInput::from_buttons(frame.port1)),
+                port2_input: None,
+            })
+            .collect();
+
+        header.length = Some(input_log.len());
+
+        Ok(FM2Movie { header, input_log })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record_n(history: &mut InputHistory, n: usize) {
+        for _ in 0..n {
+            history.record(0, JoypadButton::BUTTON_A, JoypadButton::empty());
+        }
+    }
+
+    #[test]
+    fn test_power_on_history_round_trips_through_fm2() {
+        let mut history = InputHistory::new(16);
+        history.record(COMMAND_RESET, JoypadButton::empty(), JoypadButton::empty());
+        history.record(
+            0,
+            JoypadButton::RIGHT | JoypadButton::BUTTON_A,
+            JoypadButton::START,
+        );
+
+        let movie = history
+            .to_movie(MovieHeader::for_recording("test.nes"))
+            .unwrap();
+        let mut text = Vec::new();
+        movie.write(&mut text).unwrap();
+
+        let parsed = FM2Movie::parse(text.as_slice()).unwrap();
+        assert_eq!(parsed.input_log.len(), 2);
+        assert_eq!(parsed.input_log[0].commands, COMMAND_RESET);
+        let port0 = parsed.input_log[1].port0_input.as_ref().unwrap();
+        assert_eq!(
+            port0.to_buttons().bits(),
+            (JoypadButton::RIGHT | JoypadButton::BUTTON_A).bits()
+        );
+        let port1 = parsed.input_log[1].port1_input.as_ref().unwrap();
+        assert_eq!(port1.to_buttons().bits(), JoypadButton::START.bits());
+        assert!(parsed.header.savestate.is_none());
+    }
+
+    #[test]
+    fn test_history_without_keyframes_loses_its_anchor() {
+        let mut history = InputHistory::new(4);
+        record_n(&mut history, 6);
+
+        assert_eq!(history.len(), 4);
+        assert!(
+            history
+                .to_movie(MovieHeader::for_recording("t.nes"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_history_trims_to_keyframe() {
+        let mut history = InputHistory::new(4);
+        record_n(&mut history, 2);
+        history.add_keyframe(vec![1, 2, 3, 4, 5]);
+        record_n(&mut history, 6);
+
+        let movie = history
+            .to_movie(MovieHeader::for_recording("t.nes"))
+            .unwrap();
+        assert_eq!(movie.input_log.len(), 6);
+        assert_eq!(
+            movie.header.savestate.as_deref(),
+            Some(&[1, 2, 3, 4, 5][..])
+        );
+
+        let mut text = Vec::new();
+        movie.write(&mut text).unwrap();
+        let parsed = FM2Movie::parse(text.as_slice()).unwrap();
+        assert_eq!(parsed.header.savestate, movie.header.savestate);
+    }
+
+    // Keyframes handed in whenever one is due keep the history anchored
+    // long after power-on has scrolled out of it.
+    #[test]
+    fn test_due_keyframes_keep_a_rolled_over_history_anchored() {
+        let mut history = InputHistory::new(20);
+        for frame in 0..95u8 {
+            if history.keyframe_due() {
+                history.add_keyframe(vec![frame]);
+            }
+            history.record(0, JoypadButton::BUTTON_A, JoypadButton::empty());
+        }
+
+        let movie = history
+            .to_movie(MovieHeader::for_recording("t.nes"))
+            .unwrap();
+        assert!((20..=22).contains(&movie.input_log.len()));
+        let anchor = movie.header.savestate.unwrap()[0] as usize;
+        assert_eq!(anchor + movie.input_log.len(), 95);
+    }
+
+    #[test]
+    fn test_restart_anchors_at_the_given_state() {
+        let mut history = InputHistory::new(4);
+        record_n(&mut history, 3);
+        history.restart_from(vec![9]);
+        assert!(!history.keyframe_due());
+        record_n(&mut history, 6);
+
+        let movie = history
+            .to_movie(MovieHeader::for_recording("t.nes"))
+            .unwrap();
+        assert_eq!(movie.header.savestate.as_deref(), Some(&[9][..]));
+    }
+}
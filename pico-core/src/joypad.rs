@@ -0,0 +1,402 @@
+use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
+
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+bitflags! {
+    #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+    // https://wiki.nesdev.com/w/index.php/Controller_reading_code
+    pub struct JoypadButton: u8 {
+        const RIGHT             = 0b10000000;
+        const LEFT              = 0b01000000;
+        const DOWN              = 0b00100000;
+        const UP                = 0b00010000;
+        const START             = 0b00001000;
+        const SELECT            = 0b00000100;
+        const BUTTON_B          = 0b00000010;
+        const BUTTON_A          = 0b00000001;
+    }
+}
+
+// A standard controller's shift register. While the strobe is high it keeps
+// reloading from the buttons; the 1 to 0 edge latches them, and each read
+// then shifts out the next one, A first.
+pub struct Joypad {
+    // The buttons held right now, set by the frontend.
+    pub button_status: JoypadButton,
+    // Only the Famicom's second controller has one. It isn't shifted out with
+    // the buttons but shows up directly as bit 2 of $4016.
+    pub microphone: bool,
+    latched: JoypadButton,
+    button_index: u8,
+    strobe: bool,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            strobe: false,
+            button_index: 0,
+            latched: JoypadButton::empty(),
+            microphone: false,
+            button_status: JoypadButton::from_bits_truncate(0),
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        let strobe = data & 1 == 1;
+        if self.strobe && !strobe {
+            self.latched = self.button_status;
+        }
+        self.strobe = strobe;
+        if self.strobe {
+            self.button_index = 0
+        }
+    }
+
+    // With the strobe high every read sees A as it is held now. Past the
+    // eighth read the register has shifted in nothing but 1s.
+    pub fn read(&mut self) -> u8 {
+        let response = self.peek();
+        if !self.strobe && self.button_index <= 7 {
+            self.button_index += 1;
+        }
+        response
+    }
+
+    // The bit the next read returns, without shifting it out.
+    pub fn peek(&self) -> u8 {
+        if self.strobe {
+            return self.button_status.contains(JoypadButton::BUTTON_A) as u8;
+        }
+        if self.button_index > 7 {
+            return 1;
+        }
+        (self.latched.bits() >> self.button_index) & 1
+    }
+
+    // How many buttons have been shifted out since the last strobe, 8 once
+    // they all have.
+    pub fn shift_index(&self) -> u8 {
+        self.button_index
+    }
+
+    pub fn microphone_bit(&self) -> u8 {
+        (self.microphone as u8) << 2
+    }
+
+    pub fn set_button_pressed_status(&mut self, button: JoypadButton, pressed: bool) {
+        self.button_status.set(button, pressed);
+    }
+}
+
+// What live input does when opposite directions are held together, which the
+// rocker on a real pad can't do and some games handle badly. Movies are always
+// played back as recorded, since some rely on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DpadPolicy {
+    #[default]
+    Allow,
+    // Neither direction on that axis.
+    Neutral,
+    // Only the most recently pressed direction.
+    LastPressed,
+}
+
+impl DpadPolicy {
+    pub const ALL: [DpadPolicy; 3] = [
+        DpadPolicy::Allow,
+        DpadPolicy::Neutral,
+        DpadPolicy::LastPressed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DpadPolicy::Allow => "allow",
+            DpadPolicy::Neutral => "neutral",
+            DpadPolicy::LastPressed => "last pressed",
+        }
+    }
+}
+
+// How one player's buttons are rearranged before they reach the console,
+// for pads whose face buttons sit the other way round and for holding the
+// controller upside down with the d-pad under the right thumb.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ButtonLayout {
+    pub swap_ab: bool,
+    // Up/down and left/right are exchanged, as the d-pad is turned round.
+    pub southpaw: bool,
+}
+
+impl ButtonLayout {
+    pub const ALL: [ButtonLayout; 4] = [
+        ButtonLayout {
+            swap_ab: false,
+            southpaw: false,
+        },
+        ButtonLayout {
+            swap_ab: true,
+            southpaw: false,
+        },
+        ButtonLayout {
+            swap_ab: false,
+            southpaw: true,
+        },
+        ButtonLayout {
+            swap_ab: true,
+            southpaw: true,
+        },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match (self.swap_ab, self.southpaw) {
+            (false, false) => "standard",
+            (true, false) => "swap A/B",
+            (false, true) => "southpaw",
+            (true, true) => "southpaw, swap A/B",
+        }
+    }
+
+    pub fn apply(&self, buttons: JoypadButton) -> JoypadButton {
+        let pairs = [
+            (self.swap_ab, JoypadButton::BUTTON_A, JoypadButton::BUTTON_B),
+            (self.southpaw, JoypadButton::UP, JoypadButton::DOWN),
+            (self.southpaw, JoypadButton::LEFT, JoypadButton::RIGHT),
+        ];
+
+        let mut result = buttons;
+        for (first, second) in pairs
+            .into_iter()
+            .filter_map(|(enabled, first, second)| enabled.then_some((first, second)))
+        {
+            result.set(first, buttons.contains(second));
+            result.set(second, buttons.contains(first));
+        }
+        result
+    }
+}
+
+// Applies a `DpadPolicy` to the buttons held each frame. It remembers which
+// direction on each axis went down last, so it has to see every frame.
+#[derive(Default)]
+pub struct DpadFilter {
+    pub policy: DpadPolicy,
+    previous: JoypadButton,
+    last_horizontal: JoypadButton,
+    last_vertical: JoypadButton,
+}
+
+impl DpadFilter {
+    pub fn new(policy: DpadPolicy) -> Self {
+        DpadFilter {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn apply(&mut self, held: JoypadButton) -> JoypadButton {
+        let pressed = held - self.previous;
+        self.previous = held;
+
+        let policy = self.policy;
+        let mut buttons = held;
+        for (axis, last) in [
+            (
+                JoypadButton::LEFT | JoypadButton::RIGHT,
+                &mut self.last_horizontal,
+            ),
+            (
+                JoypadButton::UP | JoypadButton::DOWN,
+                &mut self.last_vertical,
+            ),
+        ] {
+            // Both going down on the same frame leaves no winner.
+            let newly_pressed = pressed & axis;
+            if newly_pressed.bits().count_ones() == 1 {
+                *last = newly_pressed;
+            }
+
+            if held.contains(axis) {
+                buttons.remove(axis);
+                match policy {
+                    DpadPolicy::Allow => buttons.insert(axis),
+                    DpadPolicy::Neutral => {}
+                    DpadPolicy::LastPressed => buttons.insert(*last & axis),
+                }
+            }
+        }
+        buttons
+    }
+}
+
+// Buttons pressed between frames, such as while paused or in slow motion,
+// kept for the frame that runs next. Without it a tap between two frame
+// advances would never reach the console, since only what is held when a
+// frame starts counts.
+#[derive(Default)]
+pub struct PauseLatch {
+    buttons: [JoypadButton; 2],
+}
+
+impl PauseLatch {
+    // Call with each player's buttons whenever the frontend checks its input
+    // without running a frame.
+    pub fn hold(&mut self, buttons: [JoypadButton; 2]) {
+        self.buttons[0] |= buttons[0];
+        self.buttons[1] |= buttons[1];
+    }
+
+    // The buttons for the frame about to run: those held now and those
+    // pressed since the last frame.
+    pub fn take(&mut self, buttons: [JoypadButton; 2]) -> [JoypadButton; 2] {
+        let latched = std::mem::take(&mut self.buttons);
+        [buttons[0] | latched[0], buttons[1] | latched[1]]
+    }
+}
+
+impl Savestate for Joypad {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.button_status.bits());
+        state.write_u8(self.button_index);
+        state.write_bool(self.microphone);
+        state.write_bool(self.strobe);
+        state.write_u8(self.latched.bits());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.button_status = JoypadButton::from_bits_truncate(state.read_u8()?);
+        self.button_index = state.read_u8()?;
+        self.microphone = state.read_bool()?;
+        self.strobe = state.read_bool()?;
+        self.latched = JoypadButton::from_bits_truncate(state.read_u8()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pause_latch_keeps_taps_for_the_next_frame() {
+        let mut latch = PauseLatch::default();
+        latch.hold([JoypadButton::BUTTON_A, JoypadButton::empty()]);
+        latch.hold([JoypadButton::empty(), JoypadButton::START]);
+        assert_eq!(
+            latch.take([JoypadButton::RIGHT, JoypadButton::empty()]),
+            [
+                JoypadButton::BUTTON_A | JoypadButton::RIGHT,
+                JoypadButton::START
+            ]
+        );
+        let none = [JoypadButton::empty(); 2];
+        assert_eq!(latch.take(none), none, "only for one frame");
+    }
+
+    #[test]
+    fn test_strobe_mode() {
+        let mut joypad = Joypad::new();
+        joypad.write(1);
+        joypad.set_button_pressed_status(JoypadButton::BUTTON_A, true);
+        for _x in 0..10 {
+            assert_eq!(joypad.read(), 1);
+        }
+    }
+
+    #[test]
+    fn test_microphone_bit() {
+        let mut joypad = Joypad::new();
+        assert_eq!(joypad.microphone_bit(), 0);
+        joypad.microphone = true;
+        assert_eq!(joypad.microphone_bit(), 0b100);
+        assert_eq!(joypad.read(), 0);
+    }
+
+    #[test]
+    fn test_strobe_mode_on_off() {
+        let mut joypad = Joypad::new();
+
+        joypad.set_button_pressed_status(JoypadButton::RIGHT, true);
+        joypad.set_button_pressed_status(JoypadButton::LEFT, true);
+        joypad.set_button_pressed_status(JoypadButton::SELECT, true);
+        joypad.set_button_pressed_status(JoypadButton::BUTTON_B, true);
+        joypad.write(1);
+        joypad.write(0);
+
+        for _ in 0..=1 {
+            assert_eq!(joypad.read(), 0);
+            assert_eq!(joypad.read(), 1);
+            assert_eq!(joypad.read(), 1);
+            assert_eq!(joypad.read(), 0);
+            assert_eq!(joypad.read(), 0);
+            assert_eq!(joypad.read(), 0);
+            assert_eq!(joypad.read(), 1);
+            assert_eq!(joypad.read(), 1);
+
+            for _x in 0..10 {
+                assert_eq!(joypad.read(), 1);
+            }
+            joypad.write(1);
+            joypad.write(0);
+        }
+    }
+
+    #[test]
+    fn test_dpad_policies() {
+        let left = JoypadButton::LEFT;
+        let right = JoypadButton::RIGHT;
+        let both = left | right | JoypadButton::BUTTON_A;
+
+        let mut allow = DpadFilter::new(DpadPolicy::Allow);
+        assert_eq!(allow.apply(both), both);
+
+        let mut neutral = DpadFilter::new(DpadPolicy::Neutral);
+        assert_eq!(neutral.apply(left), left);
+        assert_eq!(neutral.apply(both), JoypadButton::BUTTON_A);
+
+        let mut last = DpadFilter::new(DpadPolicy::LastPressed);
+        assert_eq!(last.apply(left), left);
+        assert_eq!(last.apply(both), right | JoypadButton::BUTTON_A);
+        assert_eq!(last.apply(both), right | JoypadButton::BUTTON_A);
+        assert_eq!(last.apply(left), left);
+        // Pressed together from nothing: no winner yet on this axis.
+        let mut last = DpadFilter::new(DpadPolicy::LastPressed);
+        let up_down = JoypadButton::UP | JoypadButton::DOWN;
+        assert_eq!(last.apply(up_down), JoypadButton::empty());
+    }
+
+    #[test]
+    fn test_button_layouts() {
+        let held = JoypadButton::UP | JoypadButton::LEFT | JoypadButton::BUTTON_A;
+        assert_eq!(ButtonLayout::default().apply(held), held);
+
+        let swapped = ButtonLayout {
+            swap_ab: true,
+            southpaw: false,
+        };
+        assert_eq!(
+            swapped.apply(held),
+            JoypadButton::UP | JoypadButton::LEFT | JoypadButton::BUTTON_B
+        );
+        let both = JoypadButton::BUTTON_A | JoypadButton::BUTTON_B;
+        assert_eq!(swapped.apply(both), both);
+
+        let southpaw = ButtonLayout {
+            swap_ab: false,
+            southpaw: true,
+        };
+        assert_eq!(
+            southpaw.apply(held | JoypadButton::START),
+            JoypadButton::DOWN | JoypadButton::RIGHT | JoypadButton::BUTTON_A | JoypadButton::START
+        );
+    }
+}
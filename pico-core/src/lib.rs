@@ -0,0 +1,41 @@
+#![forbid(unsafe_code)]
+
+pub mod apu;
+pub mod battery;
+pub mod bk2;
+pub mod bus;
+pub mod cart;
+pub mod cheats;
+pub mod chr_file;
+pub mod compare;
+pub mod compat;
+pub mod cpu;
+pub mod debugger;
+pub mod decode_cache;
+pub mod disasm;
+pub mod event;
+pub mod headless;
+pub mod input_history;
+pub mod joypad;
+pub mod mapper;
+pub mod memory;
+pub mod movie;
+pub mod nes;
+pub mod nsf;
+pub mod opcodes;
+pub mod pacing;
+pub mod pipe_input;
+pub mod ppu;
+pub mod ram_search;
+pub mod region;
+pub mod rewind;
+pub mod rom_db;
+pub mod savestate;
+pub mod state_stream;
+pub mod stats;
+#[cfg(test)]
+pub mod testutil;
+pub mod trace;
+pub mod vs_system;
+
+extern crate bitflags;
@@ -0,0 +1,152 @@
+// AxROM (mapper 7): one register at $8000-$FFFF selects a 32 KiB PRG bank with
+// its low bits and which CIRAM page all four nametables show with bit 4.
+use crate::cart::Mirroring;
+use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+pub struct AxromMapper {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    bank_select: u8,
+    bus_conflicts: bool,
+    prg_remapped: bool,
+}
+
+impl AxromMapper {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = if chr_is_ram { vec![0; 0x2000] } else { chr_rom };
+
+        AxromMapper {
+            prg_rom,
+            chr,
+            chr_is_ram,
+            bank_select: 0,
+            bus_conflicts: false,
+            prg_remapped: false,
+        }
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).max(1)
+    }
+}
+
+impl Savestate for AxromMapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.bank_select);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        self.bank_select = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Mapper for AxromMapper {
+    fn read_prg(&self, addr: u16) -> u8 {
+        if addr < 0x8000 || self.prg_rom.is_empty() {
+            return 0;
+        }
+        let bank = (self.bank_select & 0x07) as usize % self.prg_bank_count();
+        let index = bank * PRG_BANK_SIZE + (addr as usize - 0x8000);
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            let bank_select = if self.bus_conflicts {
+                data & self.read_prg(addr)
+            } else {
+                data
+            };
+            self.prg_remapped |= (bank_select ^ self.bank_select) & 0x07 != 0;
+            self.bank_select = bank_select;
+        }
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
+    fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        if self.chr.is_empty() {
+            0
+        } else {
+            self.chr[addr as usize % self.chr.len()]
+        }
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        (!self.chr.is_empty()).then(|| addr as usize % self.chr.len())
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && !self.chr.is_empty() {
+            let index = addr as usize % self.chr.len();
+            self.chr[index] = data;
+        }
+    }
+
+    // Only the AMROM and AOROM revisions are wired this way.
+    fn set_bus_conflicts(&mut self, enabled: bool) {
+        self.bus_conflicts = enabled;
+    }
+
+    fn maps_read(&self, addr: u16) -> bool {
+        addr >= 0x8000
+    }
+
+    fn handles_write(&self, addr: u16) -> bool {
+        addr >= 0x8000
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.bank_select & 0x10 != 0 {
+            Mirroring::SingleScreenUpper
+        } else {
+            Mirroring::SingleScreenLower
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axrom_switches_32k_prg_and_one_screen_page() {
+        let prg = (0..8 * PRG_BANK_SIZE)
+            .map(|i| (i / PRG_BANK_SIZE) as u8)
+            .collect();
+        let mut mapper = AxromMapper::new(prg, vec![]);
+        assert_eq!(mapper.read_prg(0xFFFF), 0);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+
+        mapper.write_prg(0x8000, 0x15);
+        assert_eq!(mapper.read_prg(0x8000), 5);
+        assert_eq!(mapper.read_prg(0xFFFF), 5);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+
+        mapper.write_prg(0xC000, 0x02);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+    }
+}
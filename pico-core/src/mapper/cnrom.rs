@@ -1,5 +1,6 @@
 use crate::cart::Mirroring;
 use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 const CHR_BANK_SIZE: usize = 0x2000;
 
@@ -10,6 +11,7 @@ pub struct CnromMapper {
     prg_ram: Vec<u8>,
     chr_bank: u8,
     mirroring: Mirroring,
+    bus_conflicts: bool,
 }
 
 impl CnromMapper {
@@ -28,6 +30,7 @@ impl CnromMapper {
             prg_ram: vec![0; 0x2000],
             chr_bank: 0,
             mirroring,
+            bus_conflicts: false,
         }
     }
 
@@ -37,6 +40,25 @@ impl CnromMapper {
     }
 }
 
+impl Savestate for CnromMapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(self.chr_bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        state.read_bytes_into(&mut self.prg_ram)?;
+        self.chr_bank = state.read_u8()?;
+        Ok(())
+    }
+}
+
 impl Mapper for CnromMapper {
     fn read_prg(&self, addr: u16) -> u8 {
         match addr {
@@ -59,6 +81,11 @@ impl Mapper for CnromMapper {
                 self.prg_ram[(addr - 0x6000) as usize] = data;
             }
             0x8000..=0xFFFF => {
+                let data = if self.bus_conflicts {
+                    data & self.read_prg(addr)
+                } else {
+                    data
+                };
                 let count = self.chr_bank_count() as u8;
                 self.chr_bank = if count == 0 { 0 } else { data % count };
             }
@@ -66,14 +93,23 @@ impl Mapper for CnromMapper {
         }
     }
 
+    // Only CHR is banked.
+    fn take_prg_remapped(&mut self) -> bool {
+        false
+    }
+
     fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
         if self.chr.is_empty() {
-            0
+            None
         } else {
             let bank = (self.chr_bank as usize % self.chr_bank_count()) * CHR_BANK_SIZE;
             let offset = (addr as usize) & 0x1FFF;
             let index = bank + offset;
-            self.chr[index % self.chr.len()]
+            Some(index % self.chr.len())
         }
     }
 
@@ -88,6 +124,30 @@ impl Mapper for CnromMapper {
         }
     }
 
+    fn set_bus_conflicts(&mut self, enabled: bool) {
+        self.bus_conflicts = enabled;
+    }
+
+    fn handles_write(&self, addr: u16) -> bool {
+        addr >= 0x6000
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
     fn mirroring(&self) -> Mirroring {
         self.mirroring.clone()
     }
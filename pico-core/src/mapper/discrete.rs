@@ -0,0 +1,398 @@
+use crate::cart::Mirroring;
+use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+const PRG_BANK_SIZE_16K: usize = 0x4000;
+const PRG_BANK_SIZE_32K: usize = 0x8000;
+const CHR_BANK_SIZE_4K: usize = 0x1000;
+const CHR_BANK_SIZE_8K: usize = 0x2000;
+
+// Which CPU range the single latch register answers to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Register {
+    Low,  // $6000-$7FFF
+    High, // $8000-$FFFF, with bus conflicts against PRG ROM
+}
+
+#[derive(Clone, Copy)]
+enum PrgLayout {
+    Fixed32,
+    Switch32(fn(u8) -> usize),
+    // Switchable 16K at $8000, last bank fixed at $C000.
+    Switch16(fn(u8) -> usize),
+}
+
+#[derive(Clone, Copy)]
+enum ChrLayout {
+    Switch8(fn(u8) -> usize),
+    Switch4(fn(u8) -> usize, fn(u8) -> usize),
+}
+
+// Boards built from a latch and a few 74-series chips: one register whose bits
+// select banks, so they only differ in where the register lives and how its
+// bits are wired.
+pub struct DiscreteBoard {
+    register: Register,
+    prg: PrgLayout,
+    chr: ChrLayout,
+    mirroring: Option<fn(u8) -> Mirroring>,
+}
+
+pub fn board(mapper: u8) -> Option<&'static DiscreteBoard> {
+    let board = match mapper {
+        // Color Dreams and other unlicensed boards.
+        11 => &DiscreteBoard {
+            register: Register::High,
+            prg: PrgLayout::Switch32(|v| (v & 0x03) as usize),
+            chr: ChrLayout::Switch8(|v| (v >> 4) as usize),
+            mirroring: None,
+        },
+        // BNROM: Deadly Towers and a lot of homebrew, which uses every bit
+        // of the register for bigger ROMs. CHR is 8 KiB of RAM. NINA-001
+        // shares the mapper number; see `nina001`.
+        34 => &DiscreteBoard {
+            register: Register::High,
+            prg: PrgLayout::Switch32(|v| v as usize),
+            chr: ChrLayout::Switch8(|_| 0),
+            mirroring: None,
+        },
+        // Jaleco JF-05..08 and friends: CHR bits are wired swapped.
+        87 => &DiscreteBoard {
+            register: Register::Low,
+            prg: PrgLayout::Fixed32,
+            chr: ChrLayout::Switch8(|v| (((v & 0x01) << 1) | ((v & 0x02) >> 1)) as usize),
+            mirroring: None,
+        },
+        // GxROM/MxROM: SMB + Duck Hunt multicarts, Dragon Power.
+        66 => &DiscreteBoard {
+            register: Register::High,
+            prg: PrgLayout::Switch32(|v| ((v >> 4) & 0x03) as usize),
+            chr: ChrLayout::Switch8(|v| (v & 0x03) as usize),
+            mirroring: None,
+        },
+        // Jaleco JF-11/JF-14
+        140 => &DiscreteBoard {
+            register: Register::Low,
+            prg: PrgLayout::Switch32(|v| ((v >> 4) & 0x03) as usize),
+            chr: ChrLayout::Switch8(|v| (v & 0x0F) as usize),
+            mirroring: None,
+        },
+        // Sunsoft-1
+        184 => &DiscreteBoard {
+            register: Register::Low,
+            prg: PrgLayout::Fixed32,
+            chr: ChrLayout::Switch4(|v| (v & 0x07) as usize, |v| ((v >> 4) & 0x07) as usize),
+            mirroring: None,
+        },
+        // Bandai 74161 with one-screen mirroring control
+        152 => &DiscreteBoard {
+            register: Register::High,
+            prg: PrgLayout::Switch16(|v| ((v >> 4) & 0x07) as usize),
+            chr: ChrLayout::Switch8(|v| (v & 0x0F) as usize),
+            mirroring: Some(|v| {
+                if v & 0x80 != 0 {
+                    Mirroring::SingleScreenUpper
+                } else {
+                    Mirroring::SingleScreenLower
+                }
+            }),
+        },
+        // Bandai 74161 with hardwired mirroring
+        70 => &DiscreteBoard {
+            register: Register::High,
+            prg: PrgLayout::Switch16(|v| ((v >> 4) & 0x0F) as usize),
+            chr: ChrLayout::Switch8(|v| (v & 0x0F) as usize),
+            mirroring: None,
+        },
+        _ => return None,
+    };
+    Some(board)
+}
+
+pub struct DiscreteMapper {
+    board: &'static DiscreteBoard,
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    latch: u8,
+    mirroring: Mirroring,
+    prg_remapped: bool,
+}
+
+impl DiscreteMapper {
+    pub fn new(
+        board: &'static DiscreteBoard,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        mirroring: Mirroring,
+    ) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_BANK_SIZE_8K]
+        } else {
+            chr_rom
+        };
+
+        DiscreteMapper {
+            board,
+            prg_rom,
+            chr,
+            chr_is_ram,
+            latch: 0,
+            mirroring,
+            prg_remapped: false,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let offset = (addr - 0x8000) as usize;
+        match self.board.prg {
+            PrgLayout::Fixed32 => offset,
+            PrgLayout::Switch32(select) => select(self.latch) * PRG_BANK_SIZE_32K + offset,
+            PrgLayout::Switch16(select) => {
+                let bank = if addr < 0xC000 {
+                    select(self.latch)
+                } else {
+                    (self.prg_rom.len() / PRG_BANK_SIZE_16K).max(1) - 1
+                };
+                bank * PRG_BANK_SIZE_16K + (offset & 0x3FFF)
+            }
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = addr as usize & 0x1FFF;
+        let offset = match self.board.chr {
+            ChrLayout::Switch8(select) => select(self.latch) * CHR_BANK_SIZE_8K + addr,
+            ChrLayout::Switch4(low, high) => {
+                let bank = if addr < CHR_BANK_SIZE_4K {
+                    low(self.latch)
+                } else {
+                    high(self.latch)
+                };
+                bank * CHR_BANK_SIZE_4K + (addr & 0x0FFF)
+            }
+        };
+        offset % self.chr.len()
+    }
+}
+
+impl Savestate for DiscreteMapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_u8(self.latch);
+        self.mirroring.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        self.latch = state.read_u8()?;
+        self.mirroring.load_state(state)?;
+        Ok(())
+    }
+}
+
+impl Mapper for DiscreteMapper {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                self.prg_rom[self.prg_offset(addr) % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        let latch = self.latch;
+        match (self.board.register, addr) {
+            (Register::Low, 0x6000..=0x7FFF) => self.latch = data,
+            (Register::High, 0x8000..=0xFFFF) => self.latch = data & self.read_prg(addr),
+            _ => {}
+        }
+        self.prg_remapped |= self.latch != latch && !matches!(self.board.prg, PrgLayout::Fixed32);
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
+    fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        Some(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn maps_read(&self, addr: u16) -> bool {
+        addr >= 0x8000
+    }
+
+    fn handles_write(&self, addr: u16) -> bool {
+        matches!(
+            (self.board.register, addr),
+            (Register::Low, 0x6000..=0x7FFF) | (Register::High, 0x8000..=0xFFFF)
+        )
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.board.mirroring {
+            Some(select) => select(self.latch),
+            None => self.mirroring.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::patterned;
+
+    fn mapper(number: u8, prg_banks_16k: usize, chr_banks_4k: usize) -> DiscreteMapper {
+        DiscreteMapper::new(
+            board(number).unwrap(),
+            patterned(prg_banks_16k, PRG_BANK_SIZE_16K),
+            patterned(chr_banks_4k, CHR_BANK_SIZE_4K),
+            Mirroring::Vertical,
+        )
+    }
+
+    #[test]
+    fn mapper_11_switches_32k_prg_and_8k_chr_with_bus_conflicts() {
+        let mut prg = vec![0xFF; 8 * PRG_BANK_SIZE_16K];
+        for (i, bank) in prg.chunks_mut(PRG_BANK_SIZE_32K).enumerate() {
+            bank[0] = i as u8;
+        }
+        prg[1] = 0xF3;
+        let mut mapper = DiscreteMapper::new(
+            board(11).unwrap(),
+            prg,
+            patterned(32, CHR_BANK_SIZE_4K),
+            Mirroring::Vertical,
+        );
+
+        mapper.write_prg(0xFFFF, 0x52);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 10);
+        assert_eq!(mapper.read_chr(0x1000, ChrSource::Cpu), 11);
+
+        // ROM at $8001 of bank 0 holds $F3, dropping bits 2 and 3.
+        mapper.write_prg(0xFFFF, 0x00);
+        mapper.write_prg(0x8001, 0x7F);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 14);
+    }
+
+    #[test]
+    fn mapper_34_bnrom_switches_32k_prg_over_chr_ram() {
+        let mut prg = patterned(16, PRG_BANK_SIZE_16K);
+        for bank in prg.chunks_mut(PRG_BANK_SIZE_32K) {
+            bank[0x7FFF] = 0xFF;
+        }
+        let mut mapper =
+            DiscreteMapper::new(board(34).unwrap(), prg, Vec::new(), Mirroring::Vertical);
+
+        mapper.write_prg(0xFFFF, 0x06);
+        assert_eq!(mapper.read_prg(0x8000), 12);
+        assert_eq!(mapper.read_prg(0xC000), 13);
+
+        mapper.write_chr(0x1234, 0x99);
+        assert_eq!(mapper.read_chr(0x1234, ChrSource::Cpu), 0x99);
+        assert!(mapper.chr_ram_mut().is_some());
+    }
+
+    #[test]
+    fn mapper_87_swaps_chr_bits() {
+        let mut mapper = mapper(87, 2, 8);
+        mapper.write_prg(0x6000, 0x01);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 4);
+        mapper.write_prg(0x6000, 0x02);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 2);
+    }
+
+    #[test]
+    fn mapper_66_switches_32k_prg_and_8k_chr_from_one_register() {
+        let mut prg = patterned(8, PRG_BANK_SIZE_16K);
+        // Writes go through ROM; make the bytes written to all ones.
+        for bank in prg.chunks_mut(PRG_BANK_SIZE_32K) {
+            bank[0x7FFF] = 0xFF;
+        }
+        let mut mapper = DiscreteMapper::new(
+            board(66).unwrap(),
+            prg,
+            patterned(8, CHR_BANK_SIZE_4K),
+            Mirroring::Horizontal,
+        );
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 0);
+
+        mapper.write_prg(0xFFFF, 0x21);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xC000), 5);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 2);
+        assert_eq!(mapper.read_chr(0x1000, ChrSource::Cpu), 3);
+
+        mapper.write_prg(0xFFFF, 0x13);
+        assert_eq!(mapper.read_prg(0xFFFE), 3);
+        assert_eq!(mapper.read_chr(0x1FFF, ChrSource::Cpu), 7);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn mapper_140_switches_32k_prg_and_8k_chr() {
+        let mut mapper = mapper(140, 8, 8);
+        mapper.write_prg(0x6000, 0x23);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xC000), 5);
+        assert_eq!(mapper.read_chr(0x1000, ChrSource::Cpu), 7);
+    }
+
+    #[test]
+    fn mapper_184_switches_4k_chr_halves() {
+        let mut mapper = mapper(184, 2, 8);
+        mapper.write_prg(0x6000, 0x52);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 2);
+        assert_eq!(mapper.read_chr(0x1000, ChrSource::Cpu), 5);
+    }
+
+    #[test]
+    fn mapper_152_has_bus_conflicts_and_one_screen_mirroring() {
+        let mut prg = vec![0xFF; 4 * PRG_BANK_SIZE_16K];
+        prg[0] = 0x9F;
+        let mut mapper = DiscreteMapper::new(
+            board(152).unwrap(),
+            prg,
+            patterned(8, CHR_BANK_SIZE_4K),
+            Mirroring::Vertical,
+        );
+
+        // ROM at $8000 holds $9F, so bit 5 of the written value is lost.
+        mapper.write_prg(0x8000, 0x20);
+        assert_eq!(mapper.latch, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+
+        mapper.write_prg(0xC000, 0x91);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+        assert_eq!(mapper.read_prg(0x8000), 0xFF);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 2);
+    }
+}
@@ -0,0 +1,182 @@
+// MMC4 (mapper 10, FxROM), used by Fire Emblem and Famicom Wars. The same CHR
+// latches as MMC2, with a switchable 16 KiB PRG bank at $8000 and the last one
+// fixed at $C000.
+use crate::cart::Mirroring;
+use crate::mapper::mmc2::ChrLatches;
+use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+pub struct FxromMapper {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    prg_ram: Vec<u8>,
+    prg_bank: u8,
+    chr_latches: ChrLatches,
+    mirroring: Mirroring,
+    prg_remapped: bool,
+}
+
+impl FxromMapper {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = if chr_is_ram { vec![0; 0x2000] } else { chr_rom };
+
+        FxromMapper {
+            prg_rom,
+            chr,
+            chr_is_ram,
+            prg_ram: vec![0; 0x2000],
+            prg_bank: 0,
+            chr_latches: ChrLatches::new(false),
+            mirroring,
+            prg_remapped: false,
+        }
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).max(1)
+    }
+}
+
+impl Savestate for FxromMapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(self.prg_bank);
+        self.chr_latches.save_state(state);
+        self.mirroring.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        state.read_bytes_into(&mut self.prg_ram)?;
+        self.prg_bank = state.read_u8()?;
+        self.chr_latches.load_state(state)?;
+        self.mirroring.load_state(state)
+    }
+}
+
+impl Mapper for FxromMapper {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                if self.prg_rom.is_empty() {
+                    return 0;
+                }
+                let count = self.prg_bank_count();
+                let bank = if addr < 0xC000 {
+                    self.prg_bank as usize % count
+                } else {
+                    count - 1
+                };
+                let index = bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1));
+                self.prg_rom[index % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = data,
+            0xA000..=0xAFFF => {
+                self.prg_remapped |= data & 0x0F != self.prg_bank;
+                self.prg_bank = data & 0x0F;
+            }
+            0xB000..=0xEFFF => self.chr_latches.write_bank(addr, data),
+            0xF000..=0xFFFF => {
+                self.mirroring = if data & 0x01 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
+    fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        if self.chr.is_empty() {
+            0
+        } else {
+            self.chr[self.chr_latches.chr_index(addr, self.chr.len()) % self.chr.len()]
+        }
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        (!self.chr.is_empty())
+            .then(|| self.chr_latches.chr_index(addr, self.chr.len()) % self.chr.len())
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && !self.chr.is_empty() {
+            let index = self.chr_latches.chr_index(addr, self.chr.len()) % self.chr.len();
+            self.chr[index] = data;
+        }
+    }
+
+    fn notify_chr_fetch(&mut self, addr: u16) {
+        self.chr_latches.notify_fetch(addr);
+    }
+
+    fn handles_write(&self, addr: u16) -> bool {
+        matches!(addr, 0x6000..=0x7FFF | 0xA000..=0xFFFF)
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::patterned;
+
+    #[test]
+    fn fxrom_banks_16k_prg_and_latches_on_any_byte_of_the_bitplane() {
+        let mut mapper = FxromMapper::new(
+            patterned(8, PRG_BANK_SIZE),
+            patterned(32, 0x1000),
+            Mirroring::Vertical,
+        );
+        mapper.write_prg(0xA000, 0x03);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xBFFF), 3);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+
+        mapper.write_prg(0xB000, 1);
+        mapper.write_prg(0xC000, 2);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Background), 2);
+        mapper.notify_chr_fetch(0x0FDD);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Background), 1);
+    }
+}
@@ -0,0 +1,482 @@
+// Renders a tiny test ROM on every supported board and compares the picture
+// with a stored hash. Each ROM switches banks in vblank and again, along with
+// mirroring, partway down the screen, draws the PRG bank numbers it sees and
+// shows sprites, so a refactor of a mapper or of the renderers that changes
+// what any of them draws fails here. The renderers use the CHR banks and
+// mirroring as they are at the end of the frame, so today the mid-frame
+// writes only move where the scroll split lands. When a change is intended,
+// the failure prints every new hash to paste in.
+use crate::headless::Headless;
+use crate::ppu::render::Renderer;
+use crate::rom_db::crc32;
+use crate::testutil::{Asm, CODE_ORIGIN, IDENTITY, RomBuilder};
+
+// Enough for the ROM to settle into the same picture every frame.
+const FRAMES: usize = 20;
+
+#[derive(Clone, Copy)]
+struct Board {
+    mapper: u8,
+    prg_banks: usize,
+    chr_banks: usize,
+    vertical_mirroring: bool,
+    // Run once at reset, at the start of each frame's vblank, and about half
+    // way down the screen.
+    init: fn(&mut Asm),
+    top: fn(&mut Asm),
+    mid: fn(&mut Asm),
+    // Hashes with the ScrollSegments and Scanline renderers.
+    golden: [u32; 2],
+}
+
+fn nothing(_: &mut Asm) {}
+
+// Writes a latch through the identity table, so a bus conflict leaves the
+// value unchanged.
+fn latch(asm: &mut Asm, value: u8) {
+    asm.poke(IDENTITY + value as u16, value);
+}
+
+// MMC1 registers take five writes of one bit each.
+fn mmc1(asm: &mut Asm, register: u16, value: u8) {
+    asm.lda_imm(value).sta(register);
+    for _ in 0..4 {
+        asm.lsr().sta(register);
+    }
+}
+
+// MMC3 bank registers go through the select and data pair.
+fn mmc3(asm: &mut Asm, register: u8, bank: u8) {
+    asm.poke(0x8000, register).poke(0x8001, bank);
+}
+
+const BOARDS: [Board; 18] = [
+    Board {
+        mapper: 0,
+        prg_banks: 2,
+        chr_banks: 1,
+        vertical_mirroring: true,
+        init: nothing,
+        top: nothing,
+        mid: nothing,
+        golden: [0x27AA8F9C, 0x27AA8F9C],
+    },
+    Board {
+        mapper: 1,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: false,
+        init: |asm| {
+            asm.poke(0x8000, 0x80);
+        },
+        // 4 KiB CHR banks, last PRG bank fixed, vertical then horizontal.
+        top: |asm| {
+            mmc1(asm, 0x8000, 0x1E);
+            mmc1(asm, 0xA000, 2);
+            mmc1(asm, 0xC000, 5);
+            mmc1(asm, 0xE000, 3);
+        },
+        mid: |asm| {
+            mmc1(asm, 0xA000, 9);
+            mmc1(asm, 0x8000, 0x1F);
+        },
+        golden: [0x0F6AEF91, 0x0F6AEF91],
+    },
+    Board {
+        mapper: 2,
+        prg_banks: 8,
+        chr_banks: 0,
+        vertical_mirroring: false,
+        init: nothing,
+        top: |asm| latch(asm, 3),
+        mid: |asm| latch(asm, 5),
+        golden: [0xF88F7820, 0xF88F7820],
+    },
+    Board {
+        mapper: 3,
+        prg_banks: 2,
+        chr_banks: 4,
+        vertical_mirroring: true,
+        init: nothing,
+        top: |asm| latch(asm, 1),
+        mid: |asm| latch(asm, 2),
+        golden: [0x4B8AF8AB, 0x4B8AF8AB],
+    },
+    Board {
+        mapper: 4,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: false,
+        init: nothing,
+        top: |asm| {
+            for (register, bank) in (0..8).zip([0, 2, 4, 5, 6, 7, 1, 2]) {
+                mmc3(asm, register, bank);
+            }
+            asm.poke(0xA000, 0);
+        },
+        mid: |asm| {
+            mmc3(asm, 0, 8);
+            mmc3(asm, 2, 12);
+            asm.poke(0xA000, 1);
+        },
+        golden: [0x5346F5C8, 0x5346F5C8],
+    },
+    Board {
+        mapper: 7,
+        prg_banks: 8,
+        chr_banks: 0,
+        vertical_mirroring: false,
+        init: nothing,
+        top: |asm| latch(asm, 0x11),
+        mid: |asm| latch(asm, 0x01),
+        golden: [0x00172598, 0x00172598],
+    },
+    // MMC2 and MMC4 share registers; the nametables include tiles $FD and
+    // $FE, so the CHR latches flip while the frame is drawn.
+    Board {
+        mapper: 9,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: false,
+        init: nothing,
+        top: mmc2_top,
+        mid: mmc2_mid,
+        golden: [0xDA3AFC2F, 0x52ACD638],
+    },
+    Board {
+        mapper: 10,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: false,
+        init: nothing,
+        top: mmc2_top,
+        mid: mmc2_mid,
+        golden: [0x259EE279, 0x259EE279],
+    },
+    Board {
+        mapper: 11,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: true,
+        init: nothing,
+        top: |asm| latch(asm, 0x31),
+        mid: |asm| latch(asm, 0x52),
+        golden: [0x61CDC170, 0x61CDC170],
+    },
+    // Namco 163, ending the frame with a page of CHR ROM as a nametable.
+    Board {
+        mapper: 19,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: false,
+        init: |asm| {
+            asm.poke(0xC000, 0xE0)
+                .poke(0xC800, 0xE1)
+                .poke(0xD000, 0xE0)
+                .poke(0xD800, 0xE1);
+        },
+        top: |asm| {
+            for (register, bank) in (0..8).zip([2, 3, 8, 9, 12, 13, 4, 5]) {
+                asm.poke(0x8000 + register * 0x800, bank);
+            }
+            asm.poke(0xE000, 3).poke(0xE800, 5).poke(0xF000, 6);
+        },
+        mid: |asm| {
+            asm.poke(0x8000, 20).poke(0xD800, 33);
+        },
+        golden: [0xC39577F7, 0xC39577F7],
+    },
+    // BNROM, with CHR RAM.
+    Board {
+        mapper: 34,
+        prg_banks: 8,
+        chr_banks: 0,
+        vertical_mirroring: false,
+        init: nothing,
+        top: |asm| latch(asm, 1),
+        mid: |asm| latch(asm, 2),
+        golden: [0xA006DA2C, 0xA006DA2C],
+    },
+    // NINA-001, told apart from BNROM by its CHR ROM.
+    Board {
+        mapper: 34,
+        prg_banks: 4,
+        chr_banks: 4,
+        vertical_mirroring: true,
+        init: nothing,
+        top: |asm| {
+            asm.poke(0x7FFD, 1).poke(0x7FFE, 2).poke(0x7FFF, 5);
+        },
+        mid: |asm| {
+            asm.poke(0x7FFF, 6);
+        },
+        golden: [0xC4DEB2A5, 0xC4DEB2A5],
+    },
+    Board {
+        mapper: 66,
+        prg_banks: 8,
+        chr_banks: 4,
+        vertical_mirroring: true,
+        init: nothing,
+        top: |asm| latch(asm, 0x11),
+        mid: |asm| latch(asm, 0x12),
+        golden: [0xBC3F97B5, 0xBC3F97B5],
+    },
+    Board {
+        mapper: 70,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: false,
+        init: nothing,
+        top: |asm| latch(asm, 0x21),
+        mid: |asm| latch(asm, 0x33),
+        golden: [0x8942ECB6, 0x8942ECB6],
+    },
+    Board {
+        mapper: 87,
+        prg_banks: 2,
+        chr_banks: 4,
+        vertical_mirroring: true,
+        init: nothing,
+        top: |asm| {
+            asm.poke(0x6000, 0x01);
+        },
+        mid: |asm| {
+            asm.poke(0x6000, 0x02);
+        },
+        golden: [0x9089CDE5, 0x9089CDE5],
+    },
+    Board {
+        mapper: 140,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: false,
+        init: nothing,
+        top: |asm| {
+            asm.poke(0x6000, 0x11);
+        },
+        mid: |asm| {
+            asm.poke(0x6000, 0x23);
+        },
+        golden: [0xBDB650CF, 0xBDB650CF],
+    },
+    Board {
+        mapper: 152,
+        prg_banks: 8,
+        chr_banks: 8,
+        vertical_mirroring: false,
+        init: nothing,
+        top: |asm| latch(asm, 0x21),
+        mid: |asm| latch(asm, 0xB3),
+        golden: [0x12ACDE3C, 0x12ACDE3C],
+    },
+    Board {
+        mapper: 184,
+        prg_banks: 2,
+        chr_banks: 4,
+        vertical_mirroring: true,
+        init: nothing,
+        top: |asm| {
+            asm.poke(0x6000, 0x21);
+        },
+        mid: |asm| {
+            asm.poke(0x6000, 0x43);
+        },
+        golden: [0x675A8252, 0x675A8252],
+    },
+];
+
+fn mmc2_top(asm: &mut Asm) {
+    asm.poke(0xA000, 1)
+        .poke(0xB000, 2)
+        .poke(0xC000, 3)
+        .poke(0xD000, 4)
+        .poke(0xE000, 5)
+        .poke(0xF000, 0);
+}
+
+fn mmc2_mid(asm: &mut Asm) {
+    asm.poke(0xB000, 6).poke(0xF000, 1);
+}
+
+// Writes `pages` * 256 bytes from $xx00 in VRAM, byte n of page p being n + p
+// counting pages down from `pages`, which gives each nametable different tiles
+// and attributes and CHR RAM some varied patterns.
+fn fill_vram(asm: &mut Asm, addr_hi: u8, pages: u8, outer: &'static str, inner: &'static str) {
+    asm.lda_imm(addr_hi)
+        .sta(0x2006)
+        .lda_imm(0)
+        .sta(0x2006)
+        .ldy_imm(pages)
+        .ldx_imm(0)
+        .label(outer)
+        .sty_zp(0x00)
+        .label(inner)
+        .txa()
+        .clc()
+        .adc_zp(0x00)
+        .sta(0x2007)
+        .inx()
+        .bne(inner)
+        .dey()
+        .bne(outer);
+}
+
+fn program(board: &Board) -> Asm {
+    let mut asm = Asm::new(CODE_ORIGIN);
+    asm.label("reset").sei().cld().ldx_imm(0xFF).txs();
+    (board.init)(&mut asm);
+    asm.poke(0x2000, 0)
+        .sta(0x2001)
+        .label("vblank1")
+        .bit(0x2002)
+        .bpl("vblank1")
+        .label("vblank2")
+        .bit(0x2002)
+        .bpl("vblank2");
+
+    if board.chr_banks == 0 {
+        fill_vram(&mut asm, 0x00, 0x20, "chr_page", "chr_byte");
+    }
+    fill_vram(&mut asm, 0x20, 0x10, "nt_page", "nt_byte");
+    asm.poke(0x2006, 0x3F)
+        .poke(0x2006, 0x00)
+        .ldx_imm(0)
+        .label("palette_loop")
+        .lda_label_x("palette")
+        .sta(0x2007)
+        .inx()
+        .cpx_imm(32)
+        .bne("palette_loop");
+
+    // Sprite n sits at (4n + 3, 4n) with tile 4n + 1 and attributes 4n + 2.
+    asm.ldx_imm(0)
+        .label("oam_loop")
+        .txa()
+        .sta_x(0x0200)
+        .inx()
+        .bne("oam_loop");
+
+    asm.poke(0x2000, 0x88)
+        .poke(0x2001, 0x1E)
+        .label("forever")
+        .jmp("forever");
+
+    asm.label("nmi")
+        .bit(0x2002)
+        .poke(0x2003, 0)
+        .poke(0x4014, 0x02);
+    (board.top)(&mut asm);
+    // Tiles showing which 8 KiB PRG banks are at $8000, $A000 and $C000.
+    asm.poke(0x2006, 0x20)
+        .poke(0x2006, 0x42)
+        .lda(0x8000)
+        .sta(0x2007)
+        .lda(0xA000)
+        .sta(0x2007)
+        .lda(0xC000)
+        .sta(0x2007)
+        .poke(0x2005, 0)
+        .sta(0x2005)
+        .poke(0x2000, 0x88);
+
+    // About 140 scanlines, which with vblank and OAM DMA lands near the
+    // middle of the picture.
+    asm.ldx_imm(12)
+        .label("delay_outer")
+        .ldy_imm(0)
+        .label("delay_inner")
+        .dey()
+        .bne("delay_inner")
+        .dex()
+        .bne("delay_outer");
+    (board.mid)(&mut asm);
+    // A lone first write, moving the lower part of the picture sideways.
+    asm.poke(0x2005, 0x40).rti();
+
+    asm.label("palette").bytes(&[
+        0x0F, 0x01, 0x11, 0x21, 0x0F, 0x06, 0x16, 0x26, 0x0F, 0x09, 0x19, 0x29, 0x0F, 0x04, 0x14,
+        0x24, 0x0F, 0x02, 0x12, 0x22, 0x0F, 0x07, 0x17, 0x27, 0x0F, 0x0A, 0x1A, 0x2A, 0x0F, 0x05,
+        0x15, 0x25,
+    ]);
+    asm
+}
+
+fn rom(board: &Board) -> RomBuilder {
+    let mut rom = RomBuilder::new(board.mapper)
+        .banks(board.prg_banks, board.chr_banks)
+        .code(&program(board), "nmi", "reset", "nmi");
+    if board.vertical_mirroring {
+        rom = rom.vertical_mirroring();
+    }
+    rom
+}
+
+fn frame_hash(board: &Board, renderer: Renderer, fast_cpu: bool) -> u32 {
+    let mut headless = Headless::new(rom(board).cart());
+    headless.nes.set_renderer(Some(renderer));
+    headless.nes.set_fast_cpu(fast_cpu);
+    for _ in 0..FRAMES {
+        headless.run_frame();
+    }
+    let data = &headless.framebuffer().data;
+    assert!(
+        data.chunks(3).any(|pixel| pixel != &data[..3]),
+        "mapper {} with {} drew a blank frame",
+        board.mapper,
+        renderer.name()
+    );
+    crc32(data)
+}
+
+#[test]
+fn test_frames_match_golden_hashes() {
+    let mut mismatched = false;
+    let mut report = String::new();
+    for board in &BOARDS {
+        let hashes = Renderer::ALL.map(|renderer| frame_hash(board, renderer, false));
+        mismatched |= hashes != board.golden;
+        report += &format!(
+            "mapper {:>3}: [0x{:08X}, 0x{:08X}]{}\n",
+            board.mapper,
+            hashes[0],
+            hashes[1],
+            if hashes != board.golden {
+                " changed"
+            } else {
+                ""
+            }
+        );
+    }
+    assert!(!mismatched, "rendered frames changed:\n{}", report);
+}
+
+#[test]
+fn test_bank_switches_show_on_screen() {
+    // Without the writes each frame starts with, every board but NROM draws
+    // something else.
+    for board in BOARDS.iter().filter(|board| board.mapper != 0) {
+        let still = Board {
+            top: nothing,
+            ..*board
+        };
+        assert_ne!(
+            frame_hash(board, Renderer::default(), false),
+            frame_hash(&still, Renderer::default(), false),
+            "mapper {}",
+            board.mapper
+        );
+    }
+}
+
+#[test]
+fn test_fast_cpu_draws_the_same_frames() {
+    for board in &BOARDS {
+        assert_eq!(
+            frame_hash(board, Renderer::ALL[0], true),
+            board.golden[0],
+            "mapper {}",
+            board.mapper
+        );
+    }
+}
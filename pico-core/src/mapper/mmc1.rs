@@ -1,5 +1,6 @@
 use crate::cart::Mirroring;
-use crate::mapper::{ChrSource, Mapper};
+use crate::mapper::{BoardInfo, ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 const PRG_BANK_SIZE: usize = 0x4000;
 const CHR_BANK_SIZE_4K: usize = 0x1000;
@@ -44,13 +45,28 @@ pub struct Mmc1Mapper {
     sram_bank: usize,
 
     has_512kb_prg: bool,
+    // SEROM, SHROM and SH1ROM (submapper 5) wire 32 KiB of PRG ROM straight
+    // to the CPU, so PRG bank switching does nothing.
+    fixed_prg: bool,
     mirroring: Mirroring,
+    prg_remapped: bool,
 }
 
 impl Mmc1Mapper {
     pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mmc1Mapper::with_board(prg_rom, chr_rom, mirroring, &BoardInfo::default())
+    }
+
+    // SOROM and SXROM have 16 and 32 KiB of PRG RAM, banked by the CHR
+    // registers, which only the NES 2.0 header can tell us about.
+    pub fn with_board(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        mirroring: Mirroring,
+        board: &BoardInfo,
+    ) -> Self {
         let chr_is_ram = chr_rom.is_empty();
-        let chr = if chr_is_ram { vec![0; 0x2000] } else { chr_rom };
+        let chr = if chr_is_ram { board.chr_ram() } else { chr_rom };
 
         let prg_bank_count = std::cmp::max(1, prg_rom.len() / PRG_BANK_SIZE);
         let has_512kb_prg = prg_rom.len() > 256 * 1024;
@@ -64,7 +80,7 @@ impl Mmc1Mapper {
             prg_rom,
             chr,
             chr_is_ram,
-            prg_ram: vec![0; SRAM_BANK_SIZE],
+            prg_ram: vec![0; board.prg_ram_size.unwrap_or(SRAM_BANK_SIZE)],
             prg_mode: PrgMode::FixLastPage,
             chr_mode: ChrMode::Bank8kb,
             prg_select: 0,
@@ -80,7 +96,9 @@ impl Mmc1Mapper {
             chr_banks: [0; 2],
             sram_bank: 0,
             has_512kb_prg,
+            fixed_prg: board.submapper == 5,
             mirroring,
+            prg_remapped: false,
         };
 
         mapper.update_prg_banks();
@@ -128,6 +146,7 @@ impl Mmc1Mapper {
 
         let prg_count = self.prg_bank_count();
         let (mut bank0, mut bank1) = match self.prg_mode {
+            _ if self.fixed_prg => (0, 1),
             PrgMode::Bank32kb => {
                 let bank = self.prg_select & !1;
                 (bank, bank + 1)
@@ -192,6 +211,63 @@ impl Mmc1Mapper {
     }
 }
 
+impl Savestate for Mmc1Mapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(match self.prg_mode {
+            PrgMode::Bank32kb => 0,
+            PrgMode::FixFirstPage => 1,
+            PrgMode::FixLastPage => 2,
+        });
+        state.write_bool(self.chr_mode == ChrMode::Bank4kb);
+        state.write_usize(self.prg_select);
+        state.write_usize(self.prg_256kb_bank);
+        state.write_usize(self.chr_select0);
+        state.write_usize(self.chr_select1);
+        state.write_bool(self.last_wrote_chr_select1);
+        state.write_u8(self.shift_reg);
+        state.write_u8(self.shift_writes);
+        state.write_bool(self.prg_ram_disabled);
+        state.write_usize(self.sram_bank);
+        self.mirroring.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        state.read_bytes_into(&mut self.prg_ram)?;
+        self.prg_mode = match state.read_u8()? {
+            0 => PrgMode::Bank32kb,
+            1 => PrgMode::FixFirstPage,
+            2 => PrgMode::FixLastPage,
+            other => return Err(format!("Invalid MMC1 PRG mode {} in savestate", other)),
+        };
+        self.chr_mode = if state.read_bool()? {
+            ChrMode::Bank4kb
+        } else {
+            ChrMode::Bank8kb
+        };
+        self.prg_select = state.read_usize()?;
+        self.prg_256kb_bank = state.read_usize()?;
+        self.chr_select0 = state.read_usize()?;
+        self.chr_select1 = state.read_usize()?;
+        self.last_wrote_chr_select1 = state.read_bool()?;
+        self.shift_reg = state.read_u8()?;
+        self.shift_writes = state.read_u8()?;
+        self.prg_ram_disabled = state.read_bool()?;
+        self.sram_bank = state.read_usize()?;
+        self.mirroring.load_state(state)?;
+        // The bank tables follow from the registers above.
+        self.update_prg_banks();
+        self.update_all_banks();
+        Ok(())
+    }
+}
+
 impl Mapper for Mmc1Mapper {
     fn read_prg(&self, addr: u16) -> u8 {
         match addr {
@@ -207,7 +283,11 @@ impl Mapper for Mmc1Mapper {
                 if self.prg_rom.is_empty() {
                     0
                 } else {
-                    let bank = if addr < 0xC000 { self.prg_banks[0] } else { self.prg_banks[1] };
+                    let bank = if addr < 0xC000 {
+                        self.prg_banks[0]
+                    } else {
+                        self.prg_banks[1]
+                    };
                     let offset = bank + (addr as usize & 0x3FFF);
                     self.prg_rom.get(offset).copied().unwrap_or(0)
                 }
@@ -217,6 +297,7 @@ impl Mapper for Mmc1Mapper {
     }
 
     fn write_prg(&mut self, addr: u16, val: u8) {
+        let mapped = (self.prg_banks, self.sram_bank, self.prg_ram_disabled);
         match addr {
             0x6000..=0x7FFF => {
                 if !self.prg_ram_disabled && !self.prg_ram.is_empty() {
@@ -264,21 +345,34 @@ impl Mapper for Mmc1Mapper {
             }
             _ => {}
         }
+        self.prg_remapped |= (self.prg_banks, self.sram_bank, self.prg_ram_disabled) != mapped;
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
     }
 
     fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
-        if self.chr.is_empty() {
-            0
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        let bank = if addr < 0x1000 {
+            self.chr_banks[0]
         } else {
-            let bank = if addr < 0x1000 { self.chr_banks[0] } else { self.chr_banks[1] };
-            let offset = bank + (addr as usize & 0x0FFF);
-            self.chr.get(offset).copied().unwrap_or(0)
-        }
+            self.chr_banks[1]
+        };
+        let offset = bank + (addr as usize & 0x0FFF);
+        (offset < self.chr.len()).then_some(offset)
     }
 
     fn write_chr(&mut self, addr: u16, val: u8) {
         if self.chr_is_ram && !self.chr.is_empty() {
-            let bank = if addr < 0x1000 { self.chr_banks[0] } else { self.chr_banks[1] };
+            let bank = if addr < 0x1000 {
+                self.chr_banks[0]
+            } else {
+                self.chr_banks[1]
+            };
             let offset = bank + (addr as usize & 0x0FFF);
             if offset < self.chr.len() {
                 self.chr[offset] = val;
@@ -286,7 +380,74 @@ impl Mapper for Mmc1Mapper {
         }
     }
 
+    fn handles_write(&self, addr: u16) -> bool {
+        addr >= 0x6000
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        (!self.prg_ram.is_empty()).then_some(self.prg_ram.as_slice())
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        (!self.prg_ram.is_empty()).then_some(self.prg_ram.as_mut_slice())
+    }
+
     fn mirroring(&self) -> Mirroring {
         self.mirroring.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // MMC1 registers are loaded one bit per write, low bit first.
+    fn write_register(mapper: &mut Mmc1Mapper, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.write_prg(addr, (value >> bit) & 1);
+        }
+    }
+
+    fn mapper(submapper: u8, prg_ram_size: usize) -> Mmc1Mapper {
+        let prg_rom = (0..2 * PRG_BANK_SIZE)
+            .map(|i| (i / PRG_BANK_SIZE) as u8)
+            .collect();
+        let board = BoardInfo {
+            submapper,
+            prg_ram_size: Some(prg_ram_size),
+            chr_ram_size: None,
+        };
+        Mmc1Mapper::with_board(prg_rom, vec![], Mirroring::Vertical, &board)
+    }
+
+    #[test]
+    fn serom_ignores_prg_bank_switching() {
+        let mut mmc1 = mapper(0, SRAM_BANK_SIZE);
+        write_register(&mut mmc1, 0xE000, 1);
+        assert_eq!(mmc1.read_prg(0x8000), 1);
+
+        let mut serom = mapper(5, SRAM_BANK_SIZE);
+        write_register(&mut serom, 0xE000, 1);
+        assert_eq!(serom.read_prg(0x8000), 0);
+        assert_eq!(serom.read_prg(0xC000), 1);
+    }
+
+    #[test]
+    fn sxrom_banks_32k_of_prg_ram_through_chr_register() {
+        let mut mapper = mapper(0, 4 * SRAM_BANK_SIZE);
+        mapper.write_prg(0x6000, 0xAA);
+        write_register(&mut mapper, 0xA000, 0b01000);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+        mapper.write_prg(0x6000, 0xBB);
+        write_register(&mut mapper, 0xA000, 0);
+        assert_eq!(mapper.read_prg(0x6000), 0xAA);
+    }
+}
@@ -0,0 +1,283 @@
+// MMC2 (mapper 9, PxROM), used by Mike Tyson's Punch-Out!!. Each 4 KiB half of
+// pattern memory has two CHR banks and a latch picking between them. Fetching
+// tile $FD or $FE from that half flips its latch, so a game can switch graphics
+// partway down the screen just by placing those tiles in the nametable.
+use crate::cart::Mirroring;
+use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Latch {
+    Fd,
+    Fe,
+}
+
+// The two latched 4 KiB CHR windows, shared with MMC4 (`fxrom`).
+pub(crate) struct ChrLatches {
+    // CHR banks for each half, indexed by latch: [FD, FE].
+    banks: [[u8; 2]; 2],
+    latches: [Latch; 2],
+    // MMC2's left half only reacts to the first byte of the tile's high
+    // bitplane; MMC4 and MMC2's right half react to any of its eight.
+    exact_left: bool,
+}
+
+impl ChrLatches {
+    pub(crate) fn new(exact_left: bool) -> Self {
+        ChrLatches {
+            banks: [[0; 2]; 2],
+            latches: [Latch::Fe; 2],
+            exact_left,
+        }
+    }
+
+    // $B000-$EFFF: left FD, left FE, right FD, right FE.
+    pub(crate) fn write_bank(&mut self, addr: u16, data: u8) {
+        let register = ((addr - 0xB000) >> 12) as usize;
+        self.banks[register / 2][register % 2] = data & 0x1F;
+    }
+
+    pub(crate) fn chr_index(&self, addr: u16, chr_len: usize) -> usize {
+        let half = (addr as usize >> 12) & 0x01;
+        let bank = self.banks[half][self.latches[half] as usize] as usize;
+        let count = (chr_len / CHR_BANK_SIZE).max(1);
+        (bank % count) * CHR_BANK_SIZE + (addr as usize & 0x0FFF)
+    }
+
+    // The latches flip after the high bitplane of tile $FD or $FE is fetched.
+    pub(crate) fn notify_fetch(&mut self, addr: u16) {
+        let half = (addr as usize >> 12) & 0x01;
+        let exact = half == 1 || !self.exact_left || addr & 0x07 == 0;
+        match addr & 0x0FF8 {
+            0x0FD8 if exact => self.latches[half] = Latch::Fd,
+            0x0FE8 if exact => self.latches[half] = Latch::Fe,
+            _ => {}
+        }
+    }
+}
+
+impl Savestate for ChrLatches {
+    fn save_state(&self, state: &mut StateWriter) {
+        for banks in &self.banks {
+            state.write_u8(banks[0]);
+            state.write_u8(banks[1]);
+        }
+        for latch in &self.latches {
+            state.write_bool(*latch == Latch::Fe);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        for banks in &mut self.banks {
+            banks[0] = state.read_u8()?;
+            banks[1] = state.read_u8()?;
+        }
+        for latch in &mut self.latches {
+            *latch = if state.read_bool()? {
+                Latch::Fe
+            } else {
+                Latch::Fd
+            };
+        }
+        Ok(())
+    }
+}
+
+pub struct Mmc2Mapper {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    prg_ram: Vec<u8>,
+    prg_bank: u8,
+    chr_latches: ChrLatches,
+    mirroring: Mirroring,
+    prg_remapped: bool,
+}
+
+impl Mmc2Mapper {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = if chr_is_ram { vec![0; 0x2000] } else { chr_rom };
+
+        Mmc2Mapper {
+            prg_rom,
+            chr,
+            chr_is_ram,
+            prg_ram: vec![0; 0x2000],
+            prg_bank: 0,
+            chr_latches: ChrLatches::new(true),
+            mirroring,
+            prg_remapped: false,
+        }
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).max(1)
+    }
+}
+
+impl Savestate for Mmc2Mapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(self.prg_bank);
+        self.chr_latches.save_state(state);
+        self.mirroring.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        state.read_bytes_into(&mut self.prg_ram)?;
+        self.prg_bank = state.read_u8()?;
+        self.chr_latches.load_state(state)?;
+        self.mirroring.load_state(state)
+    }
+}
+
+impl Mapper for Mmc2Mapper {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                if self.prg_rom.is_empty() {
+                    return 0;
+                }
+                // One switchable 8 KiB bank, then the last three fixed.
+                let count = self.prg_bank_count();
+                let slot = (addr as usize - 0x8000) / PRG_BANK_SIZE;
+                let bank = match slot {
+                    0 => self.prg_bank as usize % count,
+                    _ => count.saturating_sub(4 - slot),
+                };
+                let index = bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1));
+                self.prg_rom[index % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = data,
+            0xA000..=0xAFFF => {
+                self.prg_remapped |= data & 0x0F != self.prg_bank;
+                self.prg_bank = data & 0x0F;
+            }
+            0xB000..=0xEFFF => self.chr_latches.write_bank(addr, data),
+            0xF000..=0xFFFF => {
+                self.mirroring = if data & 0x01 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
+    fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        if self.chr.is_empty() {
+            0
+        } else {
+            self.chr[self.chr_latches.chr_index(addr, self.chr.len()) % self.chr.len()]
+        }
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        (!self.chr.is_empty())
+            .then(|| self.chr_latches.chr_index(addr, self.chr.len()) % self.chr.len())
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && !self.chr.is_empty() {
+            let index = self.chr_latches.chr_index(addr, self.chr.len()) % self.chr.len();
+            self.chr[index] = data;
+        }
+    }
+
+    fn notify_chr_fetch(&mut self, addr: u16) {
+        self.chr_latches.notify_fetch(addr);
+    }
+
+    fn handles_write(&self, addr: u16) -> bool {
+        matches!(addr, 0x6000..=0x7FFF | 0xA000..=0xFFFF)
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::patterned;
+
+    fn mapper() -> Mmc2Mapper {
+        Mmc2Mapper::new(
+            patterned(16, PRG_BANK_SIZE),
+            patterned(32, CHR_BANK_SIZE),
+            Mirroring::Vertical,
+        )
+    }
+
+    #[test]
+    fn mmc2_switches_one_prg_bank_and_fixes_the_last_three() {
+        let mut mapper = mapper();
+        mapper.write_prg(0xA000, 0x05);
+        assert_eq!(mapper.read_prg(0x8000), 5);
+        assert_eq!(mapper.read_prg(0xA000), 13);
+        assert_eq!(mapper.read_prg(0xC000), 14);
+        assert_eq!(mapper.read_prg(0xFFFF), 15);
+    }
+
+    #[test]
+    fn mmc2_latches_follow_fd_and_fe_fetches() {
+        let mut mapper = mapper();
+        mapper.write_prg(0xB000, 1);
+        mapper.write_prg(0xC000, 2);
+        mapper.write_prg(0xD000, 3);
+        mapper.write_prg(0xE000, 4);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Background), 2);
+        assert_eq!(mapper.read_chr(0x1000, ChrSource::Background), 4);
+
+        mapper.notify_chr_fetch(0x0FD8);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Background), 1);
+        assert_eq!(mapper.read_chr(0x1000, ChrSource::Background), 4);
+
+        // The left half ignores the rest of the bitplane, the right doesn't.
+        mapper.notify_chr_fetch(0x0FE9);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Background), 1);
+        mapper.notify_chr_fetch(0x1FDB);
+        assert_eq!(mapper.read_chr(0x1000, ChrSource::Background), 3);
+        mapper.notify_chr_fetch(0x0FE8);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Background), 2);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::cart::Mirroring;
-use crate::mapper::{ChrSource, Mapper};
+use crate::mapper::{BoardInfo, ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 const PRG_BANK_SIZE: usize = 0x2000;
 const CHR_BANK_SIZE_1K: usize = 0x0400;
@@ -43,12 +44,26 @@ pub struct Mmc3Mapper {
     irq_reload: bool,
     irq_enabled: bool,
     irq_pending: bool,
+    // MMC3A and some MMC3B chips (submapper 4) only raise an IRQ on reaching
+    // zero by counting down or by a $C001 reload, so a latch of 0 fires once
+    // rather than on every scanline.
+    old_irq: bool,
+    prg_remapped: bool,
 }
 
 impl Mmc3Mapper {
     pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        Mmc3Mapper::with_board(prg_rom, chr_rom, mirroring, &BoardInfo::default())
+    }
+
+    pub fn with_board(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        mirroring: Mirroring,
+        board: &BoardInfo,
+    ) -> Self {
         let chr_is_ram = chr_rom.is_empty();
-        let chr = if chr_is_ram { vec![0; 0x2000] } else { chr_rom };
+        let chr = if chr_is_ram { board.chr_ram() } else { chr_rom };
 
         let mut mapper = Mmc3Mapper {
             prg_rom,
@@ -69,6 +84,8 @@ impl Mmc3Mapper {
             irq_reload: false,
             irq_enabled: false,
             irq_pending: false,
+            old_irq: board.submapper == 4,
+            prg_remapped: false,
         };
 
         mapper.init_prg_banks();
@@ -284,19 +301,75 @@ impl Mmc3Mapper {
     }
 
     fn clock_irq_counter(&mut self) {
-        if self.irq_count == 0 || self.irq_reload {
+        let reached_zero = if self.irq_count == 0 || self.irq_reload {
+            let forced = self.irq_reload;
             self.irq_count = self.irq_latch;
             self.irq_reload = false;
+            !self.old_irq || forced
         } else {
             self.irq_count = self.irq_count.wrapping_sub(1);
-        }
+            true
+        };
 
-        if self.irq_enabled && self.irq_count == 0 {
+        if self.irq_enabled && self.irq_count == 0 && reached_zero {
             self.irq_pending = true;
         }
     }
 }
 
+impl Savestate for Mmc3Mapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(self.reg_select);
+        state.write_bool(self.prg_mode == PrgMode::FixFirstPages);
+        state.write_bool(self.chr_mode == ChrMode::BiggerLast);
+        for bank in self.prg_banks.iter().chain(&self.chr_banks) {
+            state.write_usize(*bank);
+        }
+        self.mirroring.save_state(state);
+        state.write_bool(self.sram_read_enabled);
+        state.write_bool(self.sram_write_enabled);
+        state.write_u8(self.irq_latch);
+        state.write_u8(self.irq_count);
+        state.write_bool(self.irq_reload);
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_pending);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        state.read_bytes_into(&mut self.prg_ram)?;
+        self.reg_select = state.read_u8()?;
+        self.prg_mode = if state.read_bool()? {
+            PrgMode::FixFirstPages
+        } else {
+            PrgMode::FixLastPages
+        };
+        self.chr_mode = if state.read_bool()? {
+            ChrMode::BiggerLast
+        } else {
+            ChrMode::BiggerFirst
+        };
+        for bank in self.prg_banks.iter_mut().chain(&mut self.chr_banks) {
+            *bank = state.read_usize()?;
+        }
+        self.mirroring.load_state(state)?;
+        self.sram_read_enabled = state.read_bool()?;
+        self.sram_write_enabled = state.read_bool()?;
+        self.irq_latch = state.read_u8()?;
+        self.irq_count = state.read_u8()?;
+        self.irq_reload = state.read_bool()?;
+        self.irq_enabled = state.read_bool()?;
+        self.irq_pending = state.read_bool()?;
+        Ok(())
+    }
+}
+
 impl Mapper for Mmc3Mapper {
     fn read_prg(&self, addr: u16) -> u8 {
         match addr {
@@ -319,6 +392,7 @@ impl Mapper for Mmc3Mapper {
     }
 
     fn write_prg(&mut self, addr: u16, data: u8) {
+        let mapped = (self.prg_banks, self.sram_read_enabled);
         match addr {
             0x6000..=0x7FFF => {
                 if self.sram_write_enabled {
@@ -357,6 +431,11 @@ impl Mapper for Mmc3Mapper {
             }
             _ => {}
         }
+        self.prg_remapped |= (self.prg_banks, self.sram_read_enabled) != mapped;
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
     }
 
     fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
@@ -368,6 +447,10 @@ impl Mapper for Mmc3Mapper {
         }
     }
 
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        (!self.chr.is_empty()).then(|| self.chr_addr(addr))
+    }
+
     fn write_chr(&mut self, addr: u16, data: u8) {
         if self.chr_is_ram && !self.chr.is_empty() {
             let index = self.chr_addr(addr);
@@ -375,6 +458,26 @@ impl Mapper for Mmc3Mapper {
         }
     }
 
+    fn handles_write(&self, addr: u16) -> bool {
+        addr >= 0x6000
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
     fn mirroring(&self) -> Mirroring {
         self.mirroring.clone()
     }
@@ -429,6 +532,23 @@ mod tests {
         assert_eq!(mapper.read_prg(0xC000), 1);
     }
 
+    #[test]
+    fn chr_tiles_read_the_same_as_their_bytes() {
+        let chr_rom: Vec<u8> = (0..0x8000).map(|i| (i * 7 + i / 0x400) as u8).collect();
+        let mut mapper = Mmc3Mapper::new(patterned_prg(2), chr_rom, Mirroring::Vertical);
+        for (register, bank) in [(0, 6), (1, 20), (2, 9), (5, 31)] {
+            mapper.write_prg(0x8000, register);
+            mapper.write_prg(0x8001, bank);
+        }
+
+        for addr in (0..0x2000).step_by(16) {
+            let bytes: Vec<u8> = (addr..addr + 16)
+                .map(|addr| mapper.read_chr(addr, ChrSource::Background))
+                .collect();
+            assert_eq!(mapper.read_chr_tile(addr, ChrSource::Background), bytes[..]);
+        }
+    }
+
     #[test]
     fn irq_counter_respects_latch_and_enable() {
         let prg_rom = patterned_prg(2);
@@ -477,6 +597,36 @@ mod tests {
         assert!(mapper.poll_irq().is_some());
     }
 
+    #[test]
+    fn old_irq_fires_once_for_a_zero_latch() {
+        let irqs = |submapper| {
+            let board = BoardInfo {
+                submapper,
+                ..BoardInfo::default()
+            };
+            let mut mapper = Mmc3Mapper::with_board(
+                patterned_prg(2),
+                vec![0; 0x2000],
+                Mirroring::Vertical,
+                &board,
+            );
+            mapper.write_prg(0xC000, 0);
+            mapper.write_prg(0xC001, 0);
+            mapper.write_prg(0xE001, 0);
+            (0..3)
+                .filter(|_| {
+                    mapper.handle_scanline(true);
+                    let fired = mapper.poll_irq().is_some();
+                    mapper.write_prg(0xE000, 0);
+                    mapper.write_prg(0xE001, 0);
+                    fired
+                })
+                .count()
+        };
+        assert_eq!(irqs(0), 3);
+        assert_eq!(irqs(4), 1);
+    }
+
     fn patterned_chr() -> Vec<u8> {
         let mut chr = vec![0u8; 0x2000];
         for bank in 0..8 {
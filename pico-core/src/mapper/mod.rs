@@ -0,0 +1,188 @@
+pub mod axrom;
+pub mod cnrom;
+pub mod discrete;
+pub mod fxrom;
+#[cfg(test)]
+mod golden;
+pub mod mmc1;
+pub mod mmc2;
+pub mod mmc3;
+pub mod namco163;
+pub mod nina001;
+pub mod nrom;
+pub mod nsf;
+pub mod uxrom;
+pub mod vs;
+
+use crate::savestate::Savestate;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ChrSource {
+    Background,
+    Sprite,
+    Cpu,
+}
+
+// Where each of the four logical nametables ($2000/$2400/$2800/$2C00) is
+// fetched from. CIRAM is the console's 2 KiB of VRAM, `CartRam` pages live on
+// the cartridge (four-screen boards, MMC5 ExRAM) and `Fill` is a mapper
+// generated constant page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NametablePage {
+    CiramA,
+    CiramB,
+    CartRam(u8),
+    Fill,
+}
+
+pub type NametableLayout = [NametablePage; 4];
+
+// What a NES 2.0 header says about the board beyond its mapper number. iNES
+// headers leave it all unset.
+#[derive(Clone, Debug, Default)]
+pub struct BoardInfo {
+    pub submapper: u8,
+    // Work RAM plus battery-backed RAM, and CHR RAM, in bytes.
+    pub prg_ram_size: Option<usize>,
+    pub chr_ram_size: Option<usize>,
+}
+
+impl BoardInfo {
+    // CHR RAM for boards without CHR ROM, 8 KiB unless the header says more.
+    pub fn chr_ram(&self) -> Vec<u8> {
+        let size = self.chr_ram_size.filter(|&size| size > 0);
+        vec![0; size.unwrap_or(0x2000)]
+    }
+}
+
+// Savestates cover whatever the board keeps between accesses: bank
+// registers, IRQ counters, PRG RAM and CHR RAM. Boards are Send so a
+// frontend can run the console on a thread of its own.
+pub trait Mapper: Savestate + Send {
+    fn read_prg(&self, addr: u16) -> u8;
+    fn write_prg(&mut self, addr: u16, data: u8);
+    fn read_chr(&self, addr: u16, source: ChrSource) -> u8;
+    fn write_chr(&mut self, addr: u16, data: u8);
+    // PRG as a read would see it, for debugger and trace views. Boards whose
+    // reads do something beyond returning data should leave that out here.
+    fn peek_prg(&self, addr: u16) -> u8 {
+        self.read_prg(addr)
+    }
+    // CHR as a $2007 read would fetch it, without the fetch that boards like
+    // MMC2 watch for.
+    fn peek_chr(&self, addr: u16) -> u8 {
+        self.read_chr(addr, ChrSource::Cpu)
+    }
+    fn mirroring(&self) -> crate::cart::Mirroring;
+    fn nametable_layout(&self) -> NametableLayout {
+        self.mirroring().nametable_layout()
+    }
+    fn handle_scanline(&mut self, _rendering_enabled: bool) {}
+    // Called once every CPU cycle, for boards that count cycles or make sound.
+    fn clock_cpu(&mut self) {}
+    // The board's expansion audio as it is right now, on the scale of the
+    // mixed APU output.
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+    // For boards wired to the controller port's outputs, which $4016 writes
+    // set along with the controller strobe.
+    fn write_controller_port(&mut self, _data: u8) {}
+    // Whether a write since this was last asked changed what PRG reads return
+    // beyond the byte written, by switching banks or PRG RAM in or out, so
+    // code decoded ahead from cartridge space is stale. Boards that don't keep
+    // track always say so.
+    fn take_prg_remapped(&mut self) -> bool {
+        true
+    }
+    // Called with the address of each pattern byte the PPU fetches for
+    // rendering or through $2007, for boards that watch the PPU address bus.
+    fn notify_chr_fetch(&mut self, _addr: u16) {}
+    // A pattern byte fetched over the PPU bus, as a $2007 read does to refill
+    // its buffer. The byte comes from the banks selected before the fetch;
+    // anything the board does on seeing the address, like flipping an MMC2
+    // latch, applies from the next fetch on.
+    fn fetch_chr(&mut self, addr: u16, source: ChrSource) -> u8 {
+        let data = self.read_chr(addr, source);
+        self.notify_chr_fetch(addr);
+        data
+    }
+    // Where the pattern byte at `addr` sits in `chr_data` under the current
+    // banks, for boards that map CHR in plain banks. `None` leaves every byte
+    // to `read_chr`.
+    fn chr_index(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+    // Both bitplanes of the tile at `addr`, in one call instead of sixteen.
+    fn read_chr_tile(&self, addr: u16, source: ChrSource) -> [u8; 16] {
+        let chr = self.chr_data();
+        match self.chr_index(addr) {
+            Some(index) if addr & 0x0F == 0 && index + 16 <= chr.len() => {
+                chr[index..index + 16].try_into().unwrap()
+            }
+            _ => std::array::from_fn(|i| self.read_chr(addr + i as u16, source)),
+        }
+    }
+    // Whole CHR ROM/RAM regardless of banking, and mutable access to it when it
+    // is RAM, for graphics tooling.
+    fn chr_data(&self) -> &[u8] {
+        &[]
+    }
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+    // All of the board's PRG RAM regardless of banking, for keeping it in a
+    // battery save.
+    fn prg_ram(&self) -> Option<&[u8]> {
+        None
+    }
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+    // Boards whose register writes can fight with PRG ROM on the data bus.
+    // Only some revisions of them are wired that way, so it is opt-in.
+    fn set_bus_conflicts(&mut self, _enabled: bool) {}
+    // Whether a cartridge-space write does anything on this board, so writes to
+    // registers that aren't emulated can be reported.
+    fn handles_write(&self, _addr: u16) -> bool {
+        true
+    }
+    // Whether the board answers reads of a cartridge-space address. Reads it
+    // leaves unmapped, like $4020-$5FFF on most boards, see open bus.
+    fn maps_read(&self, addr: u16) -> bool {
+        addr >= 0x6000
+    }
+    // Expansion audio registers, so sound register logging can pick them up.
+    fn is_audio_register(&self, _addr: u16) -> bool {
+        false
+    }
+    fn poll_irq(&self) -> Option<u8> {
+        None // Default implementation - no IRQ support
+    }
+    // Cartridge-backed nametable pages. Returning `None` lets the PPU fall back
+    // to its own storage (CIRAM, or the plain 2 KiB four-screen RAM).
+    fn read_nametable_page(&self, _page: NametablePage, _offset: u16) -> Option<u8> {
+        None
+    }
+    fn write_nametable_page(&mut self, _page: NametablePage, _offset: u16, _value: u8) -> bool {
+        false
+    }
+    fn background_tile_override(
+        &self,
+        _table_index: usize,
+        _tile_column: usize,
+        _tile_row: usize,
+        _tile_index: u8,
+        _pattern_addr: u16,
+    ) -> Option<[u8; 16]> {
+        None
+    }
+    fn background_palette_override(
+        &self,
+        _table_index: usize,
+        _tile_column: usize,
+        _tile_row: usize,
+    ) -> Option<u8> {
+        None
+    }
+}
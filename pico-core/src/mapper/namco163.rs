@@ -0,0 +1,513 @@
+use std::cell::Cell;
+
+use crate::cart::Mirroring;
+use crate::mapper::{ChrSource, Mapper, NametableLayout, NametablePage};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+// Bank numbers from here up put CIRAM in the slot instead of CHR ROM.
+const CIRAM_BANKS: u8 = 0xE0;
+
+// The sound chip updates one channel every 15 CPU cycles, the active ones in
+// turn from channel 7 down.
+const CYCLES_PER_CHANNEL: u8 = 15;
+
+// One channel at full volume ends up about as loud as both pulse channels.
+const AUDIO_SCALE: f32 = 1.0 / 480.0;
+
+// Namco 163: eight 1 KiB CHR banks, four nametable slots that take CIRAM or
+// CHR ROM, three switchable 8 KiB PRG banks under a fixed last one, a 15-bit
+// IRQ counter ticking every CPU cycle, and up to eight wavetable channels
+// playing 4-bit samples out of 128 bytes of internal sound RAM.
+pub struct Namco163Mapper {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    prg_ram: Vec<u8>,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    // $E800 bits 6 and 7: CHR ROM for banks $E0 and up in the low and high
+    // pattern tables. CIRAM as pattern data isn't emulated, so those banks
+    // always come from CHR ROM.
+    chr_ram_disabled: u8,
+    // $F800: PRG RAM writes need $4x in the top bits, and each of the low bits
+    // protects a 2 KiB quarter.
+    prg_ram_protect: u8,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    sound_ram: [u8; 0x80],
+    // $F800 too: bits 0-6 address sound RAM through $4800 and bit 7 steps the
+    // address after each access, reads included.
+    sound_addr: Cell<u8>,
+    sound_disabled: bool,
+    sound_cycle: u8,
+    sound_channel: u8,
+    channel_outputs: [i16; 8],
+
+    mirroring: Mirroring,
+    prg_remapped: bool,
+}
+
+impl Namco163Mapper {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = if chr_is_ram { vec![0; 0x2000] } else { chr_rom };
+        Namco163Mapper {
+            prg_rom,
+            chr,
+            chr_is_ram,
+            prg_ram: vec![0; 0x2000],
+            prg_banks: [0, 1, 2],
+            chr_banks: [0, 1, 2, 3, 4, 5, 6, 7],
+            nametable_banks: [CIRAM_BANKS, CIRAM_BANKS, CIRAM_BANKS + 1, CIRAM_BANKS + 1],
+            chr_ram_disabled: 0,
+            prg_ram_protect: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            sound_ram: [0; 0x80],
+            sound_addr: Cell::new(0),
+            sound_disabled: false,
+            sound_cycle: 0,
+            sound_channel: 7,
+            channel_outputs: [0; 8],
+            mirroring,
+            prg_remapped: false,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr as usize >> 10) & 0x07] as usize;
+        (bank * CHR_BANK_SIZE + (addr as usize & 0x03FF)) % self.chr.len()
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let last_bank = (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1);
+        let bank = match addr {
+            0x8000..=0x9FFF => self.prg_banks[0] as usize,
+            0xA000..=0xBFFF => self.prg_banks[1] as usize,
+            0xC000..=0xDFFF => self.prg_banks[2] as usize,
+            _ => last_bank,
+        };
+        (bank * PRG_BANK_SIZE + (addr as usize & 0x1FFF)) % self.prg_rom.len()
+    }
+
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let quarter = (addr - 0x6000) >> 11;
+        self.prg_ram_protect & 0xF0 == 0x40 && self.prg_ram_protect & (1 << quarter) == 0
+    }
+
+    // The sound RAM byte $4800 reaches, stepping the address if asked to.
+    fn next_sound_addr(&self) -> usize {
+        let addr = self.sound_addr.get();
+        if addr & 0x80 != 0 {
+            self.sound_addr.set(0x80 | (addr.wrapping_add(1) & 0x7F));
+        }
+        (addr & 0x7F) as usize
+    }
+
+    fn active_channels(&self) -> u8 {
+        ((self.sound_ram[0x7F] >> 4) & 0x07) + 1
+    }
+
+    // Steps one channel's phase through its waveform and takes its sample.
+    // Each channel's registers sit in the eight bytes from $40 + 8 * channel,
+    // with the phase kept in sound RAM where the game can see it.
+    fn update_channel(&mut self, channel: usize) {
+        let regs = 0x40 + channel * 8;
+        let ram = &mut self.sound_ram;
+        let freq =
+            ram[regs] as u32 | (ram[regs + 2] as u32) << 8 | (ram[regs + 4] as u32 & 0x03) << 16;
+        let phase =
+            ram[regs + 1] as u32 | (ram[regs + 3] as u32) << 8 | (ram[regs + 5] as u32) << 16;
+        let length = 256 - (ram[regs + 4] as u32 & 0xFC);
+        let phase = (phase + freq) % (length << 16);
+        ram[regs + 1] = phase as u8;
+        ram[regs + 3] = (phase >> 8) as u8;
+        ram[regs + 5] = (phase >> 16) as u8;
+
+        let sample_addr = ((phase >> 16) + ram[regs + 6] as u32) as u8;
+        let sample = (ram[sample_addr as usize >> 1] >> ((sample_addr & 0x01) * 4)) & 0x0F;
+        let volume = ram[regs + 7] & 0x0F;
+        self.channel_outputs[channel] = (sample as i16 - 8) * volume as i16;
+    }
+}
+
+impl Savestate for Namco163Mapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_banks);
+        state.write_bytes(&self.chr_banks);
+        state.write_bytes(&self.nametable_banks);
+        state.write_u8(self.chr_ram_disabled);
+        state.write_u8(self.prg_ram_protect);
+        state.write_u16(self.irq_counter);
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_pending);
+        state.write_bytes(&self.sound_ram);
+        state.write_u8(self.sound_addr.get());
+        state.write_bool(self.sound_disabled);
+        state.write_u8(self.sound_cycle);
+        state.write_u8(self.sound_channel);
+        for output in self.channel_outputs {
+            state.write_i16(output);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_bytes_into(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        state.read_bytes_into(&mut self.prg_banks)?;
+        state.read_bytes_into(&mut self.chr_banks)?;
+        state.read_bytes_into(&mut self.nametable_banks)?;
+        self.chr_ram_disabled = state.read_u8()?;
+        self.prg_ram_protect = state.read_u8()?;
+        self.irq_counter = state.read_u16()?;
+        self.irq_enabled = state.read_bool()?;
+        self.irq_pending = state.read_bool()?;
+        state.read_bytes_into(&mut self.sound_ram)?;
+        self.sound_addr.set(state.read_u8()?);
+        self.sound_disabled = state.read_bool()?;
+        self.sound_cycle = state.read_u8()?;
+        self.sound_channel = state.read_u8()? & 0x07;
+        for output in &mut self.channel_outputs {
+            *output = state.read_i16()?;
+        }
+        Ok(())
+    }
+}
+
+impl Mapper for Namco163Mapper {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.sound_ram[self.next_sound_addr()],
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn peek_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.sound_ram[(self.sound_addr.get() & 0x7F) as usize],
+            _ => self.read_prg(addr),
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        let prg_banks = self.prg_banks;
+        match addr {
+            0x4800..=0x4FFF => self.sound_ram[self.next_sound_addr()] = data,
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (data as u16 & 0x7F) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(addr) => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            0x8000..=0xBFFF => self.chr_banks[(addr as usize - 0x8000) >> 11] = data,
+            0xC000..=0xDFFF => self.nametable_banks[(addr as usize - 0xC000) >> 11] = data,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = data & 0x3F;
+                self.sound_disabled = data & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => {
+                self.prg_banks[1] = data & 0x3F;
+                self.chr_ram_disabled = data >> 6;
+            }
+            0xF000..=0xF7FF => self.prg_banks[2] = data & 0x3F,
+            0xF800..=0xFFFF => {
+                self.prg_ram_protect = data;
+                self.sound_addr.set(data);
+            }
+            _ => {}
+        }
+        self.prg_remapped |= self.prg_banks != prg_banks;
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
+    fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        Some(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.clone()
+    }
+
+    fn nametable_layout(&self) -> NametableLayout {
+        std::array::from_fn(|slot| match self.nametable_banks[slot] {
+            bank if bank >= CIRAM_BANKS && bank & 0x01 == 0 => NametablePage::CiramA,
+            bank if bank >= CIRAM_BANKS => NametablePage::CiramB,
+            _ => NametablePage::CartRam(slot as u8),
+        })
+    }
+
+    // Slots holding a CHR ROM bank show up as `CartRam`, and writes to them go
+    // nowhere.
+    fn read_nametable_page(&self, page: NametablePage, offset: u16) -> Option<u8> {
+        match page {
+            NametablePage::CartRam(slot) => {
+                let bank = self.nametable_banks[slot as usize] as usize;
+                Some(self.chr[(bank * CHR_BANK_SIZE + offset as usize) % self.chr.len()])
+            }
+            _ => None,
+        }
+    }
+
+    fn write_nametable_page(&mut self, page: NametablePage, offset: u16, value: u8) -> bool {
+        match page {
+            NametablePage::CartRam(slot) => {
+                if self.chr_is_ram {
+                    let bank = self.nametable_banks[slot as usize] as usize;
+                    let index = (bank * CHR_BANK_SIZE + offset as usize) % self.chr.len();
+                    self.chr[index] = value;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+
+        if self.sound_disabled {
+            return;
+        }
+        self.sound_cycle += 1;
+        if self.sound_cycle < CYCLES_PER_CHANNEL {
+            return;
+        }
+        self.sound_cycle = 0;
+        self.update_channel(self.sound_channel as usize);
+        self.sound_channel = if self.sound_channel <= 8 - self.active_channels() {
+            7
+        } else {
+            self.sound_channel - 1
+        };
+    }
+
+    // The chip plays its channels one after another, fast enough that the
+    // mix comes out as their average.
+    fn audio_output(&self) -> f32 {
+        if self.sound_disabled {
+            return 0.0;
+        }
+        let active = self.active_channels() as usize;
+        let sum: i16 = self.channel_outputs[8 - active..].iter().sum();
+        sum as f32 / active as f32 * AUDIO_SCALE
+    }
+
+    fn handles_write(&self, addr: u16) -> bool {
+        addr >= 0x4800
+    }
+
+    fn maps_read(&self, addr: u16) -> bool {
+        addr >= 0x4800
+    }
+
+    fn is_audio_register(&self, addr: u16) -> bool {
+        (0x4800..=0x4FFF).contains(&addr) || addr >= 0xF800
+    }
+
+    fn poll_irq(&self) -> Option<u8> {
+        if self.irq_pending { Some(0) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::patterned;
+
+    fn mapper() -> Namco163Mapper {
+        Namco163Mapper::new(
+            patterned(16, PRG_BANK_SIZE),
+            patterned(64, CHR_BANK_SIZE),
+            Mirroring::Horizontal,
+        )
+    }
+
+    #[test]
+    fn namco163_switches_prg_chr_and_nametable_banks() {
+        let mut mapper = mapper();
+        mapper.write_prg(0xE000, 0x45);
+        mapper.write_prg(0xE800, 0x06);
+        mapper.write_prg(0xF000, 0x07);
+        assert_eq!(mapper.read_prg(0x8000), 5);
+        assert_eq!(mapper.read_prg(0xA000), 6);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+        assert_eq!(mapper.read_prg(0xE000), 15);
+        assert!(mapper.sound_disabled);
+        assert!(mapper.take_prg_remapped());
+
+        mapper.write_prg(0x8000, 0x21);
+        mapper.write_prg(0xB800, 0x32);
+        assert!(!mapper.take_prg_remapped());
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 0x21);
+        assert_eq!(mapper.read_chr(0x1FFF, ChrSource::Cpu), 0x32);
+
+        mapper.write_prg(0xC000, 0xE1);
+        mapper.write_prg(0xC800, 0xE0);
+        mapper.write_prg(0xD000, 0x05);
+        mapper.write_prg(0xD800, 0xFF);
+        assert_eq!(
+            mapper.nametable_layout(),
+            [
+                NametablePage::CiramB,
+                NametablePage::CiramA,
+                NametablePage::CartRam(2),
+                NametablePage::CiramB
+            ]
+        );
+        assert_eq!(
+            mapper.read_nametable_page(NametablePage::CartRam(2), 0x3FF),
+            Some(5)
+        );
+        assert!(mapper.write_nametable_page(NametablePage::CartRam(2), 0, 0xAA));
+        assert_eq!(
+            mapper.read_nametable_page(NametablePage::CartRam(2), 0),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn namco163_prg_ram_takes_writes_only_when_unprotected() {
+        let mut mapper = mapper();
+        mapper.write_prg(0x6000, 0x11);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+
+        // Writes enabled, but the second quarter protected.
+        mapper.write_prg(0xF800, 0x42);
+        mapper.write_prg(0x6000, 0x11);
+        mapper.write_prg(0x6800, 0x22);
+        assert_eq!(mapper.read_prg(0x6000), 0x11);
+        assert_eq!(mapper.read_prg(0x6800), 0);
+    }
+
+    #[test]
+    fn namco163_irq_fires_when_the_counter_reaches_7fff() {
+        let mut mapper = mapper();
+        mapper.write_prg(0x5000, 0xFD);
+        mapper.write_prg(0x5800, 0xFF);
+        assert_eq!(mapper.read_prg(0x5800), 0xFF);
+
+        mapper.clock_cpu();
+        assert!(mapper.poll_irq().is_none());
+        mapper.clock_cpu();
+        assert!(mapper.poll_irq().is_some());
+        // The counter stops there.
+        mapper.clock_cpu();
+        assert_eq!(mapper.read_prg(0x5000), 0xFF);
+
+        mapper.write_prg(0x5800, 0x00);
+        assert!(mapper.poll_irq().is_none());
+        mapper.write_prg(0x5000, 0x00);
+        mapper.clock_cpu();
+        assert_eq!(mapper.read_prg(0x5000), 0x00);
+    }
+
+    #[test]
+    fn namco163_sound_ram_address_steps_after_each_access() {
+        let mut mapper = mapper();
+        mapper.write_prg(0xF87E, 0xFE);
+        mapper.write_prg(0x4800, 0x12);
+        mapper.write_prg(0x4800, 0x34);
+        mapper.write_prg(0x4800, 0x56);
+        assert_eq!(mapper.sound_ram[0x7E..], [0x12, 0x34]);
+        assert_eq!(mapper.sound_ram[0], 0x56);
+
+        mapper.write_prg(0xF800, 0xFE);
+        assert_eq!(mapper.peek_prg(0x4800), 0x12);
+        assert_eq!(mapper.read_prg(0x4800), 0x12);
+        assert_eq!(mapper.read_prg(0x4800), 0x34);
+
+        // Without bit 7 the address stays put.
+        mapper.write_prg(0xF800, 0x7E);
+        assert_eq!(mapper.read_prg(0x4800), 0x12);
+        assert_eq!(mapper.read_prg(0x4800), 0x12);
+    }
+
+    #[test]
+    fn namco163_channel_plays_its_wave_from_sound_ram() {
+        let mut mapper = mapper();
+        let mut poke = |addr: u8, data: u8| {
+            mapper.write_prg(0xF800, addr);
+            mapper.write_prg(0x4800, data);
+        };
+        // A 4-sample wave going 15, 0, 15, 0, stepped through a sample per
+        // update by channel 7 at full volume, the only channel on.
+        poke(0x00, 0x0F);
+        poke(0x01, 0x0F);
+        poke(0x7C, 0xFD);
+        poke(0x7F, 0x0F);
+
+        let mut outputs = Vec::new();
+        for _ in 0..4 {
+            for _ in 0..CYCLES_PER_CHANNEL {
+                mapper.clock_cpu();
+            }
+            outputs.push(mapper.audio_output());
+        }
+        let high = 7.0 * 15.0 * AUDIO_SCALE;
+        let low = -8.0 * 15.0 * AUDIO_SCALE;
+        assert_eq!(outputs, [low, high, low, high]);
+
+        mapper.write_prg(0xE000, 0x40);
+        assert_eq!(mapper.audio_output(), 0.0);
+    }
+}
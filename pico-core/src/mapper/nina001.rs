@@ -0,0 +1,173 @@
+use crate::cart::Mirroring;
+use crate::mapper::{BoardInfo, ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+// AVE NINA-001, which shares mapper 34 with BNROM: one 32 KiB PRG bank and
+// two 4 KiB CHR banks, selected through the last three bytes of its 8 KiB of
+// PRG RAM. Writes there land in RAM as well.
+pub struct Nina001Mapper {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_bank: u8,
+    chr_banks: [u8; 2],
+    mirroring: Mirroring,
+    prg_remapped: bool,
+}
+
+// NES 2.0 submapper 1 is NINA-001 and 2 is BNROM. Without one, only NINA-001
+// has more CHR than a single 8 KiB bank.
+pub fn detected(board: &BoardInfo, chr_rom_len: usize) -> bool {
+    match board.submapper {
+        1 => true,
+        2 => false,
+        _ => chr_rom_len > 0x2000,
+    }
+}
+
+impl Nina001Mapper {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr = if chr_rom.is_empty() {
+            vec![0; 0x2000]
+        } else {
+            chr_rom
+        };
+        Nina001Mapper {
+            prg_rom,
+            chr,
+            prg_ram: vec![0; 0x2000],
+            prg_bank: 0,
+            chr_banks: [0, 1],
+            mirroring,
+            prg_remapped: false,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr as usize >> 12) & 0x01] as usize;
+        (bank * CHR_BANK_SIZE + (addr as usize & 0x0FFF)) % self.chr.len()
+    }
+}
+
+impl Savestate for Nina001Mapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(self.prg_bank);
+        state.write_bytes(&self.chr_banks);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_bytes_into(&mut self.prg_ram)?;
+        self.prg_bank = state.read_u8()?;
+        state.read_bytes_into(&mut self.chr_banks)?;
+        Ok(())
+    }
+}
+
+impl Mapper for Nina001Mapper {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                let offset = self.prg_bank as usize * PRG_BANK_SIZE + (addr - 0x8000) as usize;
+                self.prg_rom[offset % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x7FFD => {
+                self.prg_remapped |= data & 0x01 != self.prg_bank;
+                self.prg_bank = data & 0x01;
+            }
+            0x7FFE => self.chr_banks[0] = data & 0x0F,
+            0x7FFF => self.chr_banks[1] = data & 0x0F,
+            _ => {}
+        }
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = data;
+        }
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
+    fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        Some(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, _addr: u16, _data: u8) {}
+
+    fn handles_write(&self, addr: u16) -> bool {
+        (0x6000..=0x7FFF).contains(&addr)
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::patterned;
+
+    #[test]
+    fn nina001_switches_banks_through_the_top_of_prg_ram() {
+        let mut mapper = Nina001Mapper::new(
+            patterned(2, PRG_BANK_SIZE),
+            patterned(16, CHR_BANK_SIZE),
+            Mirroring::Horizontal,
+        );
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_chr(0x1000, ChrSource::Cpu), 1);
+
+        mapper.write_prg(0x7FFD, 0x03);
+        mapper.write_prg(0x7FFE, 0x15);
+        mapper.write_prg(0x7FFF, 0x0C);
+        assert_eq!(mapper.read_prg(0xFFFF), 1);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Cpu), 5);
+        assert_eq!(mapper.read_chr(0x1FFF, ChrSource::Cpu), 12);
+        // The registers are RAM too.
+        assert_eq!(mapper.read_prg(0x7FFE), 0x15);
+
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0x42);
+        assert_eq!(mapper.read_prg(0x8000), 1);
+    }
+
+    #[test]
+    fn mapper_34_is_nina001_by_submapper_or_chr_size() {
+        let board = |submapper| BoardInfo {
+            submapper,
+            ..BoardInfo::default()
+        };
+        assert!(detected(&board(0), 0x10000));
+        assert!(!detected(&board(0), 0));
+        assert!(!detected(&board(0), 0x2000));
+        assert!(detected(&board(1), 0x2000));
+        assert!(!detected(&board(2), 0x10000));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::cart::Mirroring;
 use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 pub struct NromMapper {
     prg_rom: Vec<u8>,
@@ -22,6 +23,21 @@ impl NromMapper {
     }
 }
 
+impl Savestate for NromMapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        Ok(())
+    }
+}
+
 impl Mapper for NromMapper {
     fn read_prg(&self, addr: u16) -> u8 {
         if !(0x8000..=0xFFFF).contains(&addr) {
@@ -45,10 +61,18 @@ impl Mapper for NromMapper {
         // NROM has no PRG RAM, ignore writes
     }
 
+    fn take_prg_remapped(&mut self) -> bool {
+        false
+    }
+
     fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
         self.chr[addr as usize % self.chr.len()]
     }
 
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        Some(addr as usize % self.chr.len())
+    }
+
     fn write_chr(&mut self, addr: u16, data: u8) {
         if self.chr_is_ram {
             let index = addr as usize % self.chr.len();
@@ -56,6 +80,22 @@ impl Mapper for NromMapper {
         }
     }
 
+    fn maps_read(&self, addr: u16) -> bool {
+        addr >= 0x8000
+    }
+
+    fn handles_write(&self, _addr: u16) -> bool {
+        false
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
     fn mirroring(&self) -> Mirroring {
         self.mirroring.clone()
     }
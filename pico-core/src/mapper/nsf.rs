@@ -0,0 +1,168 @@
+use crate::cart::Mirroring;
+use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+pub struct NsfMapper {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    mirroring: Mirroring,
+
+    banks: [usize; 8],
+
+    // Only when playing an NSF file: work RAM at $6000, and the player's
+    // driver code at $5000.
+    prg_ram: Vec<u8>,
+    driver: Vec<u8>,
+    prg_remapped: bool,
+}
+
+const DRIVER_START: u16 = 0x5000;
+
+impl NsfMapper {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        assert!(!prg_rom.is_empty(), "PRG ROM must contain at least 4kB");
+
+        let total_banks = prg_rom.len() / 0x1000;
+
+        let last_bank = total_banks - 1;
+
+        let mut banks = [0usize; 8];
+        banks[7] = last_bank;
+
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = if chr_is_ram { vec![0; 0x2000] } else { chr_rom };
+
+        NsfMapper {
+            prg_rom,
+            banks,
+            chr,
+            chr_is_ram,
+            mirroring,
+            prg_ram: Vec::new(),
+            driver: Vec::new(),
+            prg_remapped: false,
+        }
+    }
+
+    // `banks` are the 4 KiB banks at $8000-$FFFF to start with.
+    pub fn for_nsf(prg_rom: Vec<u8>, banks: [u8; 8], driver: Vec<u8>) -> Self {
+        let mut mapper = NsfMapper::new(prg_rom, vec![], Mirroring::Vertical);
+        let total_banks = mapper.prg_rom.len() / 0x1000;
+        for (slot, bank) in mapper.banks.iter_mut().zip(banks) {
+            *slot = bank as usize % total_banks;
+        }
+        mapper.prg_ram = vec![0; 0x2000];
+        mapper.driver = driver;
+        mapper
+    }
+
+    fn driver_index(&self, addr: u16) -> Option<usize> {
+        let index = addr.checked_sub(DRIVER_START)? as usize;
+        (index < self.driver.len()).then_some(index)
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let offset_within_slice = (addr.wrapping_sub(0x8000)) as usize & 0x0FFF;
+        let slice_idx = ((addr.wrapping_sub(0x8000)) as usize >> 12) & 0x07;
+
+        let bank = self.banks[slice_idx] % (self.prg_rom.len() / 0x1000);
+
+        (bank * 0x1000) + offset_within_slice
+    }
+}
+
+impl Savestate for NsfMapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        for bank in self.banks {
+            state.write_usize(bank);
+        }
+        state.write_bytes(&self.prg_ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        for bank in &mut self.banks {
+            *bank = state.read_usize()?;
+        }
+        state.read_bytes_into(&mut self.prg_ram)
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn read_prg(&self, addr: u16) -> u8 {
+        if let Some(index) = self.driver_index(addr) {
+            return self.driver[index];
+        }
+        if (0x6000..=0x7FFF).contains(&addr) && !self.prg_ram.is_empty() {
+            return self.prg_ram[(addr - 0x6000) as usize];
+        }
+        if !(0x8000..=0xFFFF).contains(&addr) {
+            return 0;
+        }
+        if self.prg_rom.is_empty() {
+            return 0;
+        }
+
+        let off = self.prg_offset(addr);
+        self.prg_rom[off]
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if (0x5FF8..=0x5FFF).contains(&addr) {
+            let idx = (addr - 0x5FF8) as usize;
+            let total_banks = self.prg_rom.len() / 0x1000;
+            let bank = (data as usize) % total_banks;
+            self.prg_remapped |= bank != self.banks[idx];
+            self.banks[idx] = bank;
+        } else if (0x6000..=0x7FFF).contains(&addr) && !self.prg_ram.is_empty() {
+            self.prg_ram[(addr - 0x6000) as usize] = data;
+        }
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
+    fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        self.chr[(addr as usize) % self.chr.len()]
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        Some(addr as usize % self.chr.len())
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let idx = (addr as usize) % self.chr.len();
+            self.chr[idx] = data;
+        }
+    }
+
+    fn maps_read(&self, addr: u16) -> bool {
+        self.driver_index(addr).is_some() || addr >= 0x6000
+    }
+
+    fn handles_write(&self, addr: u16) -> bool {
+        (0x5FF8..=0x5FFF).contains(&addr)
+            || ((0x6000..=0x7FFF).contains(&addr) && !self.prg_ram.is_empty())
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.clone()
+    }
+}
@@ -1,5 +1,6 @@
 use crate::cart::Mirroring;
 use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 
 const PRG_BANK_SIZE: usize = 0x4000;
 
@@ -10,6 +11,8 @@ pub struct UxromMapper {
     prg_ram: Vec<u8>,
     bank_select: u8,
     mirroring: Mirroring,
+    bus_conflicts: bool,
+    prg_remapped: bool,
 }
 
 impl UxromMapper {
@@ -28,6 +31,8 @@ impl UxromMapper {
             prg_ram: vec![0; 0x2000],
             bank_select: 0,
             mirroring,
+            bus_conflicts: false,
+            prg_remapped: false,
         }
     }
 
@@ -42,6 +47,25 @@ impl UxromMapper {
     }
 }
 
+impl Savestate for UxromMapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        if self.chr_is_ram {
+            state.write_bytes(&self.chr);
+        }
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(self.bank_select);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            state.read_bytes_into(&mut self.chr)?;
+        }
+        state.read_bytes_into(&mut self.prg_ram)?;
+        self.bank_select = state.read_u8()?;
+        Ok(())
+    }
+}
+
 impl Mapper for UxromMapper {
     fn read_prg(&self, addr: u16) -> u8 {
         match addr {
@@ -75,13 +99,24 @@ impl Mapper for UxromMapper {
                 self.prg_ram[(addr - 0x6000) as usize] = data;
             }
             0x8000..=0xFFFF => {
+                let data = if self.bus_conflicts {
+                    data & self.read_prg(addr)
+                } else {
+                    data
+                };
                 let count = self.prg_bank_count() as u8;
-                self.bank_select = if count == 0 { 0 } else { data % count };
+                let bank_select = if count == 0 { 0 } else { data % count };
+                self.prg_remapped |= bank_select != self.bank_select;
+                self.bank_select = bank_select;
             }
             _ => {}
         }
     }
 
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
     fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
         if self.chr.is_empty() {
             0
@@ -90,6 +125,10 @@ impl Mapper for UxromMapper {
         }
     }
 
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        (!self.chr.is_empty()).then(|| addr as usize % self.chr.len())
+    }
+
     fn write_chr(&mut self, addr: u16, data: u8) {
         if self.chr_is_ram && !self.chr.is_empty() {
             let index = addr as usize % self.chr.len();
@@ -97,6 +136,22 @@ impl Mapper for UxromMapper {
         }
     }
 
+    fn set_bus_conflicts(&mut self, enabled: bool) {
+        self.bus_conflicts = enabled;
+    }
+
+    fn handles_write(&self, addr: u16) -> bool {
+        addr >= 0x6000
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn chr_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.chr_is_ram.then_some(self.chr.as_mut_slice())
+    }
+
     fn mirroring(&self) -> Mirroring {
         self.mirroring.clone()
     }
@@ -0,0 +1,145 @@
+use crate::cart::Mirroring;
+use crate::mapper::{ChrSource, Mapper};
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+// Mapper 99, the Vs. System's own board. Bit 2 of $4016 writes selects the
+// 8 KiB CHR bank and, on 40 KiB games, swaps the first PRG bank for the
+// fifth. Writes to $4020 drive the coin counter, which isn't emulated.
+pub struct VsMapper {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    prg_ram: Vec<u8>,
+    bank: u8,
+    mirroring: Mirroring,
+    prg_remapped: bool,
+}
+
+impl VsMapper {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr = if chr_rom.is_empty() {
+            vec![0; CHR_BANK_SIZE]
+        } else {
+            chr_rom
+        };
+
+        VsMapper {
+            prg_rom,
+            chr,
+            // 2 KiB shared with the second CPU on dual boards, mirrored.
+            prg_ram: vec![0; 0x0800],
+            bank: 0,
+            mirroring,
+            prg_remapped: false,
+        }
+    }
+}
+
+impl Savestate for VsMapper {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        state.write_u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_bytes_into(&mut self.prg_ram)?;
+        self.bank = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Mapper for VsMapper {
+    fn read_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[addr as usize % self.prg_ram.len()],
+            0x8000..=0xFFFF => {
+                if self.prg_rom.is_empty() {
+                    return 0;
+                }
+                let offset = (addr - 0x8000) as usize;
+                let offset = if offset < PRG_BANK_SIZE && self.prg_rom.len() > 0x8000 {
+                    self.bank as usize * 4 * PRG_BANK_SIZE + offset
+                } else {
+                    offset
+                };
+                self.prg_rom[offset % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            let len = self.prg_ram.len();
+            self.prg_ram[addr as usize % len] = data;
+        }
+    }
+
+    fn write_controller_port(&mut self, data: u8) {
+        let bank = (data >> 2) & 1;
+        self.prg_remapped |= bank != self.bank && self.prg_rom.len() > 0x8000;
+        self.bank = bank;
+    }
+
+    fn take_prg_remapped(&mut self) -> bool {
+        std::mem::take(&mut self.prg_remapped)
+    }
+
+    fn read_chr(&self, addr: u16, _source: ChrSource) -> u8 {
+        let index = self.bank as usize * CHR_BANK_SIZE + (addr as usize & 0x1FFF);
+        self.chr[index % self.chr.len()]
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        Some((self.bank as usize * CHR_BANK_SIZE + (addr as usize & 0x1FFF)) % self.chr.len())
+    }
+
+    fn write_chr(&mut self, _addr: u16, _data: u8) {}
+
+    fn handles_write(&self, addr: u16) -> bool {
+        addr == 0x4020 || (0x6000..=0x7FFF).contains(&addr)
+    }
+
+    fn chr_data(&self) -> &[u8] {
+        &self.chr
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::patterned;
+
+    #[test]
+    fn vs_controller_port_switches_chr_and_extra_prg() {
+        let mut mapper = VsMapper::new(
+            patterned(5, PRG_BANK_SIZE),
+            patterned(2, CHR_BANK_SIZE),
+            Mirroring::FourScreen,
+        );
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_chr(0x0000, ChrSource::Background), 0);
+
+        mapper.write_controller_port(0x04);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xA000), 1);
+        assert_eq!(mapper.read_chr(0x1FFF, ChrSource::Background), 1);
+
+        mapper.write_controller_port(0x01);
+        assert_eq!(mapper.read_prg(0x9FFF), 0);
+    }
+}
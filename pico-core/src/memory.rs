@@ -1,3 +1,5 @@
+use crate::decode_cache::Decoded;
+
 pub trait Memory {
     fn read(&mut self, addr: u16) -> u8;
 
@@ -15,4 +17,10 @@ pub trait Memory {
         self.write(addr, lo);
         self.write(addr + 1, hi);
     }
+
+    // The instruction at `addr` already decoded, for the CPU to run without
+    // fetching it, or `None` to have it fetched.
+    fn decoded(&mut self, _addr: u16) -> Option<Decoded> {
+        None
+    }
 }
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
 use crate::joypad::JoypadButton;
@@ -59,6 +59,50 @@ pub struct GamepadInput {
     pub a: bool,
 }
 
+impl GamepadInput {
+    pub fn from_buttons(buttons: JoypadButton) -> Self {
+        GamepadInput {
+            right: buttons.contains(JoypadButton::RIGHT),
+            left: buttons.contains(JoypadButton::LEFT),
+            down: buttons.contains(JoypadButton::DOWN),
+            up: buttons.contains(JoypadButton::UP),
+            start: buttons.contains(JoypadButton::START),
+            select: buttons.contains(JoypadButton::SELECT),
+            b: buttons.contains(JoypadButton::BUTTON_B),
+            a: buttons.contains(JoypadButton::BUTTON_A),
+        }
+    }
+
+    pub fn to_buttons(&self) -> JoypadButton {
+        let mut buttons = JoypadButton::empty();
+        buttons.set(JoypadButton::RIGHT, self.right);
+        buttons.set(JoypadButton::LEFT, self.left);
+        buttons.set(JoypadButton::DOWN, self.down);
+        buttons.set(JoypadButton::UP, self.up);
+        buttons.set(JoypadButton::START, self.start);
+        buttons.set(JoypadButton::SELECT, self.select);
+        buttons.set(JoypadButton::BUTTON_B, self.b);
+        buttons.set(JoypadButton::BUTTON_A, self.a);
+        buttons
+    }
+
+    fn to_fm2(&self) -> String {
+        [
+            (self.right, 'R'),
+            (self.left, 'L'),
+            (self.down, 'D'),
+            (self.up, 'U'),
+            (self.start, 'T'),
+            (self.select, 'S'),
+            (self.b, 'B'),
+            (self.a, 'A'),
+        ]
+        .iter()
+        .map(|&(pressed, ch)| if pressed { ch } else { '.' })
+        .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Subtitle {
     pub frame: u32,
@@ -89,6 +133,7 @@ impl FM2Movie {
 
         let mut lines = contents.lines();
         let mut header = String::new();
+        let mut rest = "";
 
         for line in &mut lines {
             if line.trim().is_empty() {
@@ -96,6 +141,7 @@ impl FM2Movie {
             }
 
             if line.starts_with('|') {
+                rest = line;
                 break;
             }
 
@@ -105,7 +151,7 @@ impl FM2Movie {
 
         let movie_header = parse_header(&header)?;
 
-        let input_log = parse_input_log(lines.clone(), &movie_header)?;
+        let input_log = parse_input_log(std::iter::once(rest).chain(lines), &movie_header)?;
 
         Ok(FM2Movie {
             header: movie_header,
@@ -132,68 +178,98 @@ impl FM2Movie {
             .ok_or_else(|| format!("Frame {} out of range", frame))?;
 
         if let Some(gamepad_input) = &input.port0_input {
-            let mut buttons = JoypadButton::empty();
-
-            if gamepad_input.right {
-                buttons |= JoypadButton::RIGHT;
-            }
-            if gamepad_input.left {
-                buttons |= JoypadButton::LEFT;
-            }
-            if gamepad_input.down {
-                buttons |= JoypadButton::DOWN;
-            }
-            if gamepad_input.up {
-                buttons |= JoypadButton::UP;
-            }
-            if gamepad_input.start {
-                buttons |= JoypadButton::START;
-            }
-            if gamepad_input.select {
-                buttons |= JoypadButton::SELECT;
-            }
-            if gamepad_input.b {
-                buttons |= JoypadButton::BUTTON_B;
-            }
-            if gamepad_input.a {
-                buttons |= JoypadButton::BUTTON_A;
-            }
-
-            joypad1.button_status = buttons;
+            joypad1.button_status = gamepad_input.to_buttons();
         }
 
         if let Some(gamepad_input) = &input.port1_input {
-            let mut buttons = JoypadButton::empty();
+            joypad2.button_status = gamepad_input.to_buttons();
+        }
 
-            if gamepad_input.right {
-                buttons |= JoypadButton::RIGHT;
-            }
-            if gamepad_input.left {
-                buttons |= JoypadButton::LEFT;
-            }
-            if gamepad_input.down {
-                buttons |= JoypadButton::DOWN;
-            }
-            if gamepad_input.up {
-                buttons |= JoypadButton::UP;
-            }
-            if gamepad_input.start {
-                buttons |= JoypadButton::START;
-            }
-            if gamepad_input.select {
-                buttons |= JoypadButton::SELECT;
-            }
-            if gamepad_input.b {
-                buttons |= JoypadButton::BUTTON_B;
-            }
-            if gamepad_input.a {
-                buttons |= JoypadButton::BUTTON_A;
-            }
+        Ok(())
+    }
 
-            joypad2.button_status = buttons;
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        self.write(&mut writer)?;
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write file: {}", e))
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), String> {
+        let header = &self.header;
+        let mut text = String::new();
+
+        text.push_str(&format!("version {}\n", header.version));
+        text.push_str(&format!("emuVersion {}\n", header.emu_version));
+        if let Some(count) = header.rerecord_count {
+            text.push_str(&format!("rerecordCount {}\n", count));
+        }
+        text.push_str(&format!("palFlag {}\n", header.pal_flag as u8));
+        text.push_str(&format!("romFilename {}\n", header.rom_filename));
+        text.push_str(&format!("romChecksum {}\n", header.rom_checksum));
+        text.push_str(&format!("guid {}\n", header.guid));
+        text.push_str(&format!("fourscore {}\n", header.fourscore as u8));
+        text.push_str(&format!("port0 {}\n", header.port0 as u8));
+        text.push_str(&format!("port1 {}\n", header.port1 as u8));
+        text.push_str(&format!("port2 {}\n", header.port2 as u8));
+        text.push_str(&format!("FDS {}\n", header.fds as u8));
+        text.push_str(&format!("NewPPU {}\n", header.new_ppu as u8));
+        if let Some(comment) = &header.comment {
+            text.push_str(&format!("comment {}\n", comment));
+        }
+        for subtitle in header.subtitles.iter().flatten() {
+            text.push_str(&format!("subtitle {} {}\n", subtitle.frame, subtitle.text));
+        }
+        if let Some(state) = &header.savestate {
+            text.push_str(&format!("savestate base64:{}\n", encode_base64(state)));
         }
 
-        Ok(())
+        for record in &self.input_log {
+            let port0 = record
+                .port0_input
+                .as_ref()
+                .map(GamepadInput::to_fm2)
+                .unwrap_or_default();
+            let port1 = record
+                .port1_input
+                .as_ref()
+                .map(GamepadInput::to_fm2)
+                .unwrap_or_default();
+            text.push_str(&format!("|{}|{}|{}||\n", record.commands, port0, port1));
+        }
+
+        writer
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("Failed to write movie: {}", e))
+    }
+}
+
+impl MovieHeader {
+    pub fn for_recording(rom_filename: &str) -> Self {
+        MovieHeader {
+            version: 3,
+            emu_version: format!("pico {}", env!("CARGO_PKG_VERSION")),
+            rerecord_count: Some(0),
+            pal_flag: false,
+            new_ppu: false,
+            fds: false,
+            fourscore: false,
+            port0: InputDevice::Gamepad,
+            port1: InputDevice::Gamepad,
+            port2: FamicomExpPort::None,
+            binary: false,
+            length: None,
+            rom_filename: rom_filename.to_string(),
+            comment: None,
+            subtitles: None,
+            // FCEUX expects an MD5 here; an all-zero digest only triggers a
+            // mismatch warning on playback.
+            rom_checksum: "base64:AAAAAAAAAAAAAAAAAAAAAA==".to_string(),
+            guid: "00000000-0000-0000-0000-000000000000".to_string(),
+            savestate: None,
+        }
     }
 }
 
@@ -237,7 +313,7 @@ fn parse_header(header_text: &str) -> Result<MovieHeader, String> {
         .get("rerecordCount")
         .and_then(|v| v.parse::<i32>().ok());
 
-    let pal_flag = false;
+    let pal_flag = pairs.get("palFlag").map(|v| *v == "1").unwrap_or(false);
 
     let new_ppu = pairs.get("NewPPU").map(|v| *v == "1").unwrap_or(false);
 
@@ -285,6 +361,11 @@ fn parse_header(header_text: &str) -> Result<MovieHeader, String> {
         .ok_or("Missing romChecksum field")?
         .to_string();
 
+    let savestate = match pairs.get("savestate") {
+        Some(value) => Some(parse_binary_field(value)?),
+        None => None,
+    };
+
     Ok(MovieHeader {
         version,
         emu_version,
@@ -303,12 +384,12 @@ fn parse_header(header_text: &str) -> Result<MovieHeader, String> {
         subtitles: Some(subtitles),
         guid,
         rom_checksum,
-        savestate: None,
+        savestate,
     })
 }
 
-fn parse_input_log(
-    lines: std::str::Lines,
+fn parse_input_log<'a>(
+    lines: impl Iterator<Item = &'a str>,
     header: &MovieHeader,
 ) -> Result<Vec<InputRecord>, String> {
     let mut input_log = Vec::new();
@@ -373,7 +454,7 @@ fn parse_text_record(line: &str, header: &MovieHeader) -> Result<InputRecord, St
     })
 }
 
-fn parse_gamepad_input(input: &str) -> Result<GamepadInput, String> {
+pub fn parse_gamepad_input(input: &str) -> Result<GamepadInput, String> {
     let input = input.trim();
 
     if input.is_empty() {
@@ -421,3 +502,63 @@ fn parse_subtitle_line(line: &str) -> Result<Subtitle, String> {
 
     Ok(Subtitle { frame, text })
 }
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let triple = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (triple >> (18 - 6 * i)) & 0x3F;
+                out.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for ch in text.bytes().filter(|&c| c != b'=') {
+        let value = BASE64_ALPHABET
+            .iter()
+            .position(|&c| c == ch)
+            .ok_or_else(|| format!("Invalid base64 character: {}", ch as char))?;
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+// Binary header fields are either "base64:..." or a plain hex string.
+fn parse_binary_field(value: &str) -> Result<Vec<u8>, String> {
+    if let Some(encoded) = value.strip_prefix("base64:") {
+        return decode_base64(encoded);
+    }
+
+    let hex = value.trim_start_matches("0x");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| "Invalid hex binary field".to_string())
+        })
+        .collect()
+}
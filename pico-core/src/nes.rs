@@ -0,0 +1,827 @@
+use crate::{
+    apu::APU,
+    bus::Bus,
+    cart::Cart,
+    cpu::{CPU, InterruptType},
+    debugger::{Debugger, Stop},
+    event::EmulatorEvent,
+    joypad::Joypad,
+    mapper::Mapper,
+    ppu::render::Renderer,
+    region::Region,
+    rom_db::Quirks,
+    savestate::{MAGIC, Savestate, StateReader, StateWriter, VERSION},
+    trace::{TraceHook, TraceRecord},
+};
+
+// What one PPU dot of `Nes::clock` did, so a scheduler doesn't have to ask
+// each part of the console after every call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClockResult {
+    pub frame_complete: bool,
+    pub instruction_complete: bool,
+    // The PPU position the dot moved to.
+    pub scanline: i16,
+    pub dot: i16,
+    // Interrupts the CPU started handling on this dot.
+    pub nmi_taken: bool,
+    pub irq_taken: bool,
+    // Audio samples the APU put out on this dot.
+    pub samples: u32,
+}
+
+// A point in emulated time, counted from power-on. It only moves as the
+// console runs, so external tools can line up with it rather than with the
+// host clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EmulatedTime {
+    pub cpu_cycles: u64,
+    pub nanos: u64,
+}
+
+impl EmulatedTime {
+    pub fn from_cpu_cycles(cpu_cycles: u64, cpu_clock_rate: u64) -> Self {
+        let nanos = cpu_cycles as u128 * 1_000_000_000 / cpu_clock_rate.max(1) as u128;
+        EmulatedTime {
+            cpu_cycles,
+            nanos: nanos as u64,
+        }
+    }
+}
+
+// How closely the console is emulated where exactness costs speed. Lower
+// profiles suit slow devices; every game that works on a higher one is
+// expected to mostly work on a lower one too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccuracyProfile {
+    // OAM DMA copies all 256 bytes at once and DMC sample fetches are free,
+    // neither stalling the CPU.
+    Fast,
+    // OAM DMA halts the CPU for its real 513-514 cycles, copying a byte
+    // every other cycle, and each DMC sample fetch for up to 4.
+    #[default]
+    Balanced,
+    // Timed DMA as in `Balanced`, drawn a scanline at a time unless a
+    // renderer is picked, and never running the CPU from decoded blocks.
+    Accurate,
+}
+
+impl AccuracyProfile {
+    pub const ALL: [AccuracyProfile; 3] = [
+        AccuracyProfile::Fast,
+        AccuracyProfile::Balanced,
+        AccuracyProfile::Accurate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccuracyProfile::Fast => "fast",
+            AccuracyProfile::Balanced => "balanced",
+            AccuracyProfile::Accurate => "accurate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AccuracyProfile> {
+        AccuracyProfile::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn timed_dma(&self) -> bool {
+        *self != AccuracyProfile::Fast
+    }
+
+    // What draws the picture when neither the user nor the ROM database
+    // picks a renderer.
+    pub fn renderer(&self) -> Renderer {
+        match self {
+            AccuracyProfile::Accurate => Renderer::Scanline,
+            _ => Renderer::default(),
+        }
+    }
+
+    // Whether the CPU may run from decoded blocks, which skip fetching the
+    // code over the bus.
+    pub fn allows_fast_cpu(&self) -> bool {
+        *self != AccuracyProfile::Accurate
+    }
+}
+
+pub struct Nes {
+    pub cpu: CPU,
+    pub bus: Bus,
+    pub system_clock: u64,
+    frame_time: EmulatedTime,
+    // PPU frame and CPU cycle counts at the last reset.
+    reset_frame: u64,
+    reset_cycle: u64,
+    region: Region,
+    trace_hook: Option<TraceHook>,
+    events: Vec<EmulatorEvent>,
+    compat_notes_seen: usize,
+    // The renderer asked for through `set_renderer`, over any other pick.
+    forced_renderer: Option<Renderer>,
+}
+
+impl Nes {
+    pub fn new(cart: Cart, apu: APU) -> Self {
+        let region = cart.region;
+        let mut nes = Nes {
+            cpu: CPU::new(),
+            bus: Bus::new(cart, apu),
+            system_clock: 0,
+            frame_time: EmulatedTime::default(),
+            reset_frame: 0,
+            reset_cycle: 0,
+            region,
+            trace_hook: None,
+            events: Vec::new(),
+            compat_notes_seen: 0,
+            forced_renderer: None,
+        };
+        nes.set_region(region);
+        nes.set_renderer(None);
+        nes.set_sprite_limit(None);
+        nes
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // Defaults to the cartridge's region; overriding it is mostly useful for
+    // ROMs with an iNES header, which can't say.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.bus.ppu.set_region(region);
+        self.bus.apu.set_region(region);
+    }
+
+    // Restarts `frame_count` and `cpu_cycles`, but not emulated time.
+    pub fn reset(&mut self) {
+        self.bus.cpu_reset(&mut self.cpu);
+        self.reset_frame = self.bus.ppu.frame_count;
+        self.reset_cycle = self.bus.apu.cycle();
+    }
+
+    // Frames completed since power-on or the last reset, which is what movie
+    // input is indexed by.
+    pub fn frame_count(&self) -> u64 {
+        self.bus.ppu.frame_count - self.reset_frame
+    }
+
+    // CPU cycles run since power-on or the last reset.
+    pub fn cpu_cycles(&self) -> u64 {
+        self.bus.apu.cycle() - self.reset_cycle
+    }
+
+    // The CPU's own count of cycles since power-on, which resets don't
+    // restart. Trace lines show it as CYC.
+    pub fn total_cpu_cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
+    // The cartridge's compatibility report as JSON, along with how the
+    // console was set up to run it.
+    pub fn compat_json(&self) -> String {
+        let setup = [
+            ("region", self.region.name()),
+            ("accuracy", self.accuracy().name()),
+            ("renderer", self.renderer().name()),
+        ];
+        self.bus
+            .cart
+            .compat
+            .to_json(&setup, self.bus.ppu.frame_count)
+    }
+
+    // Called with the next instruction each time the CPU finishes one.
+    // Without a hook nothing is captured, so tracing costs nothing when off.
+    pub fn set_trace_hook(&mut self, hook: Option<TraceHook>) {
+        self.trace_hook = hook;
+    }
+
+    pub fn accuracy(&self) -> AccuracyProfile {
+        self.bus.accuracy
+    }
+
+    pub fn set_accuracy(&mut self, profile: AccuracyProfile) {
+        self.bus.accuracy = profile;
+        if !profile.allows_fast_cpu() {
+            self.bus.set_fast_cpu(false);
+        }
+        self.set_renderer(self.forced_renderer);
+    }
+
+    pub fn fast_cpu(&self) -> bool {
+        self.bus.fast_cpu()
+    }
+
+    // Left off under a profile that doesn't allow it.
+    pub fn set_fast_cpu(&mut self, enabled: bool) {
+        self.bus
+            .set_fast_cpu(enabled && self.accuracy().allows_fast_cpu());
+    }
+
+    pub fn renderer(&self) -> Renderer {
+        self.bus.ppu.renderer
+    }
+
+    // Forces a renderer, or with `None` goes back to the ROM database's pick
+    // for the game and otherwise the accuracy profile's.
+    pub fn set_renderer(&mut self, renderer: Option<Renderer>) {
+        self.forced_renderer = renderer;
+        self.bus.ppu.renderer = renderer
+            .or(self.bus.cart.renderer)
+            .unwrap_or(self.accuracy().renderer());
+    }
+
+    // Whether to drop sprites past the eighth on a line, which makes them
+    // flicker in games that cycle through them. `None` leaves it on unless
+    // the ROM database says the game is better off without it.
+    pub fn set_sprite_limit(&mut self, enabled: Option<bool>) {
+        let quirk = self.bus.cart.quirks.contains(Quirks::NO_SPRITE_LIMIT);
+        self.bus.ppu.sprite_limit = enabled.unwrap_or(!quirk);
+    }
+
+    pub fn clock(&mut self) -> ClockResult {
+        let frame_complete = self.bus.ppu_clock();
+        self.cpu.set_nmi_line(self.bus.ppu.nmi_line());
+        let mut instruction_complete = false;
+        let mut started = None;
+        let samples_before = self.bus.apu.samples_generated();
+
+        // The CPU runs on every third PPU dot, or 5 out of 16 on PAL.
+        let (dots, cpu_cycles) = self.region.ppu_dots_per_cpu_cycle();
+        if (self.system_clock % dots) * cpu_cycles % dots < cpu_cycles {
+            let irq = self.bus.poll_irq();
+            self.cpu.set_irq_line(irq);
+            instruction_complete = self.bus.cpu_clock(&mut self.cpu);
+            started = self.cpu.take_interrupt_started();
+            self.bus.apu_clock();
+        }
+
+        if instruction_complete {
+            self.raise_events();
+        }
+        if instruction_complete && let Some(hook) = &mut self.trace_hook {
+            hook(&TraceRecord::capture(&self.cpu, &self.bus));
+        }
+        if instruction_complete && let Some(debugger) = &mut self.bus.debugger {
+            debugger.instruction_complete(self.cpu.registers.pc);
+        }
+
+        self.system_clock = self.system_clock.wrapping_add(1);
+        if frame_complete {
+            self.bus.apply_cheat_freezes();
+            self.frame_time = self.emulated_time();
+            if let Some(debugger) = &mut self.bus.debugger {
+                debugger.frame_complete();
+            }
+        }
+
+        ClockResult {
+            frame_complete,
+            instruction_complete,
+            scanline: self.bus.ppu.scanline,
+            dot: self.bus.ppu.cycle,
+            nmi_taken: started == Some(InterruptType::NMI),
+            irq_taken: started == Some(InterruptType::IRQ),
+            samples: (self.bus.apu.samples_generated() - samples_before) as u32,
+        }
+    }
+
+    // Clocks until `done` accepts a dot's result, and returns that result,
+    // e.g. to run to a scanline or to the next NMI.
+    pub fn run_until(&mut self, mut done: impl FnMut(&ClockResult) -> bool) -> ClockResult {
+        loop {
+            let result = self.clock();
+            if done(&result) {
+                return result;
+            }
+        }
+    }
+
+    fn raise_events(&mut self) {
+        let compat = &self.bus.cart.compat;
+        if compat.notes().len() > self.compat_notes_seen {
+            let mapper = compat.mapper();
+            for note in &compat.notes()[self.compat_notes_seen..] {
+                self.events.push(EmulatorEvent::Compat {
+                    mapper,
+                    note: note.clone(),
+                });
+            }
+            self.compat_notes_seen = compat.notes().len();
+        }
+
+        // A halted CPU never completes another instruction, so this is only
+        // seen once per jam.
+        if self.cpu.is_halted() {
+            let pc = self.cpu.registers.pc.wrapping_sub(1);
+            self.events.push(EmulatorEvent::CpuJam { pc });
+        }
+    }
+
+    // Queues an event of the frontend's own, so it reaches the player the same
+    // way as the console's.
+    pub fn push_event(&mut self, event: EmulatorEvent) {
+        self.events.push(event);
+    }
+
+    // Events raised since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<EmulatorEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // The APU is clocked on every CPU cycle, so its counter doubles as the
+    // console's CPU cycle count.
+    pub fn emulated_time(&self) -> EmulatedTime {
+        EmulatedTime::from_cpu_cycles(self.bus.apu.cycle(), self.bus.apu.cpu_clock_rate())
+    }
+
+    // When the most recently completed frame finished.
+    pub fn frame_time(&self) -> EmulatedTime {
+        self.frame_time
+    }
+
+    pub fn step_frame(&mut self) {
+        let start_frame = self.bus.ppu.frame_count;
+        while self.bus.ppu.frame_count == start_frame {
+            self.clock();
+        }
+    }
+
+    // Like `step_frame`, but returns early if the debugger stops the CPU,
+    // with why it did. Does nothing while the debugger is paused.
+    pub fn debug_step_frame(&mut self) -> Option<Stop> {
+        match &self.bus.debugger {
+            Some(debugger) if debugger.is_paused() => return None,
+            Some(_) => {}
+            None => {
+                self.step_frame();
+                return None;
+            }
+        }
+        let start_frame = self.bus.ppu.frame_count;
+        while self.bus.ppu.frame_count == start_frame {
+            self.clock();
+            if let Some(stop) = self.bus.debugger.as_mut().and_then(Debugger::take_stop) {
+                return Some(stop);
+            }
+        }
+        None
+    }
+
+    pub fn joypad_mut(&mut self, index: usize) -> Option<&mut Joypad> {
+        self.bus.joypad_mut(index)
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut dyn Mapper {
+        self.bus.mapper_mut()
+    }
+
+    pub fn joypads_mut(&mut self) -> (&mut Joypad, &mut Joypad) {
+        self.bus.joypads_mut()
+    }
+
+    // Snapshot of the whole machine. Only loads back into a console running
+    // the same ROM.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_raw(MAGIC);
+        state.write_u16(VERSION);
+        state.write_u32(self.bus.cart.crc32);
+        state.write_u8(self.region as u8);
+        state.write_u64(self.system_clock);
+        state.write_u64(self.frame_time.cpu_cycles);
+        state.write_u64(self.frame_time.nanos);
+        state.write_u64(self.reset_frame);
+        state.write_u64(self.reset_cycle);
+        self.cpu.save_state(&mut state);
+        self.bus.save_state(&mut state);
+        state.into_inner()
+    }
+
+    // On failure the console is left exactly as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let backup = self.save_state();
+        self.read_state(data).inspect_err(|_| {
+            self.read_state(&backup)
+                .expect("restoring the previous state cannot fail");
+        })?;
+        self.bus.mark_prg_ram_dirty();
+        Ok(())
+    }
+
+    fn read_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut state = StateReader::new(data);
+        if state.read_raw(MAGIC.len())? != MAGIC {
+            return Err("Not a savestate".to_string());
+        }
+        let version = state.read_u16()?;
+        if version != VERSION {
+            return Err(format!(
+                "Savestate version {} is not supported (expected {})",
+                version, VERSION
+            ));
+        }
+        let crc32 = state.read_u32()?;
+        if crc32 != self.bus.cart.crc32 {
+            return Err(format!(
+                "Savestate is for ROM {:08X}, not the loaded {:08X}",
+                crc32, self.bus.cart.crc32
+            ));
+        }
+        let region = match state.read_u8()? {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            2 => Region::Dendy,
+            other => return Err(format!("Invalid region {} in savestate", other)),
+        };
+        self.set_region(region);
+        self.system_clock = state.read_u64()?;
+        self.frame_time = EmulatedTime {
+            cpu_cycles: state.read_u64()?,
+            nanos: state.read_u64()?,
+        };
+        self.reset_frame = state.read_u64()?;
+        self.reset_cycle = state.read_u64()?;
+        self.cpu.load_state(&mut state)?;
+        self.bus.load_state(&mut state)?;
+        if !state.is_finished() {
+            return Err("Savestate has trailing data".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::cart::test::test_rom;
+    use crate::compat::CompatNote;
+    use crate::testutil::{Asm, CODE_ORIGIN, IDENTITY, RomBuilder};
+
+    // Counts frames in RAM forever: INX; STX $10; INC $0200; JMP $8000
+    fn counting_nes() -> Nes {
+        nes_running(vec![0xE8, 0x86, 0x10, 0xEE, 0x00, 0x02, 0x4C, 0x00, 0x80])
+    }
+
+    fn nes_running(mut program: Vec<u8>) -> Nes {
+        program.resize(0x8000, 0);
+        program[0x7FFC] = 0x00;
+        program[0x7FFD] = 0x80;
+        let apu = APU::new(48_000);
+        let mut nes = Nes::new(test_rom(program), apu);
+        nes.reset();
+        nes
+    }
+
+    #[test]
+    fn test_emulated_time_from_cpu_cycles() {
+        let time = EmulatedTime::from_cpu_cycles(1_789_773, 1_789_773);
+        assert_eq!(time.nanos, 1_000_000_000);
+
+        let frame = EmulatedTime::from_cpu_cycles(29_781, 1_789_773);
+        assert_eq!(frame.nanos, 16_639_540);
+    }
+
+    #[test]
+    fn test_pal_frames_are_longer_with_fewer_cpu_cycles_per_dot() {
+        let mut nes = counting_nes();
+        nes.step_frame();
+        assert_eq!(nes.emulated_time().cpu_cycles, 29_781);
+
+        let mut nes = counting_nes();
+        nes.set_region(Region::Pal);
+        nes.step_frame();
+        // 312 lines of 341 dots at 3.2 dots per cycle.
+        assert_eq!(nes.emulated_time().cpu_cycles, 33_248);
+    }
+
+    #[test]
+    fn test_compat_json_says_how_the_console_ran() {
+        let mut nes = counting_nes();
+        nes.step_frame();
+        let json = nes.compat_json();
+        assert!(json.contains("\"frames\": 1,"), "{}", json);
+        assert!(
+            json.contains("\"renderer\": \"scroll_segments\","),
+            "{}",
+            json
+        );
+        assert!(json.contains("\"irq_sources\": []"), "{}", json);
+    }
+
+    #[test]
+    fn test_frame_count_and_cpu_cycles_restart_on_reset() {
+        let mut nes = counting_nes();
+        nes.step_frame();
+        nes.step_frame();
+        assert_eq!(nes.frame_count(), 2);
+        assert_eq!(nes.cpu_cycles(), nes.emulated_time().cpu_cycles);
+        let state = nes.save_state();
+
+        nes.reset();
+        assert_eq!((nes.frame_count(), nes.cpu_cycles()), (0, 0));
+        nes.step_frame();
+        assert_eq!(nes.frame_count(), 1);
+        assert!(nes.cpu_cycles() < nes.emulated_time().cpu_cycles);
+
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.frame_count(), 2);
+        assert_eq!(nes.cpu_cycles(), nes.emulated_time().cpu_cycles);
+    }
+
+    #[test]
+    fn test_trace_hook_sees_each_instruction_before_it_runs() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        let mut nes = counting_nes();
+        nes.set_trace_hook(Some(Box::new(move |record| {
+            sink.lock().unwrap().push(record.to_string());
+        })));
+        while lines.lock().unwrap().len() < 3 {
+            nes.clock();
+        }
+
+        let lines = lines.lock().unwrap();
+        assert_eq!(
+            lines[0],
+            "8001  86 10     STX $10 = 00                    A:00 X:01 Y:00 P:04 SP:FD CYC:9"
+        );
+        assert!(
+            lines[1].starts_with("8003  EE 00 02  INC $0200 = 00"),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].starts_with("8006  4C 00 80  JMP $8000"),
+            "{}",
+            lines[2]
+        );
+    }
+
+    #[test]
+    fn test_clock_reports_interrupts_position_and_samples() {
+        // LDA #$80; STA $2000; JMP $8005, with NMI pointing at an RTI.
+        let mut program = vec![0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80, 0x40];
+        program.resize(0x7FFA, 0);
+        program.extend([0x08, 0x80]);
+        let mut nes = nes_running(program);
+
+        let before = nes.bus.apu.samples_generated();
+        let mut samples = 0;
+        let result = nes.run_until(|result| {
+            samples += result.samples;
+            result.nmi_taken
+        });
+        assert_eq!(result.scanline, 241);
+        assert!(result.dot < 12, "{:?}", result);
+        assert!(!result.irq_taken);
+        assert_eq!(samples as u64, nes.bus.apu.samples_generated() - before);
+        assert!(samples > 700, "{}", samples);
+    }
+
+    #[test]
+    fn test_fast_cpu_runs_rewritten_ram_code_as_written() {
+        // Puts INX; RTS at $0300 and calls it, then makes it INY and calls
+        // it again.
+        let program = vec![
+            0xA9, 0xE8, 0x8D, 0x00, 0x03, 0xA9, 0x60, 0x8D, 0x01, 0x03, 0x20, 0x00, 0x03, 0xA9,
+            0xC8, 0x8D, 0x00, 0x03, 0x20, 0x00, 0x03, 0x4C, 0x15, 0x80,
+        ];
+        let mut fetched = nes_running(program.clone());
+        let mut fast = nes_running(program);
+        fast.set_fast_cpu(true);
+        fetched.step_frame();
+        fast.step_frame();
+
+        let registers = &fast.cpu.registers;
+        assert_eq!((registers.x, registers.y, registers.pc), (1, 1, 0x8015));
+        assert_eq!(
+            format!("{:?}", registers),
+            format!("{:?}", fetched.cpu.registers)
+        );
+        assert_eq!(fast.cpu_cycles(), fetched.cpu_cycles());
+    }
+
+    // Calls $9000 with `switch` showing PRG bank 0, then 1, then 0 there.
+    // The routines at `routines` in PRG ROM, one per bank, are INX; RTS and
+    // INY; RTS, so X and Y count the calls that reached each bank.
+    fn assert_fast_cpu_follows_prg_switches(
+        rom: RomBuilder,
+        routines: [usize; 2],
+        switch: fn(&mut Asm, u8),
+    ) {
+        let mut asm = Asm::new(CODE_ORIGIN);
+        asm.label("reset");
+        for bank in [0, 1, 0] {
+            switch(&mut asm, bank);
+            asm.jsr(0x9000);
+        }
+        asm.label("done").jmp("done");
+        let mut image = rom.code(&asm, "reset", "reset", "reset").build();
+        for (offset, routine) in routines.into_iter().zip([Asm::inx, Asm::iny]) {
+            let mut asm = Asm::new(0x9000);
+            routine(&mut asm).rts();
+            image[16 + offset..16 + offset + 2].copy_from_slice(&asm.finish());
+        }
+
+        let run = |fast| {
+            let mut nes = Nes::new(Cart::new(&image).unwrap(), APU::new(48_000));
+            nes.set_fast_cpu(fast);
+            nes.reset();
+            nes.step_frame();
+            let registers = &nes.cpu.registers;
+            (registers.x, registers.y)
+        };
+        assert_eq!(run(false), (2, 1));
+        assert_eq!(run(true), (2, 1));
+    }
+
+    #[test]
+    fn test_fast_cpu_follows_prg_switches_through_the_controller_port() {
+        // Mapper 99 swaps the first 8 KiB of a 40 KiB or bigger PRG ROM for
+        // the fifth with bit 2 of $4016.
+        assert_fast_cpu_follows_prg_switches(
+            RomBuilder::new(99).banks(3, 1),
+            [0x1000, 0x9000],
+            |asm, bank| {
+                asm.poke(0x4016, bank << 2);
+            },
+        );
+    }
+
+    #[test]
+    fn test_fast_cpu_follows_prg_switches_on_both_mapper_34_boards() {
+        // NINA-001 switches its 32 KiB bank with a write to $7FFD, in PRG
+        // RAM, and BNROM with one to PRG ROM, through the identity table for
+        // its bus conflicts.
+        assert_fast_cpu_follows_prg_switches(
+            RomBuilder::new(34).banks(4, 2),
+            [0x1000, 0x9000],
+            |asm, bank| {
+                asm.poke(0x7FFD, bank);
+            },
+        );
+        assert_fast_cpu_follows_prg_switches(
+            RomBuilder::new(34).banks(4, 1),
+            [0x1000, 0x9000],
+            |asm, bank| {
+                asm.poke(IDENTITY + bank as u16, bank);
+            },
+        );
+    }
+
+    #[test]
+    fn test_renderer_defaults_to_the_rom_database_pick() {
+        let mut cart = test_rom(vec![0; 0x8000]);
+        cart.renderer = Some(Renderer::Scanline);
+        let apu = APU::new(48_000);
+        let mut nes = Nes::new(cart, apu);
+        assert_eq!(nes.renderer(), Renderer::Scanline);
+
+        nes.set_renderer(Some(Renderer::ScrollSegments));
+        assert_eq!(nes.renderer(), Renderer::ScrollSegments);
+        nes.set_renderer(None);
+        assert_eq!(nes.renderer(), Renderer::Scanline);
+    }
+
+    #[test]
+    fn test_sprite_limit_follows_the_rom_database() {
+        let apu = || APU::new(48_000);
+        let nes = Nes::new(test_rom(vec![0; 0x8000]), apu());
+        assert!(nes.bus.ppu.sprite_limit);
+
+        let mut cart = test_rom(vec![0; 0x8000]);
+        cart.quirks = Quirks::NO_SPRITE_LIMIT;
+        let mut nes = Nes::new(cart, apu());
+        assert!(!nes.bus.ppu.sprite_limit);
+        nes.set_sprite_limit(Some(true));
+        assert!(nes.bus.ppu.sprite_limit);
+    }
+
+    #[test]
+    fn test_jams_and_compat_notes_are_raised_as_events() {
+        // STA $5000; then JAM
+        let mut nes = nes_running(vec![0x8D, 0x00, 0x50, 0x02]);
+        nes.step_frame();
+        nes.step_frame();
+
+        assert_eq!(
+            nes.take_events(),
+            [
+                EmulatorEvent::Compat {
+                    mapper: 3,
+                    note: CompatNote::IgnoredWrite {
+                        addr: 0x5000,
+                        value: 0
+                    },
+                },
+                EmulatorEvent::CpuJam { pc: 0x8003 },
+            ]
+        );
+        assert_eq!(nes.take_events(), [], "events are only handed out once");
+    }
+
+    #[test]
+    fn test_cheats_patch_rom_reads_and_freeze_ram() {
+        let mut nes = counting_nes();
+        // INX at $8000 reads as DEX.
+        nes.bus.cheats.add("XGAAAE", "Count down").unwrap();
+        nes.bus
+            .cheats
+            .add("0200:42", "Hold the loop count")
+            .unwrap();
+        nes.step_frame();
+        assert_eq!(nes.bus.peek(0x8000), 0xCA);
+        assert_eq!(nes.bus.peek(0x0200), 0x42);
+
+        // Both loops take as long, so X ends as far below zero as it would
+        // otherwise be above.
+        let mut plain = counting_nes();
+        plain.step_frame();
+        assert_eq!(nes.bus.peek(0x10), plain.bus.peek(0x10).wrapping_neg());
+    }
+
+    #[test]
+    fn test_timed_oam_dma_stalls_the_cpu() {
+        // LDA #$02; STA $4014; then INC $0200; JMP $8005 forever
+        let program = vec![
+            0xA9, 0x02, 0x8D, 0x14, 0x40, 0xEE, 0x00, 0x02, 0x4C, 0x05, 0x80,
+        ];
+        let loops_in_a_frame = |profile| {
+            let mut nes = nes_running(program.clone());
+            nes.set_accuracy(profile);
+            nes.step_frame();
+            nes.bus.peek(0x0200)
+        };
+
+        let fast = loops_in_a_frame(AccuracyProfile::Fast);
+        let balanced = loops_in_a_frame(AccuracyProfile::Balanced);
+        // Each loop takes 9 cycles, so 513 or 514 cycles of DMA cost 57. The
+        // counter wraps many times a frame.
+        assert_eq!(fast.wrapping_sub(balanced), 57);
+    }
+
+    #[test]
+    fn test_accurate_profile_draws_by_scanline_without_decoded_blocks() {
+        let mut nes = counting_nes();
+        nes.set_fast_cpu(true);
+        nes.set_accuracy(AccuracyProfile::Accurate);
+        assert_eq!(nes.renderer(), Renderer::Scanline);
+        assert!(!nes.fast_cpu());
+        nes.set_fast_cpu(true);
+        assert!(!nes.fast_cpu());
+
+        // A picked renderer still wins, and stays picked across profiles.
+        nes.set_renderer(Some(Renderer::ScrollSegments));
+        assert_eq!(nes.renderer(), Renderer::ScrollSegments);
+        nes.set_accuracy(AccuracyProfile::Balanced);
+        assert_eq!(nes.renderer(), Renderer::ScrollSegments);
+        nes.set_accuracy(AccuracyProfile::Accurate);
+        assert_eq!(nes.renderer(), Renderer::ScrollSegments);
+        nes.set_renderer(None);
+        assert_eq!(nes.renderer(), Renderer::Scanline);
+        nes.set_accuracy(AccuracyProfile::Balanced);
+        nes.set_fast_cpu(true);
+        assert!(nes.fast_cpu());
+    }
+
+    #[test]
+    fn test_load_state_replays_identically() {
+        let mut nes = counting_nes();
+        nes.step_frame();
+        let state = nes.save_state();
+
+        nes.step_frame();
+        nes.step_frame();
+        let expected = nes.save_state();
+
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.save_state(), state);
+        nes.step_frame();
+        nes.step_frame();
+        assert_eq!(nes.save_state(), expected);
+    }
+
+    #[test]
+    fn test_bad_state_leaves_console_untouched() {
+        let mut nes = counting_nes();
+        nes.step_frame();
+        let state = nes.save_state();
+        nes.step_frame();
+        let before = nes.save_state();
+
+        assert!(nes.load_state(&state[..state.len() - 1]).is_err());
+        assert!(nes.load_state(b"garbage").is_err());
+        let mut other_rom = state.clone();
+        other_rom[10] ^= 0xFF;
+        assert!(nes.load_state(&other_rom).is_err());
+        assert_eq!(nes.save_state(), before);
+    }
+}
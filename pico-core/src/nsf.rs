@@ -0,0 +1,329 @@
+// NSF music rips, per https://www.nesdev.org/wiki/NSF
+use crate::apu::APU;
+use crate::cart::{Cart, Mirroring, RomFormat};
+use crate::compat::CompatReport;
+use crate::mapper::nsf::NsfMapper;
+use crate::memory::Memory;
+use crate::nes::Nes;
+use crate::region::Region;
+use crate::rom_db::{Quirks, crc32};
+use crate::vs_system::Console;
+
+pub const NSF_TAG: [u8; 5] = *b"NESM\x1A";
+const HEADER_LEN: usize = 0x80;
+const BANK_SIZE: usize = 0x1000;
+
+// The player's driver, served by the mapper from $5000 where no NSF has code:
+// JSR init, then idle until the play timer sends the CPU to JSR play.
+const DRIVER_START: u16 = 0x5000;
+const IDLE_ADDR: u16 = 0x5003;
+const PLAY_CALL_ADDR: u16 = 0x5006;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NsfHeader {
+    pub total_songs: u8,
+    // 1-based, as written in the file.
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    // Microseconds between calls to the play routine.
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // Initial 4 KiB banks at $8000-$FFFF. All zero for unbanked tunes.
+    pub bankswitch: [u8; 8],
+    pub region: Region,
+    // Expansion sound chips the tune uses, which play silently.
+    pub expansion_chips: u8,
+}
+
+pub fn is_nsf(bytes: &[u8]) -> bool {
+    bytes.starts_with(&NSF_TAG)
+}
+
+fn header_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+impl NsfHeader {
+    pub fn parse(bytes: &[u8]) -> Result<NsfHeader, String> {
+        if !is_nsf(bytes) {
+            return Err("File is not in NSF format".to_string());
+        }
+        if bytes.len() <= HEADER_LEN {
+            return Err("NSF file has no music data".to_string());
+        }
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+
+        let load_addr = word(0x08);
+        if load_addr < 0x8000 {
+            return Err(format!(
+                "NSF load address ${:04X} is below $8000",
+                load_addr
+            ));
+        }
+        // Bit 0 picks PAL, bit 1 marks tunes that play on either.
+        let region = if bytes[0x7A] & 0x03 == 0x01 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
+
+        Ok(NsfHeader {
+            total_songs: bytes[0x06].max(1),
+            starting_song: bytes[0x07].max(1),
+            load_addr,
+            init_addr: word(0x0A),
+            play_addr: word(0x0C),
+            title: header_string(&bytes[0x0E..0x2E]),
+            artist: header_string(&bytes[0x2E..0x4E]),
+            copyright: header_string(&bytes[0x4E..0x6E]),
+            ntsc_speed: word(0x6E),
+            pal_speed: word(0x78),
+            bankswitch: bytes[0x70..0x78].try_into().unwrap(),
+            region,
+            expansion_chips: bytes[0x7B],
+        })
+    }
+
+    pub fn is_banked(&self) -> bool {
+        self.bankswitch.iter().any(|&bank| bank != 0)
+    }
+
+    // CPU cycles between calls to the play routine.
+    pub fn play_period(&self, region: Region) -> u64 {
+        let speed = match region {
+            Region::Ntsc => self.ntsc_speed,
+            Region::Pal | Region::Dendy => self.pal_speed,
+        };
+        if speed == 0 {
+            return (region.cpu_clock_rate() as f64 / region.frame_rate()) as u64;
+        }
+        speed as u64 * region.cpu_clock_rate() / 1_000_000
+    }
+
+    // Banked tunes are split into 4 KiB banks from the load address rounded
+    // down; unbanked ones are simply placed at the load address.
+    fn prg_rom(&self, data: &[u8]) -> (Vec<u8>, [u8; 8]) {
+        if self.is_banked() {
+            let padding = (self.load_addr as usize) & (BANK_SIZE - 1);
+            let mut prg = vec![0; padding];
+            prg.extend_from_slice(data);
+            prg.resize(prg.len().div_ceil(BANK_SIZE) * BANK_SIZE, 0);
+            (prg, self.bankswitch)
+        } else {
+            let mut prg = vec![0; 0x8000];
+            let start = self.load_addr as usize - 0x8000;
+            let len = data.len().min(prg.len() - start);
+            prg[start..start + len].copy_from_slice(&data[..len]);
+            (prg, [0, 1, 2, 3, 4, 5, 6, 7])
+        }
+    }
+
+    fn driver(&self) -> Vec<u8> {
+        let [init_lo, init_hi] = self.init_addr.to_le_bytes();
+        let [play_lo, play_hi] = self.play_addr.to_le_bytes();
+        let [idle_lo, idle_hi] = IDLE_ADDR.to_le_bytes();
+        vec![
+            0x20, init_lo, init_hi, // JSR init
+            0x4C, idle_lo, idle_hi, // JMP idle
+            0x20, play_lo, play_hi, // JSR play
+            0x4C, idle_lo, idle_hi, // JMP idle
+        ]
+    }
+}
+
+// Plays an NSF on an otherwise ordinary console: the tune's code runs on the
+// emulated CPU and is heard through the APU, with the play routine called on
+// the timer the header asks for.
+pub struct NsfPlayer {
+    pub nes: Nes,
+    header: NsfHeader,
+    song: u8,
+    play_period: u64,
+    next_play: u64,
+}
+
+impl NsfPlayer {
+    pub fn new(bytes: &[u8], apu: APU) -> Result<NsfPlayer, String> {
+        let header = NsfHeader::parse(bytes)?;
+        if header.expansion_chips != 0 {
+            log::warn!(
+                "NSF uses expansion audio ({:#04X}), which is not played",
+                header.expansion_chips
+            );
+        }
+
+        let data = &bytes[HEADER_LEN..];
+        let (prg_rom, banks) = header.prg_rom(data);
+        let cart = Cart {
+            mapper: Box::new(NsfMapper::for_nsf(prg_rom, banks, header.driver())),
+            screen_mirroring: Mirroring::Vertical,
+            format: RomFormat::Nsf,
+            nes2_data: None,
+            console: Console::Nes,
+            compat: CompatReport::new(31),
+            crc32: crc32(data),
+            quirks: Quirks::empty(),
+            region: header.region,
+            renderer: None,
+            battery: false,
+            chr_battery: false,
+        };
+
+        let nes = Nes::new(cart, apu);
+        let mut player = NsfPlayer {
+            play_period: header.play_period(nes.region()),
+            nes,
+            song: header.starting_song - 1,
+            header,
+            next_play: 0,
+        };
+        player.play_song(player.song);
+        Ok(player)
+    }
+
+    pub fn header(&self) -> &NsfHeader {
+        &self.header
+    }
+
+    // 0-based.
+    pub fn song(&self) -> u8 {
+        self.song
+    }
+
+    // Restarts the console as the NSF spec asks before calling init.
+    pub fn play_song(&mut self, song: u8) {
+        self.song = song % self.header.total_songs;
+        self.nes.reset();
+        let region = self.nes.region();
+
+        let bus = &mut self.nes.bus;
+        bus.ram.fill(0);
+        for addr in 0x6000..=0x7FFF {
+            bus.write(addr, 0);
+        }
+        for addr in 0x4000..=0x4013 {
+            bus.write(addr, 0);
+        }
+        bus.write(0x4015, 0x00);
+        bus.write(0x4015, 0x0F);
+        bus.write(0x4017, 0x40);
+        if self.header.is_banked() {
+            for (i, bank) in self.header.bankswitch.into_iter().enumerate() {
+                bus.write(0x5FF8 + i as u16, bank);
+            }
+        }
+
+        self.next_play = bus.apu.cycle();
+        let registers = &mut self.nes.cpu.registers;
+        registers.a = self.song;
+        registers.x = (region != Region::Ntsc) as u8;
+        registers.y = 0;
+        registers.sp = 0xFD;
+        registers.pc = DRIVER_START;
+    }
+
+    pub fn next_song(&mut self) {
+        self.play_song(self.song.wrapping_add(1) % self.header.total_songs);
+    }
+
+    pub fn previous_song(&mut self) {
+        let count = self.header.total_songs;
+        self.play_song((self.song + count - 1) % count);
+    }
+
+    // Runs one video frame's worth of the tune. Play is only called once the
+    // previous call has returned, so a slow routine delays rather than nests.
+    pub fn run_frame(&mut self) {
+        loop {
+            let result = self.nes.clock();
+            let cycle = self.nes.bus.apu.cycle();
+            let cpu = &mut self.nes.cpu;
+            if result.instruction_complete
+                && cpu.registers.pc == IDLE_ADDR
+                && cycle >= self.next_play
+            {
+                cpu.registers.pc = PLAY_CALL_ADDR;
+                self.next_play = (self.next_play + self.play_period).max(cycle);
+            }
+            if result.frame_complete {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Two songs. Init stores the song number at $00, play counts calls at $01:
+    // $8000: STA $00; RTS   $8003: INC $01; RTS
+    fn test_nsf(speed: u16) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_LEN];
+        bytes[..5].copy_from_slice(&NSF_TAG);
+        bytes[0x05] = 1;
+        bytes[0x06] = 2;
+        bytes[0x07] = 1;
+        bytes[0x08..0x0A].copy_from_slice(&0x8000u16.to_le_bytes());
+        bytes[0x0A..0x0C].copy_from_slice(&0x8000u16.to_le_bytes());
+        bytes[0x0C..0x0E].copy_from_slice(&0x8003u16.to_le_bytes());
+        bytes[0x0E..0x12].copy_from_slice(b"Test");
+        bytes[0x6E..0x70].copy_from_slice(&speed.to_le_bytes());
+        bytes.extend_from_slice(&[0x85, 0x00, 0x60, 0xE6, 0x01, 0x60]);
+        bytes
+    }
+
+    fn player(speed: u16) -> NsfPlayer {
+        let apu = APU::new(48_000);
+        NsfPlayer::new(&test_nsf(speed), apu).unwrap()
+    }
+
+    #[test]
+    fn test_parses_the_header() {
+        let header = NsfHeader::parse(&test_nsf(16_639)).unwrap();
+        assert_eq!(header.title, "Test");
+        assert_eq!(header.total_songs, 2);
+        assert_eq!((header.init_addr, header.play_addr), (0x8000, 0x8003));
+        assert!(!header.is_banked());
+        assert_eq!(header.play_period(Region::Ntsc), 29_780);
+        assert!(NsfHeader::parse(b"NES\x1A").is_err());
+    }
+
+    #[test]
+    fn test_init_gets_the_song_and_play_runs_on_its_timer() {
+        let mut player = player(16_639);
+        for _ in 0..10 {
+            player.run_frame();
+        }
+        assert_eq!(player.nes.bus.peek(0x00), 0);
+        let calls = player.nes.bus.peek(0x01);
+        assert!((9..=11).contains(&calls), "{} play calls", calls);
+
+        // Twice as often.
+        let mut player = self::player(8_320);
+        for _ in 0..10 {
+            player.run_frame();
+        }
+        let calls = player.nes.bus.peek(0x01);
+        assert!((19..=21).contains(&calls), "{} play calls", calls);
+
+        player.next_song();
+        assert_eq!(player.song(), 1);
+        player.run_frame();
+        assert_eq!(player.nes.bus.peek(0x00), 1);
+        assert!(
+            player.nes.bus.peek(0x01) <= 2,
+            "RAM is cleared between songs"
+        );
+        player.next_song();
+        assert_eq!(player.song(), 0);
+        player.previous_song();
+        assert_eq!(player.song(), 1);
+    }
+}
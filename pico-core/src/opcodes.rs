@@ -133,6 +133,9 @@ impl Opcode {
 
 pub struct OpcodeMap {
     opcodes: Vec<Opcode>,
+    // Index into `opcodes` of each byte's opcode, looked up on every
+    // instruction.
+    by_code: [Option<usize>; 256],
 }
 
 impl Default for OpcodeMap {
@@ -143,7 +146,7 @@ impl Default for OpcodeMap {
 
 impl OpcodeMap {
     pub fn new() -> Self {
-        OpcodeMap {
+        let mut map = OpcodeMap {
             opcodes: vec![
                 // ADC
                 Opcode::new(0x69, Mnemonic::ADC, 2, 2, AddressingMode::Immediate),
@@ -481,11 +484,16 @@ impl OpcodeMap {
                 Opcode::new(0xDC, Mnemonic::NOP, 3, 4, AddressingMode::AbsoluteX),
                 Opcode::new(0xFC, Mnemonic::NOP, 3, 4, AddressingMode::AbsoluteX),
             ],
+            by_code: [None; 256],
+        };
+        for (index, opcode) in map.opcodes.iter().enumerate() {
+            map.by_code[opcode.code as usize].get_or_insert(index);
         }
+        map
     }
 
     pub fn find_by_code(&self, code: u8) -> Option<&Opcode> {
-        self.opcodes.iter().find(|opcode| opcode.code == code)
+        self.by_code[code as usize].map(|index| &self.opcodes[index])
     }
 
     #[allow(dead_code)]
@@ -495,3 +503,15 @@ impl OpcodeMap {
 }
 
 pub static CPU_OPCODES: LazyLock<OpcodeMap> = LazyLock::new(OpcodeMap::new);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_every_byte_finds_its_opcode() {
+        for code in 0..=0xFF {
+            assert_eq!(CPU_OPCODES.find_by_code(code).unwrap().code, code);
+        }
+    }
+}
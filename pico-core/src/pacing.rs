@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+// Host time, as seen by a frontend. The core only ever gets durations from
+// it, to decide when frames run; the console itself moves on frame ticks and
+// inputs alone, so a recorded run replays the same at any speed.
+pub trait HostClock {
+    // Time since a fixed point of the clock's choosing.
+    fn now(&self) -> Duration;
+}
+
+// Paces emulated frames to the console's frame rate. It can only say whether
+// a frame is due, never change what the frame does.
+#[derive(Default)]
+pub struct FramePacer {
+    next_frame_at: Duration,
+}
+
+impl FramePacer {
+    pub fn frame_due(&self, now: Duration) -> bool {
+        now >= self.next_frame_at
+    }
+
+    // How long a frontend can sleep before the next frame is due.
+    pub fn time_until_due(&self, now: Duration) -> Duration {
+        self.next_frame_at.saturating_sub(now)
+    }
+
+    // Called whenever a frame runs, due or not. After a stall it only runs a
+    // couple of frames to catch up, not one for every frame missed.
+    pub fn start_frame(&mut self, now: Duration, frame_rate: f64) {
+        let frame_duration = Duration::from_secs_f64(1.0 / frame_rate);
+        let earliest = now.checked_sub(frame_duration).unwrap_or(now);
+        self.next_frame_at = (self.next_frame_at + frame_duration).max(earliest);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::path::Path;
+
+    use super::*;
+    use crate::apu::APU;
+    use crate::cart::test::test_rom;
+    use crate::joypad::JoypadButton;
+    use crate::nes::Nes;
+
+    struct MockClock(Cell<Duration>);
+
+    impl HostClock for MockClock {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    // Reads the first pad's A button every loop and adds it up at $10:
+    // LDA #1; STA $4016; LDA #0; STA $4016; LDA $4016; AND #1; CLC;
+    // ADC $10; STA $10; JMP $8000
+    fn reading_nes() -> Nes {
+        let mut program = vec![
+            0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xAD, 0x16, 0x40, 0x29,
+            0x01, 0x18, 0x65, 0x10, 0x85, 0x10, 0x4C, 0x00, 0x80,
+        ];
+        program.resize(0x8000, 0);
+        program[0x7FFC] = 0x00;
+        program[0x7FFD] = 0x80;
+        let apu = APU::new(48_000);
+        let mut nes = Nes::new(test_rom(program), apu);
+        nes.reset();
+        nes
+    }
+
+    // Runs 30 frames, polling like a frontend's main loop, with the host
+    // taking each of `steps` in turn between polls.
+    fn run_with_clock(steps: &[u64]) -> Vec<u8> {
+        let clock = MockClock(Cell::new(Duration::ZERO));
+        let mut pacer = FramePacer::default();
+        let mut nes = reading_nes();
+        let mut frame = 0;
+        for step in steps.iter().cycle() {
+            if frame == 30 {
+                break;
+            }
+            clock.0.set(clock.now() + Duration::from_micros(*step));
+            if !pacer.frame_due(clock.now()) {
+                continue;
+            }
+            pacer.start_frame(clock.now(), nes.region().frame_rate());
+            let joypad = nes.joypad_mut(0).unwrap();
+            joypad.button_status = if frame % 3 == 0 {
+                JoypadButton::BUTTON_A
+            } else {
+                JoypadButton::empty()
+            };
+            nes.step_frame();
+            frame += 1;
+        }
+        nes.save_state()
+    }
+
+    #[test]
+    fn test_host_clock_only_changes_when_frames_run() {
+        let steady = run_with_clock(&[16_639]);
+        let jittery = run_with_clock(&[1_000, 40_000, 3, 250_000, 16_000]);
+        let fast = run_with_clock(&[100]);
+        assert_eq!(steady, jittery);
+        assert_eq!(steady, fast);
+    }
+
+    #[test]
+    fn test_pacer_does_not_burst_after_a_stall() {
+        let mut pacer = FramePacer::default();
+        let frame = Duration::from_millis(20);
+        pacer.start_frame(Duration::ZERO, 50.0);
+        assert!(!pacer.frame_due(Duration::from_millis(19)));
+        assert_eq!(
+            pacer.time_until_due(Duration::from_millis(19)),
+            Duration::from_millis(1)
+        );
+        assert!(pacer.frame_due(frame));
+        assert_eq!(pacer.time_until_due(frame), Duration::ZERO);
+
+        // 49 frames late, but only caught up by two.
+        let stalled = Duration::from_secs(1);
+        let mut frames = 0;
+        while pacer.frame_due(stalled) {
+            pacer.start_frame(stalled, 50.0);
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+        assert!(pacer.frame_due(stalled + frame));
+    }
+
+    // Wall-clock types would let host time leak into emulation, so the core
+    // sticks to the durations a `HostClock` hands it.
+    #[test]
+    fn test_core_never_reads_host_time() {
+        let mut dirs = vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("src")];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(&dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if path.extension().is_none_or(|ext| ext != "rs")
+                    || path.file_name() == Path::new(file!()).file_name()
+                {
+                    continue;
+                }
+                let source = std::fs::read_to_string(&path).unwrap();
+                for name in ["Instant", "SystemTime"] {
+                    assert!(!source.contains(name), "{} uses {}", path.display(), name);
+                }
+            }
+        }
+    }
+}
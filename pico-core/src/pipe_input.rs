@@ -0,0 +1,168 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::input_history::COMMAND_RESET;
+use crate::joypad::JoypadButton;
+use crate::movie::parse_gamepad_input;
+
+#[derive(Clone, Copy)]
+pub struct PipeFrame {
+    pub reset: bool,
+    pub pads: [JoypadButton; 2],
+}
+
+// Controller input fed line by line from another process, one line per frame.
+// Lines use the FM2 pad layout, with an optional leading FM2 command field:
+//
+//   R......A
+//   R......A|...T....
+//   |1|........|........||
+//
+// When the driver runs ahead, lines are applied one per frame; when it falls
+// behind, the last state is held.
+pub struct PipeInput {
+    receiver: Receiver<PipeFrame>,
+    current: [JoypadButton; 2],
+    connected: bool,
+}
+
+impl PipeInput {
+    // `path` is a file or named pipe, or "-" for stdin. Named pipes are reopened
+    // when the writer goes away so drivers can reconnect.
+    pub fn open(path: &str) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let path = path.to_string();
+
+        thread::spawn(move || {
+            loop {
+                let reader: Box<dyn BufRead> = if path == "-" {
+                    Box::new(BufReader::new(io::stdin()))
+                } else {
+                    match File::open(&path) {
+                        Ok(file) => Box::new(BufReader::new(file)),
+                        Err(e) => {
+                            log::error!("Failed to open input pipe {path}: {e}");
+                            return;
+                        }
+                    }
+                };
+
+                for line in reader.lines() {
+                    let Ok(line) = line else { break };
+                    match parse_line(&line) {
+                        Ok(Some(frame)) => {
+                            if sender.send(frame).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => log::warn!("Ignoring input line {line:?}: {e}"),
+                    }
+                }
+
+                if path == "-" || !is_fifo(&path) {
+                    return;
+                }
+            }
+        });
+
+        PipeInput {
+            receiver,
+            current: [JoypadButton::empty(); 2],
+            connected: true,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    // Called once per emulated frame.
+    pub fn poll(&mut self) -> PipeFrame {
+        match self.receiver.try_recv() {
+            Ok(frame) => {
+                self.current = frame.pads;
+                frame
+            }
+            Err(TryRecvError::Empty) => PipeFrame {
+                reset: false,
+                pads: self.current,
+            },
+            Err(TryRecvError::Disconnected) => {
+                self.connected = false;
+                PipeFrame {
+                    reset: false,
+                    pads: self.current,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &str) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &str) -> bool {
+    false
+}
+
+pub fn parse_line(line: &str) -> Result<Option<PipeFrame>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut fields: Vec<&str> = line.trim_matches('|').split('|').collect();
+
+    let mut commands = 0;
+    if fields.len() > 1 && fields[0].chars().all(|c| c.is_ascii_digit()) {
+        commands = fields[0]
+            .parse::<u8>()
+            .map_err(|_| "Invalid commands field")?;
+        fields.remove(0);
+    }
+
+    let mut pads = [JoypadButton::empty(); 2];
+    for (pad, field) in pads.iter_mut().zip(&fields) {
+        if field.len() > 8 {
+            return Err("Pad field longer than 8 buttons".to_string());
+        }
+        *pad = parse_gamepad_input(field)?.to_buttons();
+    }
+
+    Ok(Some(PipeFrame {
+        reset: commands & COMMAND_RESET != 0,
+        pads,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_line_formats() {
+        let frame = parse_line("R......A").unwrap().unwrap();
+        assert_eq!(
+            frame.pads[0].bits(),
+            (JoypadButton::RIGHT | JoypadButton::BUTTON_A).bits()
+        );
+        assert!(frame.pads[1].is_empty());
+        assert!(!frame.reset);
+
+        let frame = parse_line("|1|....T...|...U....||").unwrap().unwrap();
+        assert!(frame.reset);
+        assert_eq!(frame.pads[0].bits(), JoypadButton::START.bits());
+        assert_eq!(frame.pads[1].bits(), JoypadButton::UP.bits());
+
+        assert!(parse_line("   ").unwrap().is_none());
+        assert!(parse_line("# comment").unwrap().is_none());
+        assert!(parse_line("RLDUTSBAX").is_err());
+    }
+}
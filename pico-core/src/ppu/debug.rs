@@ -0,0 +1,297 @@
+// Whole-VRAM views for debugging tools. These read through the same paths as
+// rendering but ignore scrolling, masking and the sprite layer.
+
+use crate::mapper::{ChrSource, Mapper};
+use crate::ppu::PPU;
+use crate::ppu::render::{bg_palette, system_palette_color};
+
+// An RGB24 image of arbitrary size, laid out like `Framebuffer`.
+pub struct DebugImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl DebugImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        DebugImage {
+            width,
+            height,
+            data: vec![0; width * height * 3],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = (y * self.width + x) * 3;
+        (self.data[base], self.data[base + 1], self.data[base + 2])
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        let base = (y * self.width + x) * 3;
+        self.data[base] = rgb.0;
+        self.data[base + 1] = rgb.1;
+        self.data[base + 2] = rgb.2;
+    }
+}
+
+fn draw_tile(
+    image: &mut DebugImage,
+    mapper: &dyn Mapper,
+    addr: u16,
+    source: ChrSource,
+    x: usize,
+    y: usize,
+    colors: &[(u8, u8, u8); 4],
+) {
+    for row in 0..8 {
+        let plane0 = mapper.read_chr(addr + row, source);
+        let plane1 = mapper.read_chr(addr + row + 8, source);
+        for col in 0..8 {
+            let bit = 7 - col;
+            let value = ((plane1 >> bit) & 1) << 1 | ((plane0 >> bit) & 1);
+            image.set_pixel(x + col, y + row as usize, colors[value as usize]);
+        }
+    }
+}
+
+// The four logical nametables as a 512x480 image, in the order they sit in
+// PPU address space.
+pub fn render_nametables(ppu: &PPU, mapper: &dyn Mapper) -> DebugImage {
+    let mut image = DebugImage::new(512, 480);
+    let pattern_base = ppu.ctrl.bknd_pattern_addr();
+
+    for table in 0..4 {
+        let origin_x = (table % 2) * 256;
+        let origin_y = (table / 2) * 240;
+        for tile_row in 0..30 {
+            for tile_column in 0..32 {
+                let tile = ppu.read_nametable_entry(mapper, table, tile_column, tile_row) as u16;
+                let palette = bg_palette(ppu, mapper, table, tile_column, tile_row);
+                let colors = palette.map(|index| system_palette_color(ppu, index));
+                draw_tile(
+                    &mut image,
+                    mapper,
+                    pattern_base + tile * 16,
+                    ChrSource::Background,
+                    origin_x + tile_column * 8,
+                    origin_y + tile_row * 8,
+                    &colors,
+                );
+            }
+        }
+    }
+    image
+}
+
+// Outlines the part of `render_nametables`' image shown at the top of the
+// frame, wrapping around its edges as the scroll does.
+pub fn outline_scroll(image: &mut DebugImage, ppu: &PPU) {
+    let Some(segment) = ppu.scroll_segments().first() else {
+        return;
+    };
+    let left = (segment.base_nametable & 1) * 256 + segment.scroll_x;
+    let top = (segment.base_nametable >> 1) * 240 + segment.scroll_y;
+    let (width, height) = (image.width, image.height);
+    let white = (255, 255, 255);
+    for x in left..left + 256 {
+        image.set_pixel(x % width, top % height, white);
+        image.set_pixel(x % width, (top + 239) % height, white);
+    }
+    for y in top..top + 240 {
+        image.set_pixel(left % width, y % height, white);
+        image.set_pixel((left + 255) % width, y % height, white);
+    }
+}
+
+// One of the 64 sprites in OAM, decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteInfo {
+    pub x: u8,
+    // OAM holds the line above the sprite's first.
+    pub y: u16,
+    pub tile: u8,
+    // Of the four sprite palettes.
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+pub fn decode_oam(oam: &[u8; 256]) -> [SpriteInfo; 64] {
+    std::array::from_fn(|index| {
+        let entry = &oam[index * 4..index * 4 + 4];
+        SpriteInfo {
+            x: entry[3],
+            y: entry[0] as u16 + 1,
+            tile: entry[1],
+            palette: entry[2] & 0x03,
+            behind_background: entry[2] & 0x20 != 0,
+            flip_horizontal: entry[2] & 0x40 != 0,
+            flip_vertical: entry[2] & 0x80 != 0,
+        }
+    })
+}
+
+// The sprites in OAM order as eight rows of eight 8x16 cells, which 8x8
+// sprites fill the top of, each with its palette and flips.
+pub fn render_sprites(ppu: &PPU, mapper: &dyn Mapper) -> DebugImage {
+    let mut image = DebugImage::new(64, 128);
+    let height = ppu.ctrl.sprite_size() as u16;
+
+    for (index, sprite) in decode_oam(&ppu.oam_data).iter().enumerate() {
+        let colors = palette_colors(ppu, 4 + sprite.palette);
+        let tile = sprite.tile as u16;
+        for row in 0..height {
+            let source_row = if sprite.flip_vertical {
+                height - 1 - row
+            } else {
+                row
+            };
+            let addr = if height == 16 {
+                (tile & 1) * 0x1000 + ((tile & 0xFE) + source_row / 8) * 16 + source_row % 8
+            } else {
+                ppu.ctrl.sprt_pattern_addr() + tile * 16 + source_row
+            };
+            let plane0 = mapper.read_chr(addr, ChrSource::Sprite);
+            let plane1 = mapper.read_chr(addr + 8, ChrSource::Sprite);
+            for col in 0..8 {
+                let bit = if sprite.flip_horizontal { col } else { 7 - col };
+                let value = ((plane1 >> bit) & 1) << 1 | ((plane0 >> bit) & 1);
+                image.set_pixel(
+                    (index % 8) * 8 + col,
+                    (index / 8) * 16 + row as usize,
+                    colors[value as usize],
+                );
+            }
+        }
+    }
+    image
+}
+
+// Colors of one of the eight palettes (0-3 background, 4-7 sprite).
+pub fn palette_colors(ppu: &PPU, palette: u8) -> [(u8, u8, u8); 4] {
+    let start = (palette as usize & 0x07) * 4;
+    [0, 1, 2, 3].map(|entry| {
+        let index = if entry == 0 {
+            ppu.palette_table[0]
+        } else {
+            ppu.palette_table[start + entry]
+        };
+        system_palette_color(ppu, index)
+    })
+}
+
+// Both pattern tables side by side as a 256x128 image, colored with the
+// given palette.
+pub fn render_pattern_tables(ppu: &PPU, mapper: &dyn Mapper, palette: u8) -> DebugImage {
+    let mut image = DebugImage::new(256, 128);
+    let colors = palette_colors(ppu, palette);
+    let source = if palette < 4 {
+        ChrSource::Background
+    } else {
+        ChrSource::Sprite
+    };
+
+    for table in 0..2 {
+        for tile in 0..256 {
+            draw_tile(
+                &mut image,
+                mapper,
+                (table * 0x1000 + tile * 16) as u16,
+                source,
+                table * 128 + (tile % 16) * 8,
+                (tile / 16) * 8,
+                &colors,
+            );
+        }
+    }
+    image
+}
+
+// The 32 palette RAM entries as a 16x2 image, background palettes on top.
+pub fn render_palettes(ppu: &PPU) -> DebugImage {
+    let mut image = DebugImage::new(16, 2);
+    for (i, index) in ppu.palette_table.iter().enumerate() {
+        image.set_pixel(i % 16, i / 16, system_palette_color(ppu, *index));
+    }
+    image
+}
+
+// All 64 system palette colors as a 16x4 image, in color index order.
+pub fn render_system_palette(ppu: &PPU) -> DebugImage {
+    let mut image = DebugImage::new(16, 4);
+    for (i, rgb) in ppu.system_palette.colors().iter().enumerate() {
+        image.set_pixel(i % 16, i / 16, *rgb);
+    }
+    image
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cart::Mirroring;
+    use crate::mapper::nrom::NromMapper;
+    use crate::ppu::palette::SYSTEM_PALLETE;
+
+    #[test]
+    fn test_pattern_table_uses_palette_colors() {
+        // Tile 1 is solid color 3, everything else color 0.
+        let mut chr = vec![0u8; 0x2000];
+        chr[16..32].fill(0xFF);
+        let mapper = NromMapper::new(vec![0; 0x4000], chr, Mirroring::Horizontal);
+
+        let mut ppu = PPU::new();
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[4 + 3] = 0x16;
+
+        let image = render_pattern_tables(&ppu, &mapper, 1);
+        assert_eq!(image.pixel(0, 0), SYSTEM_PALLETE[0x0F]);
+        assert_eq!(image.pixel(8, 0), SYSTEM_PALLETE[0x16]);
+        assert_eq!(image.pixel(15, 7), SYSTEM_PALLETE[0x16]);
+        assert_eq!(image.pixel(16, 0), SYSTEM_PALLETE[0x0F]);
+    }
+
+    #[test]
+    fn test_sprites_are_drawn_with_their_flips() {
+        // Tile 1's top row is color 1 on its left half only.
+        let mut chr = vec![0u8; 0x2000];
+        chr[16] = 0xF0;
+        let mapper = NromMapper::new(vec![0; 0x4000], chr, Mirroring::Horizontal);
+
+        let mut ppu = PPU::new();
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[0x11 + 4] = 0x2A;
+        // Sprite 9 at (30, 21) with palette 1, flipped both ways.
+        ppu.oam_data[36..40].copy_from_slice(&[20, 1, 0xC1, 30]);
+
+        let sprite = decode_oam(&ppu.oam_data)[9];
+        assert_eq!(
+            (sprite.x, sprite.y, sprite.tile, sprite.palette),
+            (30, 21, 1, 1)
+        );
+        assert!(sprite.flip_horizontal && sprite.flip_vertical && !sprite.behind_background);
+
+        let image = render_sprites(&ppu, &mapper);
+        assert_eq!(image.pixel(8 + 7, 16 + 7), SYSTEM_PALLETE[0x2A]);
+        assert_eq!(image.pixel(8, 16 + 7), SYSTEM_PALLETE[0x0F]);
+        assert_eq!(image.pixel(8 + 7, 16), SYSTEM_PALLETE[0x0F]);
+    }
+
+    #[test]
+    fn test_scroll_outline_wraps_around() {
+        let mut ppu = PPU::new();
+        ppu.write_to_ctrl(0x01);
+        ppu.write_to_scroll(200);
+        ppu.write_to_scroll(0);
+        ppu.reset_scroll_segments_for_new_frame();
+
+        let mut image = DebugImage::new(512, 480);
+        outline_scroll(&mut image, &ppu);
+        let white = (255, 255, 255);
+        // Starts at x 456 in the right-hand nametables and ends at 199.
+        assert_eq!(image.pixel(456, 100), white);
+        assert_eq!(image.pixel(199, 100), white);
+        assert_eq!(image.pixel(0, 239), white);
+        assert_eq!(image.pixel(300, 0), (0, 0, 0));
+    }
+}
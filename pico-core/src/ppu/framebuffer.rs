@@ -0,0 +1,51 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+pub struct Framebuffer {
+    pub data: Vec<u8>,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Framebuffer {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 240;
+
+    pub fn new() -> Self {
+        Framebuffer {
+            data: vec![0; (Framebuffer::WIDTH) * (Framebuffer::HEIGHT) * 3],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        let base = y * 3 * Framebuffer::WIDTH + x * 3;
+        if base + 2 < self.data.len() {
+            self.data[base] = rgb.0;
+            self.data[base + 1] = rgb.1;
+            self.data[base + 2] = rgb.2;
+        }
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(file),
+            Framebuffer::WIDTH as u32,
+            Framebuffer::HEIGHT as u32,
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| format!("Failed to write PNG header: {}", e))?;
+        writer
+            .write_image_data(&self.data)
+            .map_err(|e| format!("Failed to write PNG data: {}", e))
+    }
+}
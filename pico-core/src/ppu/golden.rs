@@ -0,0 +1,328 @@
+// Sprite and background priority scenes, rendered headless and compared with
+// stored hashes. Each scene puts a few sprites over a background with opaque
+// bars, a half transparent checkerboard and a strip down each edge, the way
+// the sprite test ROMs do, and covers one part of the pixel pipeline: the
+// behind-background bit, the priority between sprites, flips, 8x16 sprites,
+// the left column masks and the screen edges. When a change is intended, the
+// failure prints every new hash to paste in.
+use crate::headless::Headless;
+use crate::ppu::framebuffer::Framebuffer;
+use crate::ppu::render::Renderer;
+use crate::rom_db::crc32;
+use crate::testutil::{Asm, CODE_ORIGIN, RomBuilder};
+
+const FRAMES: usize = 20;
+
+struct Scene {
+    name: &'static str,
+    ctrl: u8,
+    mask: u8,
+    // Y, tile, attributes and X of the first sprites; the rest are hidden.
+    sprites: &'static [[u8; 4]],
+    // Hashes with the ScrollSegments and Scanline renderers.
+    golden: [u32; 2],
+}
+
+const SCENES: [Scene; 8] = [
+    // Solid sprites in front of and behind the opaque bar, the checkerboard
+    // and the backdrop.
+    Scene {
+        name: "behind_background",
+        ctrl: 0x80,
+        mask: 0x1E,
+        sprites: &[
+            [35, 4, 0x00, 40],
+            [35, 4, 0x20, 60],
+            [99, 4, 0x01, 40],
+            [99, 4, 0x21, 60],
+            [99, 3, 0x22, 80],
+            [159, 5, 0x23, 40],
+        ],
+        golden: [0x72879B6D, 0x72879B6D],
+    },
+    // A sprite behind the background still wins over later sprites in
+    // front, so the background shows where the two overlap.
+    Scene {
+        name: "priority_quirk",
+        ctrl: 0x80,
+        mask: 0x1E,
+        sprites: &[
+            [35, 5, 0x21, 100],
+            [35, 4, 0x00, 104],
+            [99, 5, 0x21, 100],
+            [99, 4, 0x00, 104],
+            [159, 5, 0x21, 100],
+            [159, 4, 0x00, 104],
+            [159, 3, 0x02, 108],
+        ],
+        golden: [0x3261DE78, 0x3261DE78],
+    },
+    Scene {
+        name: "flips",
+        ctrl: 0x80,
+        mask: 0x1E,
+        sprites: &[
+            [149, 3, 0x00, 40],
+            [149, 3, 0x41, 60],
+            [149, 3, 0x82, 80],
+            [149, 3, 0xC3, 100],
+            [149, 7, 0x00, 120],
+            [149, 7, 0x80, 140],
+        ],
+        golden: [0xE1041C22, 0xE1041C22],
+    },
+    // Even tiles come from $0000 and odd ones from $1000, whatever $2000
+    // says; flipping vertically swaps the two halves.
+    Scene {
+        name: "tall_sprites",
+        ctrl: 0xA8,
+        mask: 0x1E,
+        sprites: &[
+            [149, 6, 0x00, 40],
+            [149, 6, 0x41, 60],
+            [149, 7, 0x82, 80],
+            [149, 7, 0xC3, 100],
+            [93, 6, 0x20, 120],
+            [93, 7, 0x00, 140],
+        ],
+        golden: [0x38D51A14, 0x38D51A14],
+    },
+    Scene {
+        name: "left_column_hidden",
+        ctrl: 0x80,
+        mask: 0x18,
+        sprites: LEFT_COLUMN,
+        golden: [0x6141B514, 0x6141B514],
+    },
+    Scene {
+        name: "left_column_background",
+        ctrl: 0x80,
+        mask: 0x1A,
+        sprites: LEFT_COLUMN,
+        golden: [0xCCABE5FE, 0xCCABE5FE],
+    },
+    Scene {
+        name: "left_column_sprites",
+        ctrl: 0x80,
+        mask: 0x1C,
+        sprites: LEFT_COLUMN,
+        golden: [0xAC83E8B8, 0xAC83E8B8],
+    },
+    // Sprites cut off by the right and bottom edges, one on the first line
+    // sprites can reach, and one whose Y puts it below the picture.
+    Scene {
+        name: "edges",
+        ctrl: 0x80,
+        mask: 0x1E,
+        sprites: &[
+            [179, 3, 0x00, 252],
+            [187, 4, 0x21, 248],
+            [0, 3, 0x02, 120],
+            [235, 3, 0x03, 60],
+            [239, 4, 0x00, 80],
+            [199, 3, 0x40, 255],
+        ],
+        golden: [0x995D7798, 0x995D7798],
+    },
+];
+
+// Over the strip down the left edge and over the backdrop, straddling x = 8.
+const LEFT_COLUMN: &[[u8; 4]] = &[
+    [163, 4, 0x00, 0],
+    [171, 4, 0x21, 4],
+    [207, 3, 0x02, 2],
+    [215, 5, 0x03, 6],
+];
+
+// Eight tiles: blank, solid colour 1, a colour 3 checkerboard, an F in three
+// colours, solid colours 2 and 3, and the top and bottom of an arrow for 8x16
+// sprites. The $1000 table has the arrow's halves the other way round.
+const TILES: [[u8; 16]; 8] = [
+    [0; 16],
+    [
+        0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [
+        0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA,
+        0x55,
+    ],
+    [
+        0xFF, 0x80, 0x80, 0xFC, 0x80, 0x80, 0x80, 0x00, 0xFF, 0xC0, 0xA0, 0x90, 0x88, 0x84, 0x82,
+        0x81,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    ],
+    [0xFF; 16],
+    [
+        0x18, 0x3C, 0x7E, 0xFF, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18,
+        0x18,
+    ],
+    [
+        0x18, 0x18, 0x18, 0x18, 0x3C, 0x3C, 0x7E, 0x00, 0x18, 0x18, 0x18, 0x18, 0x24, 0x24, 0x42,
+        0x00,
+    ],
+];
+
+// Background tile rows: an opaque bar, a checkerboard and opaque strips down
+// both edges, everything else blank.
+const OPAQUE_ROWS: [u16; 2] = [4, 5];
+const CHECKER_ROWS: [u16; 2] = [12, 13];
+const EDGE_ROWS: std::ops::Range<u16> = 20..28;
+
+fn write_row(asm: &mut Asm, row: u16, column: u16, tile: u8, count: usize) {
+    let [lo, hi] = (0x2000 + row * 32 + column).to_le_bytes();
+    asm.poke(0x2006, hi).poke(0x2006, lo).lda_imm(tile);
+    for _ in 0..count {
+        asm.sta(0x2007);
+    }
+}
+
+fn program(scene: &Scene) -> Asm {
+    let mut asm = Asm::new(CODE_ORIGIN);
+    asm.label("reset").sei().cld().ldx_imm(0xFF).txs();
+    asm.poke(0x2000, 0)
+        .sta(0x2001)
+        .label("vblank1")
+        .bit(0x2002)
+        .bpl("vblank1")
+        .label("vblank2")
+        .bit(0x2002)
+        .bpl("vblank2");
+
+    for (table, tiles, label) in [(0x00, "tiles", "copy_lo"), (0x10, "tiles_hi", "copy_hi")] {
+        asm.poke(0x2006, table)
+            .poke(0x2006, 0)
+            .ldx_imm(0)
+            .label(label)
+            .lda_label_x(tiles)
+            .sta(0x2007)
+            .inx()
+            .cpx_imm(128)
+            .bne(label);
+    }
+
+    asm.poke(0x2006, 0x20)
+        .poke(0x2006, 0x00)
+        .lda_imm(0)
+        .ldy_imm(4)
+        .ldx_imm(0)
+        .label("clear")
+        .sta(0x2007)
+        .inx()
+        .bne("clear")
+        .dey()
+        .bne("clear");
+    for row in OPAQUE_ROWS {
+        write_row(&mut asm, row, 0, 1, 32);
+    }
+    for row in CHECKER_ROWS {
+        write_row(&mut asm, row, 0, 2, 32);
+    }
+    for row in EDGE_ROWS {
+        write_row(&mut asm, row, 0, 1, 1);
+        write_row(&mut asm, row, 31, 1, 1);
+    }
+
+    asm.poke(0x2006, 0x3F)
+        .poke(0x2006, 0x00)
+        .ldx_imm(0)
+        .label("palette_loop")
+        .lda_label_x("palette")
+        .sta(0x2007)
+        .inx()
+        .cpx_imm(32)
+        .bne("palette_loop");
+
+    asm.ldx_imm(0)
+        .label("oam_loop")
+        .lda_label_x("oam")
+        .sta_x(0x0200)
+        .inx()
+        .bne("oam_loop");
+
+    asm.poke(0x2000, scene.ctrl)
+        .poke(0x2001, scene.mask)
+        .label("forever")
+        .jmp("forever");
+
+    asm.label("nmi")
+        .bit(0x2002)
+        .poke(0x2003, 0)
+        .poke(0x4014, 0x02)
+        .poke(0x2005, 0)
+        .sta(0x2005)
+        .poke(0x2000, scene.ctrl)
+        .rti();
+
+    asm.label("tiles").bytes(TILES.as_flattened());
+    let mut tiles_hi = TILES;
+    tiles_hi.swap(6, 7);
+    asm.label("tiles_hi").bytes(tiles_hi.as_flattened());
+    asm.label("palette").bytes(&[
+        0x01, 0x00, 0x10, 0x20, 0x01, 0x00, 0x10, 0x20, 0x01, 0x00, 0x10, 0x20, 0x01, 0x00, 0x10,
+        0x20, 0x01, 0x06, 0x16, 0x26, 0x01, 0x09, 0x19, 0x29, 0x01, 0x02, 0x12, 0x22, 0x01, 0x07,
+        0x17, 0x27,
+    ]);
+    let mut oam = [0xFF; 256];
+    oam[..scene.sprites.len() * 4].copy_from_slice(scene.sprites.as_flattened());
+    asm.label("oam").bytes(&oam);
+    asm
+}
+
+fn frame(scene: &Scene, renderer: Renderer) -> Vec<u8> {
+    let rom = RomBuilder::new(0)
+        .banks(2, 0)
+        .code(&program(scene), "nmi", "reset", "nmi");
+    let mut headless = Headless::new(rom.cart());
+    headless.nes.set_renderer(Some(renderer));
+    for _ in 0..FRAMES {
+        headless.run_frame();
+    }
+    headless.framebuffer().data.clone()
+}
+
+fn pixel(frame: &[u8], x: usize, y: usize) -> &[u8] {
+    let offset = (y * Framebuffer::WIDTH + x) * 3;
+    &frame[offset..offset + 3]
+}
+
+#[test]
+fn test_scenes_match_golden_hashes() {
+    let mut mismatched = false;
+    let mut report = String::new();
+    for scene in &SCENES {
+        let hashes = Renderer::ALL.map(|renderer| crc32(&frame(scene, renderer)));
+        mismatched |= hashes != scene.golden;
+        report += &format!(
+            "{:>22}: [0x{:08X}, 0x{:08X}]{}\n",
+            scene.name,
+            hashes[0],
+            hashes[1],
+            if hashes != scene.golden {
+                " changed"
+            } else {
+                ""
+            }
+        );
+    }
+    assert!(!mismatched, "rendered frames changed:\n{}", report);
+}
+
+#[test]
+fn test_behind_sprite_hides_later_sprites() {
+    let frame = frame(&SCENES[1], Renderer::default());
+    let bar = pixel(&frame, 20, 40);
+    let backdrop = pixel(&frame, 20, 170);
+    let behind = pixel(&frame, 101, 165);
+    let front = pixel(&frame, 110, 165);
+    assert_ne!(behind, backdrop);
+    assert_ne!(front, behind);
+
+    // Over the bar the background covers both sprites where they overlap,
+    // and the front sprite shows past the end of the one behind.
+    assert_eq!(pixel(&frame, 106, 40), bar);
+    assert_eq!(pixel(&frame, 110, 40), front);
+    // Over the backdrop the sprite behind shows, hiding the one in front.
+    assert_eq!(pixel(&frame, 106, 165), behind);
+}
@@ -0,0 +1,1628 @@
+pub mod debug;
+pub mod framebuffer;
+#[cfg(test)]
+mod golden;
+pub mod palette;
+pub mod registers;
+pub mod render;
+pub mod snapshot;
+
+use crate::mapper::{ChrSource, Mapper, NametablePage};
+use crate::region::Region;
+use crate::savestate::{Savestate, StateReader, StateWriter};
+use palette::SystemPalette;
+use registers::addr::AddrRegister;
+use registers::control::ControlRegister;
+use registers::mask::MaskRegister;
+use registers::scroll::ScrollRegister;
+use registers::status::StatusRegister;
+use render::Renderer;
+use snapshot::PpuSnapshot;
+
+// Frames a bit of the I/O latch holds its value without being driven, about
+// 600ms on hardware.
+const IO_LATCH_DECAY_FRAMES: u64 = 36;
+
+#[derive(Clone, Debug)]
+pub struct ScrollSegment {
+    pub start_scanline: usize,
+    pub scroll_x: usize,
+    pub scroll_y: usize,
+    pub base_nametable: usize,
+    pub screen_origin: usize,
+}
+
+// The sprites from `start_scanline` down, until the next segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OamSegment {
+    pub start_scanline: usize,
+    pub oam: [u8; 256],
+}
+
+pub struct PPU {
+    pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+    pub scroll: ScrollRegister,
+    pub addr: AddrRegister,
+    // 2 KiB of CIRAM followed by 2 KiB standing in for the extra RAM found on
+    // four-screen cartridges that don't provide their own nametable pages.
+    pub vram: [u8; 4096],
+
+    pub oam_addr: u8,
+    pub oam_data: [u8; 256],
+    // OAM as each visible line started, wherever it changed, for the frame
+    // being drawn and for the last finished one.
+    frame_oam: Vec<OamSegment>,
+    render_oam: Vec<OamSegment>,
+    pub palette_table: [u8; 32],
+    // Maps color indices to RGB. Host-side, so not part of savestates.
+    pub system_palette: SystemPalette,
+    // Also host-side; see `Nes::set_renderer`.
+    pub renderer: Renderer,
+    // Whether only the first eight sprites on a line are drawn, as on the
+    // console. See `Nes::set_sprite_limit`.
+    pub sprite_limit: bool,
+
+    pub cycle: i16,
+    pub scanline: i16,
+    pub frame_count: u64,
+    region: Region,
+    // Dot of the current scanline where sprite 0 hits the background, worked
+    // out from the pixels when the line starts.
+    sprite_zero_hit_dot: Option<i16>,
+    // Set by a $2002 read on the dot before vblank starts, which keeps the
+    // flag, and so the NMI, from being raised that frame.
+    suppress_vblank: bool,
+
+    internal_data_buf: u8,
+    // The PPU's side of the CPU data bus, which reads of write-only registers
+    // and unused status bits return, and the frame each bit was last driven.
+    io_latch: u8,
+    io_latch_frames: [u64; 8],
+    scroll_segments: Vec<ScrollSegment>,
+    pending_scroll_descriptor: Option<(usize, usize, usize, usize)>,
+}
+
+impl PPU {
+    pub fn empty() -> Self {
+        PPU::new()
+    }
+
+    pub fn new() -> Self {
+        let mut ppu = PPU {
+            ctrl: ControlRegister::new(),
+            mask: MaskRegister::new(),
+            status: StatusRegister::new(),
+            oam_addr: 0,
+            scroll: ScrollRegister::new(),
+            addr: AddrRegister::new(),
+            vram: [0; 4096],
+            oam_data: [0; 64 * 4],
+            frame_oam: Vec::new(),
+            render_oam: Vec::new(),
+            palette_table: [0; 32],
+            system_palette: SystemPalette::default(),
+            renderer: Renderer::default(),
+            sprite_limit: true,
+            cycle: 0,
+            scanline: 0,
+            frame_count: 0,
+            region: Region::Ntsc,
+            sprite_zero_hit_dot: None,
+            suppress_vblank: false,
+            internal_data_buf: 0,
+            io_latch: 0,
+            io_latch_frames: [0; 8],
+            scroll_segments: Vec::new(),
+            pending_scroll_descriptor: None,
+        };
+
+        ppu.reset_scroll_segments_for_new_frame();
+        ppu.latch_line_oam();
+        ppu.render_oam = ppu.frame_oam.clone();
+        ppu
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    fn nametable_page(mapper: &dyn Mapper, addr: u16) -> (NametablePage, u16) {
+        let vram_index = (addr & 0x2FFF) - 0x2000;
+        let name_table = (vram_index / 0x400) as usize;
+        (mapper.nametable_layout()[name_table], vram_index & 0x03FF)
+    }
+
+    fn page_vram_base(page: NametablePage) -> Option<u16> {
+        match page {
+            NametablePage::CiramA => Some(0x000),
+            NametablePage::CiramB => Some(0x400),
+            NametablePage::CartRam(index) => Some(0x800 + (index as u16 & 0x01) * 0x400),
+            NametablePage::Fill => None,
+        }
+    }
+
+    pub fn mirror_vram_addr(&self, mapper: &dyn Mapper, addr: u16) -> u16 {
+        let (page, offset) = Self::nametable_page(mapper, addr);
+        Self::page_vram_base(page).unwrap_or(0) + offset
+    }
+
+    fn mirror_palette_addr(addr: u16) -> usize {
+        let mut palette_index = (addr - 0x3f00) & 0x1f;
+        if palette_index >= 0x10 && (palette_index & 0x03) == 0 {
+            palette_index -= 0x10;
+        }
+        palette_index as usize
+    }
+
+    pub fn peek_nametable_byte(&self, mapper: &dyn Mapper, addr: u16) -> u8 {
+        let (page, offset) = Self::nametable_page(mapper, addr);
+        if let Some(value) = mapper.read_nametable_page(page, offset) {
+            return value;
+        }
+        match Self::page_vram_base(page) {
+            Some(base) => self.vram[(base + offset) as usize],
+            None => 0,
+        }
+    }
+
+    fn write_nametable_byte(&mut self, mapper: &mut dyn Mapper, addr: u16, value: u8) {
+        let (page, offset) = Self::nametable_page(mapper, addr);
+        if mapper.write_nametable_page(page, offset, value) {
+            return;
+        }
+        if let Some(base) = Self::page_vram_base(page) {
+            self.vram[(base + offset) as usize] = value;
+        }
+    }
+
+    fn nametable_base_addr(table_index: usize) -> u16 {
+        0x2000 + ((table_index & 0x03) * 0x400) as u16
+    }
+
+    pub fn read_nametable_entry(
+        &self,
+        mapper: &dyn Mapper,
+        table_index: usize,
+        tile_column: usize,
+        tile_row: usize,
+    ) -> u8 {
+        let base = Self::nametable_base_addr(table_index);
+        let offset = (tile_row * 32 + tile_column) as u16;
+        self.peek_nametable_byte(mapper, base + offset)
+    }
+
+    pub fn read_attribute_entry(
+        &self,
+        mapper: &dyn Mapper,
+        table_index: usize,
+        tile_column: usize,
+        tile_row: usize,
+    ) -> u8 {
+        let base = Self::nametable_base_addr(table_index);
+        let attr = 0x3C0 + (tile_row / 4 * 8 + tile_column / 4) as u16;
+        self.peek_nametable_byte(mapper, base + attr)
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let step = self.ctrl.vram_addr_increment();
+        self.addr.increment(step);
+        self.scroll.increment(step);
+    }
+
+    pub fn scroll_segments(&self) -> &[ScrollSegment] {
+        &self.scroll_segments
+    }
+
+    pub fn render_oam(&self) -> &[OamSegment] {
+        &self.render_oam
+    }
+
+    // A line's sprites are picked while the line before it is drawn, so OAM
+    // DMA during rendering only shows from the lines after it.
+    fn latch_line_oam(&mut self) {
+        let scanline = self.scanline as usize;
+        if self
+            .frame_oam
+            .last()
+            .is_none_or(|segment| segment.oam != self.oam_data)
+        {
+            self.frame_oam.push(OamSegment {
+                start_scanline: scanline,
+                oam: self.oam_data,
+            });
+        }
+    }
+
+    // Taken after a frame, to compare with the one before it.
+    pub fn snapshot(&self) -> PpuSnapshot {
+        PpuSnapshot::new(self)
+    }
+
+    fn current_scroll_descriptor(&self) -> (usize, usize, usize) {
+        (
+            self.scroll.scroll_x(),
+            self.scroll.scroll_y(),
+            self.scroll.base_nametable(),
+        )
+    }
+
+    fn visible_scanline(&self) -> Option<usize> {
+        if (self.scanline as usize) < 240 {
+            Some(self.scanline as usize)
+        } else {
+            None
+        }
+    }
+
+    fn push_scroll_segment(
+        &mut self,
+        descriptor: (usize, usize, usize),
+        scanline: usize,
+        screen_origin: usize,
+    ) {
+        let (scroll_x, scroll_y, base_nametable) = descriptor;
+        if let Some(last) = self.scroll_segments.last_mut() {
+            if last.start_scanline == scanline {
+                *last = ScrollSegment {
+                    start_scanline: scanline,
+                    scroll_x,
+                    scroll_y,
+                    base_nametable,
+                    screen_origin,
+                };
+                return;
+            }
+
+            if last.scroll_x == scroll_x
+                && last.scroll_y == scroll_y
+                && last.base_nametable == base_nametable
+                && last.screen_origin == screen_origin
+            {
+                return;
+            }
+        }
+
+        self.scroll_segments.push(ScrollSegment {
+            start_scanline: scanline,
+            scroll_x,
+            scroll_y,
+            base_nametable,
+            screen_origin,
+        });
+    }
+
+    fn queue_scroll_state_change(&mut self, reset_origin: bool) {
+        let descriptor = self.current_scroll_descriptor();
+        if let Some(scanline) = self.visible_scanline() {
+            let scanline = scanline.min(239);
+            let screen_origin = if reset_origin {
+                scanline
+            } else {
+                self.scroll_segments
+                    .last()
+                    .map(|segment| segment.screen_origin)
+                    .unwrap_or(0)
+            };
+            self.push_scroll_segment(descriptor, scanline, screen_origin);
+        } else {
+            self.pending_scroll_descriptor = Some((descriptor.0, descriptor.1, descriptor.2, 0));
+        }
+    }
+
+    pub fn reset_scroll_segments_for_new_frame(&mut self) {
+        let descriptor = self.pending_scroll_descriptor.take().unwrap_or_else(|| {
+            let (scroll_x, scroll_y, base_nametable) = self.current_scroll_descriptor();
+            (scroll_x, scroll_y, base_nametable, 0)
+        });
+        self.scroll_segments.clear();
+        self.scroll_segments.push(ScrollSegment {
+            start_scanline: 0,
+            scroll_x: descriptor.0,
+            scroll_y: descriptor.1,
+            base_nametable: descriptor.2,
+            screen_origin: descriptor.3,
+        });
+    }
+}
+
+impl PPU {
+    pub fn write_to_ctrl(&mut self, value: u8) {
+        let previous_base_nametable = self.scroll.base_nametable();
+        self.ctrl.update(value);
+        self.scroll.update_ctrl(value);
+        let base_changed = previous_base_nametable != self.scroll.base_nametable();
+        self.queue_scroll_state_change(base_changed);
+    }
+
+    pub fn write_to_mask(&mut self, value: u8) {
+        self.mask.update(value);
+    }
+
+    // What a read of a write-only register returns. Bits fade to 0 once they
+    // haven't been driven for a while.
+    pub fn open_bus(&self) -> u8 {
+        (0..8)
+            .filter(|&bit| {
+                self.frame_count.wrapping_sub(self.io_latch_frames[bit]) < IO_LATCH_DECAY_FRAMES
+            })
+            .fold(0, |value, bit| value | (self.io_latch & (1 << bit)))
+    }
+
+    // Puts `value` on the bits in `mask`, as register accesses do, and returns
+    // the latch as the CPU then sees it.
+    pub fn drive_io_latch(&mut self, value: u8, mask: u8) -> u8 {
+        self.io_latch = (value & mask) | (self.open_bus() & !mask);
+        for bit in (0..8).filter(|bit| mask & (1 << bit) != 0) {
+            self.io_latch_frames[bit] = self.frame_count;
+        }
+        self.io_latch
+    }
+
+    // Only the top three bits are status; the rest are open bus. A read one
+    // dot before vblank starts sees the flag clear and stops it being set at
+    // all that frame; one on either of the two dots after sees it set, but
+    // clears it before the NMI goes out.
+    pub fn read_status(&mut self) -> u8 {
+        if self.scanline == self.region.vblank_scanline() && self.cycle == 0 {
+            self.suppress_vblank = true;
+        }
+        let data = self.status.snapshot();
+        self.status.reset_vblank_status();
+        self.addr.reset_latch();
+        self.scroll.reset_latch();
+        self.drive_io_latch(data, 0xE0)
+    }
+
+    pub fn write_to_oam_addr(&mut self, value: u8) {
+        self.oam_addr = value;
+    }
+
+    // Bits 2-4 of each sprite's attribute byte don't exist, so they read
+    // back as 0 rather than as what was written.
+    pub fn write_to_oam_data(&mut self, value: u8) {
+        let value = if self.oam_addr % 4 == 2 {
+            value & 0xE3
+        } else {
+            value
+        };
+        self.oam_data[self.oam_addr as usize] = value;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    pub fn read_oam_data(&mut self) -> u8 {
+        self.drive_io_latch(self.oam_data[self.oam_addr as usize], 0xFF)
+    }
+
+    // What a read of register `reg`, $2000-$2007, would return, without
+    // clearing vblank, resetting the write latch, moving the VRAM address or
+    // refreshing the I/O latch.
+    pub fn peek_register(&self, reg: u16) -> u8 {
+        let (value, mask) = match reg {
+            0x2002 => (self.status.snapshot(), 0xE0),
+            0x2004 => (self.oam_data[self.oam_addr as usize], 0xFF),
+            0x2007 => match self.scroll.addr() {
+                addr @ 0x3f00..=0x3fff => {
+                    (self.palette_table[PPU::mirror_palette_addr(addr)], 0x3F)
+                }
+                _ => (self.internal_data_buf, 0xFF),
+            },
+            _ => (0, 0),
+        };
+        (value & mask) | (self.open_bus() & !mask)
+    }
+
+    pub fn write_to_scroll(&mut self, value: u8) {
+        // Fine X applies at once and coarse X from the next line, so a lone
+        // first write already moves the picture sideways. Only a completed
+        // pair restarts the vertical scroll.
+        let completed_sequence = self.scroll.write(value);
+        self.queue_scroll_state_change(completed_sequence);
+    }
+
+    pub fn write_to_ppu_addr(&mut self, value: u8) {
+        self.addr.update(value);
+        let completed_sequence = self.scroll.write_ppu_addr(value);
+
+        if completed_sequence {
+            self.queue_scroll_state_change(true);
+        }
+    }
+
+    pub fn write_to_data(&mut self, mapper: &mut dyn Mapper, value: u8) {
+        let addr = self.scroll.addr();
+        match addr {
+            0..=0x1fff => mapper.write_chr(addr, value),
+            0x2000..=0x3eff => self.write_nametable_byte(mapper, addr, value),
+            0x3f00..=0x3fff => {
+                let palette_index = PPU::mirror_palette_addr(addr);
+                self.palette_table[palette_index] = value & 0x3f;
+            }
+            _ => panic!("unexpected access to mirrored space {}", addr),
+        }
+        self.increment_vram_addr();
+    }
+
+    // Returns what the read buffer held and refills it from the address as it
+    // was before the increment. Only pattern addresses reach the board as a
+    // fetch; nametable and palette reads, including the nametable byte under
+    // a palette entry, stay inside the console.
+    pub fn read_data(&mut self, mapper: &mut dyn Mapper) -> u8 {
+        let addr = self.scroll.addr();
+
+        self.increment_vram_addr();
+
+        match addr {
+            0..=0x1fff => {
+                let result = self.internal_data_buf;
+                self.internal_data_buf = mapper.fetch_chr(addr, ChrSource::Cpu);
+                self.drive_io_latch(result, 0xFF)
+            }
+            0x2000..=0x3eff => {
+                let result = self.internal_data_buf;
+                self.internal_data_buf = self.peek_nametable_byte(mapper, addr);
+                self.drive_io_latch(result, 0xFF)
+            }
+            // Palette entries are six bits wide, leaving the top two open.
+            0x3f00..=0x3fff => {
+                let palette_index = PPU::mirror_palette_addr(addr);
+                self.internal_data_buf = self.peek_nametable_byte(mapper, addr - 0x1000);
+                self.drive_io_latch(self.palette_table[palette_index], 0x3F)
+            }
+            _ => panic!("unexpected access to mirrored space {}", addr),
+        }
+    }
+
+    pub fn write_oam_dma(&mut self, data: &[u8; 256]) {
+        for &x in data {
+            self.write_to_oam_data(x);
+        }
+    }
+
+    // The $2001 emphasis bits as red, green and blue in bits 0-2.
+    pub fn emphasis(&self) -> u8 {
+        let bits = self.mask.bits() >> 5;
+        if self.region.swaps_red_green_emphasis() {
+            (bits & 0b100) | ((bits & 0b001) << 1) | ((bits & 0b010) >> 1)
+        } else {
+            bits
+        }
+    }
+
+    fn pre_render_scanline(&self) -> i16 {
+        self.region.scanlines_per_frame() - 1
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask.show_background() || self.mask.show_sprites()
+    }
+
+    pub fn clock(&mut self, mapper: &mut dyn Mapper) -> bool {
+        self.cycle += 1;
+
+        // On odd NTSC frames with rendering on, the pre-render line is a dot
+        // short, which evens out the colour subcarrier phase between frames.
+        if self.cycle == 340
+            && self.scanline == self.pre_render_scanline()
+            && self.frame_count % 2 == 1
+            && self.rendering_enabled()
+            && self.region.skips_odd_frame_dot()
+        {
+            self.cycle = 341;
+        }
+
+        if self.sprite_zero_hit_dot == Some(self.cycle) {
+            self.status.set_sprite_zero_hit(true);
+            self.sprite_zero_hit_dot = None;
+        }
+
+        if self.cycle >= 341 {
+            self.cycle -= 341;
+
+            if self.scanline < 240 {
+                mapper.handle_scanline(self.rendering_enabled());
+            }
+
+            self.scanline += 1;
+            // Pixel x is output on dot x + 1.
+            self.sprite_zero_hit_dot = self
+                .visible_scanline()
+                .and_then(|scanline| render::sprite_zero_hit_x(self, mapper, scanline))
+                .map(|x| x as i16 + 1);
+
+            if self.scanline == self.region.vblank_scanline() {
+                self.render_oam = std::mem::take(&mut self.frame_oam);
+            }
+
+            if self.scanline >= self.region.scanlines_per_frame() {
+                self.scanline = 0;
+                self.cycle = 0;
+                self.frame_count = self.frame_count.wrapping_add(1);
+                self.latch_line_oam();
+                return true;
+            }
+            if self.visible_scanline().is_some() {
+                self.latch_line_oam();
+            }
+        }
+
+        // Sprite evaluation for the next line runs over dots 65 to 256.
+        if self.cycle == 257
+            && self.rendering_enabled()
+            && let Some(scanline) = self.visible_scanline()
+            && render::sprite_overflow(self, scanline)
+        {
+            self.status.set_sprite_overflow(true);
+        }
+
+        // The vblank flag goes up on dot 1 of the first vblank line, and all
+        // three flags come down on dot 1 of the pre-render line.
+        if self.cycle == 1 {
+            if self.scanline == self.region.vblank_scanline() {
+                let suppressed = std::mem::take(&mut self.suppress_vblank);
+                self.status.set_vblank_status(!suppressed);
+            } else if self.scanline == self.pre_render_scanline() {
+                self.status.reset_vblank_status();
+                self.status.set_sprite_zero_hit(false);
+                self.status.set_sprite_overflow(false);
+            }
+        }
+
+        // The pre-render line reloads the vertical scroll from t for the
+        // frame ahead, over and over from dot 280 to 304.
+        if self.scanline == self.pre_render_scanline()
+            && (280..=304).contains(&self.cycle)
+            && self.rendering_enabled()
+        {
+            self.scroll.copy_vertical_bits();
+        }
+        false
+    }
+
+    // The two dots after vblank starts, when a $2002 read still races the NMI.
+    fn in_vblank_race(&self) -> bool {
+        self.scanline == self.region.vblank_scanline() && (1..3).contains(&self.cycle)
+    }
+
+    // Level of the PPU's /NMI output, true while asserted. It follows the
+    // vblank flag two dots late, which is the window where a $2002 read or
+    // clearing the enable bit in $2000 cancels the NMI. The CPU watches for
+    // the rising edge, so enabling NMI in $2000 mid-vblank fires another one.
+    pub fn nmi_line(&self) -> bool {
+        self.status.is_in_vblank() && self.ctrl.generate_vblank_nmi() && !self.in_vblank_race()
+    }
+}
+
+impl Savestate for PPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.ctrl.bits());
+        state.write_u8(self.mask.bits());
+        state.write_u8(self.status.bits());
+        self.scroll.save_state(state);
+        self.addr.save_state(state);
+        state.write_bytes(&self.vram);
+        state.write_u8(self.oam_addr);
+        state.write_bytes(&self.oam_data);
+        for segments in [&self.frame_oam, &self.render_oam] {
+            state.write_usize(segments.len());
+            for segment in segments {
+                state.write_usize(segment.start_scanline);
+                state.write_bytes(&segment.oam);
+            }
+        }
+        state.write_bytes(&self.palette_table);
+        state.write_i16(self.cycle);
+        state.write_i16(self.scanline);
+        state.write_u64(self.frame_count);
+        state.write_bool(self.sprite_zero_hit_dot.is_some());
+        state.write_i16(self.sprite_zero_hit_dot.unwrap_or(0));
+        state.write_bool(self.suppress_vblank);
+        state.write_u8(self.internal_data_buf);
+        state.write_u8(self.io_latch);
+        for frame in self.io_latch_frames {
+            state.write_u64(frame);
+        }
+
+        // The renderer draws the whole frame at once from the scroll changes
+        // seen so far, so a mid-frame state needs them too.
+        state.write_usize(self.scroll_segments.len());
+        for segment in &self.scroll_segments {
+            state.write_usize(segment.start_scanline);
+            state.write_usize(segment.scroll_x);
+            state.write_usize(segment.scroll_y);
+            state.write_usize(segment.base_nametable);
+            state.write_usize(segment.screen_origin);
+        }
+        state.write_bool(self.pending_scroll_descriptor.is_some());
+        let (a, b, c, d) = self.pending_scroll_descriptor.unwrap_or_default();
+        for value in [a, b, c, d] {
+            state.write_usize(value);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.ctrl = ControlRegister::from_bits_truncate(state.read_u8()?);
+        self.mask = MaskRegister::from_bits_truncate(state.read_u8()?);
+        self.status = StatusRegister::from_bits_truncate(state.read_u8()?);
+        self.scroll.load_state(state)?;
+        self.addr.load_state(state)?;
+        state.read_bytes_into(&mut self.vram)?;
+        self.oam_addr = state.read_u8()?;
+        state.read_bytes_into(&mut self.oam_data)?;
+        for segments in [&mut self.frame_oam, &mut self.render_oam] {
+            let count = state.read_usize()?;
+            if count > 240 {
+                return Err(format!("Savestate has {} OAM segments", count));
+            }
+            segments.clear();
+            for _ in 0..count {
+                let mut segment = OamSegment {
+                    start_scanline: state.read_usize()?,
+                    oam: [0; 256],
+                };
+                state.read_bytes_into(&mut segment.oam)?;
+                segments.push(segment);
+            }
+        }
+        state.read_bytes_into(&mut self.palette_table)?;
+        self.cycle = state.read_i16()?;
+        self.scanline = state.read_i16()?;
+        self.frame_count = state.read_u64()?;
+        let sprite_zero_hit = state.read_bool()?;
+        let sprite_zero_hit_dot = state.read_i16()?;
+        self.sprite_zero_hit_dot = sprite_zero_hit.then_some(sprite_zero_hit_dot);
+        self.suppress_vblank = state.read_bool()?;
+        self.internal_data_buf = state.read_u8()?;
+        self.io_latch = state.read_u8()?;
+        for frame in &mut self.io_latch_frames {
+            *frame = state.read_u64()?;
+        }
+
+        let segments = state.read_usize()?;
+        if segments > 240 {
+            return Err(format!("Savestate has {} scroll segments", segments));
+        }
+        self.scroll_segments.clear();
+        for _ in 0..segments {
+            self.scroll_segments.push(ScrollSegment {
+                start_scanline: state.read_usize()?,
+                scroll_x: state.read_usize()?,
+                scroll_y: state.read_usize()?,
+                base_nametable: state.read_usize()?,
+                screen_origin: state.read_usize()?,
+            });
+        }
+        let pending = state.read_bool()?;
+        let descriptor = (
+            state.read_usize()?,
+            state.read_usize()?,
+            state.read_usize()?,
+            state.read_usize()?,
+        );
+        self.pending_scroll_descriptor = pending.then_some(descriptor);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::cart::Mirroring;
+    use crate::mapper::nrom::NromMapper;
+
+    use super::*;
+    use framebuffer::Framebuffer;
+    use registers::PpuRegisters;
+
+    #[test]
+    fn test_ppu_vram_writes() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.write_to_ppu_addr(0x23);
+        ppu.write_to_ppu_addr(0x05);
+        ppu.write_to_data(&mut mapper, 0x66);
+
+        assert_eq!(ppu.vram[0x0305], 0x66);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        let mut regs = PpuRegisters::new(&mut ppu, &mut mapper);
+        regs.write_ctrl(ControlRegister::empty());
+        regs.write_addr(0x2305);
+        regs.write_data(0x66);
+
+        regs.write_addr(0x2305);
+        regs.read_data();
+        assert_eq!(regs.peek_addr(), 0x2306);
+        assert_eq!(regs.read_data(), 0x66);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads_cross_page() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        let mut regs = PpuRegisters::new(&mut ppu, &mut mapper);
+        regs.write_ctrl(ControlRegister::empty());
+        regs.write_addr(0x21ff);
+        regs.write_data(0x66);
+        regs.write_data(0x77);
+
+        regs.write_addr(0x21ff);
+        regs.read_data();
+        assert_eq!(regs.read_data(), 0x66);
+        assert_eq!(regs.read_data(), 0x77);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads_step_32() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        let mut regs = PpuRegisters::new(&mut ppu, &mut mapper);
+        regs.write_ctrl(ControlRegister::VRAM_ADD_INCREMENT);
+        regs.write_addr(0x21ff);
+        regs.write_data(0x66);
+        regs.write_data(0x77);
+        regs.write_data(0x88);
+
+        regs.write_addr(0x21ff);
+        regs.read_data();
+        assert_eq!(regs.read_data(), 0x66);
+        assert_eq!(regs.read_data(), 0x77);
+        assert_eq!(regs.read_data(), 0x88);
+    }
+
+    #[test]
+    fn test_registers_status_read_clears_vblank_and_latch() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.status.set_vblank_status(true);
+        // Half of a $2006 write, which the status read should discard.
+        ppu.write_to_ppu_addr(0x3f);
+
+        let mut regs = PpuRegisters::new(&mut ppu, &mut mapper);
+        assert!(regs.read_status().contains(StatusRegister::VBLANK_STARTED));
+        assert!(!regs.peek_status().contains(StatusRegister::VBLANK_STARTED));
+
+        ppu.write_to_ppu_addr(0x23);
+        ppu.write_to_ppu_addr(0x05);
+        assert_eq!(ppu.scroll.addr(), 0x2305);
+    }
+
+    #[test]
+    fn test_vram_horizontal_mirror() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.write_to_ppu_addr(0x24);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.write_to_data(&mut mapper, 0x66);
+
+        ppu.write_to_ppu_addr(0x28);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.write_to_data(&mut mapper, 0x77);
+
+        ppu.write_to_ppu_addr(0x20);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(&mut mapper);
+        assert_eq!(ppu.read_data(&mut mapper), 0x66);
+
+        ppu.write_to_ppu_addr(0x2C);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(&mut mapper);
+        assert_eq!(ppu.read_data(&mut mapper), 0x77);
+    }
+
+    #[test]
+    fn test_vram_vertical_mirror() {
+        let mut mapper = NromMapper::new(vec![], vec![0; 2048], Mirroring::Vertical);
+        let mut ppu = PPU::empty();
+
+        ppu.write_to_ppu_addr(0x20);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.write_to_data(&mut mapper, 0x66);
+
+        ppu.write_to_ppu_addr(0x2C);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.write_to_data(&mut mapper, 0x77);
+
+        ppu.write_to_ppu_addr(0x28);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(&mut mapper);
+        assert_eq!(ppu.read_data(&mut mapper), 0x66);
+
+        ppu.write_to_ppu_addr(0x24);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(&mut mapper);
+        assert_eq!(ppu.read_data(&mut mapper), 0x77);
+    }
+
+    #[test]
+    fn test_vram_single_screen_lower_mirror() {
+        let mapper = NromMapper::new(vec![], vec![0; 2048], Mirroring::SingleScreenLower);
+        let ppu = PPU::empty();
+
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2000), 0);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x23ff), 0x03ff);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2400), 0);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x27ff), 0x03ff);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2c00), 0);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2fff), 0x03ff);
+    }
+
+    #[test]
+    fn test_vram_single_screen_upper_mirror() {
+        let mapper = NromMapper::new(vec![], vec![0; 2048], Mirroring::SingleScreenUpper);
+        let ppu = PPU::empty();
+
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2000), 0x0400);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x23ff), 0x07ff);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2400), 0x0400);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x27ff), 0x07ff);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2c00), 0x0400);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2fff), 0x07ff);
+    }
+
+    #[test]
+    fn test_vram_four_screen_pages_are_independent() {
+        let mut mapper = NromMapper::new(vec![], vec![0; 2048], Mirroring::FourScreen);
+        let mut ppu = PPU::empty();
+
+        for (hi, value) in [(0x20, 0x11), (0x24, 0x22), (0x28, 0x33), (0x2C, 0x44)] {
+            ppu.write_to_ppu_addr(hi);
+            ppu.write_to_ppu_addr(0x05);
+            ppu.write_to_data(&mut mapper, value);
+        }
+
+        assert_eq!(ppu.peek_nametable_byte(&mapper, 0x2005), 0x11);
+        assert_eq!(ppu.peek_nametable_byte(&mapper, 0x2405), 0x22);
+        assert_eq!(ppu.peek_nametable_byte(&mapper, 0x2805), 0x33);
+        assert_eq!(ppu.peek_nametable_byte(&mapper, 0x2C05), 0x44);
+        assert_eq!(ppu.mirror_vram_addr(&mapper, 0x2C05), 0x0C05);
+    }
+
+    #[test]
+    fn test_scroll_segments_capture_mid_frame_changes() {
+        let mut ppu = PPU::empty();
+
+        assert_eq!(ppu.scroll_segments().len(), 1);
+        ppu.scanline = 100;
+        ppu.write_to_scroll(0x14);
+        ppu.write_to_scroll(0x08);
+
+        assert_eq!(ppu.scroll_segments().len(), 2);
+        let segment = &ppu.scroll_segments()[1];
+        assert_eq!(segment.start_scanline, 100);
+        assert_eq!(segment.scroll_x, 20);
+        assert_eq!(segment.scroll_y, 8);
+    }
+
+    #[test]
+    fn test_lone_first_scroll_write_changes_only_x() {
+        let mut ppu = PPU::empty();
+        ppu.scanline = 241;
+        ppu.write_to_scroll(0x00);
+        ppu.write_to_scroll(0x10);
+        ppu.reset_scroll_segments_for_new_frame();
+
+        // A raster effect nudging fine X alone.
+        ppu.scanline = 60;
+        ppu.write_to_scroll(0x03);
+        assert_eq!(ppu.scroll_segments().len(), 2);
+        let segment = &ppu.scroll_segments()[1];
+        assert_eq!(segment.start_scanline, 60);
+        assert_eq!(segment.scroll_x, 3);
+        assert_eq!(segment.scroll_y, 16);
+        assert_eq!(segment.screen_origin, 0);
+
+        // Reading $2002 drops the latch, so this is a first write again.
+        ppu.scanline = 90;
+        ppu.read_status();
+        ppu.write_to_scroll(0x2D);
+        assert_eq!(ppu.scroll_segments().len(), 3);
+        assert_eq!(ppu.scroll_segments()[2].scroll_x, 0x2D);
+        assert_eq!(ppu.scroll_segments()[2].screen_origin, 0);
+    }
+
+    #[test]
+    fn test_completing_a_scroll_pair_replaces_the_lines_first_write() {
+        let mut ppu = PPU::empty();
+        ppu.scanline = 100;
+        ppu.write_to_scroll(0x14);
+        assert_eq!(ppu.scroll_segments()[1].screen_origin, 0);
+        ppu.write_to_scroll(0x08);
+
+        assert_eq!(ppu.scroll_segments().len(), 2);
+        assert_eq!(ppu.scroll_segments()[1].screen_origin, 100);
+
+        // Split across lines, the first write takes effect on its own line.
+        ppu.scanline = 150;
+        ppu.write_to_scroll(0x30);
+        ppu.scanline = 151;
+        ppu.write_to_scroll(0x20);
+        let segments = ppu.scroll_segments();
+        assert_eq!(segments.len(), 4);
+        assert_eq!((segments[2].scroll_x, segments[2].scroll_y), (0x30, 8));
+        assert_eq!((segments[3].scroll_x, segments[3].scroll_y), (0x30, 0x20));
+        assert_eq!(segments[3].screen_origin, 151);
+    }
+
+    #[test]
+    fn test_scroll_writes_during_vblank_apply_next_frame() {
+        let mut ppu = PPU::empty();
+
+        assert_eq!(ppu.scroll_segments()[0].scroll_y, 0);
+        ppu.scanline = 241;
+        ppu.write_to_scroll(0x00);
+        ppu.write_to_scroll(0x10);
+
+        ppu.reset_scroll_segments_for_new_frame();
+        assert_eq!(ppu.scroll_segments()[0].scroll_y, 16);
+    }
+
+    #[test]
+    fn test_read_status_resets_latch() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.vram[0x0305] = 0x66;
+
+        ppu.write_to_ppu_addr(0x21);
+        ppu.write_to_ppu_addr(0x23);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(&mut mapper);
+        assert_ne!(ppu.read_data(&mut mapper), 0x66);
+
+        ppu.read_status();
+
+        ppu.write_to_ppu_addr(0x23);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(&mut mapper);
+        assert_eq!(ppu.read_data(&mut mapper), 0x66);
+    }
+
+    #[test]
+    fn test_ppu_vram_mirroring() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.write_to_ctrl(0);
+        ppu.vram[0x0305] = 0x66;
+
+        ppu.write_to_ppu_addr(0x63);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(&mut mapper);
+        assert_eq!(ppu.read_data(&mut mapper), 0x66);
+    }
+
+    #[test]
+    fn test_palette_address_mirroring() {
+        let mut ppu = PPU::empty();
+
+        fn write_palette(ppu: &mut PPU, addr: u16, value: u8) {
+            let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+
+            ppu.write_to_ppu_addr((addr >> 8) as u8);
+            ppu.write_to_ppu_addr((addr & 0xff) as u8);
+            ppu.write_to_data(&mut mapper, value);
+        }
+
+        write_palette(&mut ppu, 0x3f00, 0x11);
+        assert_eq!(ppu.palette_table[0x00], 0x11);
+
+        write_palette(&mut ppu, 0x3f10, 0x22);
+        assert_eq!(ppu.palette_table[0x00], 0x22);
+
+        write_palette(&mut ppu, 0x3f20, 0x33);
+        assert_eq!(ppu.palette_table[0x00], 0x33);
+
+        write_palette(&mut ppu, 0x3f1c, 0x3d);
+        assert_eq!(ppu.palette_table[0x0c], 0x3d & 0x3f);
+    }
+
+    #[test]
+    fn test_palette_reads_update_buffer() {
+        let mut mapper = NromMapper::new(vec![], vec![0; 2048], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+
+        let mirrored_vram = ppu.mirror_vram_addr(&mapper, 0x2f00) as usize;
+        ppu.vram[mirrored_vram] = 0xaa;
+
+        ppu.write_to_ppu_addr(0x3f);
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_data(&mut mapper, 0x2f);
+
+        ppu.write_to_ppu_addr(0x3f);
+        ppu.write_to_ppu_addr(0x00);
+        ppu.internal_data_buf = 0;
+        let data = ppu.read_data(&mut mapper);
+        assert_eq!(data, 0x2f);
+        assert_eq!(ppu.internal_data_buf, 0xaa);
+    }
+
+    // Two CHR banks, each filled with its number plus one, and an MMC2 style
+    // latch: fetching $0FE8 selects bank 1 and $0FD8 bank 0.
+    struct LatchingMapper {
+        bank: u8,
+        fetches: Vec<u16>,
+    }
+
+    impl Savestate for LatchingMapper {
+        fn save_state(&self, _state: &mut StateWriter) {}
+
+        fn load_state(&mut self, _state: &mut StateReader) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl Mapper for LatchingMapper {
+        fn read_prg(&self, _addr: u16) -> u8 {
+            0
+        }
+
+        fn write_prg(&mut self, _addr: u16, _data: u8) {}
+
+        fn read_chr(&self, _addr: u16, _source: ChrSource) -> u8 {
+            self.bank + 1
+        }
+
+        fn write_chr(&mut self, _addr: u16, _data: u8) {}
+
+        fn notify_chr_fetch(&mut self, addr: u16) {
+            self.fetches.push(addr);
+            match addr {
+                0x0FD8 => self.bank = 0,
+                0x0FE8 => self.bank = 1,
+                _ => {}
+            }
+        }
+
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Horizontal
+        }
+    }
+
+    #[test]
+    fn test_buffered_reads_fetch_before_the_latch_flips() {
+        let mut mapper = LatchingMapper {
+            bank: 0,
+            fetches: Vec::new(),
+        };
+        let mut ppu = PPU::empty();
+        ppu.write_to_ppu_addr(0x0f);
+        ppu.write_to_ppu_addr(0xe8);
+
+        // The buffer is refilled from bank 0, and only then does the latch
+        // see the address and switch.
+        assert_eq!(ppu.read_data(&mut mapper), 0);
+        assert_eq!(ppu.internal_data_buf, 1);
+        assert_eq!(mapper.bank, 1);
+        assert_eq!(ppu.read_data(&mut mapper), 1);
+        assert_eq!(ppu.read_data(&mut mapper), 2);
+        assert_eq!(mapper.fetches, [0x0fe8, 0x0fe9, 0x0fea]);
+    }
+
+    #[test]
+    fn test_nametable_and_palette_reads_are_not_chr_fetches() {
+        let mut mapper = LatchingMapper {
+            bank: 0,
+            fetches: Vec::new(),
+        };
+        let mut ppu = PPU::empty();
+        ppu.write_to_ppu_addr(0x2f);
+        ppu.write_to_ppu_addr(0xd8);
+        ppu.read_data(&mut mapper);
+        ppu.write_to_ppu_addr(0x3f);
+        ppu.write_to_ppu_addr(0xd8);
+        ppu.read_data(&mut mapper);
+        assert!(mapper.fetches.is_empty());
+    }
+
+    #[test]
+    fn test_read_status_resets_vblank() {
+        let mut ppu = PPU::empty();
+        ppu.status.set_vblank_status(true);
+
+        let status = ppu.read_status();
+
+        assert_eq!(status >> 7, 1);
+        assert_eq!(ppu.status.snapshot() >> 7, 0);
+    }
+
+    #[test]
+    fn test_io_latch_fills_undriven_bits_and_decays() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.drive_io_latch(0x5F, 0xFF);
+        ppu.status.set_vblank_status(true);
+        assert_eq!(ppu.read_status(), 0x9F);
+        assert_eq!(ppu.open_bus(), 0x9F);
+
+        ppu.frame_count += 20;
+        ppu.palette_table[0] = 0x2A;
+        ppu.write_to_ppu_addr(0x3F);
+        ppu.write_to_ppu_addr(0x00);
+        assert_eq!(ppu.read_data(&mut mapper), 0xAA);
+
+        // Only the palette's bits were driven since, so the rest fade first.
+        ppu.frame_count += IO_LATCH_DECAY_FRAMES - 20;
+        assert_eq!(ppu.open_bus(), 0x2A);
+        ppu.frame_count += 20;
+        assert_eq!(ppu.open_bus(), 0x00);
+    }
+
+    #[test]
+    fn test_oam_read_write() {
+        let mut ppu = PPU::empty();
+        ppu.write_to_oam_addr(0x10);
+        ppu.write_to_oam_data(0x66);
+        ppu.write_to_oam_data(0x77);
+
+        ppu.write_to_oam_addr(0x10);
+        assert_eq!(ppu.read_oam_data(), 0x66);
+
+        ppu.write_to_oam_addr(0x11);
+        assert_eq!(ppu.read_oam_data(), 0x77);
+    }
+
+    #[test]
+    fn test_oam_attribute_bytes_drop_unused_bits() {
+        let mut ppu = PPU::empty();
+        ppu.write_to_oam_addr(0x11);
+        for _ in 0..3 {
+            ppu.write_to_oam_data(0xFF);
+        }
+        ppu.write_oam_dma(&[0xFF; 256]);
+        for (addr, value) in [(0x11, 0xFF), (0x12, 0xE3), (0x13, 0xFF), (0x16, 0xE3)] {
+            ppu.write_to_oam_addr(addr);
+            assert_eq!(ppu.read_oam_data(), value, "{:02X}", addr);
+        }
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut ppu = PPU::empty();
+
+        let mut data = [0x66; 256];
+        data[0] = 0x77;
+        data[255] = 0x88;
+
+        ppu.write_to_oam_addr(0x10);
+        ppu.write_oam_dma(&data);
+
+        ppu.write_to_oam_addr(0xf);
+        assert_eq!(ppu.read_oam_data(), 0x88);
+
+        ppu.write_to_oam_addr(0x10);
+        assert_eq!(ppu.read_oam_data(), 0x77);
+
+        ppu.write_to_oam_addr(0x11);
+        assert_eq!(ppu.read_oam_data(), 0x66);
+    }
+
+    fn run_to_scanline(ppu: &mut PPU, mapper: &mut dyn Mapper, scanline: i16) {
+        while ppu.scanline != scanline {
+            ppu.clock(mapper);
+        }
+    }
+
+    fn run_to_dot(ppu: &mut PPU, mapper: &mut dyn Mapper, scanline: i16, dot: i16) {
+        while (ppu.scanline, ppu.cycle) != (scanline, dot) {
+            ppu.clock(mapper);
+        }
+    }
+
+    // Runs the rest of the frame and says whether the NMI line rose.
+    fn nmi_before_frame_end(ppu: &mut PPU, mapper: &mut dyn Mapper) -> bool {
+        let mut raised = ppu.nmi_line();
+        while !ppu.clock(mapper) {
+            raised |= ppu.nmi_line();
+        }
+        raised
+    }
+
+    // Tile 1 is solid and sits in the given columns of the second tile row,
+    // so at y=8-15. Sprite 0 uses it too, with its top-left corner at `sprite`.
+    fn sprite_zero_scene(
+        sprite: (u8, u8),
+        columns: &[u16],
+        mask: MaskRegister,
+    ) -> (PPU, NromMapper) {
+        let mut chr = vec![0; 0x2000];
+        chr[16..24].fill(0xFF);
+        let mut mapper = NromMapper::new(vec![], chr, Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        let mut regs = PpuRegisters::new(&mut ppu, &mut mapper);
+        for column in columns {
+            regs.write_addr(0x2000 + 32 + column);
+            regs.write_data(1);
+        }
+        regs.write_scroll(0, 0);
+        regs.write_mask(mask);
+        ppu.oam_data[..4].copy_from_slice(&[sprite.1 - 1, 1, 0, sprite.0]);
+        (ppu, mapper)
+    }
+
+    fn all_shown() -> MaskRegister {
+        MaskRegister::SHOW_BACKGROUND
+            | MaskRegister::SHOW_SPRITES
+            | MaskRegister::LEFTMOST_8PXL_BACKGROUND
+            | MaskRegister::LEFTMOST_8PXL_SPRITE
+    }
+
+    fn sprite_zero_hit_at(
+        sprite: (u8, u8),
+        columns: &[u16],
+        mask: MaskRegister,
+    ) -> Option<(i16, i16)> {
+        let (mut ppu, mut mapper) = sprite_zero_scene(sprite, columns, mask);
+        while ppu.scanline < 240 {
+            ppu.clock(&mut mapper);
+            if ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT) {
+                return Some((ppu.scanline, ppu.cycle));
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_needs_overlapping_opaque_pixels() {
+        // The sprite's top row overlaps the tile from x=24.
+        assert_eq!(
+            sprite_zero_hit_at((20, 10), &[3], all_shown()),
+            Some((10, 25))
+        );
+        // Same line, but over transparent background.
+        assert_eq!(sprite_zero_hit_at((40, 10), &[3], all_shown()), None);
+        // Below the tile.
+        assert_eq!(sprite_zero_hit_at((24, 16), &[3], all_shown()), None);
+        // Sprites hidden.
+        let background_only =
+            MaskRegister::SHOW_BACKGROUND | MaskRegister::LEFTMOST_8PXL_BACKGROUND;
+        assert_eq!(sprite_zero_hit_at((24, 8), &[3], background_only), None);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_skips_clipped_left_column_and_x_255() {
+        assert_eq!(sprite_zero_hit_at((0, 8), &[0], all_shown()), Some((8, 1)));
+        let clipped = || MaskRegister::SHOW_BACKGROUND | MaskRegister::SHOW_SPRITES;
+        assert_eq!(sprite_zero_hit_at((0, 8), &[0], clipped()), None);
+        assert_eq!(sprite_zero_hit_at((2, 8), &[1], clipped()), Some((8, 9)));
+
+        assert_eq!(sprite_zero_hit_at((255, 8), &[31], all_shown()), None);
+        assert_eq!(
+            sprite_zero_hit_at((254, 8), &[31], all_shown()),
+            Some((8, 255))
+        );
+    }
+
+    #[test]
+    fn test_emphasis_tints_the_picture_by_region() {
+        let mut mapper = NromMapper::new(vec![], vec![0; 0x2000], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.palette_table[0] = 0x30;
+        let white = ppu.system_palette.color(0x30);
+        let tinted = |emphasis| palette::emphasize(white, emphasis);
+        let backdrop = |ppu: &PPU, mapper: &mut NromMapper| {
+            let mut frame = Framebuffer::new();
+            render::render(ppu, mapper, &mut frame);
+            (frame.data[0], frame.data[1], frame.data[2])
+        };
+        assert_eq!(backdrop(&ppu, &mut mapper), white);
+
+        // Bit 5 is red on NTSC and green on PAL.
+        ppu.write_to_mask(0x20);
+        assert_eq!(backdrop(&ppu, &mut mapper), tinted(0b001));
+        ppu.set_region(Region::Pal);
+        assert_eq!(backdrop(&ppu, &mut mapper), tinted(0b010));
+        ppu.write_to_mask(0x80);
+        assert_eq!(backdrop(&ppu, &mut mapper), tinted(0b100));
+    }
+
+    #[test]
+    fn test_renderers_agree_on_a_scrolled_screen() {
+        let (mut ppu, mut mapper) = sprite_zero_scene((30, 20), &[0, 3, 4, 31], all_shown());
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[1] = 0x30;
+        ppu.palette_table[0x12] = 0x16;
+        // Scrolled during vblank, so from the top of the next frame.
+        ppu.scanline = 241;
+        PpuRegisters::new(&mut ppu, &mut mapper).write_scroll(13, 10);
+        ppu.reset_scroll_segments_for_new_frame();
+
+        let frames = Renderer::ALL.map(|renderer| {
+            ppu.renderer = renderer;
+            let mut frame = Framebuffer::new();
+            render::render(&ppu, &mut mapper, &mut frame);
+            frame.data
+        });
+        assert!(frames[0] == frames[1]);
+
+        // Column 0 of tile row 1 wraps around to x=243-250, y=0-5.
+        let pixel = |x: usize, y: usize| {
+            let i = (y * Framebuffer::WIDTH + x) * 3;
+            (frames[0][i], frames[0][i + 1], frames[0][i + 2])
+        };
+        assert_eq!(pixel(250, 5), ppu.system_palette.color(0x30));
+        assert_eq!(pixel(251, 5), ppu.system_palette.color(0x0F));
+        assert_eq!(pixel(250, 6), ppu.system_palette.color(0x0F));
+    }
+
+    // Nine of tile 1 side by side on lines 51-58, with the rest hidden.
+    fn crowded_line_scene() -> (PPU, NromMapper) {
+        let (mut ppu, mapper) = sprite_zero_scene((0, 1), &[], all_shown());
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[0x11] = 0x30;
+        ppu.oam_data.fill(0xFF);
+        for sprite in 0..9 {
+            ppu.oam_data[sprite * 4..sprite * 4 + 4].copy_from_slice(&[
+                50,
+                1,
+                0,
+                sprite as u8 * 10,
+            ]);
+        }
+        (ppu, mapper)
+    }
+
+    fn overflow_lines(ppu: &mut PPU, mapper: &mut NromMapper) -> Vec<i16> {
+        let mut lines = Vec::new();
+        while ppu.scanline < 240 {
+            ppu.clock(mapper);
+            if ppu.status.contains(StatusRegister::SPRITE_OVERFLOW) {
+                lines.push(ppu.scanline);
+                ppu.status.set_sprite_overflow(false);
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn test_oam_written_mid_frame_shows_from_the_next_line() {
+        let (mut ppu, mut mapper) = crowded_line_scene();
+        ppu.oam_data.fill(0xFF);
+        ppu.oam_data[..8].copy_from_slice(&[20, 1, 0, 80, 100, 1, 0, 80]);
+        run_to_dot(&mut ppu, &mut mapper, 24, 100);
+        ppu.oam_data[3] = 160;
+        ppu.oam_data[7] = 160;
+        run_to_dot(&mut ppu, &mut mapper, 241, 5);
+
+        let mut frame = Framebuffer::new();
+        render::render(&ppu, &mut mapper, &mut frame);
+        let white = ppu.system_palette.color(0x30);
+        let sprite_at = |x: usize, y: usize| {
+            let i = (y * Framebuffer::WIDTH + x) * 3;
+            (frame.data[i], frame.data[i + 1], frame.data[i + 2]) == white
+        };
+        // The first sprite moves partway down, the second is drawn moved.
+        assert!(sprite_at(80, 24) && !sprite_at(160, 24));
+        assert!(!sprite_at(80, 25) && sprite_at(160, 25));
+        assert!(!sprite_at(80, 105) && sprite_at(160, 105));
+        assert_eq!(ppu.render_oam().last().unwrap().start_scanline, 25);
+    }
+
+    #[test]
+    fn test_sprite_limit_drops_sprites_past_the_eighth() {
+        let (mut ppu, mut mapper) = crowded_line_scene();
+        ppu.render_oam = vec![OamSegment {
+            start_scanline: 0,
+            oam: ppu.oam_data,
+        }];
+        let ninth_sprite = |ppu: &PPU, mapper: &mut NromMapper| {
+            let mut frame = Framebuffer::new();
+            render::render(ppu, mapper, &mut frame);
+            let i = (51 * Framebuffer::WIDTH + 80) * 3;
+            (frame.data[i], frame.data[i + 1], frame.data[i + 2])
+        };
+        assert_eq!(
+            ninth_sprite(&ppu, &mut mapper),
+            ppu.system_palette.color(0x0F)
+        );
+        ppu.sprite_limit = false;
+        assert_eq!(
+            ninth_sprite(&ppu, &mut mapper),
+            ppu.system_palette.color(0x30)
+        );
+    }
+
+    #[test]
+    fn test_sprite_overflow_is_set_while_evaluating_a_crowded_line() {
+        let (mut ppu, mut mapper) = crowded_line_scene();
+        assert_eq!(
+            overflow_lines(&mut ppu, &mut mapper),
+            (50..58).collect::<Vec<_>>()
+        );
+
+        // Not for eight, and not with rendering off.
+        let (mut ppu, mut mapper) = crowded_line_scene();
+        ppu.oam_data[32] = 0xFF;
+        assert!(overflow_lines(&mut ppu, &mut mapper).is_empty());
+        let (mut ppu, mut mapper) = crowded_line_scene();
+        ppu.write_to_mask(0);
+        assert!(overflow_lines(&mut ppu, &mut mapper).is_empty());
+    }
+
+    #[test]
+    fn test_sprite_overflow_reads_the_wrong_bytes_after_eight() {
+        // With the ninth sprite off the line, the PPU takes the tenth's tile
+        // number for its Y, so eight sprites can overflow.
+        let (mut ppu, mut mapper) = crowded_line_scene();
+        ppu.oam_data[32] = 0xFF;
+        ppu.oam_data[36..40].copy_from_slice(&[0xFF, 50, 0, 0]);
+        assert_eq!(
+            overflow_lines(&mut ppu, &mut mapper),
+            (50..58).collect::<Vec<_>>()
+        );
+
+        // And the eleventh's attributes, missing a real ninth sprite there.
+        let (mut ppu, mut mapper) = crowded_line_scene();
+        ppu.oam_data[32] = 0xFF;
+        ppu.oam_data[36..40].copy_from_slice(&[0xFF, 0xFF, 0, 0]);
+        ppu.oam_data[40..44].copy_from_slice(&[50, 1, 0xFF, 0]);
+        assert!(overflow_lines(&mut ppu, &mut mapper).is_empty());
+    }
+
+    #[test]
+    fn test_nmi_line_follows_vblank_and_ctrl() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.write_to_ctrl(0x80);
+
+        run_to_dot(&mut ppu, &mut mapper, 241, 3);
+        assert!(ppu.nmi_line());
+
+        // disabling and re-enabling NMI during vblank produces a second edge
+        ppu.write_to_ctrl(0x00);
+        assert!(!ppu.nmi_line());
+        ppu.write_to_ctrl(0x80);
+        assert!(ppu.nmi_line());
+
+        ppu.read_status();
+        assert!(!ppu.nmi_line());
+
+        run_to_scanline(&mut ppu, &mut mapper, 0);
+        run_to_dot(&mut ppu, &mut mapper, 241, 3);
+        assert!(ppu.nmi_line());
+        run_to_dot(&mut ppu, &mut mapper, 261, 1);
+        assert!(!ppu.nmi_line());
+    }
+
+    #[test]
+    fn test_status_read_before_vblank_suppresses_flag_and_nmi() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.write_to_ctrl(0x80);
+
+        run_to_dot(&mut ppu, &mut mapper, 241, 0);
+        assert_eq!(ppu.read_status() & 0x80, 0);
+        ppu.clock(&mut mapper);
+        assert!(!ppu.status.is_in_vblank());
+        assert!(!nmi_before_frame_end(&mut ppu, &mut mapper));
+
+        // Only for that frame.
+        run_to_dot(&mut ppu, &mut mapper, 241, 1);
+        assert!(ppu.status.is_in_vblank());
+        assert!(nmi_before_frame_end(&mut ppu, &mut mapper));
+    }
+
+    #[test]
+    fn test_status_read_as_vblank_starts_suppresses_nmi() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        for dot in [1, 2] {
+            let mut ppu = PPU::empty();
+            ppu.write_to_ctrl(0x80);
+            run_to_dot(&mut ppu, &mut mapper, 241, dot);
+            assert!(!ppu.nmi_line());
+            assert_eq!(ppu.read_status() & 0x80, 0x80, "dot {}", dot);
+            assert!(!nmi_before_frame_end(&mut ppu, &mut mapper), "dot {}", dot);
+        }
+
+        // From the third dot the NMI is already out.
+        let mut ppu = PPU::empty();
+        ppu.write_to_ctrl(0x80);
+        run_to_dot(&mut ppu, &mut mapper, 241, 3);
+        assert!(ppu.nmi_line());
+        assert_eq!(ppu.read_status() & 0x80, 0x80);
+    }
+
+    #[test]
+    fn test_disabling_nmi_as_vblank_starts_cancels_it() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.write_to_ctrl(0x80);
+        run_to_dot(&mut ppu, &mut mapper, 241, 2);
+        ppu.write_to_ctrl(0x00);
+        ppu.clock(&mut mapper);
+        assert!(!ppu.nmi_line());
+
+        // Turning it back on while the flag is still up fires it late, but
+        // not once the flag drops on the pre-render line.
+        run_to_dot(&mut ppu, &mut mapper, 260, 340);
+        ppu.write_to_ctrl(0x80);
+        assert!(ppu.nmi_line());
+        run_to_dot(&mut ppu, &mut mapper, 261, 1);
+        assert!(!ppu.status.is_in_vblank());
+        assert!(!ppu.nmi_line());
+    }
+
+    #[test]
+    fn test_odd_ntsc_frames_skip_a_dot_while_rendering() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let frame_dots = |ppu: &mut PPU, mapper: &mut NromMapper| {
+            let mut dots = 1;
+            while !ppu.clock(mapper) {
+                dots += 1;
+            }
+            dots
+        };
+        for (region, mask, lengths) in [
+            (Region::Ntsc, 0x08, [89342, 89341, 89342]),
+            (Region::Ntsc, 0x00, [89342, 89342, 89342]),
+            (Region::Pal, 0x08, [106392, 106392, 106392]),
+        ] {
+            let mut ppu = PPU::empty();
+            ppu.set_region(region);
+            ppu.write_to_mask(mask);
+            let dots = [(); 3].map(|_| frame_dots(&mut ppu, &mut mapper));
+            assert_eq!(dots, lengths, "{:?} mask {:02X}", region, mask);
+        }
+    }
+
+    #[test]
+    fn test_pre_render_line_clears_flags_and_reloads_vertical_scroll() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        let mut ppu = PPU::empty();
+        ppu.write_to_mask(0x08);
+        run_to_dot(&mut ppu, &mut mapper, 250, 0);
+        ppu.status.set_sprite_zero_hit(true);
+        ppu.status.set_sprite_overflow(true);
+        ppu.write_to_scroll(0x00);
+        ppu.write_to_scroll(0x5B);
+        let v = ppu.scroll.v_debug();
+
+        // The flags last through vblank.
+        run_to_dot(&mut ppu, &mut mapper, 261, 0);
+        assert_eq!(ppu.status.snapshot() & 0xE0, 0xE0);
+        ppu.clock(&mut mapper);
+        assert_eq!(ppu.status.snapshot() & 0xE0, 0);
+
+        run_to_dot(&mut ppu, &mut mapper, 261, 279);
+        assert_eq!(ppu.scroll.v_debug(), v);
+        ppu.clock(&mut mapper);
+        assert_eq!(ppu.scroll.v_debug() & 0x7BE0, ppu.scroll.t_debug() & 0x7BE0);
+        assert_eq!(ppu.scroll.v_debug() & 0x7BE0, 0x3160);
+    }
+
+    #[test]
+    fn test_frame_length_follows_region() {
+        let mut mapper = NromMapper::new(vec![], vec![], Mirroring::Horizontal);
+        for (region, scanlines, vblank) in [
+            (Region::Ntsc, 262, 241),
+            (Region::Pal, 312, 241),
+            (Region::Dendy, 312, 291),
+        ] {
+            let mut ppu = PPU::empty();
+            ppu.set_region(region);
+            let mut dots = 0;
+            let mut vblank_dot = None;
+            while !ppu.clock(&mut mapper) {
+                dots += 1;
+                if vblank_dot.is_none() && ppu.status.is_in_vblank() {
+                    vblank_dot = Some(dots);
+                }
+            }
+            assert_eq!(dots + 1, scanlines * 341, "{:?}", region);
+            assert_eq!(vblank_dot, Some(vblank * 341 + 1), "{:?}", region);
+        }
+    }
+}
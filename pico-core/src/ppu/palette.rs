@@ -0,0 +1,294 @@
+use std::path::Path;
+use std::sync::LazyLock;
+
+use serde::{Deserialize, Serialize};
+
+pub static SYSTEM_PALLETE: LazyLock<[(u8, u8, u8); 64]> =
+    LazyLock::new(|| *SystemPalette::from_pal(COMPOSITE_DIRECT).unwrap().colors());
+
+const COMPOSITE_DIRECT: &[u8] = include_bytes!("../../palettes/Composite Direct (FBX).pal");
+const NES_CLASSIC: &[u8] = include_bytes!("../../palettes/NES Classic (FBX).pal");
+const SONY_CXA: &[u8] = include_bytes!("../../palettes/Sony CXA.pal");
+
+// Roughly how much an emphasis bit dims the other two channels, out of 256.
+const EMPHASIS_ATTENUATION: u16 = 191;
+
+// Applies the colour emphasis bits, red, green and blue in bits 0-2. Each one
+// darkens the channels it doesn't name, so with all three set the whole
+// picture is darker.
+pub fn emphasize(rgb: (u8, u8, u8), emphasis: u8) -> (u8, u8, u8) {
+    let mut channels = [rgb.0, rgb.1, rgb.2];
+    for bit in (0..3).filter(|bit| emphasis & (1 << bit) != 0) {
+        for (channel, value) in channels.iter_mut().enumerate() {
+            if channel != bit {
+                *value = (*value as u16 * EMPHASIS_ATTENUATION / 256) as u8;
+            }
+        }
+    }
+    (channels[0], channels[1], channels[2])
+}
+
+// Palettes built in, to pick from without a .pal file at hand.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PalettePreset {
+    #[default]
+    CompositeDirect,
+    NesClassic,
+    SonyCxa,
+    // Worked out from the PPU's video signal, emphasis included.
+    Ntsc,
+}
+
+impl PalettePreset {
+    pub const ALL: [PalettePreset; 4] = [
+        PalettePreset::CompositeDirect,
+        PalettePreset::NesClassic,
+        PalettePreset::SonyCxa,
+        PalettePreset::Ntsc,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PalettePreset::CompositeDirect => "Composite Direct",
+            PalettePreset::NesClassic => "NES Classic",
+            PalettePreset::SonyCxa => "Sony CXA",
+            PalettePreset::Ntsc => "NTSC",
+        }
+    }
+
+    pub fn palette(&self) -> SystemPalette {
+        let pal = match self {
+            PalettePreset::CompositeDirect => COMPOSITE_DIRECT,
+            PalettePreset::NesClassic => NES_CLASSIC,
+            PalettePreset::SonyCxa => SONY_CXA,
+            PalettePreset::Ntsc => return ntsc_palette(),
+        };
+        SystemPalette::from_pal(pal).unwrap()
+    }
+}
+
+// Signal levels of the PPU's composite output in volts, low and high for
+// each brightness, and the share of a colour cycle spent high.
+const NTSC_LEVELS: [[f32; 4]; 2] = [[0.350, 0.518, 0.962, 1.550], [1.094, 1.506, 1.962, 1.962]];
+const NTSC_BLACK: f32 = 0.518;
+const NTSC_WHITE: f32 = 1.962;
+const NTSC_EMPHASIS_ATTENUATION: f32 = 0.746;
+
+// Whether hue `color` is high during twelfth `phase` of a colour cycle.
+fn in_color_phase(color: usize, phase: usize) -> bool {
+    (color + phase) % 12 < 6
+}
+
+// Decodes the PPU's square wave for each colour and emphasis the way a TV
+// does, as YIQ over one colour cycle, following
+// https://www.nesdev.org/wiki/NTSC_video
+fn ntsc_palette() -> SystemPalette {
+    let mut table = Vec::with_capacity(512);
+    for entry in 0..512 {
+        let color = entry & 0x0F;
+        let emphasis = entry >> 6;
+        // Columns $E and $F are black at every brightness.
+        let level = if color > 0x0D { 1 } else { (entry >> 4) & 3 };
+        let low = NTSC_LEVELS[(color == 0) as usize][level];
+        let high = NTSC_LEVELS[(color < 0x0D) as usize][level];
+
+        let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+        for phase in 0..12 {
+            let mut signal = if in_color_phase(color, phase) {
+                high
+            } else {
+                low
+            };
+            let dimmed = [0x0C, 0x04, 0x08]
+                .iter()
+                .enumerate()
+                .any(|(bit, &hue)| emphasis & (1 << bit) != 0 && in_color_phase(hue, phase));
+            if dimmed {
+                signal *= NTSC_EMPHASIS_ATTENUATION;
+            }
+            let value = (signal - NTSC_BLACK) / (NTSC_WHITE - NTSC_BLACK);
+            let angle = std::f32::consts::PI * (phase + 4) as f32 / 6.0;
+            y += value / 12.0;
+            i += value * angle.cos() / 12.0;
+            q += value * angle.sin() / 12.0;
+        }
+
+        let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        table.push((
+            channel(y + 0.946882 * i + 0.623557 * q),
+            channel(y - 0.274788 * i - 0.635691 * q),
+            channel(y - 1.108545 * i + 1.709007 * q),
+        ));
+    }
+    SystemPalette::with_emphasis(table)
+}
+
+// The 64 colors the PPU's color indices map to. Each PPU owns one so it can be
+// edited while a game runs, e.g. to match captures from real hardware.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SystemPalette {
+    colors: [(u8, u8, u8); 64],
+    // All 512 colours for palettes that say how each emphasis looks, indexed
+    // by emphasis times 64 plus colour. Without it emphasis is approximated.
+    emphasized: Option<Vec<(u8, u8, u8)>>,
+}
+
+impl Default for SystemPalette {
+    fn default() -> Self {
+        SystemPalette {
+            colors: *SYSTEM_PALLETE,
+            emphasized: None,
+        }
+    }
+}
+
+impl SystemPalette {
+    fn with_emphasis(table: Vec<(u8, u8, u8)>) -> SystemPalette {
+        SystemPalette {
+            colors: table[..64].try_into().unwrap(),
+            emphasized: Some(table),
+        }
+    }
+
+    // A .pal file is 64 RGB triples, or 512 with a set of 64 for each
+    // combination of emphasis bits. Files of other multiples of 64 colors
+    // only have the first 64 used.
+    pub fn from_pal(bytes: &[u8]) -> Result<SystemPalette, String> {
+        if bytes.len() < 64 * 3 || !bytes.len().is_multiple_of(64 * 3) {
+            return Err(format!(
+                "Palette is {} bytes, expected a multiple of 192",
+                bytes.len()
+            ));
+        }
+        let table: Vec<(u8, u8, u8)> = bytes
+            .chunks(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect();
+        if table.len() == 512 {
+            return Ok(SystemPalette::with_emphasis(table));
+        }
+        Ok(SystemPalette {
+            colors: table[..64].try_into().unwrap(),
+            emphasized: None,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<SystemPalette, String> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("Failed to read palette {}: {}", path.display(), e))?;
+        SystemPalette::from_pal(&bytes)
+    }
+
+    pub fn to_pal(&self) -> Vec<u8> {
+        self.emphasized
+            .as_deref()
+            .unwrap_or(&self.colors)
+            .iter()
+            .flat_map(|&(r, g, b)| [r, g, b])
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_pal())
+            .map_err(|e| format!("Failed to write palette {}: {}", path.display(), e))
+    }
+
+    pub fn color(&self, index: u8) -> (u8, u8, u8) {
+        self.colors[index as usize & 0x3f]
+    }
+
+    // `emphasis` holds red, green and blue in bits 0-2, as `PPU::emphasis`
+    // gives them.
+    pub fn emphasized_color(&self, index: u8, emphasis: u8) -> (u8, u8, u8) {
+        match (&self.emphasized, emphasis & 7) {
+            (_, 0) => self.color(index),
+            (Some(table), emphasis) => table[emphasis as usize * 64 + (index as usize & 0x3f)],
+            (None, emphasis) => emphasize(self.color(index), emphasis),
+        }
+    }
+
+    // Only the colour without emphasis changes; the emphasized ones of a
+    // 512-colour palette keep their own.
+    pub fn set_color(&mut self, index: u8, rgb: (u8, u8, u8)) {
+        self.colors[index as usize & 0x3f] = rgb;
+        if let Some(table) = &mut self.emphasized {
+            table[index as usize & 0x3f] = rgb;
+        }
+    }
+
+    pub fn colors(&self) -> &[(u8, u8, u8); 64] {
+        &self.colors
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_edited_palette_round_trips_through_pal() {
+        let mut palette = SystemPalette::default();
+        palette.set_color(0x2A, (1, 2, 3));
+        palette.set_color(0x40 | 0x0F, (4, 5, 6));
+
+        let pal = palette.to_pal();
+        assert_eq!(pal.len(), 192);
+        assert_eq!(&pal[0x2A * 3..0x2A * 3 + 3], &[1, 2, 3]);
+
+        let loaded = SystemPalette::from_pal(&pal).unwrap();
+        assert_eq!(loaded, palette);
+        assert_eq!(loaded.color(0x0F), (4, 5, 6));
+        assert_eq!(loaded.color(0x00), SYSTEM_PALLETE[0]);
+
+        assert!(SystemPalette::from_pal(&pal[..190]).is_err());
+        assert!(SystemPalette::from_pal(&pal.repeat(8)).is_ok());
+    }
+
+    #[test]
+    fn test_512_color_palettes_keep_their_emphasis() {
+        let mut pal = SystemPalette::default().to_pal().repeat(8);
+        pal[(3 * 64 + 0x30) * 3..][..3].copy_from_slice(&[1, 2, 3]);
+        let palette = SystemPalette::from_pal(&pal).unwrap();
+        assert_eq!(palette.to_pal(), pal);
+        assert_eq!(palette.emphasized_color(0x30, 0), SYSTEM_PALLETE[0x30]);
+        assert_eq!(palette.emphasized_color(0x30, 3), (1, 2, 3));
+        assert_eq!(palette.emphasized_color(0x30, 4), SYSTEM_PALLETE[0x30]);
+
+        // Smaller files get the approximation.
+        let palette = SystemPalette::default();
+        assert_eq!(
+            palette.emphasized_color(0x30, 3),
+            emphasize(SYSTEM_PALLETE[0x30], 3)
+        );
+    }
+
+    #[test]
+    fn test_ntsc_preset_has_the_right_hues() {
+        let palette = PalettePreset::Ntsc.palette();
+        let (r, g, b) = palette.color(0x16);
+        assert!(r > g && r > b, "red {:?}", (r, g, b));
+        let (r, g, b) = palette.color(0x1A);
+        assert!(g > r && g > b, "green {:?}", (r, g, b));
+        let (r, g, b) = palette.color(0x12);
+        assert!(b > r && b > g, "blue {:?}", (r, g, b));
+        assert_eq!(palette.color(0x0F), (0, 0, 0));
+        assert_eq!(palette.color(0x30), (255, 255, 255));
+
+        // Red emphasis leaves white's red alone and dims the rest.
+        let (r, g, b) = palette.emphasized_color(0x30, 1);
+        assert!(r > g && r > b, "{:?}", (r, g, b));
+        for preset in PalettePreset::ALL {
+            assert_eq!(preset.palette().to_pal().len() % 192, 0);
+        }
+    }
+
+    #[test]
+    fn test_emphasis_dims_the_other_channels() {
+        let white = (200, 200, 200);
+        assert_eq!(emphasize(white, 0b000), white);
+        assert_eq!(emphasize(white, 0b001), (200, 149, 149));
+        assert_eq!(emphasize(white, 0b110), (111, 149, 149));
+        assert_eq!(emphasize(white, 0b111), (111, 111, 111));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
 pub struct AddrRegister {
     value: (u8, u8),
     hi_ptr: bool,
@@ -56,3 +58,16 @@ impl AddrRegister {
         ((self.value.0 as u16) << 8) | (self.value.1 as u16)
     }
 }
+
+impl Savestate for AddrRegister {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.get());
+        state.write_bool(self.hi_ptr);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.set(state.read_u16()?);
+        self.hi_ptr = state.read_bool()?;
+        Ok(())
+    }
+}
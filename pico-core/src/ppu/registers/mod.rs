@@ -0,0 +1,107 @@
+pub mod addr;
+pub mod control;
+pub mod mask;
+pub mod scroll;
+pub mod status;
+
+use crate::mapper::Mapper;
+use crate::ppu::PPU;
+use control::ControlRegister;
+use mask::MaskRegister;
+use status::StatusRegister;
+
+// The PPU as the CPU sees it through $2000-$2007, for tools and tests that
+// want to drive it without reaching into its fields. Every call has the same
+// side effects as the matching register access on hardware; the `peek_`
+// methods have none.
+pub struct PpuRegisters<'a> {
+    ppu: &'a mut PPU,
+    mapper: &'a mut dyn Mapper,
+}
+
+impl<'a> PpuRegisters<'a> {
+    pub fn new(ppu: &'a mut PPU, mapper: &'a mut dyn Mapper) -> Self {
+        PpuRegisters { ppu, mapper }
+    }
+
+    // $2000. Also sets the nametable bits of the scroll address. The /NMI
+    // output follows the new enable bit straight away, even mid-vblank.
+    pub fn write_ctrl(&mut self, ctrl: ControlRegister) {
+        self.ppu.write_to_ctrl(ctrl.bits());
+    }
+
+    // $2001.
+    pub fn write_mask(&mut self, mask: MaskRegister) {
+        self.ppu.write_to_mask(mask.bits());
+    }
+
+    // $2002. Clears the vblank flag and resets the $2005/$2006 write latch.
+    pub fn read_status(&mut self) -> StatusRegister {
+        StatusRegister::from_bits_truncate(self.ppu.read_status())
+    }
+
+    // $2003.
+    pub fn write_oam_addr(&mut self, addr: u8) {
+        self.ppu.write_to_oam_addr(addr);
+    }
+
+    // $2004. Advances the OAM address.
+    pub fn write_oam_data(&mut self, value: u8) {
+        self.ppu.write_to_oam_data(value);
+    }
+
+    // $2004. Leaves the OAM address alone.
+    pub fn read_oam_data(&mut self) -> u8 {
+        self.ppu.read_oam_data()
+    }
+
+    // Both $2005 writes, horizontal then vertical. Resets the write latch
+    // first so a stray earlier write can't swap them.
+    pub fn write_scroll(&mut self, x: u8, y: u8) {
+        self.reset_latch();
+        self.ppu.write_to_scroll(x);
+        self.ppu.write_to_scroll(y);
+    }
+
+    // Both $2006 writes, high byte then low. Resets the write latch first and
+    // replaces the scroll position, as on hardware.
+    pub fn write_addr(&mut self, addr: u16) {
+        self.reset_latch();
+        self.ppu.write_to_ppu_addr((addr >> 8) as u8);
+        self.ppu.write_to_ppu_addr(addr as u8);
+    }
+
+    // $2007. Advances the VRAM address by 1 or 32 depending on $2000.
+    pub fn write_data(&mut self, value: u8) {
+        self.ppu.write_to_data(self.mapper, value);
+    }
+
+    // $2007. Advances the VRAM address. Below $3F00 this returns the byte
+    // buffered by the previous read and buffers the new one; palette reads
+    // come back immediately.
+    pub fn read_data(&mut self) -> u8 {
+        self.ppu.read_data(self.mapper)
+    }
+
+    fn reset_latch(&mut self) {
+        self.ppu.addr.reset_latch();
+        self.ppu.scroll.reset_latch();
+    }
+
+    pub fn peek_ctrl(&self) -> ControlRegister {
+        ControlRegister::from_bits_truncate(self.ppu.ctrl.bits())
+    }
+
+    pub fn peek_mask(&self) -> MaskRegister {
+        MaskRegister::from_bits_truncate(self.ppu.mask.bits())
+    }
+
+    pub fn peek_status(&self) -> StatusRegister {
+        StatusRegister::from_bits_truncate(self.ppu.status.bits())
+    }
+
+    // Where the next $2007 access will go.
+    pub fn peek_addr(&self) -> u16 {
+        self.ppu.scroll.addr()
+    }
+}
@@ -1,3 +1,5 @@
+use crate::savestate::{Savestate, StateReader, StateWriter};
+
 #[derive(Clone, Debug)]
 pub struct ScrollRegister {
     v: u16,
@@ -147,3 +149,20 @@ impl ScrollRegister {
         self.w
     }
 }
+
+impl Savestate for ScrollRegister {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.v);
+        state.write_u16(self.t);
+        state.write_u8(self.x);
+        state.write_bool(self.w);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.v = state.read_u16()?;
+        self.t = state.read_u16()?;
+        self.x = state.read_u8()?;
+        self.w = state.read_bool()?;
+        Ok(())
+    }
+}
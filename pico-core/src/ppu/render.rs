@@ -0,0 +1,672 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mapper::{ChrSource, Mapper},
+    ppu::framebuffer::Framebuffer,
+    ppu::{PPU, ScrollSegment},
+};
+
+const SPRITES_PER_LINE: u8 = 8;
+
+// How the background is put together at the end of a frame. Both draw from
+// the scroll changes recorded while the frame ran; they differ in the order
+// pattern data is fetched and in how much of the picture one scroll state
+// covers, so a game can look right on one and not yet on the other.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Renderer {
+    // Each run of scanlines sharing a scroll is drawn as whole nametables,
+    // clipped to those lines. Fast, and fine for status bars and splits.
+    #[default]
+    ScrollSegments,
+    // Each scanline is drawn on its own, fetching tiles left to right the way
+    // the PPU does, which mid-frame CHR switching relies on.
+    Scanline,
+}
+
+impl Renderer {
+    pub const ALL: [Renderer; 2] = [Renderer::ScrollSegments, Renderer::Scanline];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Renderer::ScrollSegments => "scroll_segments",
+            Renderer::Scanline => "scanline",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Renderer> {
+        Renderer::ALL
+            .into_iter()
+            .find(|renderer| renderer.name() == name)
+    }
+}
+
+struct Rect {
+    x1: usize,
+    y1: usize,
+    x2: usize,
+    y2: usize,
+}
+
+impl Rect {
+    fn new(x1: usize, y1: usize, x2: usize, y2: usize) -> Self {
+        Rect { x1, y1, x2, y2 }
+    }
+}
+
+pub(super) fn system_palette_color(ppu: &PPU, color_index: u8) -> (u8, u8, u8) {
+    let mut idx = color_index & 0x3f;
+    if ppu.mask.is_grayscale() {
+        idx &= 0x30;
+    }
+    ppu.system_palette.emphasized_color(idx, ppu.emphasis())
+}
+
+pub(super) fn bg_palette(
+    ppu: &PPU,
+    mapper: &dyn Mapper,
+    nametable_index: usize,
+    tile_column: usize,
+    tile_row: usize,
+) -> [u8; 4] {
+    if let Some(override_idx) =
+        mapper.background_palette_override(nametable_index, tile_column, tile_row)
+    {
+        let pallete_start: usize = 1 + (override_idx as usize) * 4;
+        return [
+            ppu.palette_table[0],
+            ppu.palette_table[pallete_start],
+            ppu.palette_table[pallete_start + 1],
+            ppu.palette_table[pallete_start + 2],
+        ];
+    }
+
+    let attr_byte = ppu.read_attribute_entry(mapper, nametable_index, tile_column, tile_row);
+
+    let pallet_idx = match (tile_column % 4 / 2, tile_row % 4 / 2) {
+        (0, 0) => attr_byte & 0b11,
+        (1, 0) => (attr_byte >> 2) & 0b11,
+        (0, 1) => (attr_byte >> 4) & 0b11,
+        (1, 1) => (attr_byte >> 6) & 0b11,
+        (_, _) => panic!("should not happen"),
+    };
+
+    let pallete_start: usize = 1 + (pallet_idx as usize) * 4;
+    [
+        ppu.palette_table[0],
+        ppu.palette_table[pallete_start],
+        ppu.palette_table[pallete_start + 1],
+        ppu.palette_table[pallete_start + 2],
+    ]
+}
+
+fn sprite_palette(ppu: &PPU, pallete_idx: u8) -> [u8; 4] {
+    let start = 0x11 + (pallete_idx * 4) as usize;
+    [
+        0,
+        ppu.palette_table[start],
+        ppu.palette_table[start + 1],
+        ppu.palette_table[start + 2],
+    ]
+}
+
+// Pixel values (0-3) of a tile, row by row.
+type DecodedTile = [u8; 64];
+
+fn decode_tile(tile: &[u8; 16]) -> DecodedTile {
+    std::array::from_fn(|i| {
+        let (row, bit) = (i / 8, 7 - i % 8);
+        ((tile[row + 8] >> bit) & 1) << 1 | ((tile[row] >> bit) & 1)
+    })
+}
+
+// Background tiles decoded over one frame's drawing, which fetches most of
+// them many times. They are kept by where they sit in CHR, 4 KiB bank by 4 KiB
+// bank, so a latch that switches banks between fetches finds a different
+// tile. Boards without a `chr_index` are read every time.
+#[derive(Default)]
+struct TileCache {
+    banks: Vec<Option<Box<[Option<DecodedTile>; 256]>>>,
+}
+
+impl TileCache {
+    fn tile(&mut self, mapper: &dyn Mapper, addr: u16, source: ChrSource) -> DecodedTile {
+        let Some(index) = mapper.chr_index(addr).filter(|index| index % 16 == 0) else {
+            return decode_tile(&mapper.read_chr_tile(addr, source));
+        };
+        let bank = index / 0x1000;
+        if self.banks.len() <= bank {
+            self.banks.resize_with(bank + 1, || None);
+        }
+        let tiles = self.banks[bank].get_or_insert_with(|| Box::new([None; 256]));
+        *tiles[index / 16 % 256]
+            .get_or_insert_with(|| decode_tile(&mapper.read_chr_tile(addr, source)))
+    }
+}
+
+// What one scroll segment's background is drawn into, and the scanlines it
+// covers there.
+struct SegmentTarget<'a> {
+    frame: &'a mut Framebuffer,
+    bg_priority: &'a mut [u8],
+    tiles: &'a mut TileCache,
+    clip_y: (usize, usize),
+}
+
+fn render_nametable(
+    ppu: &PPU,
+    mapper: &mut dyn Mapper,
+    target: &mut SegmentTarget,
+    nametable_index: usize,
+    viewport: Rect,
+    shift_x: isize,
+    shift_y: isize,
+) {
+    if !ppu.mask.show_background() {
+        return;
+    }
+
+    for i in 0..0x3c0 {
+        let tile_column = i % 32;
+        let tile_row = i / 32;
+        let tile_idx =
+            ppu.read_nametable_entry(mapper, nametable_index, tile_column, tile_row) as u16;
+        let pattern_addr = ppu.ctrl.bknd_pattern_addr() + tile_idx * 16;
+        let tile = if let Some(override_tile) = mapper.background_tile_override(
+            nametable_index,
+            tile_column,
+            tile_row,
+            tile_idx as u8,
+            pattern_addr,
+        ) {
+            decode_tile(&override_tile)
+        } else {
+            let tile = target
+                .tiles
+                .tile(mapper, pattern_addr, ChrSource::Background);
+            // Tiles are drawn in the order the PPU fetches them on an unscrolled
+            // screen, so latching boards see the same sequence.
+            mapper.notify_chr_fetch(pattern_addr + 8);
+            tile
+        };
+        let palette = bg_palette(ppu, mapper, nametable_index, tile_column, tile_row);
+
+        for y in 0..=7 {
+            for x in 0..=7 {
+                let value = tile[y * 8 + x];
+                let pixel_x = tile_column * 8 + x;
+                let pixel_y = tile_row * 8 + y;
+
+                if pixel_x >= viewport.x1
+                    && pixel_x < viewport.x2
+                    && pixel_y >= viewport.y1
+                    && pixel_y < viewport.y2
+                {
+                    let target_x = shift_x + pixel_x as isize;
+                    let target_y = shift_y + pixel_y as isize;
+
+                    if target_x < 0
+                        || target_x >= Framebuffer::WIDTH as isize
+                        || target_y < 0
+                        || target_y >= Framebuffer::HEIGHT as isize
+                    {
+                        continue;
+                    }
+
+                    let (clip_start, clip_end) = target.clip_y;
+                    if target_y < clip_start as isize || target_y >= clip_end as isize {
+                        continue;
+                    }
+
+                    if !ppu.mask.leftmost_8pxl_background() && target_x < 8 {
+                        continue;
+                    }
+
+                    let palette_index = match value {
+                        0 => ppu.palette_table[0],
+                        1 => palette[1],
+                        2 => palette[2],
+                        3 => palette[3],
+                        _ => unreachable!(),
+                    };
+
+                    let rgb = system_palette_color(ppu, palette_index);
+
+                    target
+                        .frame
+                        .set_pixel(target_x as usize, target_y as usize, rgb);
+                    target.bg_priority
+                        [target_y as usize * Framebuffer::WIDTH + target_x as usize] = value;
+                }
+            }
+        }
+    }
+}
+
+fn render_sprites(ppu: &PPU, mapper: &mut dyn Mapper, frame: &mut Framebuffer, bg_priority: &[u8]) {
+    if !ppu.mask.show_sprites() {
+        return;
+    }
+
+    let segments = ppu.render_oam();
+    let sprite_height = ppu.ctrl.sprite_size() as usize;
+    // Pixels some sprite has already claimed. The lowest numbered sprite with
+    // an opaque pixel wins it even when that sprite is behind the background,
+    // so a later sprite in front doesn't show through there.
+    let mut claimed = vec![false; Framebuffer::WIDTH * Framebuffer::HEIGHT];
+    // Sprites on each line so far. Past eight the PPU has no room for more.
+    let mut line_sprites = [0u8; Framebuffer::HEIGHT];
+
+    // Each segment draws its sprites only on the lines it covers.
+    for (idx, segment) in segments.iter().enumerate() {
+        let oam = &segment.oam;
+        let next = segments.get(idx + 1);
+        let end = next.map_or(Framebuffer::HEIGHT, |next| next.start_scanline);
+        let lines = segment.start_scanline as isize..end as isize;
+        for i in (0..oam.len()).step_by(4) {
+            let sprite_y = (oam[i] as u16 + 1) as isize;
+            if sprite_y >= lines.end || sprite_y + sprite_height as isize <= lines.start {
+                continue;
+            }
+
+            let sprite_x = oam[i + 3] as isize;
+            let tile_idx = oam[i + 1] as u16;
+            let attributes = oam[i + 2];
+
+            let priority_behind_bg = attributes & 0x20 != 0;
+            let flip_horizontal = attributes & 0x40 != 0;
+            let flip_vertical = attributes & 0x80 != 0;
+            let pallette_idx = attributes & 0b11;
+            let sprite_palette = sprite_palette(ppu, pallette_idx);
+
+            let mut tile = [0u8; 32];
+            if sprite_height == 16 {
+                let base_tile = tile_idx & 0xFE;
+                let bank = (tile_idx & 0x01) * 0x1000;
+                for half in 0..2 {
+                    let addr = bank + (base_tile + half as u16) * 16;
+                    tile[half * 16..half * 16 + 16]
+                        .copy_from_slice(&mapper.read_chr_tile(addr, ChrSource::Sprite));
+                    mapper.notify_chr_fetch(addr + 8);
+                }
+            } else {
+                let addr = ppu.ctrl.sprt_pattern_addr() + tile_idx * 16;
+                tile[..16].copy_from_slice(&mapper.read_chr_tile(addr, ChrSource::Sprite));
+                mapper.notify_chr_fetch(addr + 8);
+            }
+
+            for row in 0..sprite_height {
+                let target_y = sprite_y + row as isize;
+                if !lines.contains(&target_y) {
+                    continue;
+                }
+                let on_line = &mut line_sprites[target_y as usize];
+                if ppu.sprite_limit && *on_line >= SPRITES_PER_LINE {
+                    continue;
+                }
+                *on_line += 1;
+
+                let source_row = if flip_vertical {
+                    sprite_height - 1 - row
+                } else {
+                    row
+                };
+
+                let chunk = (source_row / 8) * 16;
+                let plane0 = tile[chunk + (source_row % 8)];
+                let plane1 = tile[chunk + (source_row % 8) + 8];
+
+                for col in 0..8 {
+                    let bit = if flip_horizontal { col } else { 7 - col };
+                    let value = ((plane1 >> bit) & 1) << 1 | ((plane0 >> bit) & 1);
+                    if value == 0 {
+                        continue;
+                    }
+
+                    let target_x = sprite_x + col as isize;
+
+                    if target_x < 0 || target_x >= Framebuffer::WIDTH as isize {
+                        continue;
+                    }
+
+                    if !ppu.mask.leftmost_8pxl_sprite() && target_x < 8 {
+                        continue;
+                    }
+
+                    let buffer_idx = target_y as usize * Framebuffer::WIDTH + target_x as usize;
+                    if claimed[buffer_idx] {
+                        continue;
+                    }
+                    claimed[buffer_idx] = true;
+                    if priority_behind_bg && bg_priority[buffer_idx] != 0 {
+                        continue;
+                    }
+
+                    let palette_index = sprite_palette[value as usize];
+                    let rgb = system_palette_color(ppu, palette_index);
+                    frame.set_pixel(target_x as usize, target_y as usize, rgb);
+                }
+            }
+        }
+    }
+}
+
+// Two bitplanes of one row of a sprite tile, honoring 8x16 sprites and
+// vertical flip. `row` counts from the top of the sprite as drawn.
+fn sprite_row(
+    ppu: &PPU,
+    mapper: &dyn Mapper,
+    tile_idx: u8,
+    attributes: u8,
+    row: usize,
+) -> (u8, u8) {
+    let sprite_height = ppu.ctrl.sprite_size() as usize;
+    let row = if attributes & 0x80 != 0 {
+        sprite_height - 1 - row
+    } else {
+        row
+    };
+    let addr = if sprite_height == 16 {
+        let bank = (tile_idx as u16 & 0x01) * 0x1000;
+        let tile = (tile_idx as u16 & 0xFE) + (row / 8) as u16;
+        bank + tile * 16 + (row % 8) as u16
+    } else {
+        ppu.ctrl.sprt_pattern_addr() + tile_idx as u16 * 16 + row as u16
+    };
+    (
+        mapper.read_chr(addr, ChrSource::Sprite),
+        mapper.read_chr(addr + 8, ChrSource::Sprite),
+    )
+}
+
+// Background pixel value (0-3) at a screen position under the current scroll,
+// mapped onto the four nametables the same way `render` draws them.
+fn background_pixel(ppu: &PPU, mapper: &dyn Mapper, x: usize, y: usize) -> u8 {
+    let world_x = x + ppu.scroll.scroll_x();
+    let world_y = y + ppu.scroll.scroll_y();
+    let mut nametable_index = ppu.scroll.base_nametable() & 0x03;
+    if world_x >= 256 {
+        nametable_index ^= 0x01;
+    }
+    if world_y % 480 >= 240 {
+        nametable_index ^= 0x02;
+    }
+    let (pixel_x, pixel_y) = (world_x % 256, world_y % 240);
+    let (tile_column, tile_row) = (pixel_x / 8, pixel_y / 8);
+
+    let tile_idx = ppu.read_nametable_entry(mapper, nametable_index, tile_column, tile_row) as u16;
+    let pattern_addr = ppu.ctrl.bknd_pattern_addr() + tile_idx * 16;
+    let fine_y = pixel_y % 8;
+    let (plane0, plane1) = match mapper.background_tile_override(
+        nametable_index,
+        tile_column,
+        tile_row,
+        tile_idx as u8,
+        pattern_addr,
+    ) {
+        Some(tile) => (tile[fine_y], tile[fine_y + 8]),
+        None => (
+            mapper.read_chr(pattern_addr + fine_y as u16, ChrSource::Background),
+            mapper.read_chr(pattern_addr + fine_y as u16 + 8, ChrSource::Background),
+        ),
+    };
+    let bit = 7 - pixel_x % 8;
+    ((plane1 >> bit) & 1) << 1 | ((plane0 >> bit) & 1)
+}
+
+// First x on `scanline` where an opaque pixel of sprite 0 lands on an opaque
+// background pixel, which is where the PPU raises the sprite 0 hit flag. Hits
+// need both layers enabled, never happen at x=255, and not in the leftmost
+// 8 pixels while either layer is clipped there.
+pub(super) fn sprite_zero_hit_x(ppu: &PPU, mapper: &dyn Mapper, scanline: usize) -> Option<usize> {
+    if !ppu.mask.show_background() || !ppu.mask.show_sprites() {
+        return None;
+    }
+
+    let sprite_top = ppu.oam_data[0] as usize + 1;
+    let row = scanline.checked_sub(sprite_top)?;
+    if row >= ppu.ctrl.sprite_size() as usize {
+        return None;
+    }
+
+    let attributes = ppu.oam_data[2];
+    let (plane0, plane1) = sprite_row(ppu, mapper, ppu.oam_data[1], attributes, row);
+    let left_clipped = !ppu.mask.leftmost_8pxl_background() || !ppu.mask.leftmost_8pxl_sprite();
+
+    (0..8).find_map(|col| {
+        let x = ppu.oam_data[3] as usize + col;
+        let bit = if attributes & 0x40 != 0 { col } else { 7 - col };
+        let opaque = ((plane1 >> bit) | (plane0 >> bit)) & 1 != 0;
+        let visible = x < 255 && !(left_clipped && x < 8);
+        (opaque && visible && background_pixel(ppu, mapper, x, scanline) != 0).then_some(x)
+    })
+}
+
+// Whether evaluating sprites for the line after `scanline` sets the overflow
+// flag. Once eight sprites are found the PPU keeps looking for a ninth, but
+// a bug makes it step through the bytes of each entry as if they were Y
+// coordinates, so it both misses sprites that are there and finds some that
+// aren't.
+pub(super) fn sprite_overflow(ppu: &PPU, scanline: usize) -> bool {
+    let height = ppu.ctrl.sprite_size() as usize;
+    let in_range = |y: u8| scanline.wrapping_sub(y as usize) < height;
+    let oam = &ppu.oam_data;
+
+    let mut n = 0;
+    let mut found = 0;
+    while n < 64 && found < SPRITES_PER_LINE {
+        found += in_range(oam[n * 4]) as u8;
+        n += 1;
+    }
+    let mut m = 0;
+    while n < 64 {
+        if in_range(oam[n * 4 + m]) {
+            return true;
+        }
+        n += 1;
+        m = (m + 1) % 4;
+    }
+    false
+}
+
+pub fn render(ppu: &PPU, mapper: &mut dyn Mapper, frame: &mut Framebuffer) {
+    let universal_color = system_palette_color(ppu, ppu.palette_table[0]);
+    for chunk in frame.data.chunks_mut(3) {
+        chunk[0] = universal_color.0;
+        chunk[1] = universal_color.1;
+        chunk[2] = universal_color.2;
+    }
+
+    let mut bg_priority = vec![0u8; Framebuffer::WIDTH * Framebuffer::HEIGHT];
+    if ppu.mask.show_background() {
+        match ppu.renderer {
+            Renderer::ScrollSegments => {
+                render_scroll_segments(ppu, mapper, frame, &mut bg_priority)
+            }
+            Renderer::Scanline => render_scanlines(ppu, mapper, frame, &mut bg_priority),
+        }
+    }
+
+    render_sprites(ppu, mapper, frame, &bg_priority);
+}
+
+fn render_scroll_segments(
+    ppu: &PPU,
+    mapper: &mut dyn Mapper,
+    frame: &mut Framebuffer,
+    bg_priority: &mut [u8],
+) {
+    let scroll_segments = ppu.scroll_segments();
+    let mut tiles = TileCache::default();
+    for (idx, segment) in scroll_segments.iter().enumerate() {
+        let clip_start = segment.start_scanline.min(Framebuffer::HEIGHT);
+        let clip_end = scroll_segments
+            .get(idx + 1)
+            .map(|next| next.start_scanline.min(Framebuffer::HEIGHT))
+            .unwrap_or(Framebuffer::HEIGHT);
+
+        if clip_start >= clip_end {
+            continue;
+        }
+
+        let scroll_x_full = segment.scroll_x;
+        let scroll_y_full = segment.scroll_y;
+
+        let scroll_x = scroll_x_full % 256;
+        let scroll_y = scroll_y_full % 240;
+
+        let h_offset = (scroll_x_full / 256) & 0x01;
+        let v_offset = (scroll_y_full / 240) & 0x01;
+
+        let base_nametable = segment.base_nametable & 0x03;
+        let active_base = (base_nametable ^ (h_offset) ^ (v_offset << 1)) & 0x03;
+        let horizontal_index = (active_base ^ 0x01) & 0x03;
+        let vertical_index = (active_base ^ 0x02) & 0x03;
+        let diagonal_index = (active_base ^ 0x03) & 0x03;
+
+        let base_shift_x = -(scroll_x as isize);
+        let base_shift_y = -(scroll_y as isize);
+        let mut target = SegmentTarget {
+            frame,
+            bg_priority,
+            tiles: &mut tiles,
+            clip_y: (clip_start, clip_end),
+        };
+
+        render_nametable(
+            ppu,
+            mapper,
+            &mut target,
+            active_base,
+            Rect::new(scroll_x, scroll_y, 256, 240),
+            base_shift_x,
+            base_shift_y,
+        );
+
+        if scroll_x > 0 {
+            render_nametable(
+                ppu,
+                mapper,
+                &mut target,
+                horizontal_index,
+                Rect::new(0, scroll_y, scroll_x, 240),
+                base_shift_x + Framebuffer::WIDTH as isize,
+                base_shift_y,
+            );
+        }
+
+        if scroll_y > 0 {
+            render_nametable(
+                ppu,
+                mapper,
+                &mut target,
+                vertical_index,
+                Rect::new(scroll_x, 0, 256, scroll_y),
+                base_shift_x,
+                base_shift_y + Framebuffer::HEIGHT as isize,
+            );
+        }
+
+        if scroll_x > 0 && scroll_y > 0 {
+            render_nametable(
+                ppu,
+                mapper,
+                &mut target,
+                diagonal_index,
+                Rect::new(0, 0, scroll_x, scroll_y),
+                base_shift_x + Framebuffer::WIDTH as isize,
+                base_shift_y + Framebuffer::HEIGHT as isize,
+            );
+        }
+    }
+}
+
+fn render_scanlines(
+    ppu: &PPU,
+    mapper: &mut dyn Mapper,
+    frame: &mut Framebuffer,
+    bg_priority: &mut [u8],
+) {
+    let scroll_segments = ppu.scroll_segments();
+    let mut segment = 0;
+    for y in 0..Framebuffer::HEIGHT {
+        while scroll_segments
+            .get(segment + 1)
+            .is_some_and(|next| next.start_scanline <= y)
+        {
+            segment += 1;
+        }
+        if let Some(scroll) = scroll_segments.get(segment) {
+            render_background_line(ppu, mapper, frame, bg_priority, y, scroll);
+        }
+    }
+}
+
+// One line of background under `scroll`, fetched tile by tile from the left
+// like the PPU's 33 fetches per line, fine X scroll included.
+fn render_background_line(
+    ppu: &PPU,
+    mapper: &mut dyn Mapper,
+    frame: &mut Framebuffer,
+    bg_priority: &mut [u8],
+    y: usize,
+    scroll: &ScrollSegment,
+) {
+    let world_y = y + scroll.scroll_y;
+    let mut base_nametable = scroll.base_nametable & 0x03;
+    if world_y % 480 >= 240 {
+        base_nametable ^= 0x02;
+    }
+    let pixel_y = world_y % 240;
+    let (tile_row, fine_y) = (pixel_y / 8, pixel_y % 8);
+    let fine_x = scroll.scroll_x % 8;
+
+    for fetch in 0..33 {
+        let world_x = scroll.scroll_x - fine_x + fetch * 8;
+        let nametable_index = if world_x % 512 >= 256 {
+            base_nametable ^ 0x01
+        } else {
+            base_nametable
+        };
+        let tile_column = world_x % 256 / 8;
+        let tile_idx =
+            ppu.read_nametable_entry(mapper, nametable_index, tile_column, tile_row) as u16;
+        let pattern_addr = ppu.ctrl.bknd_pattern_addr() + tile_idx * 16;
+        let (plane0, plane1) = match mapper.background_tile_override(
+            nametable_index,
+            tile_column,
+            tile_row,
+            tile_idx as u8,
+            pattern_addr,
+        ) {
+            Some(tile) => (tile[fine_y], tile[fine_y + 8]),
+            None => {
+                let row_addr = pattern_addr + fine_y as u16;
+                let planes = (
+                    mapper.read_chr(row_addr, ChrSource::Background),
+                    mapper.read_chr(row_addr + 8, ChrSource::Background),
+                );
+                mapper.notify_chr_fetch(row_addr + 8);
+                planes
+            }
+        };
+        let palette = bg_palette(ppu, mapper, nametable_index, tile_column, tile_row);
+
+        for col in 0..8 {
+            let Some(x) = (fetch * 8 + col).checked_sub(fine_x) else {
+                continue;
+            };
+            if x >= Framebuffer::WIDTH || (!ppu.mask.leftmost_8pxl_background() && x < 8) {
+                continue;
+            }
+            let bit = 7 - col;
+            let value = ((plane1 >> bit) & 1) << 1 | ((plane0 >> bit) & 1);
+            let palette_index = match value {
+                0 => ppu.palette_table[0],
+                _ => palette[value as usize],
+            };
+            frame.set_pixel(x, y, system_palette_color(ppu, palette_index));
+            bg_priority[y * Framebuffer::WIDTH + x] = value;
+        }
+    }
+}
@@ -0,0 +1,159 @@
+// The parts of the PPU games rewrite every frame, small enough to keep one
+// per frame, so tools can see what a frame changed without comparing whole
+// savestates.
+use std::fmt;
+
+use crate::ppu::PPU;
+
+const OAM_FIELDS: [&str; 4] = ["y", "tile", "attributes", "x"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PpuSnapshot {
+    pub frame: u64,
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+    // The current and temporary VRAM addresses and the fine X scroll.
+    pub v: u16,
+    pub t: u16,
+    pub fine_x: u8,
+    pub oam: [u8; 256],
+    pub palette: [u8; 32],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PpuChange {
+    Register {
+        name: &'static str,
+        old: u16,
+        new: u16,
+    },
+    // One of the four bytes of a sprite, named as in `OAM_FIELDS`.
+    Oam {
+        sprite: usize,
+        field: &'static str,
+        old: u8,
+        new: u8,
+    },
+    Palette {
+        index: usize,
+        old: u8,
+        new: u8,
+    },
+}
+
+impl fmt::Display for PpuChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PpuChange::Register { name, old, new } => {
+                write!(f, "{}: ${:02X} -> ${:02X}", name, old, new)
+            }
+            PpuChange::Oam {
+                sprite,
+                field,
+                old,
+                new,
+            } => write!(
+                f,
+                "sprite {} {}: ${:02X} -> ${:02X}",
+                sprite, field, old, new
+            ),
+            PpuChange::Palette { index, old, new } => {
+                write!(
+                    f,
+                    "palette ${:04X}: ${:02X} -> ${:02X}",
+                    0x3F00 + index,
+                    old,
+                    new
+                )
+            }
+        }
+    }
+}
+
+impl PpuSnapshot {
+    pub fn new(ppu: &PPU) -> Self {
+        PpuSnapshot {
+            frame: ppu.frame_count,
+            ctrl: ppu.ctrl.bits(),
+            mask: ppu.mask.bits(),
+            status: ppu.status.snapshot(),
+            oam_addr: ppu.oam_addr,
+            v: ppu.scroll.v_debug(),
+            t: ppu.scroll.t_debug(),
+            fine_x: ppu.scroll.fine_x_debug(),
+            oam: ppu.oam_data,
+            palette: ppu.palette_table,
+        }
+    }
+
+    fn registers(&self) -> [(&'static str, u16); 7] {
+        [
+            ("ctrl", self.ctrl as u16),
+            ("mask", self.mask as u16),
+            ("status", self.status as u16),
+            ("oam_addr", self.oam_addr as u16),
+            ("v", self.v),
+            ("t", self.t),
+            ("fine_x", self.fine_x as u16),
+        ]
+    }
+
+    // Everything that differs in `newer`: registers, then sprites, then the
+    // palette, each in address order.
+    pub fn diff(&self, newer: &PpuSnapshot) -> Vec<PpuChange> {
+        let registers = self
+            .registers()
+            .into_iter()
+            .zip(newer.registers())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((name, old), (_, new))| PpuChange::Register { name, old, new });
+        let oam = (0..self.oam.len())
+            .filter(|&i| self.oam[i] != newer.oam[i])
+            .map(|i| PpuChange::Oam {
+                sprite: i / 4,
+                field: OAM_FIELDS[i % 4],
+                old: self.oam[i],
+                new: newer.oam[i],
+            });
+        let palette = (0..self.palette.len())
+            .filter(|&i| self.palette[i] != newer.palette[i])
+            .map(|i| PpuChange::Palette {
+                index: i,
+                old: self.palette[i],
+                new: newer.palette[i],
+            });
+        registers.chain(oam).chain(palette).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ppu::registers::control::ControlRegister;
+
+    #[test]
+    fn test_diff_lists_changes_in_order() {
+        let mut ppu = PPU::new();
+        let before = ppu.snapshot();
+        assert!(before.diff(&ppu.snapshot()).is_empty());
+
+        ppu.palette_table[0x11] = 0x30;
+        ppu.oam_data[7] = 0x42;
+        ppu.ctrl = ControlRegister::from_bits_truncate(0x80);
+        let changes: Vec<String> = before
+            .diff(&ppu.snapshot())
+            .iter()
+            .map(|change| change.to_string())
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "ctrl: $00 -> $80",
+                "sprite 1 x: $00 -> $42",
+                "palette $3F11: $00 -> $30"
+            ]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use crate::bus::Bus;
+
+// How many matches a listing shows before it only counts the rest.
+const SHOWN_MATCHES: usize = 16;
+
+// What an address has to hold to stay in a search, against a value or
+// against what it held at the last search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Equal(u8),
+    NotEqual(u8),
+    Greater(u8),
+    Less(u8),
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    // Up or down by exactly this much, wrapping like the byte does.
+    ChangedBy(i16),
+}
+
+impl Filter {
+    // "= 5", "!= $1F", "> 5" and "< 5" compare with a value, decimal or hex
+    // with a `$`. On their own they compare with the last search, and "+1" or
+    // "-1" look for a change by that much.
+    pub fn parse(text: &str) -> Result<Filter, String> {
+        let text = text.trim();
+        let (operator, operand) = match text.find(|c: char| !"=!<>+-".contains(c)) {
+            Some(index) => text.split_at(index),
+            None => (text, ""),
+        };
+        let operand = operand.trim();
+        if operand.is_empty() {
+            return match operator {
+                "=" => Ok(Filter::Unchanged),
+                "!=" => Ok(Filter::Changed),
+                ">" => Ok(Filter::Increased),
+                "<" => Ok(Filter::Decreased),
+                _ => Err(format!("Invalid search {}, see help", text)),
+            };
+        }
+
+        let value = match operand.strip_prefix('$') {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => operand.parse(),
+        }
+        .map_err(|e| format!("Invalid value {}: {}", operand, e))?;
+        match operator {
+            "=" => Ok(Filter::Equal(value)),
+            "!=" => Ok(Filter::NotEqual(value)),
+            ">" => Ok(Filter::Greater(value)),
+            "<" => Ok(Filter::Less(value)),
+            "+" => Ok(Filter::ChangedBy(value as i16)),
+            "-" => Ok(Filter::ChangedBy(-(value as i16))),
+            _ => Err(format!("Invalid search {}, see help", text)),
+        }
+    }
+
+    fn keeps(&self, previous: u8, now: u8) -> bool {
+        match *self {
+            Filter::Equal(value) => now == value,
+            Filter::NotEqual(value) => now != value,
+            Filter::Greater(value) => now > value,
+            Filter::Less(value) => now < value,
+            Filter::Unchanged => now == previous,
+            Filter::Changed => now != previous,
+            Filter::Increased => now > previous,
+            Filter::Decreased => now < previous,
+            Filter::ChangedBy(delta) => now == previous.wrapping_add(delta as u8),
+        }
+    }
+}
+
+// Narrows work RAM and PRG RAM down to the addresses that behave like a
+// value in the game, such as lives or a timer, one filter at a time. Each
+// filter reads the addresses left afresh, so between filters the game can be
+// played to change the value being looked for.
+pub struct RamSearch {
+    // Addresses still matching, with the value each held at the last search.
+    matches: Vec<(u16, u8)>,
+}
+
+impl RamSearch {
+    // Starts with every address, as they are now.
+    pub fn new(bus: &Bus) -> RamSearch {
+        let prg_ram = match bus.cart.mapper.prg_ram() {
+            Some(_) => 0x6000..0x8000,
+            None => 0..0,
+        };
+        let matches = (0x0000..0x0800)
+            .chain(prg_ram)
+            .map(|addr| (addr, bus.peek(addr)))
+            .collect();
+        RamSearch { matches }
+    }
+
+    pub fn filter(&mut self, bus: &Bus, filter: Filter) {
+        self.matches.retain_mut(|(addr, value)| {
+            let now = bus.peek(*addr);
+            let keep = filter.keeps(*value, now);
+            *value = now;
+            keep
+        });
+    }
+
+    pub fn matches(&self) -> &[(u16, u8)] {
+        &self.matches
+    }
+}
+
+impl fmt::Display for RamSearch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.matches.len() {
+            1 => writeln!(f, "1 address matches")?,
+            count => writeln!(f, "{} addresses match", count)?,
+        }
+        for (addr, value) in self.matches.iter().take(SHOWN_MATCHES) {
+            writeln!(f, "${:04X}: {:02X}", addr, value)?;
+        }
+        if self.matches.len() > SHOWN_MATCHES {
+            writeln!(f, "and {} more", self.matches.len() - SHOWN_MATCHES)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apu::APU;
+    use crate::memory::Memory;
+    use crate::nes::Nes;
+    use crate::testutil::{Asm, CODE_ORIGIN, RomBuilder};
+
+    #[test]
+    fn test_filters_parse() {
+        assert_eq!(Filter::parse("= 5"), Ok(Filter::Equal(5)));
+        assert_eq!(Filter::parse("!=$1f"), Ok(Filter::NotEqual(0x1F)));
+        assert_eq!(Filter::parse(">"), Ok(Filter::Increased));
+        assert_eq!(Filter::parse("< 3"), Ok(Filter::Less(3)));
+        assert_eq!(Filter::parse("+1"), Ok(Filter::ChangedBy(1)));
+        assert_eq!(Filter::parse("- 2"), Ok(Filter::ChangedBy(-2)));
+        assert!(Filter::parse("=> 2").is_err());
+        assert!(Filter::parse("= 300").is_err());
+        assert!(Filter::parse("lives").is_err());
+    }
+
+    #[test]
+    fn test_search_narrows_to_the_changing_address() {
+        let mut asm = Asm::new(CODE_ORIGIN);
+        asm.label("reset").jmp("reset");
+        let cart = RomBuilder::new(0)
+            .code(&asm, "reset", "reset", "reset")
+            .cart();
+        let mut nes = Nes::new(cart, APU::new(48_000));
+
+        // Lives at $0075, and a decoy at $0300 that also starts at 3.
+        nes.bus.write(0x0075, 3);
+        nes.bus.write(0x0300, 3);
+        let mut search = RamSearch::new(&nes.bus);
+        search.filter(&nes.bus, Filter::Equal(3));
+        assert_eq!(search.matches(), [(0x0075, 3), (0x0300, 3)]);
+
+        nes.bus.write(0x0075, 2);
+        nes.bus.write(0x0300, 5);
+        search.filter(&nes.bus, Filter::ChangedBy(-1));
+        assert_eq!(search.matches(), [(0x0075, 2)]);
+        assert_eq!(search.to_string(), "1 address matches\n$0075: 02\n");
+
+        nes.bus.write(0x0075, 2);
+        search.filter(&nes.bus, Filter::Changed);
+        assert_eq!(search.to_string(), "0 addresses match\n");
+    }
+}
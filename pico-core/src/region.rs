@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+// TV system the console is built for. Besides the frame rate it changes the
+// CPU clock, the number of scanlines, how many PPU dots fit in a CPU cycle
+// and the APU's period tables, so PAL games run too fast and out of tune on
+// an NTSC console.
+//
+// Timings per https://www.nesdev.org/wiki/Cycle_reference_chart
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    // Famiclones sold in the former USSR: PAL frame timing with an NTSC-like
+    // CPU/PPU ratio, and vblank starting 50 lines later.
+    Dendy,
+}
+
+impl Region {
+    pub const ALL: [Region; 3] = [Region::Ntsc, Region::Pal, Region::Dendy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Ntsc => "ntsc",
+            Region::Pal => "pal",
+            Region::Dendy => "dendy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Region> {
+        Region::ALL
+            .into_iter()
+            .find(|region| region.name().eq_ignore_ascii_case(name))
+    }
+
+    // NES 2.0 header byte 12. Multi-region ROMs run as NTSC.
+    pub fn from_nes2_timing(timing: u8) -> Region {
+        match timing & 0x03 {
+            1 => Region::Pal,
+            3 => Region::Dendy,
+            _ => Region::Ntsc,
+        }
+    }
+
+    pub fn cpu_clock_rate(&self) -> u64 {
+        match self {
+            Region::Ntsc => 1_789_773,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.0070,
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> i16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    pub fn vblank_scanline(&self) -> i16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    // Whether the PPU drops the last dot of the pre-render line on odd frames
+    // with rendering on. The PAL PPU, which Dendy clones follow, never does.
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        matches!(self, Region::Ntsc)
+    }
+
+    // Whether $2001 bits 5 and 6 emphasize green and red rather than red
+    // and green, as on the PAL PPU and the Dendy's clone of it.
+    pub fn swaps_red_green_emphasis(&self) -> bool {
+        !matches!(self, Region::Ntsc)
+    }
+
+    // PPU dots per CPU cycle as a fraction: 3 on NTSC and Dendy, 3.2 on PAL.
+    pub fn ppu_dots_per_cpu_cycle(&self) -> (u64, u64) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_regions_from_nes2_timing() {
+        assert_eq!(Region::from_nes2_timing(0), Region::Ntsc);
+        assert_eq!(Region::from_nes2_timing(1), Region::Pal);
+        assert_eq!(Region::from_nes2_timing(2), Region::Ntsc);
+        assert_eq!(Region::from_nes2_timing(3), Region::Dendy);
+        assert_eq!(Region::from_name("PAL"), Some(Region::Pal));
+    }
+}
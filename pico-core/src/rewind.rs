@@ -0,0 +1,126 @@
+use crate::nes::Nes;
+use crate::state_stream::{DEFAULT_KEYFRAME_INTERVAL, DEFAULT_MEMORY_BUDGET, StateStream};
+
+// Recent history of the console for stepping backwards through play. A
+// snapshot is kept every `interval` frames, so each step goes back that many
+// frames and the history reaches that much further for the same memory.
+pub struct Rewind {
+    stream: StateStream,
+    interval: usize,
+    frames_since_snapshot: usize,
+}
+
+impl Rewind {
+    // Keeps up to `seconds` of play, within the stream's memory budget.
+    pub fn new(seconds: usize, interval: usize, frame_rate: f64) -> Self {
+        let interval = interval.max(1);
+        let mut stream = StateStream::new(DEFAULT_MEMORY_BUDGET, DEFAULT_KEYFRAME_INTERVAL);
+        stream.set_max_len((seconds as f64 * frame_rate / interval as f64).ceil() as usize);
+        Rewind {
+            stream,
+            interval,
+            frames_since_snapshot: 0,
+        }
+    }
+
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    // Snapshots held, each `interval` frames apart.
+    pub fn len(&self) -> usize {
+        self.stream.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stream.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.stream.clear();
+        self.frames_since_snapshot = 0;
+    }
+
+    // Called after every frame run forward.
+    pub fn record(&mut self, nes: &Nes) {
+        self.frames_since_snapshot += 1;
+        if self.frames_since_snapshot >= self.interval {
+            self.stream.push(&nes.save_state());
+            self.frames_since_snapshot = 0;
+        }
+    }
+
+    // Loads the newest snapshot and forgets it, so holding rewind keeps going
+    // further back. Returns false once the history has run out.
+    pub fn step_back(&mut self, nes: &mut Nes) -> bool {
+        self.frames_since_snapshot = 0;
+        while let Some(state) = self.stream.pop() {
+            match nes.load_state(&state) {
+                Ok(()) => return true,
+                Err(e) => log::warn!("Skipping rewind snapshot: {}", e),
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apu::APU;
+    use crate::cart::test::test_rom;
+
+    // Counts up at $10, once per NMI: the handler is INC $10; RTI.
+    fn counting_nes() -> Nes {
+        // LDA #$80; STA $2000; JMP $8005
+        let mut program = vec![0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80];
+        program.extend_from_slice(&[0xE6, 0x10, 0x40]);
+        program.resize(0x8000, 0);
+        program[0x7FFA] = 0x08;
+        program[0x7FFB] = 0x80;
+        program[0x7FFC] = 0x00;
+        program[0x7FFD] = 0x80;
+        let apu = APU::new(48_000);
+        let mut nes = Nes::new(test_rom(program), apu);
+        nes.reset();
+        nes
+    }
+
+    #[test]
+    fn test_steps_back_by_the_interval() {
+        let mut nes = counting_nes();
+        let mut rewind = Rewind::new(60, 2, 60.0);
+        for _ in 0..20 {
+            nes.step_frame();
+            rewind.record(&nes);
+        }
+        assert_eq!(rewind.len(), 10);
+        let now = nes.bus.peek(0x10);
+
+        assert!(rewind.step_back(&mut nes));
+        assert_eq!(nes.bus.peek(0x10), now);
+        assert!(rewind.step_back(&mut nes));
+        assert_eq!(nes.bus.peek(0x10), now - 2);
+
+        // Playing on from there records over what was rewound.
+        nes.step_frame();
+        rewind.record(&nes);
+        nes.step_frame();
+        rewind.record(&nes);
+        assert_eq!(rewind.len(), 9);
+        while rewind.step_back(&mut nes) {}
+        assert_eq!(nes.bus.peek(0x10), now - 18);
+    }
+
+    #[test]
+    fn test_history_depth_is_bounded() {
+        let mut nes = counting_nes();
+        let mut rewind = Rewind::new(1, 1, 60.0);
+        for _ in 0..200 {
+            nes.step_frame();
+            rewind.record(&nes);
+        }
+        assert!(rewind.len() <= 60);
+        assert!(!rewind.is_empty());
+    }
+}
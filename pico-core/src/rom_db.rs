@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use bitflags::bitflags;
+use serde::Deserialize;
+
+use crate::ppu::render::Renderer;
+
+bitflags! {
+    // Per-title fixes applied when a known ROM is loaded.
+    #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+    pub struct Quirks: u8 {
+        const BUS_CONFLICTS   = 0b0001;
+        const FOUR_SCREEN     = 0b0010;
+        const NO_SPRITE_LIMIT = 0b0100;
+        const PAL             = 0b1000;
+    }
+}
+
+const QUIRK_NAMES: [(&str, Quirks); 4] = [
+    ("bus_conflicts", Quirks::BUS_CONFLICTS),
+    ("four_screen", Quirks::FOUR_SCREEN),
+    ("no_sprite_limit", Quirks::NO_SPRITE_LIMIT),
+    ("pal", Quirks::PAL),
+];
+
+impl Quirks {
+    pub fn from_quirk_name(name: &str) -> Option<Quirks> {
+        QUIRK_NAMES
+            .iter()
+            .find(|(quirk_name, _)| *quirk_name == name)
+            .map(|(_, quirk)| *quirk)
+    }
+
+    pub fn quirk_names(&self) -> Vec<&'static str> {
+        QUIRK_NAMES
+            .iter()
+            .filter(|(_, quirk)| self.contains(*quirk))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RomEntry {
+    pub name: String,
+    pub quirks: Quirks,
+    // Renderer the game looks best on, when it isn't the default.
+    pub renderer: Option<Renderer>,
+}
+
+#[derive(Deserialize)]
+struct RawDb {
+    #[serde(default)]
+    rom: Vec<RawEntry>,
+}
+
+#[derive(Deserialize)]
+struct RawEntry {
+    crc32: String,
+    name: String,
+    #[serde(default)]
+    quirks: Vec<String>,
+    renderer: Option<Renderer>,
+}
+
+// Known ROMs keyed by the CRC32 of their PRG and CHR data, header excluded,
+// so the same dump matches however its header was written.
+#[derive(Clone, Default)]
+pub struct RomDb {
+    entries: HashMap<u32, RomEntry>,
+}
+
+static BUILTIN: LazyLock<RomDb> = LazyLock::new(|| {
+    RomDb::from_toml(include_str!("rom_db.toml")).expect("built-in ROM database is invalid")
+});
+
+impl RomDb {
+    pub fn builtin() -> &'static RomDb {
+        &BUILTIN
+    }
+
+    pub fn from_toml(text: &str) -> Result<RomDb, String> {
+        let raw: RawDb =
+            toml::from_str(text).map_err(|e| format!("Failed to parse ROM database: {}", e))?;
+
+        let mut entries = HashMap::new();
+        for entry in raw.rom {
+            let crc32 =
+                u32::from_str_radix(entry.crc32.trim_start_matches("0x"), 16).map_err(|e| {
+                    format!("Invalid CRC32 {:?} for {}: {}", entry.crc32, entry.name, e)
+                })?;
+            let mut quirks = Quirks::empty();
+            for name in &entry.quirks {
+                quirks |= Quirks::from_quirk_name(name)
+                    .ok_or_else(|| format!("Unknown quirk {:?} for {}", name, entry.name))?;
+            }
+            entries.insert(
+                crc32,
+                RomEntry {
+                    name: entry.name,
+                    quirks,
+                    renderer: entry.renderer,
+                },
+            );
+        }
+        Ok(RomDb { entries })
+    }
+
+    // Entries from `other` win over existing ones for the same ROM.
+    pub fn merge(&mut self, other: &RomDb) {
+        for (crc32, entry) in &other.entries {
+            self.entries.insert(*crc32, entry.clone());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn lookup(&self, crc32: u32) -> Option<&RomEntry> {
+        self.entries.get(&crc32)
+    }
+}
+
+// CRC-32 as used by zip and the common ROM databases.
+pub fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    !data.iter().fold(!0u32, |crc, byte| {
+        TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let db = RomDb::from_toml(
+            r#"
+            [[rom]]
+            crc32 = "CBF43926"
+            name = "Test"
+            quirks = ["four_screen", "pal"]
+            renderer = "scanline"
+            "#,
+        )
+        .unwrap();
+        let entry = db.lookup(0xCBF4_3926).unwrap();
+        assert_eq!(entry.name, "Test");
+        assert_eq!(entry.quirks, Quirks::FOUR_SCREEN | Quirks::PAL);
+        assert_eq!(entry.quirks.quirk_names(), ["four_screen", "pal"]);
+        assert_eq!(entry.renderer, Some(Renderer::Scanline));
+
+        assert!(
+            RomDb::from_toml("[[rom]]\ncrc32 = \"1\"\nname = \"x\"\nquirks = [\"nope\"]").is_err()
+        );
+        RomDb::builtin();
+    }
+}
@@ -0,0 +1,209 @@
+// Binary savestates. Each component writes its fields in a fixed order and
+// reads them back in the same order, so the blob has no field names or
+// padding and stays small enough to keep one per frame for rewind.
+//
+// Host-side settings (sample rate, muted channels, debug buffers) are left
+// out; a state only carries what the console itself would remember.
+
+pub const MAGIC: &[u8; 8] = b"PICOSTAT";
+pub const VERSION: u16 = 15;
+
+pub trait Savestate {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i16(&mut self, value: i16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    // Sizes and bank numbers are stored as u64 so states move between 32 and
+    // 64-bit hosts.
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    // Unprefixed, for headers whose length the reader already knows.
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    // Length-prefixed, for buffers whose size depends on the cartridge.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.saturating_add(len);
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or_else(|| format!("Savestate is truncated at byte {}", self.pos))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, String> {
+        let value = self.read_u64()?;
+        usize::try_from(value).map_err(|_| format!("Savestate value {} is out of range", value))
+    }
+
+    pub fn read_raw(&mut self, len: usize) -> Result<&'a [u8], String> {
+        self.take(len)
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.read_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    // Reads a length-prefixed buffer into one whose size is already fixed,
+    // e.g. PRG RAM, failing if the state was made for a different layout.
+    pub fn read_bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), String> {
+        let len = self.read_u32()? as usize;
+        if len != buffer.len() {
+            return Err(format!(
+                "Savestate holds {} bytes where {} were expected",
+                len,
+                buffer.len()
+            ));
+        }
+        buffer.copy_from_slice(self.take(len)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_values_round_trip() {
+        let mut writer = StateWriter::new();
+        writer.write_u8(0xAB);
+        writer.write_bool(true);
+        writer.write_u16(0x1234);
+        writer.write_u64(u64::MAX - 1);
+        writer.write_i16(-300);
+        writer.write_f32(-0.25);
+        writer.write_usize(42);
+        writer.write_bytes(&[1, 2, 3]);
+        let data = writer.into_inner();
+
+        let mut reader = StateReader::new(&data);
+        assert_eq!(reader.read_u8().unwrap(), 0xAB);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_u16().unwrap(), 0x1234);
+        assert_eq!(reader.read_u64().unwrap(), u64::MAX - 1);
+        assert_eq!(reader.read_i16().unwrap(), -300);
+        assert_eq!(reader.read_f32().unwrap(), -0.25);
+        assert_eq!(reader.read_usize().unwrap(), 42);
+        let mut buffer = [0; 3];
+        reader.read_bytes_into(&mut buffer).unwrap();
+        assert_eq!(buffer, [1, 2, 3]);
+        assert!(reader.is_finished());
+        assert!(reader.read_u8().is_err());
+    }
+
+    #[test]
+    fn test_fixed_buffer_rejects_other_sizes() {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(&[0; 4]);
+        let data = writer.into_inner();
+
+        let mut buffer = [0; 8];
+        assert!(
+            StateReader::new(&data)
+                .read_bytes_into(&mut buffer)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+
+// Enough for a minute of rewind at 60 snapshots a second with room to spare.
+pub const DEFAULT_MEMORY_BUDGET: usize = 32 * 1024 * 1024;
+pub const DEFAULT_KEYFRAME_INTERVAL: usize = 60;
+
+// One stored snapshot. Keyframes hold the whole state; every other snapshot
+// holds its XOR against the keyframe before it, which is mostly zeros since
+// little of the machine changes between frames. Both are run-length encoded.
+struct Snapshot {
+    keyframe: bool,
+    data: Vec<u8>,
+}
+
+// A bounded sequence of savestates, oldest first, for rewind and rollback.
+// Snapshots go in and come back out as the raw blobs the savestate code deals
+// in; the delta encoding stays internal.
+pub struct StateStream {
+    memory_budget: usize,
+    max_len: usize,
+    keyframe_interval: usize,
+    snapshots: VecDeque<Snapshot>,
+    keyframe: Vec<u8>,
+    since_keyframe: usize,
+    memory_usage: usize,
+}
+
+impl StateStream {
+    pub fn new(memory_budget: usize, keyframe_interval: usize) -> Self {
+        StateStream {
+            memory_budget,
+            max_len: usize::MAX,
+            keyframe_interval: keyframe_interval.max(1),
+            snapshots: VecDeque::new(),
+            keyframe: Vec::new(),
+            since_keyframe: 0,
+            memory_usage: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    // Bytes held by the encoded snapshots.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    // Caps the number of snapshots as well as their size. Like the budget, it
+    // is met by dropping whole groups, so a few less may be kept.
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+        self.evict();
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.keyframe.clear();
+        self.since_keyframe = 0;
+        self.memory_usage = 0;
+    }
+
+    pub fn push(&mut self, state: &[u8]) {
+        let keyframe = self.snapshots.is_empty()
+            || self.since_keyframe >= self.keyframe_interval
+            || state.len() != self.keyframe.len();
+
+        let data = if keyframe {
+            self.keyframe.clear();
+            self.keyframe.extend_from_slice(state);
+            self.since_keyframe = 1;
+            rle_encode(state)
+        } else {
+            self.since_keyframe += 1;
+            let delta: Vec<u8> = state
+                .iter()
+                .zip(&self.keyframe)
+                .map(|(byte, base)| byte ^ base)
+                .collect();
+            rle_encode(&delta)
+        };
+
+        self.memory_usage += data.len();
+        self.snapshots.push_back(Snapshot { keyframe, data });
+        self.evict();
+    }
+
+    // Removes and returns the newest snapshot.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.get(self.snapshots.len().checked_sub(1)?)?;
+        let snapshot = self.snapshots.pop_back()?;
+        self.memory_usage -= snapshot.data.len();
+        // The keyframe the next push would delta against may be gone, so
+        // start a fresh one.
+        self.since_keyframe = self.keyframe_interval;
+        Some(state)
+    }
+
+    // Decodes the snapshot at `index`, counting from the oldest.
+    pub fn get(&self, index: usize) -> Option<Vec<u8>> {
+        let snapshot = self.snapshots.get(index)?;
+        let keyframe_index = (0..=index).rev().find(|&i| self.snapshots[i].keyframe)?;
+        let mut state = rle_decode(&self.snapshots[keyframe_index].data)?;
+
+        if !snapshot.keyframe {
+            let delta = rle_decode(&snapshot.data)?;
+            if delta.len() != state.len() {
+                return None;
+            }
+            for (byte, diff) in state.iter_mut().zip(delta) {
+                *byte ^= diff;
+            }
+        }
+        Some(state)
+    }
+
+    // Drops whole keyframe groups from the front until the stream fits, always
+    // keeping the newest group.
+    fn evict(&mut self) {
+        while self.memory_usage > self.memory_budget || self.snapshots.len() > self.max_len {
+            let next_keyframe = self
+                .snapshots
+                .iter()
+                .skip(1)
+                .position(|snapshot| snapshot.keyframe);
+            let Some(group_len) = next_keyframe.map(|i| i + 1) else {
+                break;
+            };
+            for snapshot in self.snapshots.drain(..group_len) {
+                self.memory_usage -= snapshot.data.len();
+            }
+        }
+    }
+}
+
+// Runs of zero bytes are stored as a count, everything else verbatim:
+// repeated (zero run, literal length, literal bytes) with LEB128 lengths.
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let zeros = data[pos..].iter().take_while(|&&b| b == 0).count();
+        pos += zeros;
+
+        // A lone zero between literals is cheaper to keep in the literal.
+        let start = pos;
+        while pos < data.len() {
+            if data[pos] == 0 && data.get(pos + 1).is_none_or(|&b| b == 0) {
+                break;
+            }
+            pos += 1;
+        }
+
+        push_varint(&mut out, zeros);
+        push_varint(&mut out, pos - start);
+        out.extend_from_slice(&data[start..pos]);
+    }
+    out
+}
+
+fn rle_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let zeros = read_varint(data, &mut pos)?;
+        let literal = read_varint(data, &mut pos)?;
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(data.get(pos..pos + literal)?);
+        pos += literal;
+    }
+    Some(out)
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame_state(frame: usize) -> Vec<u8> {
+        let mut state = vec![0u8; 4096];
+        state[100..200].fill(0x55);
+        state[frame % 4096] = frame as u8 | 1;
+        state[1000] = (frame / 3) as u8;
+        state
+    }
+
+    #[test]
+    fn test_rle_round_trip() {
+        let inputs: [&[u8]; 5] = [
+            &[],
+            &[0; 1000],
+            &[1, 2, 3],
+            &[0, 0, 7, 0, 8, 0, 0, 0, 9],
+            &[5; 300],
+        ];
+        for input in inputs {
+            assert_eq!(rle_decode(&rle_encode(input)).unwrap(), input);
+        }
+        assert!(rle_encode(&[0; 1000]).len() <= 3);
+    }
+
+    #[test]
+    fn test_snapshots_decode_and_pop_newest_first() {
+        let mut stream = StateStream::new(usize::MAX, 8);
+        for frame in 0..20 {
+            stream.push(&frame_state(frame));
+        }
+        assert_eq!(stream.len(), 20);
+        for frame in 0..20 {
+            assert_eq!(stream.get(frame).unwrap(), frame_state(frame));
+        }
+
+        assert_eq!(stream.pop().unwrap(), frame_state(19));
+        assert_eq!(stream.pop().unwrap(), frame_state(18));
+        stream.push(&frame_state(100));
+        assert_eq!(stream.pop().unwrap(), frame_state(100));
+        assert_eq!(stream.pop().unwrap(), frame_state(17));
+    }
+
+    #[test]
+    fn test_memory_budget_drops_oldest_groups() {
+        let mut stream = StateStream::new(2048, 10);
+        for frame in 0..500 {
+            stream.push(&frame_state(frame));
+            assert!(stream.memory_usage() <= 2048 || stream.len() <= 10);
+        }
+        let oldest = 500 - stream.len();
+        assert!(oldest > 0);
+        assert_eq!(stream.get(0).unwrap(), frame_state(oldest));
+        assert_eq!(stream.pop().unwrap(), frame_state(499));
+    }
+
+    #[test]
+    fn test_max_len_drops_oldest_groups() {
+        let mut stream = StateStream::new(usize::MAX, 10);
+        stream.set_max_len(35);
+        for frame in 0..100 {
+            stream.push(&frame_state(frame));
+            assert!(stream.len() <= 35);
+        }
+        assert_eq!(stream.len(), 30);
+        assert_eq!(stream.get(0).unwrap(), frame_state(70));
+    }
+}
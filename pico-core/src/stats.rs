@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+pub const NTSC_FPS: f64 = 60.0988;
+
+// Number of frames the averages are taken over.
+const WINDOW: usize = 60;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StatsSnapshot {
+    // Frames emulated per second, against the console's rate times the
+    // speed. Fast-forward runs several frames for each one presented.
+    pub fps: f64,
+    pub target_fps: f64,
+    pub frame_time_ms: f64,
+    pub audio_buffered_ms: f64,
+    pub audio_fill: f64,
+    // Times the audio device ran out of samples since the frontend started.
+    pub audio_underruns: usize,
+    pub speed: f64,
+    pub compat_notes: usize,
+}
+
+// Rolling performance counters. The core has no notion of wall-clock time, so
+// the frontend measures and feeds in the durations.
+pub struct PerfStats {
+    target_fps: f64,
+    speed: f64,
+    intervals: VecDeque<Duration>,
+    emulated: VecDeque<usize>,
+    work_times: VecDeque<Duration>,
+    audio_queued: usize,
+    audio_capacity: usize,
+    audio_underruns: usize,
+    sample_rate: u32,
+    compat_notes: usize,
+}
+
+impl PerfStats {
+    pub fn new(target_fps: f64, sample_rate: u32) -> Self {
+        PerfStats {
+            target_fps,
+            speed: 1.0,
+            intervals: VecDeque::with_capacity(WINDOW),
+            emulated: VecDeque::with_capacity(WINDOW),
+            work_times: VecDeque::with_capacity(WINDOW),
+            audio_queued: 0,
+            audio_capacity: 0,
+            audio_underruns: 0,
+            sample_rate,
+            compat_notes: 0,
+        }
+    }
+
+    // `interval` is the time since the previous frame was presented, `work`
+    // the part of it spent emulating and drawing, and `frames` how many
+    // frames were emulated for it.
+    pub fn record_frame(&mut self, interval: Duration, work: Duration, frames: usize) {
+        if self.intervals.len() == WINDOW {
+            self.intervals.pop_front();
+            self.emulated.pop_front();
+            self.work_times.pop_front();
+        }
+        self.intervals.push_back(interval);
+        self.emulated.push_back(frames);
+        self.work_times.push_back(work);
+    }
+
+    pub fn set_audio_buffer(&mut self, queued: usize, capacity: usize) {
+        self.audio_queued = queued;
+        self.audio_capacity = capacity;
+    }
+
+    pub fn set_audio_underruns(&mut self, count: usize) {
+        self.audio_underruns = count;
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: f64) {
+        self.target_fps = target_fps;
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    // Number of compatibility notes the running ROM has produced.
+    pub fn set_compat_notes(&mut self, count: usize) {
+        self.compat_notes = count;
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn fps(&self) -> f64 {
+        let total: Duration = self.intervals.iter().sum();
+        if total.is_zero() {
+            return 0.0;
+        }
+        self.emulated.iter().sum::<usize>() as f64 / total.as_secs_f64()
+    }
+
+    pub fn frame_time_ms(&self) -> f64 {
+        if self.work_times.is_empty() {
+            return 0.0;
+        }
+        let total: Duration = self.work_times.iter().sum();
+        total.as_secs_f64() * 1000.0 / self.work_times.len() as f64
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let audio_fill = if self.audio_capacity == 0 {
+            0.0
+        } else {
+            self.audio_queued as f64 / self.audio_capacity as f64
+        };
+
+        StatsSnapshot {
+            fps: self.fps(),
+            target_fps: self.target_fps * self.speed,
+            frame_time_ms: self.frame_time_ms(),
+            audio_buffered_ms: self.audio_queued as f64 * 1000.0 / self.sample_rate as f64,
+            audio_fill,
+            audio_underruns: self.audio_underruns,
+            speed: self.speed,
+            compat_notes: self.compat_notes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fps_averages_over_window() {
+        let mut stats = PerfStats::new(NTSC_FPS, 48000);
+        assert_eq!(stats.fps(), 0.0);
+
+        for _ in 0..WINDOW * 2 {
+            stats.record_frame(Duration::from_millis(20), Duration::from_millis(5), 1);
+        }
+        assert!((stats.fps() - 50.0).abs() < 1e-9);
+        assert!((stats.frame_time_ms() - 5.0).abs() < 1e-9);
+
+        stats.set_audio_buffer(2400, 9600);
+        let snapshot = stats.snapshot();
+        assert!((snapshot.audio_buffered_ms - 50.0).abs() < 1e-9);
+        assert!((snapshot.audio_fill - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fast_forward_counts_every_emulated_frame() {
+        let mut stats = PerfStats::new(60.0, 48000);
+        stats.set_speed(4.0);
+        for _ in 0..WINDOW {
+            stats.record_frame(Duration::from_secs(1) / 60, Duration::ZERO, 4);
+        }
+        let snapshot = stats.snapshot();
+        assert!((snapshot.fps - 240.0).abs() < 1e-3);
+        assert!((snapshot.target_fps - 240.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,323 @@
+// Builders for the tiny ROMs tests run: a few-instruction 6502 assembler, an
+// iNES image around its output, and bank-numbered ROM for testing boards on
+// their own.
+use std::collections::HashMap;
+
+use crate::cart::Cart;
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+// `banks` banks of `size` bytes, each filled with its bank number, so a read
+// shows which bank it landed in.
+pub fn patterned(banks: usize, size: usize) -> Vec<u8> {
+    (0..banks * size).map(|i| (i / size) as u8).collect()
+}
+
+// Where `RomBuilder` puts the code, and a table whose byte at `IDENTITY + n`
+// is n, for writing latches on boards with bus conflicts.
+pub const CODE_ORIGIN: u16 = 0xE200;
+pub const IDENTITY: u16 = 0xE100;
+
+// Assembles at a fixed origin. Labels may be used before they are defined;
+// `finish` fills them in.
+pub struct Asm {
+    origin: u16,
+    code: Vec<u8>,
+    labels: HashMap<&'static str, u16>,
+    // Offset of the operand, the label, and whether it is a branch.
+    fixups: Vec<(usize, &'static str, bool)>,
+}
+
+impl Asm {
+    pub fn new(origin: u16) -> Self {
+        Asm {
+            origin,
+            code: Vec::new(),
+            labels: HashMap::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.origin + self.code.len() as u16
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.code.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn label(&mut self, name: &'static str) -> &mut Self {
+        let pc = self.pc();
+        assert!(self.labels.insert(name, pc).is_none(), "label {name} twice");
+        self
+    }
+
+    fn with_abs(&mut self, opcode: u8, addr: u16) -> &mut Self {
+        let [lo, hi] = addr.to_le_bytes();
+        self.bytes(&[opcode, lo, hi])
+    }
+
+    fn with_label(&mut self, opcode: u8, name: &'static str, branch: bool) -> &mut Self {
+        self.code.push(opcode);
+        self.fixups.push((self.code.len(), name, branch));
+        let operand_len = if branch { 1 } else { 2 };
+        self.code.resize(self.code.len() + operand_len, 0);
+        self
+    }
+
+    pub fn sei(&mut self) -> &mut Self {
+        self.bytes(&[0x78])
+    }
+
+    pub fn cld(&mut self) -> &mut Self {
+        self.bytes(&[0xD8])
+    }
+
+    pub fn clc(&mut self) -> &mut Self {
+        self.bytes(&[0x18])
+    }
+
+    pub fn txs(&mut self) -> &mut Self {
+        self.bytes(&[0x9A])
+    }
+
+    pub fn txa(&mut self) -> &mut Self {
+        self.bytes(&[0x8A])
+    }
+
+    pub fn inx(&mut self) -> &mut Self {
+        self.bytes(&[0xE8])
+    }
+
+    pub fn iny(&mut self) -> &mut Self {
+        self.bytes(&[0xC8])
+    }
+
+    pub fn dex(&mut self) -> &mut Self {
+        self.bytes(&[0xCA])
+    }
+
+    pub fn dey(&mut self) -> &mut Self {
+        self.bytes(&[0x88])
+    }
+
+    pub fn lsr(&mut self) -> &mut Self {
+        self.bytes(&[0x4A])
+    }
+
+    pub fn rti(&mut self) -> &mut Self {
+        self.bytes(&[0x40])
+    }
+
+    pub fn rts(&mut self) -> &mut Self {
+        self.bytes(&[0x60])
+    }
+
+    pub fn lda_imm(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[0xA9, value])
+    }
+
+    pub fn ldx_imm(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[0xA2, value])
+    }
+
+    pub fn ldy_imm(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[0xA0, value])
+    }
+
+    pub fn cpx_imm(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[0xE0, value])
+    }
+
+    pub fn lda(&mut self, addr: u16) -> &mut Self {
+        self.with_abs(0xAD, addr)
+    }
+
+    pub fn lda_label_x(&mut self, name: &'static str) -> &mut Self {
+        self.with_label(0xBD, name, false)
+    }
+
+    pub fn sty_zp(&mut self, addr: u8) -> &mut Self {
+        self.bytes(&[0x84, addr])
+    }
+
+    pub fn adc_zp(&mut self, addr: u8) -> &mut Self {
+        self.bytes(&[0x65, addr])
+    }
+
+    pub fn sta(&mut self, addr: u16) -> &mut Self {
+        self.with_abs(0x8D, addr)
+    }
+
+    pub fn sta_x(&mut self, addr: u16) -> &mut Self {
+        self.with_abs(0x9D, addr)
+    }
+
+    pub fn bit(&mut self, addr: u16) -> &mut Self {
+        self.with_abs(0x2C, addr)
+    }
+
+    // Writes `value` to `addr`.
+    pub fn poke(&mut self, addr: u16, value: u8) -> &mut Self {
+        self.lda_imm(value).sta(addr)
+    }
+
+    pub fn jmp(&mut self, name: &'static str) -> &mut Self {
+        self.with_label(0x4C, name, false)
+    }
+
+    pub fn jsr(&mut self, addr: u16) -> &mut Self {
+        self.with_abs(0x20, addr)
+    }
+
+    pub fn bne(&mut self, name: &'static str) -> &mut Self {
+        self.with_label(0xD0, name, true)
+    }
+
+    pub fn bpl(&mut self, name: &'static str) -> &mut Self {
+        self.with_label(0x10, name, true)
+    }
+
+    pub fn finish(&self) -> Vec<u8> {
+        let mut code = self.code.clone();
+        for &(offset, name, branch) in &self.fixups {
+            let target = *self
+                .labels
+                .get(name)
+                .unwrap_or_else(|| panic!("undefined label {name}"));
+            if branch {
+                let next = self.origin as i32 + offset as i32 + 1;
+                let delta = target as i32 - next;
+                assert!((-128..=127).contains(&delta), "branch to {name} too far");
+                code[offset] = delta as i8 as u8;
+            } else {
+                code[offset..offset + 2].copy_from_slice(&target.to_le_bytes());
+            }
+        }
+        code
+    }
+
+    pub fn address_of(&self, name: &str) -> u16 {
+        self.labels[name]
+    }
+}
+
+// An iNES image around code assembled at `CODE_ORIGIN`. Every 8 KiB of PRG
+// starts with its bank number and has the identity table at offset $100, and
+// the code and vectors are in every 16 KiB bank, so they are mapped at $E000
+// whatever the board shows there. CHR tiles differ in every 1 KiB; with no
+// CHR banks the board gets CHR RAM.
+pub struct RomBuilder {
+    mapper: u8,
+    vertical_mirroring: bool,
+    prg_banks: usize,
+    chr_banks: usize,
+    code: Vec<u8>,
+    vectors: [u16; 3],
+}
+
+impl RomBuilder {
+    pub fn new(mapper: u8) -> Self {
+        RomBuilder {
+            mapper,
+            vertical_mirroring: false,
+            prg_banks: 2,
+            chr_banks: 1,
+            code: Vec::new(),
+            vectors: [CODE_ORIGIN; 3],
+        }
+    }
+
+    pub fn vertical_mirroring(mut self) -> Self {
+        self.vertical_mirroring = true;
+        self
+    }
+
+    // In 16 KiB PRG and 8 KiB CHR banks.
+    pub fn banks(mut self, prg_banks: usize, chr_banks: usize) -> Self {
+        self.prg_banks = prg_banks;
+        self.chr_banks = chr_banks;
+        self
+    }
+
+    // With the NMI, reset and IRQ addresses.
+    pub fn code(mut self, asm: &Asm, nmi: &str, reset: &str, irq: &str) -> Self {
+        self.code = asm.finish();
+        assert!(self.code.len() <= 0x1DFA, "code runs into the vectors");
+        self.vectors = [
+            asm.address_of(nmi),
+            asm.address_of(reset),
+            asm.address_of(irq),
+        ];
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let flags6 = (self.mapper << 4) | self.vertical_mirroring as u8;
+        let mut rom = vec![
+            0x4E,
+            0x45,
+            0x53,
+            0x1A,
+            self.prg_banks as u8,
+            self.chr_banks as u8,
+            flags6,
+            self.mapper & 0xF0,
+        ];
+        rom.resize(16, 0);
+
+        let code_offset = (CODE_ORIGIN - 0xC000) as usize;
+        for bank in 0..self.prg_banks {
+            let mut prg = vec![0; PRG_BANK_SIZE];
+            for half in 0..2 {
+                let base = half * 0x2000;
+                prg[base] = (bank * 2 + half) as u8;
+                for n in 0..0x100 {
+                    prg[base + 0x100 + n] = n as u8;
+                }
+            }
+            prg[code_offset..code_offset + self.code.len()].copy_from_slice(&self.code);
+            for (i, vector) in self.vectors.iter().enumerate() {
+                let offset = 0x3FFA + i * 2;
+                prg[offset..offset + 2].copy_from_slice(&vector.to_le_bytes());
+            }
+            rom.extend_from_slice(&prg);
+        }
+
+        for offset in 0..self.chr_banks * CHR_BANK_SIZE {
+            let kib = (offset / 0x400) as u8;
+            let tile = (offset / 16) as u8;
+            let row = (offset % 8) as u32;
+            let plane = (offset / 8) % 2;
+            let byte = if plane == 0 {
+                tile.rotate_left(row) ^ kib.wrapping_mul(0x25)
+            } else {
+                (tile ^ kib).rotate_right(row) | (kib & 1)
+            };
+            rom.push(byte);
+        }
+        rom
+    }
+
+    pub fn cart(&self) -> Cart {
+        Cart::new(&self.build()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_labels_resolve_forwards_and_backwards() {
+        let mut asm = Asm::new(0x8000);
+        asm.label("top")
+            .ldx_imm(3)
+            .bne("end")
+            .jmp("top")
+            .label("end");
+        assert_eq!(asm.finish(), [0xA2, 0x03, 0xD0, 0x03, 0x4C, 0x00, 0x80]);
+    }
+}
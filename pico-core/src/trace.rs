@@ -0,0 +1,265 @@
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::opcodes::{AddressingMode, CPU_OPCODES, Mnemonic, Opcode};
+use std::fmt;
+
+pub type TraceHook = Box<dyn FnMut(&TraceRecord) + Send>;
+
+// Everything a nestest-style trace line shows about the instruction the CPU
+// is about to run, captured without formatting anything. Hooks that only
+// count or filter instructions pay for a few bus peeks; the text is built
+// when the record is displayed.
+#[derive(Clone, Copy, Debug)]
+pub struct TraceRecord {
+    pub opcode: &'static Opcode,
+    pub pc: u16,
+    pub bytes: [u8; 3],
+    // Effective address and the value there, or the pointed-to target for
+    // JMP ($nnnn).
+    pub mem_addr: u16,
+    pub stored_value: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+    pub sp: u8,
+    pub cycles: u64,
+}
+
+impl TraceRecord {
+    pub fn capture(cpu: &CPU, bus: &Bus) -> TraceRecord {
+        let pc = cpu.registers.pc;
+        let opcode = CPU_OPCODES.find_by_code(bus.peek(pc)).unwrap();
+
+        let mut bytes = [0; 3];
+        for (i, byte) in bytes.iter_mut().enumerate().take(opcode.bytes as usize) {
+            *byte = bus.peek(pc.wrapping_add(i as u16));
+        }
+
+        let (mem_addr, stored_value) = match opcode.mode {
+            AddressingMode::Immediate | AddressingMode::Accumulator => (0, 0),
+            AddressingMode::None => (0, 0),
+            _ => operand(bus, cpu, &opcode.mode),
+        };
+
+        TraceRecord {
+            opcode,
+            pc,
+            bytes,
+            mem_addr,
+            stored_value,
+            a: cpu.registers.a,
+            x: cpu.registers.x,
+            y: cpu.registers.y,
+            status: cpu.registers.status.bits(),
+            sp: cpu.registers.sp,
+            cycles: cpu.cycles(),
+        }
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ops = self.opcode;
+        let pc = self.pc;
+        let (mem_addr, stored_value) = (self.mem_addr, self.stored_value);
+
+        let operand_str = match ops.bytes {
+            1 => match ops.code {
+                0x0a | 0x4a | 0x2a | 0x6a => "A ".to_string(),
+                _ => String::new(),
+            },
+            2 => {
+                let value = self.bytes[1];
+                match ops.mode {
+                    AddressingMode::Immediate => format!("#${:02x}", value),
+                    AddressingMode::ZeroPage => format!("${:02x} = {:02x}", mem_addr, stored_value),
+                    AddressingMode::ZeroPageX => {
+                        format!("${:02x},X @ {:02x} = {:02x}", value, mem_addr, stored_value)
+                    }
+                    AddressingMode::ZeroPageY => {
+                        format!("${:02x},Y @ {:02x} = {:02x}", value, mem_addr, stored_value)
+                    }
+                    AddressingMode::IndirectX => format!(
+                        "(${:02x},X) @ {:02x} = {:04x} = {:02x}",
+                        value,
+                        value.wrapping_add(self.x),
+                        mem_addr,
+                        stored_value
+                    ),
+                    AddressingMode::IndirectY => format!(
+                        "(${:02x}),Y = {:04x} @ {:04x} = {:02x}",
+                        value,
+                        mem_addr.wrapping_sub(self.y as u16),
+                        mem_addr,
+                        stored_value
+                    ),
+                    AddressingMode::Relative => {
+                        let offset = value as i8;
+                        let target = (pc as i32 + 2 + offset as i32) as u16;
+                        format!("${:04x}", target)
+                    }
+                    _ => String::new(),
+                }
+            }
+            3 => {
+                let absolute = u16::from_le_bytes([self.bytes[1], self.bytes[2]]);
+                match ops.mode {
+                    AddressingMode::Indirect => format!("(${:04x}) = {:04x}", absolute, mem_addr),
+                    AddressingMode::Absolute
+                        if matches!(ops.mnemonic, Mnemonic::JMP | Mnemonic::JSR) =>
+                    {
+                        format!("${:04x}", absolute)
+                    }
+                    AddressingMode::Absolute => {
+                        format!("${:04x} = {:02x}", mem_addr, stored_value)
+                    }
+                    AddressingMode::AbsoluteX => format!(
+                        "${:04x},X @ {:04x} = {:02x}",
+                        absolute, mem_addr, stored_value
+                    ),
+                    AddressingMode::AbsoluteY => format!(
+                        "${:04x},Y @ {:04x} = {:02x}",
+                        absolute, mem_addr, stored_value
+                    ),
+                    _ => String::new(),
+                }
+            }
+            _ => String::new(),
+        };
+
+        let hex_str = self.bytes[..ops.bytes as usize]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let asm_str = format!(
+            "{:04x}  {:8} {: >4} {}",
+            pc,
+            hex_str,
+            mnemonic(ops),
+            operand_str
+        )
+        .trim()
+        .to_string();
+
+        let line = format!(
+            "{:47} A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x} CYC:{}",
+            asm_str, self.a, self.x, self.y, self.status, self.sp, self.cycles
+        );
+        f.write_str(&line.to_ascii_uppercase())
+    }
+}
+
+// The mnemonic as nestest logs it, with a `*` in front of unofficial opcodes
+// and ISC called ISB.
+fn mnemonic(opcode: &Opcode) -> String {
+    let official = match opcode.mnemonic {
+        Mnemonic::NOP => opcode.code == 0xea,
+        Mnemonic::SBC => opcode.code != 0xeb,
+        Mnemonic::AHX
+        | Mnemonic::ALR
+        | Mnemonic::ANC
+        | Mnemonic::ARR
+        | Mnemonic::AXS
+        | Mnemonic::DCP
+        | Mnemonic::ISC
+        | Mnemonic::LAS
+        | Mnemonic::LAX
+        | Mnemonic::LXA
+        | Mnemonic::RLA
+        | Mnemonic::RRA
+        | Mnemonic::SAX
+        | Mnemonic::SHX
+        | Mnemonic::SHY
+        | Mnemonic::SLO
+        | Mnemonic::SRE
+        | Mnemonic::STP
+        | Mnemonic::TAS
+        | Mnemonic::XAA => false,
+        _ => true,
+    };
+    let name = match opcode.mnemonic {
+        Mnemonic::ISC => "ISB".to_string(),
+        ref other => other.to_string(),
+    };
+    if official { name } else { format!("*{}", name) }
+}
+
+pub fn trace(cpu: &CPU, bus: &Bus) -> String {
+    TraceRecord::capture(cpu, bus).to_string()
+}
+
+fn operand(bus: &Bus, cpu: &CPU, mode: &AddressingMode) -> (u16, u8) {
+    let pc = cpu.registers.pc;
+    match mode {
+        AddressingMode::ZeroPage => {
+            let addr = bus.peek(pc.wrapping_add(1)) as u16;
+            (addr, bus.peek(addr))
+        }
+        AddressingMode::ZeroPageX => {
+            let base = bus.peek(pc.wrapping_add(1));
+            let addr = base.wrapping_add(cpu.registers.x) as u16;
+            (addr, bus.peek(addr))
+        }
+        AddressingMode::ZeroPageY => {
+            let base = bus.peek(pc.wrapping_add(1));
+            let addr = base.wrapping_add(cpu.registers.y) as u16;
+            (addr, bus.peek(addr))
+        }
+        AddressingMode::Absolute => {
+            let addr = read_u16(bus, pc.wrapping_add(1));
+            (addr, bus.peek(addr))
+        }
+        AddressingMode::AbsoluteX => {
+            let base = read_u16(bus, pc.wrapping_add(1));
+            let addr = base.wrapping_add(cpu.registers.x as u16);
+            (addr, bus.peek(addr))
+        }
+        AddressingMode::AbsoluteY => {
+            let base = read_u16(bus, pc.wrapping_add(1));
+            let addr = base.wrapping_add(cpu.registers.y as u16);
+            (addr, bus.peek(addr))
+        }
+        AddressingMode::Indirect => {
+            let base = read_u16(bus, pc.wrapping_add(1));
+            let addr = if base & 0x00ff == 0x00ff {
+                let lo = bus.peek(base);
+                let hi = bus.peek(base & 0xff00);
+                (hi as u16) << 8 | lo as u16
+            } else {
+                read_u16(bus, base)
+            };
+            (addr, bus.peek(addr))
+        }
+        AddressingMode::IndirectX => {
+            let base = bus.peek(pc.wrapping_add(1));
+            let ptr = base.wrapping_add(cpu.registers.x);
+            let lo = bus.peek(ptr as u16);
+            let hi = bus.peek(ptr.wrapping_add(1) as u16);
+            let addr = (hi as u16) << 8 | lo as u16;
+            (addr, bus.peek(addr))
+        }
+        AddressingMode::IndirectY => {
+            let base = bus.peek(pc.wrapping_add(1));
+            let lo = bus.peek(base as u16);
+            let hi = bus.peek(base.wrapping_add(1) as u16);
+            let deref = ((hi as u16) << 8 | lo as u16).wrapping_add(cpu.registers.y as u16);
+            (deref, bus.peek(deref))
+        }
+        AddressingMode::Relative => {
+            let offset = bus.peek(pc.wrapping_add(1)) as i8;
+            let base = pc.wrapping_add(2);
+            let target = (base as i32 + offset as i32) as u16;
+            (target, bus.peek(target))
+        }
+        AddressingMode::Immediate | AddressingMode::None | AddressingMode::Accumulator => (0, 0),
+    }
+}
+
+fn read_u16(bus: &Bus, addr: u16) -> u16 {
+    let lo = bus.peek(addr) as u16;
+    let hi = bus.peek(addr.wrapping_add(1)) as u16;
+    (hi << 8) | lo
+}
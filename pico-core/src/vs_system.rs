@@ -0,0 +1,109 @@
+// Arcade boards that run NES games, per https://www.nesdev.org/wiki/Vs._System
+// and https://www.nesdev.org/wiki/PlayChoice-10.
+
+// What the cartridge was made to run on, from the iNES/NES 2.0 header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Console {
+    Nes,
+    VsSystem(VsPpu),
+    // Its games are NES games plus instruction screens shown on a second
+    // monitor, so they run as they would on an NES.
+    PlayChoice10,
+}
+
+// The Vs. System was built with several PPUs, which games were written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VsPpu {
+    // RGB PPUs with the standard colours.
+    Rp2c03,
+    // Colours scrambled by one of four lookup tables, 1 to 4 for RP2C04-0001
+    // to -0004.
+    Rp2c04(u8),
+    // Standard colours, $2000 and $2001 swapped, and an ID in the low bits
+    // of $2002.
+    Rc2c05(u8),
+}
+
+impl VsPpu {
+    // NES 2.0 byte 13, low nibble.
+    pub fn from_nes2(ppu_type: u8) -> VsPpu {
+        match ppu_type & 0x0F {
+            model @ 2..=5 => VsPpu::Rp2c04(model - 1),
+            0x8 | 0xB => VsPpu::Rc2c05(0x1B),
+            0x9 => VsPpu::Rc2c05(0x3D),
+            0xA => VsPpu::Rc2c05(0x1C),
+            0xC => VsPpu::Rc2c05(0x00),
+            _ => VsPpu::Rp2c03,
+        }
+    }
+
+    pub fn swaps_ctrl_and_mask(&self) -> bool {
+        matches!(self, VsPpu::Rc2c05(_))
+    }
+
+    pub fn status_id(&self) -> Option<u8> {
+        match *self {
+            VsPpu::Rc2c05(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+// The cabinet's inputs beyond the two controllers.
+pub struct VsSystem {
+    pub ppu: VsPpu,
+    // Switches 1 to 8 in bits 0 to 7; what they do is up to each game, and
+    // all off is usually the factory setting.
+    pub dip_switches: u8,
+    // Coin slots 1 and 2 in bits 0 and 1, set while a coin is going in.
+    pub coins: u8,
+    pub service: bool,
+}
+
+impl VsSystem {
+    pub fn new(ppu: VsPpu) -> Self {
+        VsSystem {
+            ppu,
+            dip_switches: 0,
+            coins: 0,
+            service: false,
+        }
+    }
+
+    // Bits added to controller reads: the service button, switches 1-2 and
+    // the coin slots on $4016, switches 3-8 on $4017.
+    pub fn port_bits(&self, port: usize) -> u8 {
+        match port {
+            0 => {
+                (self.service as u8) << 2
+                    | (self.dip_switches & 0x03) << 3
+                    | (self.coins & 0x03) << 5
+            }
+            _ => self.dip_switches & 0xFC,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cabinet_inputs_share_the_controller_ports() {
+        let mut vs = VsSystem::new(VsPpu::Rp2c03);
+        vs.dip_switches = 0b1010_0110;
+        vs.coins = 0b01;
+        vs.service = true;
+        assert_eq!(vs.port_bits(0), 0b0011_0100);
+        assert_eq!(vs.port_bits(1), 0b1010_0100);
+    }
+
+    #[test]
+    fn test_ppu_types() {
+        assert_eq!(VsPpu::from_nes2(0x00), VsPpu::Rp2c03);
+        assert_eq!(VsPpu::from_nes2(0x05), VsPpu::Rp2c04(4));
+        assert_eq!(VsPpu::from_nes2(0x19), VsPpu::Rc2c05(0x3D));
+        assert!(VsPpu::Rc2c05(0x1B).swaps_ctrl_and_mask());
+        assert_eq!(VsPpu::Rp2c04(1).status_id(), None);
+    }
+}
@@ -0,0 +1,108 @@
+// Runs blargg's test ROMs without a window and fails with the text a ROM
+// prints for each one that doesn't pass. The ROMs aren't kept in the
+// repository: each test runs every .nes file in a directory named after its
+// suite, such as cpu_instrs, under $BLARGG_ROMS or tests/fixtures/blargg.
+//
+//     BLARGG_ROMS=~/nes-test-roms cargo test -p pico-core --release --features blargg --test blargg
+
+#![cfg(feature = "blargg")]
+
+use std::path::PathBuf;
+
+use pico_core::apu::sink::AudioSink;
+use pico_core::cart::Cart;
+use pico_core::headless::Headless;
+
+// Frames a ROM gets to finish in. cpu_instrs, the slowest, takes about a
+// minute of emulated time.
+const TIMEOUT_FRAMES: usize = 60 * 60 * 3;
+// A ROM asking to be reset wants at least 100 ms before it is.
+const RESET_DELAY_FRAMES: usize = 6;
+
+// Written to $6001-$6003 once $6000 holds a status.
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET: u8 = 0x81;
+
+// Runs a ROM to its result code, 0 for a pass, and the text it printed at
+// $6004.
+fn run(rom: Vec<u8>) -> Result<(u8, String), String> {
+    let mut headless = Headless::with_audio_sink(Cart::new(&rom)?, AudioSink::Null);
+    let mut reset_at = None;
+    while headless.frame() < TIMEOUT_FRAMES {
+        headless.run_frame();
+        let bus = &headless.nes.bus;
+        if [0x6001, 0x6002, 0x6003].map(|addr| bus.peek(addr)) != SIGNATURE {
+            continue;
+        }
+        match bus.peek(0x6000) {
+            STATUS_RUNNING => {}
+            STATUS_RESET => {
+                let asked = *reset_at.get_or_insert(headless.frame());
+                if headless.frame() - asked >= RESET_DELAY_FRAMES {
+                    headless.nes.reset();
+                    reset_at = None;
+                }
+            }
+            code => {
+                let text = (0x6004..0x8000)
+                    .map(|addr| bus.peek(addr))
+                    .take_while(|&byte| byte != 0)
+                    .map(char::from)
+                    .collect();
+                return Ok((code, text));
+            }
+        }
+    }
+    Err(format!("No result after {} frames", TIMEOUT_FRAMES))
+}
+
+// Runs every ROM of a suite and panics listing the ones that failed.
+fn run_suite(suite: &str) {
+    let dir = std::env::var_os("BLARGG_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/blargg"))
+        .join(suite);
+    let entries = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", dir.display(), e));
+    let mut roms: Vec<PathBuf> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nes"))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty(), "No ROMs in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in &roms {
+        let name = path.file_name().unwrap().to_string_lossy();
+        let rom = std::fs::read(path).unwrap();
+        match run(rom) {
+            Ok((0, _)) => {}
+            Ok((code, text)) => {
+                failures.push(format!("{}: failed with {}\n{}", name, code, text.trim()))
+            }
+            Err(e) => failures.push(format!("{}: {}", name, e)),
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn test_cpu_instrs() {
+    run_suite("cpu_instrs");
+}
+
+#[test]
+fn test_ppu_vbl_nmi() {
+    run_suite("ppu_vbl_nmi");
+}
+
+#[test]
+fn test_apu_test() {
+    run_suite("apu_test");
+}
+
+#[test]
+fn test_sprite_hit() {
+    run_suite("sprite_hit");
+}
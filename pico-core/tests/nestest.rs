@@ -0,0 +1,57 @@
+// Runs nestest.nes from $C000, its automated mode, and checks each instruction
+// against the log of a known-good run: the registers before it and the CPU
+// cycle it starts on, which `trace` prints the same way. The ROM and log
+// aren't kept in the repository: copy nestest.nes and nestest.log into
+// tests/fixtures, and the test fails without them.
+//
+//     cargo test -p pico-core --features nestest --test nestest
+
+#![cfg(feature = "nestest")]
+
+use std::path::PathBuf;
+
+use pico_core::apu::APU;
+use pico_core::cart::Cart;
+use pico_core::cpu::StatusFlags;
+use pico_core::nes::Nes;
+use pico_core::trace::trace;
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e))
+}
+
+// A log line without its PPU position, which `trace` leaves out.
+fn without_ppu(line: &str) -> String {
+    match (line.find(" PPU:"), line.find(" CYC:")) {
+        (Some(ppu), Some(cycles)) => format!("{}{}", &line[..ppu], &line[cycles..]),
+        _ => line.to_string(),
+    }
+}
+
+#[test]
+fn test_nestest_matches_golden_log() {
+    let rom = fixture("nestest.nes");
+    let log = String::from_utf8(fixture("nestest.log")).unwrap();
+
+    let mut nes = Nes::new(Cart::new(&rom).unwrap(), APU::new(48_000));
+    nes.reset();
+    nes.cpu.registers.pc = 0xC000;
+    nes.cpu.registers.status = StatusFlags::from_bits_truncate(0x24);
+
+    for (number, line) in log.lines().enumerate() {
+        assert_eq!(
+            trace(&nes.cpu, &nes.bus),
+            without_ppu(line.trim_end()),
+            "line {}",
+            number + 1
+        );
+        nes.run_until(|result| result.instruction_complete);
+    }
+
+    // nestest leaves the number of the first failed test of official and of
+    // unofficial opcodes in $02 and $03.
+    assert_eq!([nes.bus.peek(0x02), nes.bus.peek(0x03)], [0, 0]);
+}
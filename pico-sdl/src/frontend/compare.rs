@@ -0,0 +1,54 @@
+use clap::Args;
+use pico_core::cart::Cart;
+use pico_core::compare::compare;
+use pico_core::headless::Headless;
+use pico_core::movie::FM2Movie;
+use pico_core::nes::AccuracyProfile;
+
+use crate::parse_accuracy;
+
+#[derive(Args)]
+pub struct CompareArgs {
+    rom_file: String,
+    /// Movie supplying the input for both consoles (default: no input)
+    movie_file: Option<String>,
+
+    /// Accuracy profile of the first console
+    #[arg(long, default_value = "fast", value_parser = parse_accuracy)]
+    a: AccuracyProfile,
+
+    /// Accuracy profile of the second console
+    #[arg(long, default_value = "accurate", value_parser = parse_accuracy)]
+    b: AccuracyProfile,
+
+    /// Frames to run before giving up, unless the movie ends first
+    #[arg(long, default_value_t = 60 * 60)]
+    frames: usize,
+}
+
+fn console(bytes: &[u8], accuracy: AccuracyProfile) -> Result<Headless, String> {
+    let mut headless = Headless::new(Cart::new(&bytes.to_vec())?);
+    headless.nes.set_accuracy(accuracy);
+    Ok(headless)
+}
+
+pub fn run(args: &CompareArgs) -> Result<(), String> {
+    let bytes = std::fs::read(&args.rom_file).map_err(|e| format!("Failed to read ROM: {}", e))?;
+    let movie = match &args.movie_file {
+        Some(path) => Some(FM2Movie::load_from_file(path)?),
+        None => None,
+    };
+
+    let mut a = console(&bytes, args.a)?;
+    let mut b = console(&bytes, args.b)?;
+    match compare(&mut a, &mut b, movie.as_ref(), args.frames) {
+        Some(divergence) => println!("{} vs {}: {}", args.a.name(), args.b.name(), divergence),
+        None => println!(
+            "{} and {} agree for all {} frames",
+            args.a.name(),
+            args.b.name(),
+            a.frame()
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use pico_core::debugger::{self, Debugger, Stop};
+use pico_core::disasm;
+use pico_core::nes::Nes;
+use pico_core::trace::TraceRecord;
+
+// Debugger commands typed on stdin, read on a thread of their own so the
+// window keeps drawing while the CPU is stopped.
+pub struct DebugPrompt {
+    receiver: Receiver<String>,
+}
+
+impl DebugPrompt {
+    pub fn open() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        DebugPrompt { receiver }
+    }
+
+    // Runs the commands typed since the last call.
+    pub fn poll(&self, nes: &mut Nes) {
+        for line in self.receiver.try_iter() {
+            run_command(nes, &line);
+        }
+    }
+}
+
+// For hotkeys as well as the prompt.
+pub fn run_command(nes: &mut Nes, line: &str) {
+    match debugger::execute(nes, line) {
+        Ok(text) => print!("{text}"),
+        Err(e) => eprintln!("{e}"),
+    }
+}
+
+// Shows why the CPU stopped and the instruction it will run next, or the
+// next few when following.
+pub fn print_stop(nes: &Nes, stop: Stop) {
+    println!("{stop}");
+    if nes.bus.debugger.as_ref().is_some_and(Debugger::follows) {
+        let pc = nes.cpu.registers.pc;
+        let mut listing = disasm::disassemble_count(&nes.bus, pc, 8);
+        listing.current = Some(pc);
+        print!("{listing}");
+    } else {
+        println!("{}", TraceRecord::capture(&nes.cpu, &nes.bus));
+    }
+}
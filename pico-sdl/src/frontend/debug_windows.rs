@@ -0,0 +1,410 @@
+use std::collections::VecDeque;
+
+use pico_core::apu::ChannelId;
+use pico_core::nes::Nes;
+use pico_core::ppu::debug::{self, DebugImage};
+use pico_core::ppu::palette::SystemPalette;
+use sdl2::VideoSubsystem;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use super::font::draw_text;
+
+const SCOPE_WIDTH: u32 = 800;
+const SCOPE_LANE_HEIGHT: u32 = 100;
+const SCOPE_SAMPLES: usize = 1600;
+// The piano roll right of each scope lane, one column per frame, and the
+// volume meter at the lane's edge.
+const ROLL_FRAMES: usize = 240;
+const METER_WIDTH: u32 = 12;
+// The notes the piano roll spans, as MIDI numbers from C1 to C8.
+const ROLL_LOW_NOTE: f32 = 24.0;
+const ROLL_HIGH_NOTE: f32 = 108.0;
+const SWATCH_SIZE: u32 = 40;
+const INFO_HEIGHT: u32 = 24;
+// The sprite list beside the sprite viewer's tiles, in two columns of 32.
+const SPRITE_SCALE: u32 = 3;
+const SPRITE_LIST_WIDTH: u32 = 2 * 200;
+// How far one key press moves a color component in the palette editor.
+const COLOR_STEP: u8 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DebugView {
+    Nametables,
+    Patterns,
+    Sprites,
+    ApuScope,
+    SystemPalette,
+}
+
+impl DebugView {
+    fn title(&self) -> &'static str {
+        match self {
+            DebugView::Nametables => "pico - nametables",
+            DebugView::Patterns => "pico - patterns",
+            DebugView::Sprites => "pico - sprites",
+            DebugView::ApuScope => "pico - APU",
+            DebugView::SystemPalette => "pico - system palette",
+        }
+    }
+
+    fn size(&self) -> (u32, u32) {
+        match self {
+            DebugView::Nametables => (512 * 2, 480 * 2),
+            DebugView::Patterns => (256 * 3, (128 + 16) * 3),
+            DebugView::Sprites => (64 * SPRITE_SCALE + SPRITE_LIST_WIDTH, 128 * SPRITE_SCALE),
+            DebugView::ApuScope => (
+                SCOPE_WIDTH + ROLL_FRAMES as u32 + METER_WIDTH,
+                SCOPE_LANE_HEIGHT * ChannelId::ALL.len() as u32,
+            ),
+            DebugView::SystemPalette => (16 * SWATCH_SIZE, 4 * SWATCH_SIZE + INFO_HEIGHT),
+        }
+    }
+}
+
+struct DebugWindow {
+    view: DebugView,
+    canvas: Canvas<Window>,
+}
+
+// Auxiliary windows showing emulator internals next to the game window. Each
+// one is redrawn once per presented frame while it is open.
+#[derive(Default)]
+pub struct DebugWindows {
+    windows: Vec<DebugWindow>,
+    // Palette the pattern tables are shown with, cycled by clicking.
+    pattern_palette: u8,
+    // System palette entry being edited, picked by clicking its swatch.
+    palette_entry: u8,
+    // Each channel's note over the last frames, oldest first, for the piano
+    // roll.
+    notes: VecDeque<[Option<f32>; 5]>,
+    notes_frame: u64,
+}
+
+impl DebugWindows {
+    pub fn toggle(&mut self, video: &VideoSubsystem, view: DebugView) -> Result<(), String> {
+        if let Some(index) = self.windows.iter().position(|w| w.view == view) {
+            self.windows.remove(index);
+            return Ok(());
+        }
+
+        let (width, height) = view.size();
+        let window = video
+            .window(view.title(), width, height)
+            .resizable()
+            .build()
+            .map_err(|e| format!("Failed to open {} window: {}", view.title(), e))?;
+        let canvas = window
+            .into_canvas()
+            .build()
+            .map_err(|e| format!("Failed to open {} window: {}", view.title(), e))?;
+        self.windows.push(DebugWindow { view, canvas });
+        Ok(())
+    }
+
+    // Takes events aimed at one of the debug windows. Returns whether the
+    // event was used up here.
+    pub fn handle_event(&mut self, event: &Event, nes: &mut Nes) -> bool {
+        let Some(window_id) = event.get_window_id() else {
+            return false;
+        };
+        let Some(index) = self
+            .windows
+            .iter()
+            .position(|w| w.canvas.window().id() == window_id)
+        else {
+            return false;
+        };
+
+        match event {
+            Event::Window {
+                win_event: WindowEvent::Close,
+                ..
+            } => {
+                self.windows.remove(index);
+                true
+            }
+            Event::MouseButtonDown { .. } if self.windows[index].view == DebugView::Patterns => {
+                self.pattern_palette = (self.pattern_palette + 1) % 8;
+                true
+            }
+            Event::MouseButtonDown { x, y, .. }
+                if self.windows[index].view == DebugView::SystemPalette =>
+            {
+                let (width, height) = self.windows[index].canvas.window().size();
+                let swatches_height = height.saturating_sub(INFO_HEIGHT).max(1) as i32;
+                let column = (*x * 16 / width.max(1) as i32).clamp(0, 15);
+                let row = *y * 4 / swatches_height;
+                if row < 4 {
+                    self.palette_entry = (row * 16 + column) as u8;
+                }
+                true
+            }
+            Event::KeyDown {
+                keycode: Some(key),
+                keymod,
+                ..
+            } if self.windows[index].view == DebugView::SystemPalette => {
+                edit_palette(nes, self.palette_entry, *key, *keymod)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn draw(&mut self, nes: &Nes) {
+        let ppu = &nes.bus.ppu;
+        let mapper = nes.bus.cart.mapper.as_ref();
+
+        let scope_open = self.windows.iter().any(|w| w.view == DebugView::ApuScope);
+        if scope_open && nes.frame_count() != self.notes_frame {
+            self.notes_frame = nes.frame_count();
+            if self.notes.len() == ROLL_FRAMES {
+                self.notes.pop_front();
+            }
+            let apu = &nes.bus.apu;
+            self.notes
+                .push_back(ChannelId::ALL.map(|c| apu.channel_frequency(c).map(midi_note)));
+        }
+
+        for window in &mut self.windows {
+            let canvas = &mut window.canvas;
+            canvas.set_draw_color(Color::BLACK);
+            canvas.clear();
+            let (width, height) = canvas.output_size().unwrap_or((1, 1));
+
+            match window.view {
+                DebugView::Nametables => {
+                    let mut image = debug::render_nametables(ppu, mapper);
+                    debug::outline_scroll(&mut image, ppu);
+                    copy_image(canvas, &image, Rect::new(0, 0, width, height));
+                }
+                DebugView::Patterns => {
+                    let patterns = debug::render_pattern_tables(ppu, mapper, self.pattern_palette);
+                    let palettes = debug::render_palettes(ppu);
+                    let split = height * 128 / 144;
+                    copy_image(canvas, &patterns, Rect::new(0, 0, width, split));
+                    copy_image(
+                        canvas,
+                        &palettes,
+                        Rect::new(0, split as i32, width, height - split),
+                    );
+
+                    // Mark the palette in use.
+                    let swatch = width / 16;
+                    let row_height = (height - split) / 2;
+                    let index = self.pattern_palette as u32 * 4;
+                    canvas.set_draw_color(Color::WHITE);
+                    let _ = canvas.draw_rect(Rect::new(
+                        ((index % 16) * swatch) as i32,
+                        (split + (index / 16) * row_height) as i32,
+                        swatch * 4,
+                        row_height,
+                    ));
+                }
+                DebugView::Sprites => draw_sprites(canvas, nes, width, height),
+                DebugView::ApuScope => draw_scope(canvas, nes, &self.notes, width, height),
+                DebugView::SystemPalette => {
+                    draw_palette_editor(canvas, nes, self.palette_entry, width, height)
+                }
+            }
+            canvas.present();
+        }
+    }
+}
+
+// R, G and B raise the selected entry's components, with Shift lowering
+// them; Backspace puts back the default color.
+fn edit_palette(nes: &mut Nes, entry: u8, key: Keycode, keymod: Mod) -> bool {
+    let palette = &mut nes.bus.ppu.system_palette;
+    let (mut r, mut g, mut b) = palette.color(entry);
+    let component = match key {
+        Keycode::R => &mut r,
+        Keycode::G => &mut g,
+        Keycode::B => &mut b,
+        Keycode::Backspace => {
+            palette.set_color(entry, SystemPalette::default().color(entry));
+            return true;
+        }
+        _ => return false,
+    };
+    *component = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+        component.saturating_sub(COLOR_STEP)
+    } else {
+        component.saturating_add(COLOR_STEP)
+    };
+    palette.set_color(entry, (r, g, b));
+    true
+}
+
+fn draw_palette_editor(canvas: &mut Canvas<Window>, nes: &Nes, entry: u8, width: u32, height: u32) {
+    let ppu = &nes.bus.ppu;
+    let swatches_height = height.saturating_sub(INFO_HEIGHT);
+    let image = debug::render_system_palette(ppu);
+    copy_image(canvas, &image, Rect::new(0, 0, width, swatches_height));
+
+    let swatch_width = width / 16;
+    let swatch_height = swatches_height / 4;
+    canvas.set_draw_color(Color::WHITE);
+    let _ = canvas.draw_rect(Rect::new(
+        ((entry as u32 % 16) * swatch_width) as i32,
+        ((entry as u32 / 16) * swatch_height) as i32,
+        swatch_width,
+        swatch_height,
+    ));
+
+    let (r, g, b) = ppu.system_palette.color(entry);
+    let info = format!("{:02X}  R {:02X}  G {:02X}  B {:02X}", entry, r, g, b);
+    draw_text(canvas, 4, swatches_height as i32 + 4, 3, &info);
+}
+
+// The sprites' tiles on the left, in OAM order, and what each entry holds on
+// the right: position, tile, palette and flags for horizontal and vertical
+// flip and for being behind the background.
+fn draw_sprites(canvas: &mut Canvas<Window>, nes: &Nes, width: u32, height: u32) {
+    let ppu = &nes.bus.ppu;
+    let image = debug::render_sprites(ppu, nes.bus.cart.mapper.as_ref());
+    let tiles_width = width * 64 * SPRITE_SCALE / (64 * SPRITE_SCALE + SPRITE_LIST_WIDTH);
+    copy_image(canvas, &image, Rect::new(0, 0, tiles_width, height));
+
+    canvas.set_draw_color(Color::WHITE);
+    let column_width = (width - tiles_width) / 2;
+    let line_height = (height / 32) as i32;
+    for (index, sprite) in debug::decode_oam(&ppu.oam_data).iter().enumerate() {
+        let flag = |set: bool, name: char| if set { name } else { ' ' };
+        let line = format!(
+            "{:02X} X{:02X} Y{:02X} T{:02X} P{} {}{}{}",
+            index,
+            sprite.x,
+            sprite.y,
+            sprite.tile,
+            sprite.palette,
+            flag(sprite.flip_horizontal, 'H'),
+            flag(sprite.flip_vertical, 'V'),
+            flag(sprite.behind_background, 'B'),
+        );
+        let x = (tiles_width + (index as u32 / 32) * column_width) as i32 + 4;
+        let y = (index as i32 % 32) * line_height + 2;
+        draw_text(canvas, x, y, 2, &line);
+    }
+}
+
+fn copy_image(canvas: &mut Canvas<Window>, image: &DebugImage, dst: Rect) {
+    let texture_creator = canvas.texture_creator();
+    let Ok(mut texture) = texture_creator.create_texture_streaming(
+        PixelFormatEnum::RGB24,
+        image.width as u32,
+        image.height as u32,
+    ) else {
+        return;
+    };
+    if texture.update(None, &image.data, image.width * 3).is_ok() {
+        let _ = canvas.copy(&texture, None, Some(dst));
+    }
+}
+
+// MIDI note numbers, fractional between notes.
+fn midi_note(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+fn note_name(note: f32) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    let note = note.round() as i32;
+    format!(
+        "{}{}",
+        NAMES[note.rem_euclid(12) as usize],
+        note.div_euclid(12) - 1
+    )
+}
+
+// Each channel in a lane: its recent waveform, then a piano roll of the notes
+// it played over the last frames, then a meter of its volume now.
+fn draw_scope(
+    canvas: &mut Canvas<Window>,
+    nes: &Nes,
+    notes: &VecDeque<[Option<f32>; 5]>,
+    width: u32,
+    height: u32,
+) {
+    let apu = &nes.bus.apu;
+    let lane_height = height / ChannelId::ALL.len() as u32;
+    let total_width = SCOPE_WIDTH + ROLL_FRAMES as u32 + METER_WIDTH;
+    let scope_width = width * SCOPE_WIDTH / total_width;
+    let meter_width = (width * METER_WIDTH / total_width).max(1);
+    let roll_width = width.saturating_sub(scope_width + meter_width);
+
+    for (lane, channel) in ChannelId::ALL.into_iter().enumerate() {
+        let top = (lane as u32 * lane_height) as i32;
+        let samples = apu.channel_samples(channel, SCOPE_SAMPLES);
+        let (min, max) = apu.channel_range(channel);
+        let span = (max as i32 - min as i32).max(1);
+
+        let points: Vec<Point> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let x = i as i64 * scope_width as i64 / SCOPE_SAMPLES as i64;
+                let level = (max as i32 - *sample as i32).clamp(0, span);
+                let y = top + 4 + level * (lane_height as i32 - 8) / span;
+                Point::new(x as i32, y)
+            })
+            .collect();
+
+        let color = if apu.channel_muted(channel) {
+            Color::RGB(90, 90, 90)
+        } else {
+            Color::RGB(120, 220, 120)
+        };
+        canvas.set_draw_color(color);
+        let _ = canvas.draw_lines(points.as_slice());
+
+        // Newest frame at the right, higher notes further up.
+        canvas.set_draw_color(Color::RGB(25, 25, 25));
+        let _ = canvas.fill_rect(Rect::new(
+            scope_width as i32,
+            top,
+            roll_width.max(1),
+            lane_height,
+        ));
+        canvas.set_draw_color(color);
+        let column_width = roll_width.div_ceil(ROLL_FRAMES as u32).max(1);
+        let first = ROLL_FRAMES - notes.len();
+        for (i, frame) in notes.iter().enumerate() {
+            let Some(note) = frame[lane] else {
+                continue;
+            };
+            let x = scope_width as usize + (first + i) * roll_width as usize / ROLL_FRAMES;
+            let level = (ROLL_HIGH_NOTE - note) / (ROLL_HIGH_NOTE - ROLL_LOW_NOTE);
+            let y = top + 4 + (level.clamp(0.0, 1.0) * (lane_height - 8) as f32) as i32;
+            let _ = canvas.fill_rect(Rect::new(x as i32, y - 1, column_width, 3));
+        }
+
+        let volume = apu.channel_volume(channel).clamp(0.0, 1.0);
+        let meter_height = (volume * (lane_height - 8) as f32) as u32;
+        let _ = canvas.fill_rect(Rect::new(
+            (width - meter_width) as i32,
+            top + lane_height as i32 - 4 - meter_height as i32,
+            meter_width,
+            meter_height,
+        ));
+
+        canvas.set_draw_color(Color::RGB(60, 60, 60));
+        let _ = canvas.draw_line(
+            Point::new(0, top + lane_height as i32 - 1),
+            Point::new(width as i32, top + lane_height as i32 - 1),
+        );
+        canvas.set_draw_color(Color::WHITE);
+        let mut label = channel.name().to_uppercase();
+        if let Some(frequency) = apu.channel_frequency(channel) {
+            label.push_str(&format!(" {}", note_name(midi_note(frequency))));
+        }
+        draw_text(canvas, 4, top + 4, 2, &label);
+    }
+}
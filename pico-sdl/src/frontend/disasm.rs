@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use pico_core::cart::Cart;
+use pico_core::debugger::parse_addr;
+use pico_core::disasm;
+use pico_core::headless::Headless;
+
+#[derive(Args)]
+pub struct DisasmArgs {
+    rom_file: String,
+
+    /// First address to decode, in hex
+    #[arg(long, default_value = "8000", value_parser = parse_addr)]
+    from: u16,
+
+    /// Last address to decode, in hex
+    #[arg(long, default_value = "FFFF", value_parser = parse_addr)]
+    to: u16,
+
+    /// Frames to run first, so the listing shows the PRG banks the game has
+    /// switched in by then rather than those at power on
+    #[arg(long, default_value_t = 0)]
+    frames: usize,
+
+    /// File to write the listing to instead of printing it
+    #[arg(long, value_name = "PATH")]
+    out: Option<PathBuf>,
+}
+
+// Lists the code mapped into CPU memory, labelling the vectors and the
+// targets of branches, jumps and subroutine calls.
+pub fn run(args: &DisasmArgs) -> Result<(), String> {
+    if args.from > args.to {
+        return Err(format!(
+            "Address range ${:04X}-${:04X} ends before it starts",
+            args.from, args.to
+        ));
+    }
+    let bytes = std::fs::read(&args.rom_file).map_err(|e| format!("Failed to read ROM: {}", e))?;
+    let mut headless = Headless::new(Cart::new(&bytes)?);
+    for _ in 0..args.frames {
+        headless.run_frame();
+    }
+
+    let listing = disasm::disassemble(&headless.nes.bus, args.from..=args.to).to_string();
+    match &args.out {
+        Some(path) => std::fs::write(path, listing)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e)),
+        None => {
+            print!("{listing}");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use clap::Args;
+use pico_core::apu::sink::WavWriter;
+use pico_core::cart::Cart;
+use pico_core::headless::{Headless, SAMPLE_RATE};
+use pico_core::movie::FM2Movie;
+use pico_core::ppu::framebuffer::Framebuffer;
+
+#[derive(Args)]
+pub struct DumpFramesArgs {
+    rom_file: String,
+    movie_file: String,
+
+    /// First frame to save (0-based, inclusive)
+    #[arg(long, default_value_t = 0)]
+    from: usize,
+
+    /// Last frame to save (inclusive), defaults to the end of the movie
+    #[arg(long)]
+    to: Option<usize>,
+
+    /// Directory the PNGs and timestamps.csv are written to
+    #[arg(long, default_value = ".")]
+    out: PathBuf,
+
+    /// Also record the audio of the saved frames to this WAV file
+    #[arg(long)]
+    audio: Option<PathBuf>,
+
+    /// Pipe the frames to ffmpeg, encoding them to this file instead of
+    /// saving PNGs. The --audio WAV lines up with it for muxing
+    #[arg(long, value_name = "VIDEO")]
+    ffmpeg: Option<PathBuf>,
+}
+
+// Framebuffers going round between emulation and the encoder thread: one
+// being copied into, one waiting and one being encoded.
+const FRAME_BUFFERS: usize = 3;
+
+// Where each saved frame goes.
+enum FrameOutput {
+    Png(PathBuf),
+    Ffmpeg(Child),
+}
+
+impl FrameOutput {
+    fn ffmpeg(video: &Path, frame_rate: f64) -> Result<FrameOutput, String> {
+        let size = format!("{}x{}", Framebuffer::WIDTH, Framebuffer::HEIGHT);
+        let child = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgb24"])
+            .args(["-video_size", &size])
+            .args(["-framerate", &frame_rate.to_string()])
+            .args(["-i", "-", "-pix_fmt", "yuv420p"])
+            .arg(video)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+        Ok(FrameOutput::Ffmpeg(child))
+    }
+
+    fn write(&mut self, frame: usize, framebuffer: &Framebuffer) -> Result<(), String> {
+        match self {
+            FrameOutput::Png(dir) => {
+                framebuffer.save_png(dir.join(format!("frame_{frame:06}.png")))
+            }
+            FrameOutput::Ffmpeg(child) => child
+                .stdin
+                .as_mut()
+                .unwrap()
+                .write_all(&framebuffer.data)
+                .map_err(|e| format!("Failed to write to ffmpeg: {}", e)),
+        }
+    }
+
+    // Waits for ffmpeg to finish encoding.
+    fn finish(self) -> Result<(), String> {
+        let FrameOutput::Ffmpeg(mut child) = self else {
+            return Ok(());
+        };
+        drop(child.stdin.take());
+        let status = child
+            .wait()
+            .map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+        if !status.success() {
+            return Err(format!("ffmpeg failed: {}", status));
+        }
+        Ok(())
+    }
+}
+
+// Saves frames on a worker thread, so emulation doesn't wait for PNG
+// compression or for ffmpeg to take each one.
+struct Encoder {
+    frames: Sender<(usize, Framebuffer)>,
+    free: Receiver<Framebuffer>,
+    worker: JoinHandle<Result<(), String>>,
+}
+
+impl Encoder {
+    fn spawn(mut output: FrameOutput) -> Encoder {
+        let (frames, queued) = mpsc::channel::<(usize, Framebuffer)>();
+        let (recycle, free) = mpsc::channel();
+        for _ in 0..FRAME_BUFFERS {
+            recycle.send(Framebuffer::new()).unwrap();
+        }
+        let worker = thread::spawn(move || {
+            for (frame, framebuffer) in queued {
+                output.write(frame, &framebuffer)?;
+                // Emulation stops taking buffers back once it has finished.
+                let _ = recycle.send(framebuffer);
+            }
+            output.finish()
+        });
+        Encoder {
+            frames,
+            free,
+            worker,
+        }
+    }
+
+    // Queues a copy of the frame, waiting for a free buffer while the worker
+    // is behind. False once the worker has stopped on an error, which
+    // `finish` returns.
+    fn write(&self, frame: usize, framebuffer: &Framebuffer) -> bool {
+        let Ok(mut copy) = self.free.recv() else {
+            return false;
+        };
+        copy.data.copy_from_slice(&framebuffer.data);
+        self.frames.send((frame, copy)).is_ok()
+    }
+
+    // Waits for the queued frames to be saved.
+    fn finish(self) -> Result<(), String> {
+        drop(self.frames);
+        self.worker
+            .join()
+            .map_err(|_| "The frame encoder crashed".to_string())?
+    }
+}
+
+pub fn run(args: &DumpFramesArgs) -> Result<(), String> {
+    let bytes = std::fs::read(&args.rom_file).map_err(|e| format!("Failed to read ROM: {}", e))?;
+    let cart = Cart::new(&bytes)?;
+    let movie = FM2Movie::load_from_file(&args.movie_file)?;
+
+    let last = args
+        .to
+        .unwrap_or(usize::MAX)
+        .min(movie.frame_count().saturating_sub(1));
+    if args.from > last {
+        return Err(format!(
+            "Frame range {}..={} is outside the movie ({} frames)",
+            args.from,
+            last,
+            movie.frame_count()
+        ));
+    }
+
+    std::fs::create_dir_all(&args.out)
+        .map_err(|e| format!("Failed to create {}: {}", args.out.display(), e))?;
+
+    // Emulated time each saved frame finished at, for syncing with captures.
+    let mut timestamps = String::from("frame,cpu_cycles,nanos\n");
+
+    // Only the saved frames' samples are written, so the audio starts with
+    // the first frame of the video and runs exactly as long.
+    let mut audio = match &args.audio {
+        Some(path) => {
+            let file = File::create(path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+            let writer = WavWriter::new(BufWriter::new(file), SAMPLE_RATE)
+                .map_err(|e| format!("Failed to write audio: {}", e))?;
+            Some(writer)
+        }
+        None => None,
+    };
+
+    let mut headless = Headless::new(cart);
+    let output = match &args.ffmpeg {
+        Some(video) => FrameOutput::ffmpeg(video, headless.nes.region().frame_rate())?,
+        None => FrameOutput::Png(args.out.clone()),
+    };
+    let encoder = Encoder::spawn(output);
+    while headless.frame() <= last {
+        let frame = headless.frame();
+        let Some(framebuffer) = headless.run_movie_frame(&movie) else {
+            break;
+        };
+
+        if frame >= args.from {
+            if !encoder.write(frame, framebuffer) {
+                break;
+            }
+            let time = headless.frame_time();
+            timestamps.push_str(&format!("{},{},{}\n", frame, time.cpu_cycles, time.nanos));
+            if let Some(audio) = &mut audio {
+                for &sample in headless.audio() {
+                    audio.push(sample);
+                }
+            }
+        }
+        for event in headless.events() {
+            eprintln!("frame {}: {}: {}", frame, event.level().name(), event);
+        }
+    }
+
+    encoder.finish()?;
+    if let Some(audio) = audio {
+        audio.finish()?;
+    }
+
+    let timestamps_path = args.out.join("timestamps.csv");
+    std::fs::write(&timestamps_path, timestamps)
+        .map_err(|e| format!("Failed to write {}: {}", timestamps_path.display(), e))?;
+
+    let destination = args.ffmpeg.as_ref().unwrap_or(&args.out);
+    println!(
+        "Saved frames {}..={} to {}",
+        args.from,
+        headless.frame().saturating_sub(1),
+        destination.display()
+    );
+    Ok(())
+}
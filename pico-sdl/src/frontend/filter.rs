@@ -0,0 +1,34 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+use super::settings::VideoFilter;
+
+const NES_LINES: u32 = 240;
+
+// Post-processing drawn over the scaled frame, which fills `frame`.
+pub fn apply(canvas: &mut Canvas<Window>, filter: VideoFilter, frame: Rect) {
+    match filter {
+        VideoFilter::None => {}
+        VideoFilter::Scanlines => {
+            let line_height = frame.height() / NES_LINES;
+            if line_height < 2 {
+                return;
+            }
+
+            let gap = (line_height / 3).max(1);
+            let rects: Vec<Rect> = (0..NES_LINES)
+                .map(|line| {
+                    let y = frame.y() + (line * line_height + line_height - gap) as i32;
+                    Rect::new(frame.x(), y, frame.width(), gap)
+                })
+                .collect();
+
+            canvas.set_blend_mode(BlendMode::Blend);
+            canvas.set_draw_color(Color::RGBA(0, 0, 0, 110));
+            let _ = canvas.fill_rects(&rects);
+            canvas.set_blend_mode(BlendMode::None);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use pico_core::joypad::JoypadButton;
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+
+// How far a stick has to be pushed to count as the d-pad, out of 32767.
+const STICK_DEADZONE: i16 = 12_000;
+
+// Connected game controllers and the player each one is for. Controllers go
+// to the first free player as they are plugged in; any beyond two wait and
+// take over a player whose controller is unplugged.
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    players: [Option<GameController>; 2],
+    spare: Vec<GameController>,
+    button_map: Vec<(Button, JoypadButton)>,
+}
+
+impl Gamepads {
+    // SDL reports controllers already plugged in as added, so they are picked
+    // up with the first events.
+    pub fn new(
+        subsystem: GameControllerSubsystem,
+        button_map: Vec<(Button, JoypadButton)>,
+    ) -> Self {
+        Gamepads {
+            subsystem,
+            players: [None, None],
+            spare: Vec::new(),
+            button_map,
+        }
+    }
+
+    pub fn set_button_map(&mut self, button_map: Vec<(Button, JoypadButton)>) {
+        self.button_map = button_map;
+    }
+
+    // Handles hot-plugging. Returns a message for the player when a
+    // controller comes or goes.
+    pub fn handle_event(&mut self, event: &Event) -> Option<String> {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                let controller = match self.subsystem.open(which) {
+                    Ok(controller) => controller,
+                    Err(e) => return Some(format!("Failed to open controller: {}", e)),
+                };
+                let id = controller.instance_id();
+                if self.is_open(id) {
+                    return None;
+                }
+                let name = controller.name();
+                match self.players.iter().position(Option::is_none) {
+                    Some(player) => {
+                        self.players[player] = Some(controller);
+                        Some(format!("{} connected as player {}", name, player + 1))
+                    }
+                    None => {
+                        self.spare.push(controller);
+                        Some(format!("{} connected, no player free", name))
+                    }
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.spare
+                    .retain(|controller| controller.instance_id() != which);
+                let player = self.players.iter().position(|slot| {
+                    slot.as_ref()
+                        .is_some_and(|controller| controller.instance_id() == which)
+                })?;
+                self.players[player] = (!self.spare.is_empty()).then(|| self.spare.remove(0));
+                Some(format!("Player {} controller disconnected", player + 1))
+            }
+            _ => None,
+        }
+    }
+
+    fn is_open(&self, id: u32) -> bool {
+        self.players
+            .iter()
+            .flatten()
+            .chain(&self.spare)
+            .any(|controller| controller.instance_id() == id)
+    }
+
+    // Gives player 1's controller to player 2 and the other way round.
+    pub fn swap_players(&mut self) {
+        self.players.swap(0, 1);
+    }
+
+    pub fn buttons(&self, player: usize) -> JoypadButton {
+        let Some(controller) = &self.players[player] else {
+            return JoypadButton::empty();
+        };
+        let mut buttons = self
+            .button_map
+            .iter()
+            .filter(|(button, _)| controller.button(*button))
+            .fold(JoypadButton::empty(), |held, (_, nes)| held | *nes);
+        buttons |= stick_directions(controller.axis(Axis::LeftX), controller.axis(Axis::LeftY));
+        buttons
+    }
+}
+
+// SDL's stick axes grow to the right and downwards.
+fn stick_directions(x: i16, y: i16) -> JoypadButton {
+    let mut buttons = JoypadButton::empty();
+    buttons.set(JoypadButton::LEFT, x < -STICK_DEADZONE);
+    buttons.set(JoypadButton::RIGHT, x > STICK_DEADZONE);
+    buttons.set(JoypadButton::UP, y < -STICK_DEADZONE);
+    buttons.set(JoypadButton::DOWN, y > STICK_DEADZONE);
+    buttons
+}
@@ -0,0 +1,205 @@
+use pico_core::apu::ChannelId;
+use sdl2::keyboard::Keycode;
+
+use super::debug_windows::DebugView;
+
+// Savestate slots, numbered from 1 as the player sees them.
+pub const STATE_SLOTS: u8 = 4;
+
+// Emulator controls that can be bound to a key in the settings file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Menu,
+    Quit,
+    Reset,
+    // A numbered slot, or with `None` the one `NextStateSlot` selects.
+    SaveState(Option<u8>),
+    LoadState(Option<u8>),
+    NextStateSlot,
+    Pause,
+    FrameAdvance,
+    // Only with --debugger.
+    StepInstruction,
+    ToggleFastForward,
+    ToggleSlowMotion,
+    Screenshot,
+    ToggleMute(ChannelId),
+    ToggleStats,
+    ToggleDebugView(DebugView),
+    SaveInputHistory,
+    ExportChr,
+    ExportVisibleChr,
+    ImportChr,
+    ToggleRegisterLog,
+    ToggleMicrophone,
+    ExportPalette,
+    ToggleRenderer,
+    SwapAB,
+    SwapGamepads,
+    // Act while held rather than on the key press.
+    Rewind,
+    HoldFastForward,
+    // Vs. System cabinet buttons.
+    InsertCoin,
+    Service,
+}
+
+impl Action {
+    pub const ALL: [Action; 45] = [
+        Action::Menu,
+        Action::Quit,
+        Action::Reset,
+        Action::SaveState(None),
+        Action::SaveState(Some(1)),
+        Action::SaveState(Some(2)),
+        Action::SaveState(Some(3)),
+        Action::SaveState(Some(4)),
+        Action::LoadState(None),
+        Action::LoadState(Some(1)),
+        Action::LoadState(Some(2)),
+        Action::LoadState(Some(3)),
+        Action::LoadState(Some(4)),
+        Action::NextStateSlot,
+        Action::Pause,
+        Action::FrameAdvance,
+        Action::StepInstruction,
+        Action::ToggleFastForward,
+        Action::ToggleSlowMotion,
+        Action::Screenshot,
+        Action::ToggleMute(ChannelId::Pulse1),
+        Action::ToggleMute(ChannelId::Pulse2),
+        Action::ToggleMute(ChannelId::Triangle),
+        Action::ToggleMute(ChannelId::Noise),
+        Action::ToggleMute(ChannelId::Dmc),
+        Action::ToggleStats,
+        Action::ToggleDebugView(DebugView::Nametables),
+        Action::ToggleDebugView(DebugView::Patterns),
+        Action::ToggleDebugView(DebugView::Sprites),
+        Action::ToggleDebugView(DebugView::ApuScope),
+        Action::ToggleDebugView(DebugView::SystemPalette),
+        Action::SaveInputHistory,
+        Action::ExportChr,
+        Action::ExportVisibleChr,
+        Action::ImportChr,
+        Action::ToggleRegisterLog,
+        Action::ToggleMicrophone,
+        Action::ExportPalette,
+        Action::ToggleRenderer,
+        Action::SwapAB,
+        Action::SwapGamepads,
+        Action::Rewind,
+        Action::HoldFastForward,
+        Action::InsertCoin,
+        Action::Service,
+    ];
+
+    // Name used for the action in the settings file.
+    pub fn name(&self) -> String {
+        match self {
+            Action::Menu => "menu".to_string(),
+            Action::Quit => "quit".to_string(),
+            Action::Reset => "reset".to_string(),
+            Action::SaveState(None) => "save_state".to_string(),
+            Action::SaveState(Some(slot)) => format!("save_state_{slot}"),
+            Action::LoadState(None) => "load_state".to_string(),
+            Action::LoadState(Some(slot)) => format!("load_state_{slot}"),
+            Action::NextStateSlot => "next_state_slot".to_string(),
+            Action::Pause => "pause".to_string(),
+            Action::FrameAdvance => "frame_advance".to_string(),
+            Action::StepInstruction => "step_instruction".to_string(),
+            Action::ToggleFastForward => "fast_forward".to_string(),
+            Action::ToggleSlowMotion => "slow_motion".to_string(),
+            Action::Screenshot => "screenshot".to_string(),
+            Action::ToggleMute(channel) => format!("mute_{}", channel.name()),
+            Action::ToggleStats => "stats".to_string(),
+            Action::ToggleDebugView(DebugView::Nametables) => "nametable_viewer".to_string(),
+            Action::ToggleDebugView(DebugView::Patterns) => "pattern_viewer".to_string(),
+            Action::ToggleDebugView(DebugView::Sprites) => "sprite_viewer".to_string(),
+            Action::ToggleDebugView(DebugView::ApuScope) => "apu_scope".to_string(),
+            Action::ToggleDebugView(DebugView::SystemPalette) => "palette_editor".to_string(),
+            Action::SaveInputHistory => "save_input_history".to_string(),
+            Action::ExportChr => "export_chr".to_string(),
+            Action::ExportVisibleChr => "export_visible_chr".to_string(),
+            Action::ImportChr => "import_chr".to_string(),
+            Action::ToggleRegisterLog => "register_log".to_string(),
+            Action::ToggleMicrophone => "microphone".to_string(),
+            Action::ExportPalette => "export_palette".to_string(),
+            Action::ToggleRenderer => "renderer".to_string(),
+            Action::SwapAB => "swap_ab".to_string(),
+            Action::SwapGamepads => "swap_gamepads".to_string(),
+            Action::Rewind => "rewind".to_string(),
+            Action::HoldFastForward => "hold_fast_forward".to_string(),
+            Action::InsertCoin => "insert_coin".to_string(),
+            Action::Service => "service".to_string(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    pub fn default_key(&self) -> Option<Keycode> {
+        let key = match self {
+            Action::Menu => Keycode::Escape,
+            Action::Quit => return None,
+            Action::Reset => Keycode::R,
+            Action::SaveState(None) => Keycode::F5,
+            Action::LoadState(None) => Keycode::F1,
+            Action::SaveState(Some(_)) | Action::LoadState(Some(_)) => return None,
+            Action::NextStateSlot => Keycode::Equals,
+            Action::Pause => Keycode::P,
+            Action::FrameAdvance => Keycode::Period,
+            Action::StepInstruction => Keycode::Comma,
+            Action::ToggleFastForward => Keycode::Tab,
+            Action::ToggleSlowMotion => Keycode::Slash,
+            Action::Screenshot => Keycode::F12,
+            Action::ToggleMute(ChannelId::Pulse1) => Keycode::Num1,
+            Action::ToggleMute(ChannelId::Pulse2) => Keycode::Num2,
+            Action::ToggleMute(ChannelId::Triangle) => Keycode::Num3,
+            Action::ToggleMute(ChannelId::Noise) => Keycode::Num4,
+            Action::ToggleMute(ChannelId::Dmc) => Keycode::Num5,
+            Action::ToggleStats => Keycode::F3,
+            Action::ToggleDebugView(DebugView::Nametables) => Keycode::F10,
+            Action::ToggleDebugView(DebugView::Patterns) => Keycode::F11,
+            Action::ToggleDebugView(DebugView::Sprites) => Keycode::O,
+            Action::ToggleDebugView(DebugView::ApuScope) => Keycode::F4,
+            Action::ToggleDebugView(DebugView::SystemPalette) => Keycode::C,
+            Action::SaveInputHistory => Keycode::F2,
+            Action::ExportChr => Keycode::F6,
+            Action::ExportVisibleChr => Keycode::F7,
+            Action::ImportChr => Keycode::F8,
+            Action::ToggleRegisterLog => Keycode::F9,
+            Action::ToggleMicrophone => Keycode::M,
+            Action::ExportPalette => Keycode::V,
+            Action::ToggleRenderer => Keycode::N,
+            Action::SwapAB => Keycode::B,
+            Action::SwapGamepads => Keycode::G,
+            Action::Rewind => Keycode::Backspace,
+            Action::HoldFastForward => Keycode::Backquote,
+            Action::InsertCoin => Keycode::Num9,
+            Action::Service => Keycode::Num0,
+        };
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_action_names_round_trip() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(&action.name()), Some(action));
+        }
+        assert_eq!(
+            Action::from_name("mute_triangle"),
+            Some(Action::ToggleMute(ChannelId::Triangle))
+        );
+        assert_eq!(
+            Action::from_name("load_state_3"),
+            Some(Action::LoadState(Some(3)))
+        );
+        assert_eq!(Action::from_name("nope"), None);
+    }
+}
@@ -0,0 +1,604 @@
+use std::path::{Path, PathBuf};
+
+use pico_core::apu::sink::FastForwardAudio;
+use pico_core::cheats::Cheats;
+use pico_core::joypad::{ButtonLayout, DpadPolicy};
+use pico_core::ppu::palette::PalettePreset;
+use pico_core::ppu::render::Renderer;
+use pico_core::region::Region;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+use super::font::{GLYPH_HEIGHT, draw_text};
+use super::hotkeys::STATE_SLOTS;
+use super::settings::{
+    BUTTONS, FastForwardSpeed, FocusLoss, OnJam, Settings, SlowMotion, VideoFilter,
+};
+use super::video::{PixelAspect, ScaleMode};
+
+const TEXT_SCALE: i32 = 3;
+const LINE_HEIGHT: i32 = (GLYPH_HEIGHT + 3) * TEXT_SCALE;
+const MARGIN: i32 = 24;
+const VISIBLE_ROWS: usize = 24;
+const MAX_LABEL_CHARS: usize = 56;
+// Rows of the input page after the key bindings, one layout per player.
+const LAYOUT_ROWS: usize = 2;
+
+// Things the menu can't do by itself and hands back to the main loop.
+pub enum MenuAction {
+    Reset,
+    SaveState,
+    LoadState,
+    OpenRom(PathBuf),
+    ToggleCheat(usize),
+    SettingsChanged,
+    SetAudioDevice,
+    SetPalette,
+    SetRegion,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MainItem {
+    Resume,
+    Reset,
+    SaveState,
+    LoadState,
+    StateSlot,
+    OpenRom,
+    Cheats,
+    Input,
+    Filter,
+    Scaling,
+    PixelAspect,
+    Palette,
+    Dpad,
+    Renderer,
+    Region,
+    AudioDevice,
+    FocusLoss,
+    FastForwardSpeed,
+    FastForwardAudio,
+    SlowMotion,
+    OnJam,
+    Quit,
+}
+
+const MAIN_ITEMS: [MainItem; 22] = [
+    MainItem::Resume,
+    MainItem::Reset,
+    MainItem::SaveState,
+    MainItem::LoadState,
+    MainItem::StateSlot,
+    MainItem::OpenRom,
+    MainItem::Cheats,
+    MainItem::Input,
+    MainItem::Filter,
+    MainItem::Scaling,
+    MainItem::PixelAspect,
+    MainItem::Palette,
+    MainItem::Dpad,
+    MainItem::Renderer,
+    MainItem::Region,
+    MainItem::AudioDevice,
+    MainItem::FocusLoss,
+    MainItem::FastForwardSpeed,
+    MainItem::FastForwardAudio,
+    MainItem::SlowMotion,
+    MainItem::OnJam,
+    MainItem::Quit,
+];
+
+enum Page {
+    Main,
+    OpenRom { dir: PathBuf, entries: Vec<PathBuf> },
+    Cheats,
+    Input { waiting: bool },
+    AudioDevice,
+}
+
+pub struct Menu {
+    pub open: bool,
+    page: Page,
+    selected: usize,
+    audio_devices: Vec<String>,
+}
+
+impl Menu {
+    pub fn new(audio_devices: Vec<String>) -> Self {
+        Menu {
+            open: false,
+            page: Page::Main,
+            selected: 0,
+            audio_devices,
+        }
+    }
+
+    pub fn show(&mut self) {
+        self.open = true;
+        self.page = Page::Main;
+        self.selected = 0;
+    }
+
+    fn go_to(&mut self, page: Page) {
+        self.page = page;
+        self.selected = 0;
+    }
+
+    fn row_count(&self, cheats: &Cheats) -> usize {
+        match &self.page {
+            Page::Main => MAIN_ITEMS.len(),
+            Page::OpenRom { entries, .. } => entries.len(),
+            Page::Cheats => cheats.cheats().len(),
+            Page::Input { .. } => BUTTONS.len() + LAYOUT_ROWS,
+            Page::AudioDevice => self.audio_devices.len() + 1,
+        }
+    }
+
+    // `state_slot` is the slot saving and loading go to, which the menu can
+    // change.
+    pub fn handle_key(
+        &mut self,
+        key: Keycode,
+        settings: &mut Settings,
+        current_rom: &Path,
+        rom_crc: u32,
+        cheats: &Cheats,
+        state_slot: &mut u8,
+    ) -> Option<MenuAction> {
+        if let Page::Input { waiting: true } = self.page {
+            self.page = Page::Input { waiting: false };
+            if key != Keycode::Escape {
+                settings.bind_key(BUTTONS[self.selected].0, key);
+                return Some(MenuAction::SettingsChanged);
+            }
+            return None;
+        }
+
+        let rows = self.row_count(cheats);
+        match key {
+            Keycode::Up if rows > 0 => self.selected = (self.selected + rows - 1) % rows,
+            Keycode::Down if rows > 0 => self.selected = (self.selected + 1) % rows,
+            Keycode::Left | Keycode::Right => {
+                let forward = key == Keycode::Right;
+                match self.page {
+                    Page::Main => match MAIN_ITEMS[self.selected] {
+                        MainItem::StateSlot => cycle_state_slot(state_slot, forward),
+                        MainItem::Filter => return Some(cycle_filter(settings, forward)),
+                        MainItem::Scaling => return Some(cycle_scale_mode(settings, forward)),
+                        MainItem::PixelAspect => {
+                            return Some(cycle_pixel_aspect(settings, forward));
+                        }
+                        MainItem::Palette => return Some(cycle_palette(settings, forward)),
+                        MainItem::Dpad => return Some(cycle_dpad_policy(settings, forward)),
+                        MainItem::Renderer => return Some(cycle_renderer(settings, forward)),
+                        MainItem::Region => return Some(cycle_region(settings, forward)),
+                        MainItem::FocusLoss => return Some(cycle_focus_loss(settings, forward)),
+                        MainItem::FastForwardSpeed => {
+                            return Some(cycle_fast_forward_speed(settings, forward));
+                        }
+                        MainItem::FastForwardAudio => {
+                            return Some(cycle_fast_forward_audio(settings, forward));
+                        }
+                        MainItem::SlowMotion => return Some(cycle_slow_motion(settings, forward)),
+                        MainItem::OnJam => return Some(cycle_on_jam(settings, forward)),
+                        _ => {}
+                    },
+                    Page::Input { .. } if self.selected >= BUTTONS.len() => {
+                        let player = self.selected - BUTTONS.len();
+                        return Some(cycle_layout(settings, rom_crc, player, forward));
+                    }
+                    _ => {}
+                }
+            }
+            Keycode::Escape | Keycode::Backspace => {
+                if let Page::Main = self.page {
+                    self.open = false;
+                } else {
+                    self.go_to(Page::Main);
+                }
+            }
+            Keycode::Return | Keycode::KpEnter => {
+                return self.activate(settings, current_rom, rom_crc, state_slot);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn activate(
+        &mut self,
+        settings: &mut Settings,
+        current_rom: &Path,
+        rom_crc: u32,
+        state_slot: &mut u8,
+    ) -> Option<MenuAction> {
+        match &self.page {
+            Page::Main => match MAIN_ITEMS[self.selected] {
+                MainItem::Resume => self.open = false,
+                MainItem::Reset => {
+                    self.open = false;
+                    return Some(MenuAction::Reset);
+                }
+                MainItem::SaveState => {
+                    self.open = false;
+                    return Some(MenuAction::SaveState);
+                }
+                MainItem::LoadState => {
+                    self.open = false;
+                    return Some(MenuAction::LoadState);
+                }
+                MainItem::StateSlot => cycle_state_slot(state_slot, true),
+                MainItem::OpenRom => {
+                    let dir = current_rom
+                        .parent()
+                        .filter(|dir| !dir.as_os_str().is_empty())
+                        .unwrap_or(Path::new("."))
+                        .to_path_buf();
+                    self.open_dir(dir);
+                }
+                MainItem::Cheats => self.go_to(Page::Cheats),
+                MainItem::Input => self.go_to(Page::Input { waiting: false }),
+                MainItem::Filter => return Some(cycle_filter(settings, true)),
+                MainItem::Scaling => return Some(cycle_scale_mode(settings, true)),
+                MainItem::PixelAspect => return Some(cycle_pixel_aspect(settings, true)),
+                MainItem::Palette => return Some(cycle_palette(settings, true)),
+                MainItem::Dpad => return Some(cycle_dpad_policy(settings, true)),
+                MainItem::Renderer => return Some(cycle_renderer(settings, true)),
+                MainItem::Region => return Some(cycle_region(settings, true)),
+                MainItem::AudioDevice => self.go_to(Page::AudioDevice),
+                MainItem::FocusLoss => return Some(cycle_focus_loss(settings, true)),
+                MainItem::FastForwardSpeed => {
+                    return Some(cycle_fast_forward_speed(settings, true));
+                }
+                MainItem::FastForwardAudio => {
+                    return Some(cycle_fast_forward_audio(settings, true));
+                }
+                MainItem::SlowMotion => return Some(cycle_slow_motion(settings, true)),
+                MainItem::OnJam => return Some(cycle_on_jam(settings, true)),
+                MainItem::Quit => return Some(MenuAction::Quit),
+            },
+            Page::OpenRom { entries, .. } => {
+                let path = entries.get(self.selected)?.clone();
+                if path.is_dir() {
+                    self.open_dir(path);
+                } else {
+                    self.open = false;
+                    return Some(MenuAction::OpenRom(path));
+                }
+            }
+            Page::Cheats => return Some(MenuAction::ToggleCheat(self.selected)),
+            Page::Input { .. } if self.selected >= BUTTONS.len() => {
+                let player = self.selected - BUTTONS.len();
+                return Some(cycle_layout(settings, rom_crc, player, true));
+            }
+            Page::Input { .. } => self.page = Page::Input { waiting: true },
+            Page::AudioDevice => {
+                settings.audio_device = match self.selected {
+                    0 => None,
+                    i => self.audio_devices.get(i - 1).cloned(),
+                };
+                self.go_to(Page::Main);
+                return Some(MenuAction::SetAudioDevice);
+            }
+        }
+        None
+    }
+
+    fn open_dir(&mut self, dir: PathBuf) {
+        let dir = dir.canonicalize().unwrap_or(dir);
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .map(|read_dir| {
+                read_dir
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| {
+                        path.is_dir()
+                            || path
+                                .extension()
+                                .is_some_and(|ext| ext.eq_ignore_ascii_case("nes"))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        entries.sort_by_key(|path| (!path.is_dir(), path.file_name().map(|n| n.to_owned())));
+        if let Some(parent) = dir.parent() {
+            entries.insert(0, parent.to_path_buf());
+        }
+
+        self.go_to(Page::OpenRom { dir, entries });
+    }
+
+    fn title_and_rows(
+        &self,
+        settings: &Settings,
+        rom_crc: u32,
+        cheats: &Cheats,
+        state_slot: u8,
+    ) -> (String, Vec<String>) {
+        match &self.page {
+            Page::Main => {
+                let rows = MAIN_ITEMS
+                    .iter()
+                    .map(|item| match item {
+                        MainItem::Resume => "Resume".to_string(),
+                        MainItem::Reset => "Reset".to_string(),
+                        MainItem::SaveState => "Save state".to_string(),
+                        MainItem::LoadState => "Load state".to_string(),
+                        MainItem::StateSlot => format!("State slot: < {state_slot} >"),
+                        MainItem::OpenRom => "Open ROM".to_string(),
+                        MainItem::Cheats => format!("Cheats ({})", cheats.cheats().len()),
+                        MainItem::Input => "Input".to_string(),
+                        MainItem::Filter => {
+                            format!("Filter: < {} >", settings.video_filter.name())
+                        }
+                        MainItem::Scaling => {
+                            format!("Scaling: < {} >", settings.scale_mode.name())
+                        }
+                        MainItem::PixelAspect => {
+                            format!("Pixel shape: < {} >", settings.pixel_aspect.name())
+                        }
+                        MainItem::Palette => {
+                            format!("Palette: < {} >", settings.palette.name())
+                        }
+                        MainItem::Dpad => {
+                            format!("Left+Right: < {} >", settings.dpad_policy.name())
+                        }
+                        MainItem::Renderer => format!(
+                            "Renderer: < {} >",
+                            settings.renderer.map_or("auto", |renderer| renderer.name())
+                        ),
+                        MainItem::Region => format!(
+                            "Region: < {} >",
+                            settings.region.map_or("auto", |region| region.name())
+                        ),
+                        MainItem::AudioDevice => format!(
+                            "Audio: {}",
+                            settings.audio_device.as_deref().unwrap_or("Default")
+                        ),
+                        MainItem::FocusLoss => {
+                            format!("In background: < {} >", settings.focus_loss.name())
+                        }
+                        MainItem::FastForwardSpeed => {
+                            format!("Fast-forward: < {} >", settings.fast_forward_speed.name())
+                        }
+                        MainItem::FastForwardAudio => format!(
+                            "Fast-forward sound: < {} >",
+                            settings.fast_forward_audio.name()
+                        ),
+                        MainItem::SlowMotion => {
+                            format!("Slow motion: < {} >", settings.slow_motion.name())
+                        }
+                        MainItem::OnJam => {
+                            format!("On CPU jam: < {} >", settings.on_jam.name())
+                        }
+                        MainItem::Quit => "Quit".to_string(),
+                    })
+                    .collect();
+                ("Paused".to_string(), rows)
+            }
+            Page::OpenRom { dir, entries } => {
+                let rows = entries
+                    .iter()
+                    .enumerate()
+                    .map(|(i, path)| {
+                        let name = path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        if i == 0 && dir.parent() == Some(path.as_path()) {
+                            "..".to_string()
+                        } else if path.is_dir() {
+                            format!("{name}/")
+                        } else {
+                            name
+                        }
+                    })
+                    .collect();
+                (format!("Open {}", dir.display()), rows)
+            }
+            Page::Cheats => {
+                let rows = cheats
+                    .cheats()
+                    .iter()
+                    .map(|cheat| {
+                        let mark = if cheat.enabled { "x" } else { " " };
+                        format!("[{mark}] {} {}", cheat.code, cheat.name)
+                    })
+                    .collect();
+                let title = if cheats.is_empty() {
+                    "Cheats: none, add them to the ROM's .cht file"
+                } else {
+                    "Cheats"
+                };
+                (title.to_string(), rows)
+            }
+            Page::Input { waiting } => {
+                let layouts = settings.rom(rom_crc).layouts;
+                let rows = BUTTONS
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, _))| {
+                        let key = if *waiting && i == self.selected {
+                            "press a key".to_string()
+                        } else {
+                            settings
+                                .key_for(name)
+                                .map(|key| key.name())
+                                .unwrap_or_else(|| "-".to_string())
+                        };
+                        format!("{name}: {key}")
+                    })
+                    .chain(layouts.iter().enumerate().map(|(player, layout)| {
+                        format!("P{} layout: < {} >", player + 1, layout.name())
+                    }))
+                    .collect();
+                ("Input".to_string(), rows)
+            }
+            Page::AudioDevice => {
+                let rows = std::iter::once("Default".to_string())
+                    .chain(self.audio_devices.iter().cloned())
+                    .collect();
+                ("Audio device".to_string(), rows)
+            }
+        }
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        settings: &Settings,
+        rom_crc: u32,
+        cheats: &Cheats,
+        state_slot: u8,
+    ) {
+        if !self.open {
+            return;
+        }
+
+        let (width, height) = canvas.output_size().unwrap_or((0, 0));
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 200));
+        let _ = canvas.fill_rect(Rect::new(0, 0, width, height));
+        canvas.set_blend_mode(BlendMode::None);
+
+        let (title, rows) = self.title_and_rows(settings, rom_crc, cheats, state_slot);
+        canvas.set_draw_color(Color::RGB(255, 200, 80));
+        draw_text(canvas, MARGIN, MARGIN, TEXT_SCALE, &truncate(&title));
+
+        let first = self
+            .selected
+            .saturating_sub(VISIBLE_ROWS - 1)
+            .min(rows.len().saturating_sub(VISIBLE_ROWS));
+        for (i, row) in rows.iter().enumerate().skip(first).take(VISIBLE_ROWS) {
+            let y = MARGIN + LINE_HEIGHT * (2 + (i - first) as i32);
+            let (marker, color) = if i == self.selected {
+                ("> ", Color::WHITE)
+            } else {
+                ("  ", Color::RGB(160, 160, 160))
+            };
+            canvas.set_draw_color(color);
+            draw_text(
+                canvas,
+                MARGIN,
+                y,
+                TEXT_SCALE,
+                &truncate(&format!("{marker}{row}")),
+            );
+        }
+    }
+}
+
+fn cycle_filter(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.video_filter = cycle(&VideoFilter::ALL, settings.video_filter, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_scale_mode(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.scale_mode = cycle(&ScaleMode::ALL, settings.scale_mode, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_pixel_aspect(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.pixel_aspect = cycle(&PixelAspect::ALL, settings.pixel_aspect, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_palette(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.palette = cycle(&PalettePreset::ALL, settings.palette, forward);
+    MenuAction::SetPalette
+}
+
+fn cycle_dpad_policy(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.dpad_policy = cycle(&DpadPolicy::ALL, settings.dpad_policy, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_renderer(settings: &mut Settings, forward: bool) -> MenuAction {
+    let choices = [
+        None,
+        Some(Renderer::ScrollSegments),
+        Some(Renderer::Scanline),
+    ];
+    settings.renderer = cycle(&choices, settings.renderer, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_region(settings: &mut Settings, forward: bool) -> MenuAction {
+    let choices = [
+        None,
+        Some(Region::Ntsc),
+        Some(Region::Pal),
+        Some(Region::Dendy),
+    ];
+    settings.region = cycle(&choices, settings.region, forward);
+    MenuAction::SetRegion
+}
+
+// Slots aren't a setting, so this one is kept by the caller and not saved.
+fn cycle_state_slot(state_slot: &mut u8, forward: bool) {
+    let slots: Vec<u8> = (1..=STATE_SLOTS).collect();
+    *state_slot = cycle(&slots, *state_slot, forward);
+}
+
+fn cycle_focus_loss(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.focus_loss = cycle(&FocusLoss::ALL, settings.focus_loss, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_fast_forward_speed(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.fast_forward_speed =
+        cycle(&FastForwardSpeed::ALL, settings.fast_forward_speed, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_fast_forward_audio(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.fast_forward_audio =
+        cycle(&FastForwardAudio::ALL, settings.fast_forward_audio, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_slow_motion(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.slow_motion = cycle(&SlowMotion::ALL, settings.slow_motion, forward);
+    MenuAction::SettingsChanged
+}
+
+fn cycle_on_jam(settings: &mut Settings, forward: bool) -> MenuAction {
+    settings.on_jam = cycle(&OnJam::ALL, settings.on_jam, forward);
+    MenuAction::SettingsChanged
+}
+
+// Layouts are kept for the game being played.
+fn cycle_layout(settings: &mut Settings, rom_crc: u32, player: usize, forward: bool) -> MenuAction {
+    let mut rom = settings.rom(rom_crc);
+    rom.layouts[player] = cycle(&ButtonLayout::ALL, rom.layouts[player], forward);
+    settings.set_rom(rom_crc, rom);
+    MenuAction::SettingsChanged
+}
+
+fn cycle<T: Copy + PartialEq>(all: &[T], current: T, forward: bool) -> T {
+    let index = all.iter().position(|item| *item == current).unwrap_or(0);
+    let next = if forward {
+        (index + 1) % all.len()
+    } else {
+        (index + all.len() - 1) % all.len()
+    };
+    all[next]
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_LABEL_CHARS {
+        return text.to_string();
+    }
+    let tail: String = text
+        .chars()
+        .rev()
+        .take(MAX_LABEL_CHARS - 3)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    format!("...{tail}")
+}
@@ -0,0 +1,19 @@
+pub mod compare;
+pub mod debug_prompt;
+pub mod debug_windows;
+pub mod disasm;
+pub mod dump_frames;
+pub mod filter;
+pub mod font;
+pub mod gamepads;
+pub mod hotkeys;
+pub mod menu;
+pub mod nsf_player;
+pub mod osd;
+pub mod overlay;
+pub mod report;
+pub mod script;
+pub mod settings;
+pub mod triple_buffer;
+pub mod verify;
+pub mod video;
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use pico_core::nsf::NsfPlayer;
+use pico_core::pacing::{FramePacer, HostClock};
+use sdl2::Sdl;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+
+use super::font::{GLYPH_HEIGHT, draw_text};
+
+const TEXT_SCALE: i32 = 3;
+const LINE_HEIGHT: i32 = (GLYPH_HEIGHT + 3) * TEXT_SCALE;
+const MARGIN: i32 = 24;
+
+// Plays an NSF until the window is closed: Left and Right change track, and
+// the tune's details are shown in place of a picture. The sound goes to
+// `audio_buffer` for the audio device to play.
+pub fn run(
+    sdl_ctx: &Sdl,
+    mut player: NsfPlayer,
+    audio_buffer: &Mutex<VecDeque<f32>>,
+    clock: &impl HostClock,
+) -> Result<(), String> {
+    let video_subsystem = sdl_ctx.video()?;
+    let window = video_subsystem
+        .window(
+            "pico",
+            crate::WIDTH * crate::SCALE,
+            crate::HEIGHT * crate::SCALE,
+        )
+        .position_centered()
+        .build()
+        .map_err(|e| format!("Failed to open window: {}", e))?;
+    let mut canvas = window
+        .into_canvas()
+        .present_vsync()
+        .build()
+        .map_err(|e| format!("Failed to create canvas: {}", e))?;
+    let mut event_pump = sdl_ctx.event_pump()?;
+    let mut pacer = FramePacer::default();
+    let mut samples = Vec::new();
+
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(()),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Right),
+                    ..
+                } => player.next_song(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Left),
+                    ..
+                } => player.previous_song(),
+                _ => {}
+            }
+        }
+
+        let now = clock.now();
+        if pacer.frame_due(now) {
+            pacer.start_frame(now, player.nes.region().frame_rate());
+            player.run_frame();
+            player.nes.bus.apu.take_samples(&mut samples);
+            audio_buffer.lock().unwrap().extend(&samples);
+        }
+
+        let header = player.header();
+        let lines = [
+            header.title.clone(),
+            header.artist.clone(),
+            header.copyright.clone(),
+            String::new(),
+            format!("Track {} / {}", player.song() + 1, header.total_songs),
+            String::new(),
+            "< > change track  Esc quits".to_string(),
+        ];
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        canvas.set_draw_color(Color::WHITE);
+        for (i, line) in lines.iter().enumerate() {
+            draw_text(
+                &mut canvas,
+                MARGIN,
+                MARGIN + i as i32 * LINE_HEIGHT,
+                TEXT_SCALE,
+                line,
+            );
+        }
+        canvas.present();
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+
+use pico_core::event::{EmulatorEvent, EventLevel};
+use pico_core::stats::StatsSnapshot;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+use super::font::{GLYPH_HEIGHT, draw_text, text_width};
+
+const TEXT_SCALE: i32 = 2;
+const PADDING: i32 = 4;
+// How long an event stays on screen, whether or not the stats are shown.
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
+#[derive(Default)]
+pub struct Osd {
+    pub visible: bool,
+    message: Option<(EmulatorEvent, Instant)>,
+}
+
+impl Osd {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    // Replaces whatever event was being shown.
+    pub fn show_event(&mut self, event: EmulatorEvent) {
+        self.message = Some((event, Instant::now()));
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, stats: &StatsSnapshot) {
+        self.draw_message(canvas);
+        if !self.visible {
+            return;
+        }
+
+        let mut lines = vec![
+            format!("FPS {:.1} / {:.1}", stats.fps, stats.target_fps),
+            format!("HOST {:.2} MS", stats.frame_time_ms),
+            format!(
+                "AUDIO {:.0} MS {:.0}%",
+                stats.audio_buffered_ms,
+                stats.audio_fill * 100.0
+            ),
+            format!("SPEED {:.2}X", stats.speed),
+        ];
+        if stats.audio_underruns > 0 {
+            lines.push(format!("UNDERRUNS {}", stats.audio_underruns));
+        }
+        if stats.compat_notes > 0 {
+            lines.push(format!("COMPAT NOTES {}", stats.compat_notes));
+        }
+
+        let line_height = (GLYPH_HEIGHT + 2) * TEXT_SCALE;
+        let width = lines
+            .iter()
+            .map(|line| text_width(line, TEXT_SCALE))
+            .max()
+            .unwrap_or(0);
+        let height = line_height * lines.len() as i32;
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        let _ = canvas.fill_rect(Rect::new(
+            0,
+            0,
+            (width + PADDING * 2) as u32,
+            (height + PADDING * 2) as u32,
+        ));
+        canvas.set_blend_mode(BlendMode::None);
+
+        // Turn the FPS line red when the emulator can't keep up.
+        let behind = stats.fps > 0.0 && stats.fps < stats.target_fps * 0.95;
+        for (i, line) in lines.iter().enumerate() {
+            let color = if i == 0 && behind {
+                Color::RGB(255, 80, 80)
+            } else {
+                Color::WHITE
+            };
+            canvas.set_draw_color(color);
+            draw_text(
+                canvas,
+                PADDING,
+                PADDING + i as i32 * line_height,
+                TEXT_SCALE,
+                line,
+            );
+        }
+    }
+
+    fn draw_message(&self, canvas: &mut Canvas<Window>) {
+        let Some((event, shown_at)) = &self.message else {
+            return;
+        };
+        if shown_at.elapsed() > MESSAGE_DURATION {
+            return;
+        }
+
+        let text = event.to_string();
+        let width = text_width(&text, TEXT_SCALE) + PADDING * 2;
+        let height = GLYPH_HEIGHT * TEXT_SCALE + PADDING * 2;
+        let y = canvas.viewport().height() as i32 - height;
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        let _ = canvas.fill_rect(Rect::new(0, y, width as u32, height as u32));
+        canvas.set_blend_mode(BlendMode::None);
+
+        canvas.set_draw_color(match event.level() {
+            EventLevel::Notice => Color::WHITE,
+            EventLevel::Warning => Color::RGB(255, 200, 80),
+            EventLevel::Error => Color::RGB(255, 80, 80),
+        });
+        draw_text(canvas, PADDING, y + PADDING, TEXT_SCALE, &text);
+    }
+}
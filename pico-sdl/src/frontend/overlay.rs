@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+use super::font::{GLYPH_HEIGHT, draw_text, text_width};
+
+const TEXT_SCALE: i32 = 2;
+const PADDING: i32 = 4;
+const LINE_HEIGHT: i32 = (GLYPH_HEIGHT + 2) * TEXT_SCALE;
+// How often a text file overlay looks for changes, and how much of it shows.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const TEXT_FILE_LINES: usize = 8;
+
+// What the picture under the overlays shows.
+pub struct FrameInfo {
+    // Frames run since power on or the last reset.
+    pub frame: usize,
+    pub frame_rate: f64,
+}
+
+// Something drawn over the scaled picture each time it is presented: timers,
+// chat, race standings. Overlays only ever touch the window, so the emulation
+// framebuffer, and with it screenshots, frame dumps and hashes, stays as the
+// console drew it.
+pub trait Overlay {
+    fn draw(&mut self, canvas: &mut Canvas<Window>, frame: &FrameInfo);
+}
+
+// The overlays in the order they are drawn.
+#[derive(Default)]
+pub struct Compositor {
+    overlays: Vec<Box<dyn Overlay>>,
+}
+
+impl Compositor {
+    pub fn add(&mut self, overlay: Box<dyn Overlay>) {
+        self.overlays.push(overlay);
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, frame: &FrameInfo) {
+        for overlay in &mut self.overlays {
+            overlay.draw(canvas, frame);
+        }
+    }
+}
+
+// Emulated time since power on or the last reset, in the top right corner.
+// It follows frames run, so it stops while paused and counts fast forward at
+// the console's speed.
+pub struct Timer;
+
+impl Overlay for Timer {
+    fn draw(&mut self, canvas: &mut Canvas<Window>, frame: &FrameInfo) {
+        let centiseconds = (frame.frame as f64 * 100.0 / frame.frame_rate) as u64;
+        let text = format!(
+            "{}:{:02}.{:02}",
+            centiseconds / 6000,
+            centiseconds / 100 % 60,
+            centiseconds % 100
+        );
+        let lines = [text];
+        let (width, _) = box_size(&lines);
+        draw_box(canvas, canvas.viewport().width() as i32 - width, 0, &lines);
+    }
+}
+
+// The last lines of a text file, in the bottom right corner, for anything a
+// script or bot can write out: chat, splits, commentary. The file is read
+// again whenever it changes.
+pub struct TextFile {
+    path: PathBuf,
+    lines: Vec<String>,
+    modified: Option<SystemTime>,
+    checked: Option<Instant>,
+}
+
+impl TextFile {
+    pub fn new(path: PathBuf) -> Self {
+        TextFile {
+            path,
+            lines: Vec::new(),
+            modified: None,
+            checked: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < POLL_INTERVAL)
+        {
+            return;
+        }
+        self.checked = Some(Instant::now());
+
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified.is_some() && modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        // A file that is missing or half written shows nothing until it's back.
+        let text = std::fs::read_to_string(&self.path).unwrap_or_default();
+        let lines: Vec<&str> = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        self.lines = lines[lines.len().saturating_sub(TEXT_FILE_LINES)..]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+    }
+}
+
+impl Overlay for TextFile {
+    fn draw(&mut self, canvas: &mut Canvas<Window>, _frame: &FrameInfo) {
+        self.refresh();
+        if self.lines.is_empty() {
+            return;
+        }
+
+        let viewport = canvas.viewport();
+        let (width, height) = box_size(&self.lines);
+        let x = viewport.width() as i32 - width;
+        let y = viewport.height() as i32 - height;
+        draw_box(canvas, x.max(0), y.max(0), &self.lines);
+    }
+}
+
+// Size of the box `draw_box` puts around the lines, padding included.
+fn box_size(lines: &[String]) -> (i32, i32) {
+    let width = lines
+        .iter()
+        .map(|line| text_width(line, TEXT_SCALE))
+        .max()
+        .unwrap_or(0);
+    let height = LINE_HEIGHT * lines.len() as i32;
+    (width + PADDING * 2, height + PADDING * 2)
+}
+
+// White lines of text on a translucent box with its top left at (x, y).
+fn draw_box(canvas: &mut Canvas<Window>, x: i32, y: i32, lines: &[String]) {
+    let (width, height) = box_size(lines);
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+    let _ = canvas.fill_rect(Rect::new(x, y, width as u32, height as u32));
+    canvas.set_blend_mode(BlendMode::None);
+
+    canvas.set_draw_color(Color::WHITE);
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            canvas,
+            x + PADDING,
+            y + PADDING + i as i32 * LINE_HEIGHT,
+            TEXT_SCALE,
+            line,
+        );
+    }
+}
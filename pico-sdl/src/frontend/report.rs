@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use pico_core::cart::Cart;
+use pico_core::headless::Headless;
+use pico_core::movie::FM2Movie;
+use pico_core::nes::AccuracyProfile;
+
+use crate::parse_accuracy;
+
+#[derive(Args)]
+pub struct ReportArgs {
+    rom_file: String,
+    /// Movie supplying the input (default: no input)
+    movie_file: Option<String>,
+
+    /// Frames to run, unless the movie ends first
+    #[arg(long, default_value_t = 60 * 60)]
+    frames: usize,
+
+    /// Emulation accuracy: fast, balanced or accurate
+    #[arg(long, default_value = "balanced", value_parser = parse_accuracy)]
+    accuracy: AccuracyProfile,
+
+    /// File to write the JSON to instead of printing it
+    #[arg(long, value_name = "PATH")]
+    out: Option<PathBuf>,
+}
+
+// Runs the ROM without a window and writes out what it used and what went
+// unhandled, for triaging bug reports.
+pub fn run(args: &ReportArgs) -> Result<(), String> {
+    let bytes = std::fs::read(&args.rom_file).map_err(|e| format!("Failed to read ROM: {}", e))?;
+    let movie = match &args.movie_file {
+        Some(path) => Some(FM2Movie::load_from_file(path)?),
+        None => None,
+    };
+
+    let mut headless = Headless::new(Cart::new(&bytes)?);
+    headless.nes.set_accuracy(args.accuracy);
+    while headless.frame() < args.frames {
+        match &movie {
+            Some(movie) => {
+                if headless.run_movie_frame(movie).is_none() {
+                    break;
+                }
+            }
+            None => {
+                headless.run_frame();
+            }
+        }
+    }
+
+    let json = headless.nes.compat_json();
+    match &args.out {
+        Some(path) => std::fs::write(path, json)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e)),
+        None => {
+            print!("{json}");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use pico_core::apu::APU;
+use pico_core::cart::Cart;
+use pico_core::memory::Memory;
+use pico_core::nes::Nes;
+use pico_core::ppu::framebuffer::Framebuffer;
+use rhai::{AST, Engine, EvalAltResult, Scope};
+
+use super::font::{GLYPH_WIDTH, glyph};
+use super::settings::BUTTONS;
+
+// A Rhai script run alongside the game, for bots, HUDs and autosplitters. Its
+// top level runs once when it loads, then each frame it may have:
+//
+//   fn on_frame() { ... }  before the frame runs, to press buttons
+//   fn on_draw() { ... }   after the frame is drawn, to draw over it
+//
+// with these functions to call from them:
+//
+//   frame()                         frames since power on or reset
+//   read(addr), read_word(addr)     memory as the CPU sees it, without side
+//                                   effects
+//   write(addr, value)              a write from the CPU
+//   press(player, button)           hold a button, such as "A" or "Start",
+//                                   for this frame, player 1 or 2
+//   save_state(slot)                keep a savestate in memory, in any slot
+//   load_state(slot)
+//   pixel(x, y, color)              draw on the picture, in on_draw only,
+//   rect(x, y, width, height, color) with colors as 0xRRGGBB
+//   fill(x, y, width, height, color)
+//   text(x, y, text, color)
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    nes: Lent<Nes>,
+    framebuffer: Lent<Framebuffer>,
+    on_frame: bool,
+    on_draw: bool,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// Something the script's functions act on, swapped in for them only while one
+// of its callbacks runs. Outside of that it holds a stand-in the functions
+// refuse to touch.
+struct Lent<T> {
+    value: Rc<RefCell<T>>,
+    lent: Rc<Cell<bool>>,
+}
+
+impl<T> Clone for Lent<T> {
+    fn clone(&self) -> Self {
+        Lent {
+            value: self.value.clone(),
+            lent: self.lent.clone(),
+        }
+    }
+}
+
+// Swaps the lent value back to its owner when dropped, so it goes back even
+// if the callback panics.
+struct Lending<'a, T> {
+    lent: &'a Lent<T>,
+    owner: &'a mut T,
+}
+
+impl<T> Drop for Lending<'_, T> {
+    fn drop(&mut self) {
+        std::mem::swap(self.owner, &mut *self.lent.value.borrow_mut());
+        self.lent.lent.set(false);
+    }
+}
+
+impl<T> Lent<T> {
+    fn new(stand_in: T) -> Self {
+        Lent {
+            value: Rc::new(RefCell::new(stand_in)),
+            lent: Rc::new(Cell::new(false)),
+        }
+    }
+
+    fn lend<R>(&self, target: &mut T, run: impl FnOnce() -> R) -> R {
+        std::mem::swap(target, &mut *self.value.borrow_mut());
+        self.lent.set(true);
+        let _lending = Lending {
+            lent: self,
+            owner: target,
+        };
+        run()
+    }
+
+    fn with<R>(&self, missing: &str, f: impl FnOnce(&mut T) -> R) -> ScriptResult<R> {
+        if !self.lent.get() {
+            return Err(missing.into());
+        }
+        Ok(f(&mut self.value.borrow_mut()))
+    }
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let nes = Lent::new(Nes::new(Cart::empty(), APU::new(48_000)));
+        let framebuffer = Lent::new(Framebuffer::new());
+        let mut engine = Engine::new();
+        register_memory(&mut engine, &nes);
+        register_input(&mut engine, &nes);
+        register_drawing(&mut engine, &framebuffer);
+
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("Failed to load script {}: {}", path.display(), e))?;
+        let defines = |name: &str| {
+            ast.iter_functions()
+                .any(|f| f.name == name && f.params.is_empty())
+        };
+        let (on_frame, on_draw) = (defines("on_frame"), defines("on_draw"));
+        let mut script = Script {
+            engine,
+            ast,
+            scope: Scope::new(),
+            nes,
+            framebuffer,
+            on_frame,
+            on_draw,
+        };
+        script.run(None, |engine, scope, ast| {
+            engine.run_ast_with_scope(scope, ast)
+        })?;
+        Ok(script)
+    }
+
+    // Call before the frame runs, once its input is set.
+    pub fn on_frame(&mut self, nes: &mut Nes) -> Result<(), String> {
+        if !self.on_frame {
+            return Ok(());
+        }
+        let nes_lent = self.nes.clone();
+        nes_lent.lend(nes, || self.call("on_frame"))
+    }
+
+    // Call once the frame is in `framebuffer`, before it is shown.
+    pub fn on_draw(&mut self, nes: &mut Nes, framebuffer: &mut Framebuffer) -> Result<(), String> {
+        if !self.on_draw {
+            return Ok(());
+        }
+        let (nes_lent, framebuffer_lent) = (self.nes.clone(), self.framebuffer.clone());
+        nes_lent.lend(nes, || {
+            framebuffer_lent.lend(framebuffer, || self.call("on_draw"))
+        })
+    }
+
+    fn call(&mut self, name: &str) -> Result<(), String> {
+        self.run(Some(name), |engine, scope, ast| {
+            engine.call_fn::<()>(scope, ast, name, ())
+        })
+    }
+
+    fn run(
+        &mut self,
+        name: Option<&str>,
+        run: impl FnOnce(&Engine, &mut Scope<'static>, &AST) -> ScriptResult<()>,
+    ) -> Result<(), String> {
+        run(&self.engine, &mut self.scope, &self.ast).map_err(|e| match name {
+            Some(name) => format!("Script failed in {}: {}", name, e),
+            None => format!("Script failed: {}", e),
+        })
+    }
+}
+
+const NO_CONSOLE: &str = "The console is only available in on_frame and on_draw";
+const NO_PICTURE: &str = "Drawing is only available in on_draw";
+
+fn address(addr: i64) -> ScriptResult<u16> {
+    u16::try_from(addr).map_err(|_| format!("Invalid address {}", addr).into())
+}
+
+fn register_memory(engine: &mut Engine, nes: &Lent<Nes>) {
+    let lent = nes.clone();
+    engine.register_fn("frame", move || {
+        lent.with(NO_CONSOLE, |nes| nes.frame_count() as i64)
+    });
+    let lent = nes.clone();
+    engine.register_fn("read", move |addr: i64| {
+        let addr = address(addr)?;
+        lent.with(NO_CONSOLE, |nes| nes.bus.peek(addr) as i64)
+    });
+    let lent = nes.clone();
+    engine.register_fn("read_word", move |addr: i64| {
+        let addr = address(addr)?;
+        lent.with(NO_CONSOLE, |nes| {
+            u16::from_le_bytes([nes.bus.peek(addr), nes.bus.peek(addr.wrapping_add(1))]) as i64
+        })
+    });
+    let lent = nes.clone();
+    engine.register_fn("write", move |addr: i64, value: i64| {
+        let addr = address(addr)?;
+        lent.with(NO_CONSOLE, |nes| nes.bus.write(addr, value as u8))
+    });
+}
+
+fn register_input(engine: &mut Engine, nes: &Lent<Nes>) {
+    let lent = nes.clone();
+    engine.register_fn("press", move |player: i64, button: &str| {
+        let Some((_, button)) = BUTTONS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(button))
+        else {
+            return Err(format!("Unknown button {}", button).into());
+        };
+        let index = match player {
+            1 | 2 => player as usize - 1,
+            _ => return Err(format!("Invalid player {}", player).into()),
+        };
+        lent.with(NO_CONSOLE, |nes| {
+            if let Some(joypad) = nes.joypad_mut(index) {
+                joypad.button_status |= *button;
+            }
+        })
+    });
+
+    let slots: Rc<RefCell<HashMap<i64, Vec<u8>>>> = Rc::default();
+    let (lent, saved) = (nes.clone(), slots.clone());
+    engine.register_fn("save_state", move |slot: i64| -> ScriptResult<()> {
+        let state = lent.with(NO_CONSOLE, |nes| nes.save_state())?;
+        saved.borrow_mut().insert(slot, state);
+        Ok(())
+    });
+    let lent = nes.clone();
+    engine.register_fn("load_state", move |slot: i64| -> ScriptResult<()> {
+        let slots = slots.borrow();
+        let Some(state) = slots.get(&slot) else {
+            return Err(format!("Nothing saved in slot {}", slot).into());
+        };
+        lent.with(NO_CONSOLE, |nes| nes.load_state(state))?
+            .map_err(|e| e.into())
+    });
+}
+
+fn rgb(color: i64) -> (u8, u8, u8) {
+    ((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+// The part of `start..start + len` on the picture, which is `size` long.
+fn clip(start: i64, len: i64, size: usize) -> std::ops::Range<i64> {
+    start.max(0)..start.saturating_add(len).min(size as i64)
+}
+
+// Leaves out whatever falls off the picture.
+fn plot(framebuffer: &mut Framebuffer, x: i64, y: i64, color: (u8, u8, u8)) {
+    let width = Framebuffer::WIDTH as i64;
+    let height = Framebuffer::HEIGHT as i64;
+    if (0..width).contains(&x) && (0..height).contains(&y) {
+        framebuffer.set_pixel(x as usize, y as usize, color);
+    }
+}
+
+fn register_drawing(engine: &mut Engine, framebuffer: &Lent<Framebuffer>) {
+    let lent = framebuffer.clone();
+    engine.register_fn("pixel", move |x: i64, y: i64, color: i64| {
+        lent.with(NO_PICTURE, |framebuffer| {
+            plot(framebuffer, x, y, rgb(color))
+        })
+    });
+    let lent = framebuffer.clone();
+    engine.register_fn(
+        "rect",
+        move |x: i64, y: i64, width: i64, height: i64, color: i64| {
+            lent.with(NO_PICTURE, |framebuffer| {
+                for column in clip(x, width, Framebuffer::WIDTH) {
+                    plot(framebuffer, column, y, rgb(color));
+                    plot(framebuffer, column, y + height - 1, rgb(color));
+                }
+                for row in clip(y, height, Framebuffer::HEIGHT) {
+                    plot(framebuffer, x, row, rgb(color));
+                    plot(framebuffer, x + width - 1, row, rgb(color));
+                }
+            })
+        },
+    );
+    let lent = framebuffer.clone();
+    engine.register_fn(
+        "fill",
+        move |x: i64, y: i64, width: i64, height: i64, color: i64| {
+            lent.with(NO_PICTURE, |framebuffer| {
+                for row in clip(y, height, Framebuffer::HEIGHT) {
+                    for column in clip(x, width, Framebuffer::WIDTH) {
+                        plot(framebuffer, column, row, rgb(color));
+                    }
+                }
+            })
+        },
+    );
+    let lent = framebuffer.clone();
+    engine.register_fn("text", move |x: i64, y: i64, text: &str, color: i64| {
+        lent.with(NO_PICTURE, |framebuffer| {
+            for (i, ch) in text.chars().enumerate() {
+                let left = x + i as i64 * (GLYPH_WIDTH as i64 + 1);
+                for (row, bits) in glyph(ch).iter().enumerate() {
+                    for column in 0..GLYPH_WIDTH {
+                        if bits & (0b100 >> column) != 0 {
+                            plot(
+                                framebuffer,
+                                left + column as i64,
+                                y + row as i64,
+                                rgb(color),
+                            );
+                        }
+                    }
+                }
+            }
+        })
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pico_core::joypad::JoypadButton;
+
+    // NROM spinning on JMP $8000.
+    fn looping_nes() -> Nes {
+        let mut rom = b"NES\x1a\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        let mut prg = vec![0; 0x4000];
+        prg[..3].copy_from_slice(&[0x4C, 0x00, 0x80]);
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+        rom.extend(prg);
+        rom.extend(vec![0; 0x2000]);
+        let mut nes = Nes::new(Cart::new(&rom).unwrap(), APU::new(48_000));
+        nes.reset();
+        nes
+    }
+
+    fn load(name: &str, source: &str) -> Result<Script, String> {
+        let path = std::env::temp_dir().join(format!("pico-{}-{}.rhai", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let script = Script::load(&path);
+        let _ = std::fs::remove_file(&path);
+        script
+    }
+
+    #[test]
+    fn test_callbacks_reach_the_console_and_picture() {
+        let mut script = load(
+            "callbacks",
+            r#"
+            fn on_frame() {
+                write(0x10, read(0x10) + 1);
+                press(1, "a");
+                press(2, "Start");
+                if read(0x10) == 1 { save_state(0); }
+                if read(0x10) == 3 { load_state(0); }
+            }
+            fn on_draw() {
+                fill(250, 0, 100, 1, 0xFF8000);
+                text(0, 0, "1", 0xFFFFFF);
+            }
+            "#,
+        )
+        .unwrap();
+        let mut nes = looping_nes();
+        let mut framebuffer = Framebuffer::new();
+
+        for _ in 0..3 {
+            script.on_frame(&mut nes).unwrap();
+        }
+        assert_eq!(nes.bus.peek(0x10), 1, "reloaded from the slot");
+        assert_eq!(
+            nes.joypad_mut(0).unwrap().button_status,
+            JoypadButton::BUTTON_A
+        );
+        assert_eq!(
+            nes.joypad_mut(1).unwrap().button_status,
+            JoypadButton::START
+        );
+
+        script.on_draw(&mut nes, &mut framebuffer).unwrap();
+        assert_eq!(framebuffer.data[255 * 3..256 * 3], [0xFF, 0x80, 0x00]);
+        // The top of the 1 is its middle pixel.
+        assert_eq!(framebuffer.data[..3], [0, 0, 0]);
+        assert_eq!(framebuffer.data[3..6], [0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_lent_values_go_back_after_a_panic() {
+        let lent = Lent::new(0);
+        let mut owned = 7;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            lent.lend(&mut owned, || {
+                lent.with("", |value| *value += 1).unwrap();
+                panic!("callback failed");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(owned, 8);
+        assert!(lent.with("gone", |_| ()).is_err());
+    }
+
+    #[test]
+    fn test_errors_name_the_callback() {
+        let mut nes = looping_nes();
+        let mut script = load("errors", "fn on_frame() { pixel(0, 0, 0); }").unwrap();
+        let error = script.on_frame(&mut nes).unwrap_err();
+        assert!(error.starts_with("Script failed in on_frame"), "{error}");
+        assert!(error.contains(NO_PICTURE), "{error}");
+
+        let mut script = load("slots", "fn on_frame() { load_state(1); }").unwrap();
+        assert!(script.on_frame(&mut nes).is_err());
+        assert!(load("syntax", "fn on_frame( {").is_err());
+        assert!(load("top", "frame();").is_err());
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use pico_core::apu::sink::FastForwardAudio;
+use pico_core::joypad::{ButtonLayout, DpadPolicy, JoypadButton};
+use pico_core::ppu::palette::PalettePreset;
+use pico_core::ppu::render::Renderer;
+use pico_core::region::Region;
+use pico_core::rom_db::RomDb;
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+use serde::{Deserialize, Serialize};
+
+use super::hotkeys::Action;
+use super::video::{PixelAspect, ScaleMode};
+
+// Controller buttons in the order they are listed in the input menu, with the
+// name used for them in the settings file.
+pub const BUTTONS: [(&str, JoypadButton); 8] = [
+    ("Up", JoypadButton::UP),
+    ("Down", JoypadButton::DOWN),
+    ("Left", JoypadButton::LEFT),
+    ("Right", JoypadButton::RIGHT),
+    ("A", JoypadButton::BUTTON_A),
+    ("B", JoypadButton::BUTTON_B),
+    ("Select", JoypadButton::SELECT),
+    ("Start", JoypadButton::START),
+];
+
+const DEFAULT_KEYS: [(&str, Keycode); 8] = [
+    ("Up", Keycode::Up),
+    ("Down", Keycode::Down),
+    ("Left", Keycode::Left),
+    ("Right", Keycode::Right),
+    ("A", Keycode::X),
+    ("B", Keycode::Z),
+    ("Select", Keycode::Space),
+    ("Start", Keycode::Return),
+];
+
+// Where the buttons sit on an NES pad: B on the left, A on the right. Both
+// face buttons on each side count, so either hand position works.
+const DEFAULT_GAMEPAD: [(&str, &[Button]); 8] = [
+    ("Up", &[Button::DPadUp]),
+    ("Down", &[Button::DPadDown]),
+    ("Left", &[Button::DPadLeft]),
+    ("Right", &[Button::DPadRight]),
+    ("A", &[Button::B, Button::Y]),
+    ("B", &[Button::A, Button::X]),
+    ("Select", &[Button::Back]),
+    ("Start", &[Button::Start]),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoFilter {
+    #[default]
+    None,
+    Scanlines,
+}
+
+impl VideoFilter {
+    pub const ALL: [VideoFilter; 2] = [VideoFilter::None, VideoFilter::Scanlines];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoFilter::None => "None",
+            VideoFilter::Scanlines => "Scanlines",
+        }
+    }
+}
+
+// What happens while the window is in the background.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusLoss {
+    #[default]
+    Continue,
+    Mute,
+    Pause,
+}
+
+impl FocusLoss {
+    pub const ALL: [FocusLoss; 3] = [FocusLoss::Continue, FocusLoss::Mute, FocusLoss::Pause];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FocusLoss::Continue => "Keep playing",
+            FocusLoss::Mute => "Mute",
+            FocusLoss::Pause => "Pause",
+        }
+    }
+}
+
+// How many frames fast-forward runs for each one shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FastForwardSpeed {
+    Double,
+    #[default]
+    Quadruple,
+    // As many as there is time for before the next one is shown.
+    Uncapped,
+}
+
+impl FastForwardSpeed {
+    pub const ALL: [FastForwardSpeed; 3] = [
+        FastForwardSpeed::Double,
+        FastForwardSpeed::Quadruple,
+        FastForwardSpeed::Uncapped,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FastForwardSpeed::Double => "2x",
+            FastForwardSpeed::Quadruple => "4x",
+            FastForwardSpeed::Uncapped => "Uncapped",
+        }
+    }
+
+    pub fn frames(&self) -> Option<usize> {
+        match self {
+            FastForwardSpeed::Double => Some(2),
+            FastForwardSpeed::Quadruple => Some(4),
+            FastForwardSpeed::Uncapped => None,
+        }
+    }
+}
+
+// How many times slower than the console slow motion runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlowMotion {
+    #[default]
+    Half,
+    Quarter,
+    Eighth,
+}
+
+impl SlowMotion {
+    pub const ALL: [SlowMotion; 3] = [SlowMotion::Half, SlowMotion::Quarter, SlowMotion::Eighth];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SlowMotion::Half => "1/2",
+            SlowMotion::Quarter => "1/4",
+            SlowMotion::Eighth => "1/8",
+        }
+    }
+
+    pub fn slowdown(&self) -> usize {
+        match self {
+            SlowMotion::Half => 2,
+            SlowMotion::Quarter => 4,
+            SlowMotion::Eighth => 8,
+        }
+    }
+}
+
+// What the frontend does when the CPU jams, which stops it until a reset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnJam {
+    // Leave the last frame up, as the console would.
+    #[default]
+    Halt,
+    Pause,
+    Reset,
+}
+
+impl OnJam {
+    pub const ALL: [OnJam; 3] = [OnJam::Halt, OnJam::Pause, OnJam::Reset];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OnJam::Halt => "Stay halted",
+            OnJam::Pause => "Pause",
+            OnJam::Reset => "Reset",
+        }
+    }
+}
+
+// Preferences kept for one game, since what suits it depends on how it uses
+// the buttons.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomSettings {
+    // For players 1 and 2.
+    pub layouts: [ButtonLayout; 2],
+}
+
+// Frontend preferences, stored as TOML in the user's config directory.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Where the settings are loaded from and saved to.
+    #[serde(skip)]
+    pub path: PathBuf,
+    // Button name to SDL key name.
+    pub keys: BTreeMap<String, String>,
+    // Button name to the SDL game controller buttons that press it, the same
+    // for every controller.
+    pub gamepad: BTreeMap<String, Vec<String>>,
+    // Emulator action name to SDL key name.
+    pub hotkeys: BTreeMap<String, String>,
+    pub video_filter: VideoFilter,
+    pub scale_mode: ScaleMode,
+    pub pixel_aspect: PixelAspect,
+    // Overridden by --palette.
+    pub palette: PalettePreset,
+    pub audio_device: Option<String>,
+    pub focus_loss: FocusLoss,
+    pub fast_forward_speed: FastForwardSpeed,
+    pub fast_forward_audio: FastForwardAudio,
+    pub slow_motion: SlowMotion,
+    pub on_jam: OnJam,
+    // Applied to the keyboard, not to movies or piped input.
+    pub dpad_policy: DpadPolicy,
+    // Forced for every game; unset lets the ROM database pick per game.
+    pub renderer: Option<Renderer>,
+    // Forced for every game, unless --region is given; unset takes the
+    // cartridge's.
+    pub region: Option<Region>,
+    // Unset saves screenshots to the working directory.
+    pub screenshot_dir: Option<PathBuf>,
+    // Keyed by the ROM's CRC-32 in hex.
+    pub roms: BTreeMap<String, RomSettings>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            path: Settings::default_path(),
+            keys: DEFAULT_KEYS
+                .iter()
+                .map(|(button, key)| (button.to_string(), key.name()))
+                .collect(),
+            gamepad: DEFAULT_GAMEPAD
+                .iter()
+                .map(|(button, pad_buttons)| {
+                    let names = pad_buttons.iter().map(|pad| pad.string()).collect();
+                    (button.to_string(), names)
+                })
+                .collect(),
+            hotkeys: Action::ALL
+                .iter()
+                .filter_map(|action| Some((action.name(), action.default_key()?.name())))
+                .collect(),
+            video_filter: VideoFilter::None,
+            scale_mode: ScaleMode::Fit,
+            pixel_aspect: PixelAspect::Square,
+            palette: PalettePreset::CompositeDirect,
+            audio_device: None,
+            focus_loss: FocusLoss::Continue,
+            fast_forward_speed: FastForwardSpeed::Quadruple,
+            fast_forward_audio: FastForwardAudio::Skip,
+            slow_motion: SlowMotion::Half,
+            on_jam: OnJam::Halt,
+            dpad_policy: DpadPolicy::Allow,
+            renderer: None,
+            region: None,
+            screenshot_dir: None,
+            roms: BTreeMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn default_path() -> PathBuf {
+        if let Some(path) = std::env::var_os("PICO_SETTINGS") {
+            return PathBuf::from(path);
+        }
+
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from));
+
+        match config_dir {
+            Some(dir) => dir.join("pico").join("settings.toml"),
+            None => PathBuf::from("pico.toml"),
+        }
+    }
+
+    // Missing or unreadable settings fall back to the defaults, which are
+    // saved to the same path on the first change.
+    pub fn load(path: PathBuf) -> Self {
+        let mut settings = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).unwrap_or_else(|e| {
+                eprintln!("Ignoring invalid settings in {}: {}", path.display(), e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        };
+        settings.path = path;
+        settings
+    }
+
+    // The built-in ROM database plus the user's own rom_db.toml, if any,
+    // which sits next to the settings file.
+    pub fn load_rom_db(&self) -> RomDb {
+        let mut rom_db = RomDb::builtin().clone();
+        let path = self.path.with_file_name("rom_db.toml");
+        if let Ok(text) = std::fs::read_to_string(&path) {
+            match RomDb::from_toml(&text) {
+                Ok(user_db) => rom_db.merge(&user_db),
+                Err(e) => eprintln!("Ignoring {}: {}", path.display(), e),
+            }
+        }
+        rom_db
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = &self.path;
+        if let Some(dir) = path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+
+        let text = toml::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn rom(&self, crc32: u32) -> RomSettings {
+        self.roms
+            .get(&format!("{:08X}", crc32))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_rom(&mut self, crc32: u32, rom: RomSettings) {
+        let key = format!("{:08X}", crc32);
+        // Games left at the defaults don't need an entry.
+        if rom == RomSettings::default() {
+            self.roms.remove(&key);
+        } else {
+            self.roms.insert(key, rom);
+        }
+    }
+
+    pub fn key_for(&self, button: &str) -> Option<Keycode> {
+        self.keys
+            .get(button)
+            .and_then(|name| Keycode::from_name(name))
+    }
+
+    pub fn bind_key(&mut self, button: &str, key: Keycode) {
+        // A key drives a single button, so drop any other binding of it.
+        let name = key.name();
+        self.keys.retain(|_, bound| *bound != name);
+        self.keys.insert(button.to_string(), name);
+    }
+
+    pub fn key_map(&self) -> HashMap<Keycode, JoypadButton> {
+        BUTTONS
+            .iter()
+            .filter_map(|(name, button)| Some((self.key_for(name)?, *button)))
+            .collect()
+    }
+
+    pub fn gamepad_map(&self) -> Vec<(Button, JoypadButton)> {
+        BUTTONS
+            .iter()
+            .flat_map(|(name, button)| {
+                let pad_buttons = self.gamepad.get(*name).map_or(&[][..], Vec::as_slice);
+                pad_buttons.iter().filter_map(|pad| {
+                    let Some(pad) = Button::from_string(pad) else {
+                        eprintln!("Ignoring unknown controller button {}", pad);
+                        return None;
+                    };
+                    Some((pad, *button))
+                })
+            })
+            .collect()
+    }
+
+    pub fn hotkey_map(&self) -> HashMap<Keycode, Action> {
+        self.hotkeys
+            .iter()
+            .filter_map(|(action, key)| {
+                let Some(action) = Action::from_name(action) else {
+                    eprintln!("Ignoring hotkey for unknown action {}", action);
+                    return None;
+                };
+                Some((Keycode::from_name(key)?, action))
+            })
+            .collect()
+    }
+}
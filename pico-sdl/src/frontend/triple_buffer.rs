@@ -0,0 +1,67 @@
+use std::sync::Mutex;
+
+use pico_core::ppu::framebuffer::Framebuffer;
+
+// Hands finished frames from the emulation thread to the thread presenting
+// them. Each side keeps a framebuffer of its own and swaps it with the one
+// in the middle, so neither waits for the other to finish with a frame.
+// Frames the presenter misses are dropped in favour of the newest.
+pub struct TripleBuffer {
+    middle: Mutex<(Framebuffer, bool)>,
+}
+
+impl TripleBuffer {
+    pub fn new() -> Self {
+        TripleBuffer {
+            middle: Mutex::new((Framebuffer::new(), false)),
+        }
+    }
+
+    // Passes on the finished frame in `back`, which gets an older buffer to
+    // draw the next one into.
+    pub fn publish(&self, back: &mut Framebuffer) {
+        let mut middle = self.middle.lock().unwrap();
+        std::mem::swap(&mut middle.0, back);
+        middle.1 = true;
+    }
+
+    // Swaps the newest frame into `front`. False, leaving `front` alone, if
+    // nothing was published since the last call.
+    pub fn take_latest(&self, front: &mut Framebuffer) -> bool {
+        let mut middle = self.middle.lock().unwrap();
+        if !middle.1 {
+            return false;
+        }
+        std::mem::swap(&mut middle.0, front);
+        middle.1 = false;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn filled(value: u8) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.data.fill(value);
+        framebuffer
+    }
+
+    #[test]
+    fn test_presenter_gets_only_the_newest_frame() {
+        let frames = TripleBuffer::new();
+        let mut front = filled(0);
+        assert!(!frames.take_latest(&mut front));
+
+        let mut back = filled(1);
+        frames.publish(&mut back);
+        back.data.fill(2);
+        frames.publish(&mut back);
+        assert!(frames.take_latest(&mut front));
+        assert!(front.data.iter().all(|&pixel| pixel == 2));
+
+        assert!(!frames.take_latest(&mut front));
+        assert!(front.data.iter().all(|&pixel| pixel == 2));
+    }
+}
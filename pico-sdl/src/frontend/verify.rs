@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use pico_core::apu::sink::AudioSink;
+use pico_core::cart::Cart;
+use pico_core::event::EmulatorEvent;
+use pico_core::headless::Headless;
+use pico_core::movie::FM2Movie;
+use pico_core::nes::AccuracyProfile;
+use pico_core::rom_db::crc32;
+
+use crate::parse_accuracy;
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    rom_file: String,
+    movie_file: String,
+
+    /// Hash work RAM each frame instead of the picture
+    #[arg(long)]
+    ram: bool,
+
+    /// Checksum the run has to end with, in hex, failing otherwise
+    #[arg(long, value_parser = parse_checksum)]
+    expect: Option<u32>,
+
+    /// Also write each frame's hash to this CSV file, to find where two runs
+    /// part
+    #[arg(long, value_name = "PATH")]
+    hashes: Option<PathBuf>,
+
+    /// Emulation accuracy: fast, balanced or accurate
+    #[arg(long, default_value = "balanced", value_parser = parse_accuracy)]
+    accuracy: AccuracyProfile,
+
+    /// Run the CPU from code decoded ahead a block at a time, which is
+    /// quicker, though a game reading open bus can run differently
+    #[arg(long)]
+    fast_cpu: bool,
+}
+
+fn parse_checksum(text: &str) -> Result<u32, String> {
+    let hex = text.strip_prefix("0x").unwrap_or(text);
+    u32::from_str_radix(hex, 16).map_err(|e| format!("invalid checksum {text}: {e}"))
+}
+
+// Plays the movie as fast as it runs, with nothing shown or heard, and prints
+// a checksum of every frame. The same build gives the same checksum for the
+// same ROM and movie, so a changed one means the run went differently.
+pub fn run(args: &VerifyArgs) -> Result<(), String> {
+    let bytes = std::fs::read(&args.rom_file).map_err(|e| format!("Failed to read ROM: {}", e))?;
+    let movie = FM2Movie::load_from_file(&args.movie_file)?;
+
+    let mut headless = Headless::with_audio_sink(Cart::new(&bytes)?, AudioSink::Null);
+    headless.nes.set_accuracy(args.accuracy);
+    headless.nes.set_fast_cpu(args.fast_cpu);
+    let mut checksum = 0u32;
+    let mut hashes = String::from("frame,hash\n");
+    let mut jam = None;
+    while headless.run_movie_frame(&movie).is_some() {
+        let hash = if args.ram {
+            let ram: Vec<u8> = (0x0000..0x0800)
+                .map(|addr| headless.nes.bus.peek(addr))
+                .collect();
+            crc32(&ram)
+        } else {
+            crc32(&headless.framebuffer().data)
+        };
+        let frame = headless.frame() - 1;
+        checksum = crc32(&[checksum.to_le_bytes(), hash.to_le_bytes()].concat());
+        hashes.push_str(&format!("{},{:08X}\n", frame, hash));
+        for event in headless.events() {
+            if let EmulatorEvent::CpuJam { .. } = event {
+                jam.get_or_insert(frame);
+            }
+        }
+    }
+
+    if let Some(path) = &args.hashes {
+        std::fs::write(path, hashes)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+
+    let frames = headless.frame();
+    let status = if frames < movie.frame_count() {
+        "stopped early"
+    } else {
+        "completed"
+    };
+    println!(
+        "Ran {} of {} frames: {}",
+        frames,
+        movie.frame_count(),
+        status
+    );
+    if let Some(frame) = jam {
+        println!("CPU jammed at frame {}", frame);
+    }
+    println!("Checksum: {:08X}", checksum);
+
+    match args.expect {
+        Some(expected) if expected != checksum => Err(format!(
+            "Checksum {:08X} doesn't match the expected {:08X}",
+            checksum, expected
+        )),
+        _ if frames < movie.frame_count() => Err(format!(
+            "The movie stopped at frame {} of {}",
+            frames,
+            movie.frame_count()
+        )),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,133 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
+use serde::{Deserialize, Serialize};
+
+use super::filter;
+use super::settings::Settings;
+
+const NES_WIDTH: u32 = 256;
+const NES_HEIGHT: u32 = 240;
+
+// How the picture grows with the window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleMode {
+    // As large as fits, at any size.
+    #[default]
+    Fit,
+    // Whole multiples of the console's lines, so every line is as tall as
+    // the next.
+    Integer,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 2] = [ScaleMode::Fit, ScaleMode::Integer];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScaleMode::Fit => "Fit",
+            ScaleMode::Integer => "Integer",
+        }
+    }
+}
+
+// The shape of a console pixel. On an NTSC TV they are 8:7, a little wider
+// than tall.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelAspect {
+    #[default]
+    Square,
+    Ntsc,
+}
+
+impl PixelAspect {
+    pub const ALL: [PixelAspect; 2] = [PixelAspect::Square, PixelAspect::Ntsc];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PixelAspect::Square => "Square",
+            PixelAspect::Ntsc => "8:7",
+        }
+    }
+
+    fn width_ratio(&self) -> f64 {
+        match self {
+            PixelAspect::Square => 1.0,
+            PixelAspect::Ntsc => 8.0 / 7.0,
+        }
+    }
+}
+
+// The window size for the picture at `scale` times the console's lines.
+pub fn window_size(scale: u32, aspect: PixelAspect) -> (u32, u32) {
+    let width = (NES_WIDTH * scale) as f64 * aspect.width_ratio();
+    (width.round() as u32, NES_HEIGHT * scale)
+}
+
+// Where the picture goes in a window of `output` pixels, centred with black
+// bars around it.
+pub fn frame_rect(output: (u32, u32), mode: ScaleMode, aspect: PixelAspect) -> Rect {
+    let (output_width, output_height) = output;
+    let picture_width = NES_WIDTH as f64 * aspect.width_ratio();
+    let scale = (output_width as f64 / picture_width).min(output_height as f64 / NES_HEIGHT as f64);
+    let scale = match mode {
+        ScaleMode::Fit => scale,
+        // Too small a window still gets the picture, shrunk.
+        ScaleMode::Integer if scale >= 1.0 => scale.floor(),
+        ScaleMode::Integer => scale,
+    };
+    let width = ((picture_width * scale).round() as u32).max(1);
+    let height = ((NES_HEIGHT as f64 * scale).round() as u32).max(1);
+    Rect::new(
+        (output_width.saturating_sub(width) / 2) as i32,
+        (output_height.saturating_sub(height) / 2) as i32,
+        width,
+        height,
+    )
+}
+
+// Clears the window and draws the frame in it, filtered.
+pub fn draw_frame(canvas: &mut Canvas<Window>, texture: &Texture, settings: &Settings) {
+    let output = canvas.output_size().unwrap_or((NES_WIDTH, NES_HEIGHT));
+    let rect = frame_rect(output, settings.scale_mode, settings.pixel_aspect);
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+    canvas.copy(texture, None, rect).unwrap();
+    filter::apply(canvas, settings.video_filter, rect);
+}
+
+// Switches between a window and the whole desktop.
+pub fn toggle_fullscreen(canvas: &mut Canvas<Window>) -> Result<(), String> {
+    let window = canvas.window_mut();
+    let next = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    window
+        .set_fullscreen(next)
+        .map_err(|e| format!("Failed to switch fullscreen: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frame_rect_letterboxes_the_picture() {
+        let rect = frame_rect((1000, 720), ScaleMode::Fit, PixelAspect::Square);
+        assert_eq!(rect, Rect::new(116, 0, 768, 720));
+
+        let rect = frame_rect((1000, 700), ScaleMode::Integer, PixelAspect::Square);
+        assert_eq!(rect, Rect::new(244, 110, 512, 480));
+
+        let rect = frame_rect((1000, 720), ScaleMode::Integer, PixelAspect::Ntsc);
+        assert_eq!(rect, Rect::new(61, 0, 878, 720));
+        assert_eq!(window_size(3, PixelAspect::Ntsc), (878, 720));
+
+        let rect = frame_rect((128, 120), ScaleMode::Integer, PixelAspect::Square);
+        assert_eq!(rect, Rect::new(0, 0, 128, 120));
+    }
+}
@@ -0,0 +1,1446 @@
+#![forbid(unsafe_code)]
+
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::{Parser, Subcommand};
+use pico_core::apu::APU;
+use pico_core::apu::register_log::RegisterLog;
+use pico_core::apu::sink::{self, Concealer, FastForwardAudio};
+use pico_core::battery::{BatterySave, DEFAULT_FLUSH_FRAMES, write_atomic};
+use pico_core::bk2;
+use pico_core::cart::Cart;
+use pico_core::cheats::Cheats;
+use pico_core::chr_file;
+use pico_core::debugger::Debugger;
+use pico_core::event::{EmulatorEvent, EventLevel};
+use pico_core::input_history::{COMMAND_RESET, InputHistory};
+use pico_core::joypad::{ButtonLayout, DpadFilter, JoypadButton, PauseLatch};
+use pico_core::movie::{FM2Movie, MovieHeader};
+use pico_core::nes::{AccuracyProfile, Nes};
+use pico_core::nsf::{self, NsfPlayer};
+use pico_core::pacing::{FramePacer, HostClock};
+use pico_core::pipe_input::PipeInput;
+use pico_core::ppu::framebuffer::Framebuffer;
+use pico_core::ppu::palette::SystemPalette;
+use pico_core::ppu::render::Renderer;
+use pico_core::region::Region;
+use pico_core::rewind::Rewind;
+use pico_core::rom_db::RomDb;
+use pico_core::stats::PerfStats;
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioDevice, AudioStatus};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::PixelFormatEnum;
+
+use crate::frontend::compare::{self, CompareArgs};
+use crate::frontend::debug_prompt::{self, DebugPrompt};
+use crate::frontend::debug_windows::DebugWindows;
+use crate::frontend::disasm::{self, DisasmArgs};
+use crate::frontend::dump_frames::{self, DumpFramesArgs};
+use crate::frontend::gamepads::Gamepads;
+use crate::frontend::hotkeys::{Action, STATE_SLOTS};
+use crate::frontend::menu::{Menu, MenuAction};
+use crate::frontend::nsf_player;
+use crate::frontend::osd::Osd;
+use crate::frontend::overlay::{Compositor, FrameInfo, TextFile, Timer};
+use crate::frontend::report::{self, ReportArgs};
+use crate::frontend::script::Script;
+use crate::frontend::settings::{FastForwardSpeed, FocusLoss, OnJam, Settings};
+use crate::frontend::triple_buffer::TripleBuffer;
+use crate::frontend::verify::{self, VerifyArgs};
+use crate::frontend::video;
+
+mod frontend;
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 240;
+const SCALE: u32 = 3;
+// How long uncapped fast-forward runs frames for each time the main thread
+// presents, leaving the rest of a 60 Hz refresh for it to take the session
+// and draw.
+const UNCAPPED_FRAME_TIME: Duration = Duration::from_millis(12);
+
+struct AudioCallbackImpl {
+    audio_buffer: Arc<Mutex<VecDeque<f32>>>,
+    concealer: Concealer,
+}
+
+impl sdl2::audio::AudioCallback for AudioCallbackImpl {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let mut buffer = self.audio_buffer.lock().unwrap();
+        self.concealer.fill(&mut buffer, out);
+    }
+}
+
+// The host's monotonic clock, counted from when the frontend started.
+struct SystemClock(Instant);
+
+impl HostClock for SystemClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct CliArgs {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(required = true)]
+    rom_file: Option<String>,
+    movie_file: Option<String>,
+
+    /// Print a trace line for every instruction the CPU runs
+    #[arg(short, long)]
+    debug: bool,
+
+    /// Start paused in the debugger, which takes commands such as "break
+    /// C000" on stdin ("help" lists them)
+    #[arg(long)]
+    debugger: bool,
+
+    /// Seconds of input kept for F2 to save as an FM2 movie (0 disables)
+    #[arg(long, default_value_t = 300)]
+    history_seconds: usize,
+
+    /// Seconds of play kept for rewinding while Backspace is held (0 disables)
+    #[arg(long, default_value_t = 30)]
+    rewind_seconds: usize,
+
+    /// Frames between rewind snapshots, and so how far each rewind step goes
+    #[arg(long, default_value_t = 2)]
+    rewind_interval: usize,
+
+    /// Read controller input from a file or named pipe ("-" for stdin)
+    #[arg(long, value_name = "PATH")]
+    input_pipe: Option<String>,
+
+    /// CHR file used by F6 (export), F7 (export visible banks) and F8 (import)
+    #[arg(long, value_name = "PATH")]
+    chr_file: Option<String>,
+
+    /// Emulation accuracy: fast, balanced or accurate
+    #[arg(long, default_value = "balanced", value_parser = parse_accuracy)]
+    accuracy: AccuracyProfile,
+
+    /// While fast-forwarding, run the CPU from code decoded ahead a block at a
+    /// time, which is quicker but hides code fetches from read breakpoints
+    #[arg(long)]
+    fast_cpu: bool,
+
+    /// TV system: ntsc, pal or dendy (default: detected from the ROM)
+    #[arg(long, value_parser = parse_region)]
+    region: Option<Region>,
+
+    /// System palette to start with, a .pal file of 64 or 512 colours such as
+    /// one saved with V (default: the palette picked in the menu)
+    #[arg(long, value_name = "PATH")]
+    palette: Option<PathBuf>,
+
+    /// Draw every sprite on a line rather than only the first eight, which
+    /// stops the flicker some games use to show more
+    #[arg(long)]
+    no_sprite_limit: bool,
+
+    /// Show the time since power on or reset over the picture, for races
+    #[arg(long)]
+    timer: bool,
+
+    /// Show the last lines of a text file over the picture, such as a chat
+    /// log kept up to date by a bot or script
+    #[arg(long, value_name = "PATH")]
+    overlay_text: Option<PathBuf>,
+
+    /// Settings file to load and save instead of the one in the user's
+    /// config directory
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Vs. System DIP switches 1-8 as bits 0-7, such as 0x0c or 0b00001100
+    #[arg(long, default_value = "0", value_parser = parse_dip_switches)]
+    dip_switches: u8,
+
+    /// Write a JSON compatibility report for the last ROM played to this file
+    /// on exit
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+
+    /// Run a Rhai script alongside the game, which can read and write
+    /// memory, press buttons, keep savestates and draw over the picture
+    #[arg(long, value_name = "PATH")]
+    script: Option<PathBuf>,
+
+    /// Play the file as NSF music, with Left/Right to change track (the
+    /// default for files with an NSF header)
+    #[arg(long)]
+    nsf: bool,
+}
+
+fn parse_accuracy(name: &str) -> Result<AccuracyProfile, String> {
+    AccuracyProfile::from_name(name).ok_or_else(|| format!("unknown accuracy profile {name}"))
+}
+
+fn parse_region(name: &str) -> Result<Region, String> {
+    Region::from_name(name).ok_or_else(|| format!("unknown region {name}"))
+}
+
+fn parse_dip_switches(text: &str) -> Result<u8, String> {
+    let parsed = if let Some(hex) = text.strip_prefix("0x") {
+        u8::from_str_radix(hex, 16)
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        u8::from_str_radix(binary, 2)
+    } else {
+        text.parse()
+    };
+    parsed.map_err(|e| format!("invalid DIP switches {text}: {e}"))
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play a movie without a window and save a range of frames as PNGs
+    DumpFrames(DumpFramesArgs),
+    /// Run a ROM on two accuracy profiles in lockstep and report where they
+    /// first differ
+    Compare(CompareArgs),
+    /// Run a ROM without a window and print a JSON report of the features it
+    /// used and anything the emulator didn't handle
+    Report(ReportArgs),
+    /// Disassemble the code the ROM maps into CPU memory
+    Disasm(DisasmArgs),
+    /// Play a movie as fast as possible without a window or sound and print
+    /// a checksum of its frames, for checking runs in CI
+    Verify(VerifyArgs),
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let mut args = CliArgs::parse();
+
+    if let Some(command) = args.command.take() {
+        let result = match command {
+            Command::DumpFrames(dump_args) => dump_frames::run(&dump_args),
+            Command::Compare(compare_args) => compare::run(&compare_args),
+            Command::Report(report_args) => report::run(&report_args),
+            Command::Disasm(disasm_args) => disasm::run(&disasm_args),
+            Command::Verify(verify_args) => verify::run(&verify_args),
+        };
+        if let Err(e) = result {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut rom_file = args.rom_file.take().expect("ROM file is required");
+    let settings_path = args.config.clone().unwrap_or_else(Settings::default_path);
+    let mut settings = Settings::load(settings_path);
+
+    let sdl_ctx = sdl2::init().unwrap();
+    let video_subsystem = sdl_ctx.video().unwrap();
+    let audio_subsystem = sdl_ctx.audio().unwrap();
+
+    let rom_db = settings.load_rom_db();
+    let bytes = std::fs::read(&rom_file).expect("failed to read ROM");
+    if args.nsf || nsf::is_nsf(&bytes) {
+        let sample_rate = 48000;
+        let audio_buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let _audio_device = open_audio(
+            &audio_subsystem,
+            settings.audio_device.as_deref(),
+            sample_rate,
+            &audio_buffer,
+            &Arc::default(),
+        );
+        let result = NsfPlayer::new(&bytes, APU::new(sample_rate)).and_then(|player| {
+            nsf_player::run(
+                &sdl_ctx,
+                player,
+                &audio_buffer,
+                &SystemClock(Instant::now()),
+            )
+        });
+        if let Err(e) = result {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let cart = Cart::with_rom_db(&bytes, &rom_db).expect("failed to parse cartridge");
+
+    let (window_width, window_height) = video::window_size(SCALE, settings.pixel_aspect);
+    let window = video_subsystem
+        .window("pico", window_width, window_height)
+        .position_centered()
+        .resizable()
+        .build()
+        .unwrap();
+
+    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    canvas.set_draw_color(sdl2::pixels::Color::BLACK);
+    canvas.clear();
+    canvas.present();
+
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_target(PixelFormatEnum::RGB24, WIDTH, HEIGHT)
+        .unwrap();
+
+    // Initialize emulator
+    let sample_rate = 48000;
+    let audio_capacity = sample_rate as usize * 2;
+    let audio_buffer = Arc::new(Mutex::new(VecDeque::with_capacity(audio_capacity)));
+
+    let audio_underruns = Arc::new(AtomicUsize::new(0));
+
+    let apu = APU::new(sample_rate);
+
+    // Kept alive for playback, replaced when the device changes.
+    let mut audio_device = open_audio(
+        &audio_subsystem,
+        settings.audio_device.as_deref(),
+        sample_rate,
+        &audio_buffer,
+        &audio_underruns,
+    );
+
+    let mut nes = new_nes(cart, apu, &args, &settings);
+    load_cheats(&mut nes, &rom_file);
+    let battery = load_battery(&mut nes, &rom_file);
+    nes.bus.ppu.system_palette = settings.palette.palette();
+    if let Some(path) = &args.palette {
+        match SystemPalette::load(path) {
+            Ok(palette) => nes.bus.ppu.system_palette = palette,
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    let mut key_map = settings.key_map();
+    let mut hotkeys = settings.hotkey_map();
+    let mut dpad_filters = [
+        DpadFilter::new(settings.dpad_policy),
+        DpadFilter::new(settings.dpad_policy),
+    ];
+
+    let audio_devices = (0..audio_subsystem.num_audio_playback_devices().unwrap_or(0))
+        .filter_map(|i| audio_subsystem.audio_playback_device_name(i).ok())
+        .collect();
+    let mut menu = Menu::new(audio_devices);
+
+    let movie = args
+        .movie_file
+        .take()
+        .and_then(|path| load_movie(&path).inspect_err(|e| eprintln!("{e}")).ok());
+
+    let history = (args.history_seconds > 0).then(|| InputHistory::new(args.history_seconds * 60));
+    let rewind = new_rewind(&args, &nes);
+
+    let debug_prompt = args.debugger.then(DebugPrompt::open);
+    if args.debugger {
+        println!("Debugger paused at power on, type help for commands");
+    }
+
+    let stats = PerfStats::new(nes.region().frame_rate(), sample_rate);
+    let mut osd = Osd::default();
+    let mut compositor = Compositor::default();
+    if args.timer {
+        compositor.add(Box::new(Timer));
+    }
+    if let Some(path) = &args.overlay_text {
+        compositor.add(Box::new(TextFile::new(path.clone())));
+    }
+    let mut debug_windows = DebugWindows::default();
+
+    let shared = Shared {
+        session: Mutex::new(Session {
+            nes,
+            movie,
+            history,
+            pending_commands: 0,
+            rewind,
+            battery,
+            stats,
+            pause_latch: PauseLatch::default(),
+            // Nothing runs until the main thread's first pass fills these in.
+            controls: Controls {
+                paused: true,
+                slowdown: 1,
+                ..Controls::default()
+            },
+            advance_frame: false,
+            presents: 0,
+            running: true,
+        }),
+        wake: Condvar::new(),
+    };
+    let screen = TripleBuffer::new();
+    // The frame on screen, swapped for a newer one as they come in.
+    let mut framebuffer = Framebuffer::new();
+
+    // Without the subsystem the keyboard still works.
+    let mut gamepads = match sdl_ctx.game_controller() {
+        Ok(subsystem) => Some(Gamepads::new(subsystem, settings.gamepad_map())),
+        Err(e) => {
+            eprintln!("Failed to initialize game controllers: {}", e);
+            None
+        }
+    };
+    let mut event_pump = sdl_ctx.event_pump().unwrap();
+    let mut running = true;
+    let mut paused = false;
+    let mut focused = true;
+    let mut fast_forward = false;
+    let mut slow_motion = false;
+    let mut state_slot = 1;
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            emulate(
+                &shared,
+                &screen,
+                &audio_buffer,
+                audio_capacity,
+                &audio_underruns,
+                &args,
+            )
+        });
+
+        // This thread handles events and presents. It holds the session
+        // while it reads and changes the console, and lets go of it before
+        // a present waits for vsync.
+        while running {
+            let mut session = shared.session.lock().unwrap();
+            let Session {
+                nes,
+                movie,
+                history,
+                pending_commands,
+                rewind,
+                battery,
+                stats,
+                pause_latch,
+                controls,
+                advance_frame,
+                presents,
+                ..
+            } = &mut *session;
+            *presents += 1;
+
+            for event in event_pump.poll_iter() {
+                if let Some(message) = gamepads.as_mut().and_then(|pads| pads.handle_event(&event))
+                {
+                    println!("{}", message);
+                }
+                // Moving between the main and debug windows loses focus and
+                // gains it back in the same batch of events.
+                match event {
+                    Event::Window {
+                        win_event: WindowEvent::FocusGained,
+                        ..
+                    } => focused = true,
+                    Event::Window {
+                        win_event: WindowEvent::FocusLost,
+                        ..
+                    } => focused = false,
+                    _ => {}
+                }
+                if debug_windows.handle_event(&event, nes) {
+                    continue;
+                }
+                if menu.open {
+                    if let Event::KeyDown {
+                        keycode: Some(key), ..
+                    } = event
+                    {
+                        match menu.handle_key(
+                            key,
+                            &mut settings,
+                            Path::new(&rom_file),
+                            nes.bus.cart.crc32,
+                            &nes.bus.cheats,
+                            &mut state_slot,
+                        ) {
+                            Some(MenuAction::Reset) => {
+                                nes.reset();
+                                *pending_commands |= COMMAND_RESET;
+                            }
+                            Some(MenuAction::SaveState) => {
+                                flush_battery(battery, nes);
+                                save_state(nes, &rom_file, state_slot);
+                            }
+                            Some(MenuAction::LoadState) => {
+                                load_state(nes, &rom_file, state_slot, history);
+                            }
+                            Some(MenuAction::OpenRom(path)) => match load_cart(&path, &rom_db) {
+                                Ok(cart) => {
+                                    print_compat_report(nes);
+                                    flush_battery(battery, nes);
+                                    let apu = APU::new(sample_rate);
+                                    let palette = nes.bus.ppu.system_palette.clone();
+                                    *nes = new_nes(cart, apu, &args, &settings);
+                                    nes.bus.ppu.system_palette = palette;
+                                    stats.set_target_fps(nes.region().frame_rate());
+                                    rom_file = path.to_string_lossy().into_owned();
+                                    load_cheats(nes, &rom_file);
+                                    *battery = load_battery(nes, &rom_file);
+                                    *rewind = new_rewind(&args, nes);
+                                    *movie = None;
+                                    if let Some(history) = history {
+                                        history.clear();
+                                    }
+                                }
+                                Err(e) => eprintln!("{e}"),
+                            },
+                            Some(MenuAction::ToggleCheat(index)) => {
+                                toggle_cheat(nes, &rom_file, index);
+                            }
+                            Some(MenuAction::SettingsChanged) => {
+                                for filter in &mut dpad_filters {
+                                    filter.policy = settings.dpad_policy;
+                                }
+                                nes.set_renderer(settings.renderer);
+                                key_map = settings.key_map();
+                                hotkeys = settings.hotkey_map();
+                                if let Some(gamepads) = &mut gamepads {
+                                    gamepads.set_button_map(settings.gamepad_map());
+                                }
+                                save_settings(&settings);
+                            }
+                            Some(MenuAction::SetAudioDevice) => {
+                                audio_device = open_audio(
+                                    &audio_subsystem,
+                                    settings.audio_device.as_deref(),
+                                    sample_rate,
+                                    &audio_buffer,
+                                    &audio_underruns,
+                                );
+                                save_settings(&settings);
+                            }
+                            Some(MenuAction::SetPalette) => {
+                                nes.bus.ppu.system_palette = settings.palette.palette();
+                                save_settings(&settings);
+                            }
+                            Some(MenuAction::SetRegion) => {
+                                apply_region(nes, &args, &settings);
+                                stats.set_target_fps(nes.region().frame_rate());
+                                save_settings(&settings);
+                            }
+                            Some(MenuAction::Quit) => running = false,
+                            None => {}
+                        }
+                    }
+                    if let Event::Quit { .. } = event {
+                        running = false;
+                    }
+                    continue;
+                }
+
+                let action = match event {
+                    // With debug windows open, closing the main window doesn't
+                    // quit on its own.
+                    Event::Quit { .. }
+                    | Event::Window {
+                        win_event: WindowEvent::Close,
+                        ..
+                    } => {
+                        running = false;
+                        continue;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Return),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                        if let Err(e) = video::toggle_fullscreen(&mut canvas) {
+                            eprintln!("{e}");
+                        }
+                        continue;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(key),
+                        repeat: false,
+                        ..
+                    } => match hotkeys.get(&key) {
+                        Some(action) => *action,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+
+                match action {
+                    Action::Menu => menu.show(),
+                    Action::Quit => running = false,
+                    Action::Reset => {
+                        nes.reset();
+                        *pending_commands |= COMMAND_RESET;
+                    }
+                    Action::SaveState(slot) => {
+                        flush_battery(battery, nes);
+                        save_state(nes, &rom_file, slot.unwrap_or(state_slot));
+                    }
+                    Action::LoadState(slot) => {
+                        let slot = slot.unwrap_or(state_slot);
+                        load_state(nes, &rom_file, slot, history);
+                    }
+                    Action::NextStateSlot => {
+                        state_slot = state_slot % STATE_SLOTS + 1;
+                        nes.push_event(EmulatorEvent::Notice(format!("State slot {state_slot}")));
+                    }
+                    Action::Pause if nes.bus.debugger.is_some() => {
+                        let debug_paused =
+                            nes.bus.debugger.as_ref().is_some_and(Debugger::is_paused);
+                        let command = if debug_paused { "continue" } else { "pause" };
+                        debug_prompt::run_command(nes, command);
+                    }
+                    Action::Pause => {
+                        paused = !paused;
+                        println!("{}", if paused { "Paused" } else { "Resumed" });
+                    }
+                    Action::FrameAdvance if nes.bus.debugger.is_some() => {
+                        debug_prompt::run_command(nes, "frame");
+                    }
+                    Action::FrameAdvance => {
+                        paused = true;
+                        *advance_frame = true;
+                    }
+                    Action::StepInstruction => debug_prompt::run_command(nes, "step"),
+                    Action::ToggleFastForward => {
+                        fast_forward = !fast_forward;
+                        slow_motion = false;
+                    }
+                    Action::ToggleSlowMotion => {
+                        slow_motion = !slow_motion;
+                        fast_forward = false;
+                    }
+                    Action::Screenshot => {
+                        flush_battery(battery, nes);
+                        let dir = settings.screenshot_dir.as_deref();
+                        match save_screenshot(&framebuffer, dir, &rom_file) {
+                            Ok(path) => println!("Saved screenshot to {}", path.display()),
+                            Err(e) => eprintln!("{e}"),
+                        }
+                    }
+                    Action::ToggleMute(channel) => {
+                        let muted = !nes.bus.apu.channel_muted(channel);
+                        nes.bus.apu.set_channel_muted(channel, muted);
+                        println!(
+                            "{} {}",
+                            channel.name(),
+                            if muted { "muted" } else { "unmuted" }
+                        );
+                    }
+                    Action::ToggleStats => osd.toggle(),
+                    Action::ToggleDebugView(view) => {
+                        if let Err(e) = debug_windows.toggle(&video_subsystem, view) {
+                            eprintln!("{e}");
+                        }
+                    }
+                    Action::SaveInputHistory => {
+                        if let Some(history) = history {
+                            save_history(history, &rom_file);
+                        }
+                    }
+                    Action::ExportChr => {
+                        let (chr_path, _) = chr_paths(args.chr_file.as_deref(), &rom_file);
+                        report_chr(
+                            "Exported CHR to",
+                            &chr_path,
+                            chr_file::export_full(nes.bus.cart.mapper.as_ref(), &chr_path),
+                        );
+                    }
+                    Action::ExportVisibleChr => {
+                        let (_, visible_chr_path) = chr_paths(args.chr_file.as_deref(), &rom_file);
+                        report_chr(
+                            "Exported visible CHR banks to",
+                            &visible_chr_path,
+                            chr_file::export_visible(
+                                nes.bus.cart.mapper.as_ref(),
+                                &visible_chr_path,
+                            ),
+                        );
+                    }
+                    Action::ImportChr => {
+                        let (chr_path, _) = chr_paths(args.chr_file.as_deref(), &rom_file);
+                        report_chr(
+                            "Imported CHR from",
+                            &chr_path,
+                            chr_file::import(nes.mapper_mut(), &chr_path),
+                        );
+                    }
+                    Action::ToggleRegisterLog => match nes.bus.register_log.take() {
+                        Some(log) => {
+                            save_register_log(&log, &rom_file, nes.bus.apu.cpu_clock_rate())
+                        }
+                        None => {
+                            nes.bus.register_log = Some(RegisterLog::new());
+                            println!("Logging sound register writes, toggle again to save");
+                        }
+                    },
+                    Action::ExportPalette => {
+                        let path = format!("{}.pal", timestamped_base(&rom_file));
+                        match nes.bus.ppu.system_palette.save(Path::new(&path)) {
+                            Ok(()) => println!("Saved palette to {path}"),
+                            Err(e) => eprintln!("{e}"),
+                        }
+                    }
+                    // Only for this session, to compare the two on the spot.
+                    Action::ToggleRenderer => {
+                        let renderer = match nes.renderer() {
+                            Renderer::ScrollSegments => Renderer::Scanline,
+                            Renderer::Scanline => Renderer::ScrollSegments,
+                        };
+                        nes.set_renderer(Some(renderer));
+                        println!("Renderer: {}", renderer.name());
+                    }
+                    Action::ToggleMicrophone => {
+                        if let Some(joypad2) = nes.joypad_mut(1) {
+                            joypad2.microphone = !joypad2.microphone;
+                            println!(
+                                "Microphone {}",
+                                if joypad2.microphone { "on" } else { "off" }
+                            );
+                        }
+                    }
+                    Action::SwapAB => {
+                        let mut rom = settings.rom(nes.bus.cart.crc32);
+                        rom.layouts[0].swap_ab = !rom.layouts[0].swap_ab;
+                        println!("Player 1 layout: {}", rom.layouts[0].name());
+                        settings.set_rom(nes.bus.cart.crc32, rom);
+                        save_settings(&settings);
+                    }
+                    Action::SwapGamepads => {
+                        if let Some(gamepads) = &mut gamepads {
+                            gamepads.swap_players();
+                            println!("Swapped player 1 and 2 controllers");
+                        }
+                    }
+                    // Checked along with the controller while they are held.
+                    Action::Rewind
+                    | Action::HoldFastForward
+                    | Action::InsertCoin
+                    | Action::Service => {}
+                }
+            }
+
+            if let Some(prompt) = &debug_prompt {
+                prompt.poll(nes);
+            }
+
+            for event in nes.take_events() {
+                if let EmulatorEvent::CpuJam { .. } = event {
+                    match settings.on_jam {
+                        OnJam::Halt => {}
+                        OnJam::Pause => {
+                            paused = true;
+                            println!("Paused");
+                        }
+                        OnJam::Reset => {
+                            nes.reset();
+                            *pending_commands |= COMMAND_RESET;
+                            println!("Reset after the CPU jammed");
+                        }
+                    }
+                }
+                report_event(&mut osd, event);
+            }
+
+            let keys: Vec<Keycode> = event_pump
+                .keyboard_state()
+                .pressed_scancodes()
+                .filter_map(|sc| Keycode::from_scancode(sc))
+                .collect();
+            let holding = |wanted: Action| {
+                hotkeys
+                    .iter()
+                    .any(|(key, action)| *action == wanted && keys.contains(key))
+            };
+
+            let fast = fast_forward || holding(Action::HoldFastForward);
+            nes.set_fast_cpu(args.fast_cpu && fast);
+
+            let background_pause = !focused && settings.focus_loss == FocusLoss::Pause;
+            let muted = !focused && settings.focus_loss == FocusLoss::Mute;
+            let debug_paused = nes.bus.debugger.as_ref().is_some_and(Debugger::is_paused);
+            let halted = (paused || background_pause || debug_paused) && !*advance_frame;
+            set_audio_playing(&mut audio_device, !(menu.open || halted || muted));
+
+            let layouts = settings.rom(nes.bus.cart.crc32).layouts;
+            let held = key_map
+                .iter()
+                .filter(|(key, _)| keys.contains(key))
+                .fold(JoypadButton::empty(), |held, (_, btn)| held | *btn);
+            // The keyboard and the first controller both play player 1.
+            let pads = gamepads
+                .as_ref()
+                .map_or([JoypadButton::empty(); 2], |pads| {
+                    [pads.buttons(0), pads.buttons(1)]
+                });
+            let buttons = [
+                dpad_filters[0].apply(layouts[0].apply(held | pads[0])),
+                dpad_filters[1].apply(layouts[1].apply(pads[1])),
+            ];
+            // Kept for the next frame, wherever it falls among the presents.
+            pause_latch.hold(buttons);
+
+            if let Some(vs) = &mut nes.bus.vs_system {
+                vs.coins = holding(Action::InsertCoin) as u8;
+                vs.service = holding(Action::Service);
+            }
+
+            *controls = Controls {
+                buttons,
+                layouts,
+                menu_open: menu.open,
+                paused: paused || background_pause || debug_paused,
+                fast,
+                slowdown: if slow_motion && !fast {
+                    settings.slow_motion.slowdown()
+                } else {
+                    1
+                },
+                rewinding: holding(Action::Rewind),
+                muted,
+                fast_forward_speed: settings.fast_forward_speed,
+                fast_forward_audio: settings.fast_forward_audio,
+            };
+
+            if screen.take_latest(&mut framebuffer) {
+                texture
+                    .update(None, &framebuffer.data, (WIDTH * 3) as usize)
+                    .unwrap();
+            }
+            video::draw_frame(&mut canvas, &texture, &settings);
+            if menu.open {
+                menu.draw(
+                    &mut canvas,
+                    &settings,
+                    nes.bus.cart.crc32,
+                    &nes.bus.cheats,
+                    state_slot,
+                );
+            } else {
+                compositor.draw(&mut canvas, &frame_info(nes));
+                osd.draw(&mut canvas, &stats.snapshot());
+            }
+            debug_windows.draw(nes);
+
+            drop(session);
+            shared.wake.notify_one();
+            canvas.present();
+        }
+
+        shared.session.lock().unwrap().running = false;
+        shared.wake.notify_one();
+    });
+
+    let Session {
+        mut nes,
+        mut battery,
+        ..
+    } = shared.session.into_inner().unwrap();
+    flush_battery(&mut battery, &mut nes);
+    print_compat_report(&nes);
+    if let Some(path) = &args.report
+        && let Err(e) = std::fs::write(path, nes.compat_json())
+    {
+        eprintln!("Failed to write {}: {}", path.display(), e);
+    }
+}
+
+// The console and what goes along with it, shared by the main thread, which
+// handles events and presents, and the emulation thread, which runs frames.
+struct Session {
+    nes: Nes,
+    movie: Option<FM2Movie>,
+    history: Option<InputHistory>,
+    pending_commands: u8,
+    rewind: Option<Rewind>,
+    battery: Option<BatterySave>,
+    stats: PerfStats,
+    pause_latch: PauseLatch,
+    controls: Controls,
+    advance_frame: bool,
+    // Counts the main thread's passes, each ending in a present.
+    presents: u64,
+    running: bool,
+}
+
+struct Shared {
+    session: Mutex<Session>,
+    // Signalled by the main thread after each pass, for the emulation thread
+    // to look again at what it should run.
+    wake: Condvar,
+}
+
+// What the main thread wants from the frames run next, going by the keys
+// held and the settings.
+#[derive(Clone, Copy, Default)]
+struct Controls {
+    buttons: [JoypadButton; 2],
+    layouts: [ButtonLayout; 2],
+    menu_open: bool,
+    paused: bool,
+    fast: bool,
+    slowdown: usize,
+    rewinding: bool,
+    muted: bool,
+    fast_forward_speed: FastForwardSpeed,
+    fast_forward_audio: FastForwardAudio,
+}
+
+// The emulation thread: runs frames as they fall due, queues their sound and
+// hands their pictures to `screen`. It never waits on a present, only on the
+// main thread holding the session.
+fn emulate(
+    shared: &Shared,
+    screen: &TripleBuffer,
+    audio_buffer: &Mutex<VecDeque<f32>>,
+    audio_capacity: usize,
+    audio_underruns: &AtomicUsize,
+    args: &CliArgs,
+) {
+    // Only used here, between frames, so they are made on this thread.
+    let mut pipe_input = args.input_pipe.as_deref().map(PipeInput::open);
+    let mut script = args
+        .script
+        .as_deref()
+        .and_then(|path| Script::load(path).inspect_err(|e| eprintln!("{e}")).ok());
+    let clock = SystemClock(Instant::now());
+    let mut pacer = FramePacer::default();
+    let mut framebuffer = Framebuffer::new();
+    let mut samples = Vec::new();
+    let mut last_batch = Instant::now();
+    let mut presents = 0;
+
+    let mut session = shared.session.lock().unwrap();
+    while session.running {
+        let controls = session.controls;
+        if controls.menu_open || (controls.paused && !session.advance_frame) {
+            session = shared.wake.wait(session).unwrap();
+            continue;
+        }
+        // Uncapped fast-forward runs a batch of frames for each present
+        // instead of going by the clock.
+        let uncapped = controls.fast && controls.fast_forward_speed.frames().is_none();
+        let now = clock.now();
+        if uncapped {
+            if session.presents == presents {
+                session = shared.wake.wait(session).unwrap();
+                continue;
+            }
+            presents = session.presents;
+        } else if !session.advance_frame && !pacer.frame_due(now) {
+            let wait = pacer.time_until_due(now);
+            session = shared.wake.wait_timeout(session, wait).unwrap().0;
+            continue;
+        }
+
+        let batch_start = Instant::now();
+        let Session {
+            nes,
+            movie,
+            history,
+            pending_commands,
+            rewind,
+            battery,
+            stats,
+            pause_latch,
+            advance_frame,
+            ..
+        } = &mut *session;
+        pacer.start_frame(now, nes.region().frame_rate() / controls.slowdown as f64);
+        *advance_frame = false;
+        let buttons = pause_latch.take(controls.buttons);
+        let layouts = controls.layouts;
+
+        // A movie can't be rewound, since its input is tied to frame numbers.
+        let rewinding = movie.is_none() && controls.rewinding;
+        if rewinding && let Some(rewind) = rewind {
+            // Run a frame from the snapshot to have a picture of it. Its sound
+            // is dropped, and the input history can't follow the console
+            // backwards, so it starts over from where the rewind landed.
+            if rewind.step_back(nes) {
+                nes.step_frame();
+                nes.bus.apu.take_samples(&mut samples);
+                audio_buffer.lock().unwrap().clear();
+                restart_history(history, nes);
+            }
+        }
+
+        let frames = if rewinding {
+            0
+        } else if controls.fast && !controls.paused {
+            controls.fast_forward_speed.frames().unwrap_or(usize::MAX)
+        } else {
+            1
+        };
+        let mut ran = 0;
+        for _ in 0..frames {
+            apply_inputs(nes, movie, buttons);
+            if let Some(pipe) = &mut pipe_input {
+                let frame = pipe.poll();
+                if frame.reset {
+                    nes.reset();
+                    *pending_commands |= COMMAND_RESET;
+                }
+                let (joypad1, joypad2) = nes.joypads_mut();
+                joypad1.button_status |= layouts[0].apply(frame.pads[0]);
+                joypad2.button_status |= layouts[1].apply(frame.pads[1]);
+            }
+            // A failing script stops, and the game carries on without it.
+            if let Some(running_script) = &mut script
+                && let Err(e) = running_script.on_frame(nes)
+            {
+                eprintln!("{e}");
+                script = None;
+            }
+            if let Some(history) = history {
+                if history.keyframe_due() {
+                    history.add_keyframe(nes.save_state());
+                }
+                let (joypad1, joypad2) = nes.joypads_mut();
+                history.record(
+                    *pending_commands,
+                    joypad1.button_status,
+                    joypad2.button_status,
+                );
+            }
+            *pending_commands = 0;
+            let stop = nes.debug_step_frame();
+            if let Some(rewind) = rewind {
+                rewind.record(nes);
+            }
+            if let Some(battery) = battery
+                && let Err(e) = battery.end_frame(nes)
+            {
+                eprintln!("{e}");
+            }
+            ran += 1;
+            if let Some(stop) = stop {
+                debug_prompt::print_stop(nes, stop);
+                break;
+            }
+            if frames == usize::MAX && batch_start.elapsed() >= UNCAPPED_FRAME_TIME {
+                break;
+            }
+        }
+        stats.set_speed(if controls.slowdown > 1 {
+            1.0 / controls.slowdown as f64
+        } else {
+            ran.max(1) as f64
+        });
+        nes.bus.apu.take_samples(&mut samples);
+        sink::stretch(&mut samples, controls.slowdown);
+        let mut buffer = audio_buffer.lock().unwrap();
+        // Sound made while muted is dropped rather than played late.
+        if controls.muted {
+            buffer.clear();
+        } else {
+            buffer.extend(&samples);
+            if ran > 1 {
+                controls
+                    .fast_forward_audio
+                    .trim(&mut buffer, samples.len(), ran);
+            }
+            // A stalled device loses the oldest sound rather than growing
+            // the queue forever.
+            let excess = buffer.len().saturating_sub(audio_capacity);
+            buffer.drain(..excess);
+        }
+        stats.set_audio_buffer(buffer.len(), audio_capacity);
+        drop(buffer);
+
+        framebuffer.data.fill(0);
+        nes.bus.render_frame(&mut framebuffer);
+        if let Some(running_script) = &mut script
+            && let Err(e) = running_script.on_draw(nes, &mut framebuffer)
+        {
+            eprintln!("{e}");
+            script = None;
+        }
+        screen.publish(&mut framebuffer);
+
+        stats.set_audio_underruns(audio_underruns.load(Ordering::Relaxed));
+        stats.set_compat_notes(nes.bus.cart.compat.notes().len());
+        let now = Instant::now();
+        stats.record_frame(now - last_batch, now - batch_start, ran.max(1));
+        last_batch = now;
+    }
+}
+
+fn frame_info(nes: &Nes) -> FrameInfo {
+    FrameInfo {
+        frame: nes.frame_count() as usize,
+        frame_rate: nes.region().frame_rate(),
+    }
+}
+
+fn new_nes(cart: Cart, apu: APU, args: &CliArgs, settings: &Settings) -> Nes {
+    let mut nes = Nes::new(cart, apu);
+    nes.set_accuracy(args.accuracy);
+    nes.set_renderer(settings.renderer);
+    if args.no_sprite_limit {
+        nes.set_sprite_limit(Some(false));
+    }
+    apply_region(&mut nes, args, settings);
+    if args.debug {
+        nes.set_trace_hook(Some(Box::new(|record| println!("{record}"))));
+    }
+    if args.debugger {
+        let mut debugger = Debugger::default();
+        debugger.pause();
+        nes.bus.debugger = Some(debugger);
+    }
+    println!("Region: {}", nes.region().name());
+    if let Some(vs) = &mut nes.bus.vs_system {
+        vs.dip_switches = args.dip_switches;
+        // Switch 1 first, as they are numbered on the board.
+        println!(
+            "Vs. System, DIP switches {:08b}",
+            vs.dip_switches.reverse_bits()
+        );
+    }
+    nes.reset();
+    nes
+}
+
+// --region wins over the menu's choice, which wins over the cartridge's.
+fn apply_region(nes: &mut Nes, args: &CliArgs, settings: &Settings) {
+    let region = args.region.or(settings.region);
+    nes.set_region(region.unwrap_or(nes.bus.cart.region));
+}
+
+fn new_rewind(args: &CliArgs, nes: &Nes) -> Option<Rewind> {
+    (args.rewind_seconds > 0).then(|| {
+        Rewind::new(
+            args.rewind_seconds,
+            args.rewind_interval,
+            nes.region().frame_rate(),
+        )
+    })
+}
+
+// Logs an event from the console and shows it on screen for a while.
+fn report_event(osd: &mut Osd, event: EmulatorEvent) {
+    match event.level() {
+        EventLevel::Notice => log::info!("{event}"),
+        EventLevel::Warning => log::warn!("{event}"),
+        EventLevel::Error => log::error!("{event}"),
+    }
+    osd.show_event(event);
+}
+
+fn print_compat_report(nes: &Nes) {
+    let compat = &nes.bus.cart.compat;
+    if !compat.is_empty() {
+        print!("{}", compat.report());
+    }
+}
+
+// FM2, or a BizHawk BK2 archive going by its extension or zip signature.
+fn load_movie(path: &str) -> Result<FM2Movie, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let bk2_extension = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("bk2"));
+    if bk2_extension || bk2::is_bk2(&bytes) {
+        bk2::parse_bk2(Cursor::new(bytes))
+    } else {
+        FM2Movie::parse(bytes.as_slice())
+    }
+}
+
+// Movies are indexed by the console's own frame count, so they stay in step
+// across resets, rewinds and savestates.
+fn apply_inputs(nes: &mut Nes, movie: &mut Option<FM2Movie>, buttons: [JoypadButton; 2]) {
+    if let Some(movie) = movie {
+        let frame_count = nes.frame_count() as usize;
+        if frame_count < movie.frame_count() {
+            let (joypad1, joypad2) = nes.joypads_mut();
+            let _ = movie.apply_frame_input(frame_count, joypad1, joypad2);
+            return;
+        }
+    }
+
+    for (player, buttons) in buttons.into_iter().enumerate() {
+        if let Some(joypad) = nes.joypad_mut(player) {
+            joypad.button_status = buttons;
+        }
+    }
+}
+
+fn save_history(history: &InputHistory, rom_file: &str) {
+    let rom_path = Path::new(rom_file);
+    let rom_name = rom_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = rom_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "pico".to_string());
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = format!("{stem}-{timestamp}.fm2");
+
+    let result = history
+        .to_movie(MovieHeader::for_recording(&rom_name))
+        .and_then(|movie| movie.save_to_file(&path));
+    match result {
+        Ok(()) => println!("Saved last {} frames to {path}", history.len()),
+        Err(e) => eprintln!("Failed to save input history: {e}"),
+    }
+}
+
+// `<rom stem>-<unix time>`, for files saved from hotkeys.
+fn timestamped_base(rom_file: &str) -> String {
+    let stem = Path::new(rom_file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "pico".to_string());
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("{stem}-{timestamp}")
+}
+
+// Screenshots taken within the same second are numbered rather than
+// overwriting each other.
+fn save_screenshot(
+    framebuffer: &Framebuffer,
+    dir: Option<&Path>,
+    rom_file: &str,
+) -> Result<PathBuf, String> {
+    let dir = dir.unwrap_or(Path::new(""));
+    if !dir.as_os_str().is_empty() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+
+    let base = timestamped_base(rom_file);
+    let mut path = dir.join(format!("{base}.png"));
+    let mut number = 2;
+    while path.exists() {
+        path = dir.join(format!("{base}-{number}.png"));
+        number += 1;
+    }
+    framebuffer.save_png(&path)?;
+    Ok(path)
+}
+
+fn save_register_log(log: &RegisterLog, rom_file: &str, cpu_clock_rate: u64) {
+    let base = timestamped_base(rom_file);
+
+    let result = log
+        .save_text(format!("{base}.txt"))
+        .and_then(|_| log.save_vgm(format!("{base}.vgm"), cpu_clock_rate));
+    match result {
+        Ok(()) => println!(
+            "Saved {} register writes to {base}.txt and {base}.vgm",
+            log.writes().len()
+        ),
+        Err(e) => eprintln!("Failed to save register log: {e}"),
+    }
+}
+
+// Quick-save slots per ROM, kept next to it as .state1, .state2 and so on.
+fn state_path(rom_file: &str, slot: u8) -> PathBuf {
+    Path::new(rom_file).with_extension(format!("state{slot}"))
+}
+
+// Written atomically, so a crash partway leaves the slot's old state.
+fn save_state(nes: &mut Nes, rom_file: &str, slot: u8) {
+    let path = state_path(rom_file, slot);
+    match write_atomic(&path, &nes.save_state()) {
+        Ok(()) => nes.push_event(EmulatorEvent::Notice(format!(
+            "Saved state to {}",
+            path.display()
+        ))),
+        Err(e) => eprintln!("Failed to save state: {e}"),
+    }
+}
+
+fn load_state(nes: &mut Nes, rom_file: &str, slot: u8, history: &mut Option<InputHistory>) {
+    let path = state_path(rom_file, slot);
+    let result = std::fs::read(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        .and_then(|data| nes.load_state(&data));
+    match result {
+        Ok(()) => {
+            restart_history(history, nes);
+            nes.push_event(EmulatorEvent::Notice(format!(
+                "Loaded state from {}",
+                path.display()
+            )));
+        }
+        Err(e) => eprintln!("Failed to load state: {e}"),
+    }
+}
+
+// After the console jumps to another state, the input history starts over
+// from it.
+fn restart_history(history: &mut Option<InputHistory>, nes: &Nes) {
+    if let Some(history) = history {
+        history.restart_from(nes.save_state());
+    }
+}
+
+// Battery-backed RAM for carts that have it, kept next to the ROM.
+fn load_battery(nes: &mut Nes, rom_file: &str) -> Option<BatterySave> {
+    if !nes.bus.cart.has_battery_save() {
+        return None;
+    }
+    let battery = BatterySave::new(
+        Path::new(rom_file).with_extension("sav"),
+        DEFAULT_FLUSH_FRAMES,
+    );
+    match battery.load(nes) {
+        Ok(true) => nes.push_event(EmulatorEvent::Notice(format!(
+            "Loaded battery save from {}",
+            battery.path().display()
+        ))),
+        Ok(false) => {}
+        Err(e) => eprintln!("{e}"),
+    }
+    Some(battery)
+}
+
+fn flush_battery(battery: &mut Option<BatterySave>, nes: &mut Nes) {
+    if let Some(battery) = battery
+        && let Err(e) = battery.flush(nes)
+    {
+        eprintln!("{e}");
+    }
+}
+
+// Game Genie and RAM codes for a ROM, kept next to it.
+fn cheats_path(rom_file: &str) -> PathBuf {
+    Path::new(rom_file).with_extension("cht")
+}
+
+fn load_cheats(nes: &mut Nes, rom_file: &str) {
+    let path = cheats_path(rom_file);
+    if !path.exists() {
+        return;
+    }
+    match Cheats::load(&path) {
+        Ok(cheats) => {
+            let count = cheats.cheats().len();
+            nes.bus.cheats = cheats;
+            nes.push_event(EmulatorEvent::Notice(format!(
+                "Loaded {} cheats from {}",
+                count,
+                path.display()
+            )));
+        }
+        Err(e) => eprintln!("{e}"),
+    }
+}
+
+fn toggle_cheat(nes: &mut Nes, rom_file: &str, index: usize) {
+    let Some(cheat) = nes.bus.cheats.cheats().get(index) else {
+        return;
+    };
+    let enabled = !cheat.enabled;
+    let text = format!(
+        "{} {}",
+        cheat.code,
+        if enabled { "enabled" } else { "disabled" }
+    );
+    nes.bus.cheats.set_enabled(index, enabled);
+    nes.push_event(EmulatorEvent::Notice(text));
+    if let Err(e) = nes.bus.cheats.save(&cheats_path(rom_file)) {
+        eprintln!("{e}");
+    }
+}
+
+fn open_audio(
+    audio_subsystem: &AudioSubsystem,
+    device: Option<&str>,
+    sample_rate: u32,
+    audio_buffer: &Arc<Mutex<VecDeque<f32>>>,
+    underruns: &Arc<AtomicUsize>,
+) -> AudioDevice<AudioCallbackImpl> {
+    let open = |device: Option<&str>| {
+        audio_subsystem.open_playback(
+            device,
+            &sdl2::audio::AudioSpecDesired {
+                freq: Some(sample_rate as i32),
+                channels: Some(1),
+                samples: None,
+            },
+            |spec| {
+                assert_eq!(spec.freq, sample_rate as i32);
+                assert_eq!(spec.channels, 1);
+                AudioCallbackImpl {
+                    audio_buffer: audio_buffer.clone(),
+                    concealer: Concealer::new(sample_rate, underruns.clone()),
+                }
+            },
+        )
+    };
+
+    let audio_device = open(device).unwrap_or_else(|e| {
+        eprintln!("Failed to open audio device {device:?}: {e}, using the default");
+        open(None).unwrap()
+    });
+    audio_device.resume();
+    audio_device
+}
+
+// The device is paused while nothing should play, rather than left to run
+// dry, and picks up from what is buffered when it resumes.
+fn set_audio_playing(device: &mut AudioDevice<AudioCallbackImpl>, playing: bool) {
+    match (device.status(), playing) {
+        (AudioStatus::Playing, false) => device.pause(),
+        (AudioStatus::Paused, true) => {
+            device.lock().concealer.restart();
+            device.resume();
+        }
+        _ => {}
+    }
+}
+
+fn load_cart(path: &Path, rom_db: &RomDb) -> Result<Cart, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Cart::with_rom_db(&bytes, rom_db)
+}
+
+fn save_settings(settings: &Settings) {
+    if let Err(e) = settings.save() {
+        eprintln!("{e}");
+    }
+}
+
+// Full CHR file and visible-banks file used by the CHR hotkeys.
+fn chr_paths(chr_file: Option<&str>, rom_file: &str) -> (String, String) {
+    let chr_path = chr_file
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(rom_file).with_extension("chr"));
+    let visible_chr_path = chr_path.with_extension("visible.chr");
+    (
+        chr_path.to_string_lossy().into_owned(),
+        visible_chr_path.to_string_lossy().into_owned(),
+    )
+}
+
+fn report_chr(action: &str, path: &str, result: Result<(), String>) {
+    match result {
+        Ok(()) => println!("{action} {path}"),
+        Err(e) => eprintln!("{e}"),
+    }
+}
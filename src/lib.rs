@@ -1,14 +0,0 @@
-pub mod apu;
-pub mod bus;
-pub mod cart;
-pub mod cpu;
-pub mod joypad;
-pub mod mapper;
-pub mod memory;
-pub mod nes;
-pub mod movie;
-pub mod opcodes;
-pub mod ppu;
-pub mod trace;
-
-extern crate bitflags;
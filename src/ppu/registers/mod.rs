@@ -1,5 +0,0 @@
-pub mod addr;
-pub mod control;
-pub mod mask;
-pub mod scroll;
-pub mod status;